@@ -0,0 +1,229 @@
+//! Access and property flags. The same bit can mean different things in different places, e.g.
+//! 0x0020 is `ACC_SUPER` on a class but `ACC_SYNCHRONIZED` on a method, so each context has its
+//! own set of flags.
+
+/// Defines an enum of the flags that are valid in one context, and a set of those flags.
+macro_rules! access_flags {
+    (
+        $(#[$access_meta:meta])*
+        $access:ident;
+        $(#[$flags_meta:meta])*
+        $flags:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $bits:literal,)*
+        }
+    ) => {
+        $(#[$access_meta])*
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        #[repr(u16)]
+        pub enum $access {
+            $($(#[$variant_meta])* $variant = $bits,)*
+        }
+
+        $(#[$flags_meta])*
+        #[derive(Copy, Clone, PartialEq, Eq)]
+        pub struct $flags(u16);
+
+        impl $flags {
+            pub fn has(self, flag: $access) -> bool {
+                self.0 & (flag as u16) != 0
+            }
+
+            /// The flags as they're stored in the class file.
+            pub fn bits(self) -> u16 {
+                self.0
+            }
+
+            pub(crate) fn new(bits: u16) -> Self {
+                Self(bits)
+            }
+        }
+
+        impl FromIterator<$access> for $flags {
+            fn from_iter<I: IntoIterator<Item = $access>>(iter: I) -> Self {
+                Self(iter.into_iter().fold(0, |bits, flag| bits | flag as u16))
+            }
+        }
+
+        impl std::fmt::Debug for $flags {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("{ ")?;
+
+                for (string, access) in [$((stringify!($variant), $access::$variant),)*] {
+                    if self.has(access) {
+                        f.write_fmt(format_args!("{string} "))?;
+                    }
+                }
+
+                f.write_str("}")
+            }
+        }
+    };
+}
+
+access_flags! {
+    /// A bitflag to check against `ClassAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-E.1>
+    ClassAccess;
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-E.1>
+    ClassAccessFlags {
+        Public = 0x0001,
+        Final = 0x0010,
+        Super = 0x0020,
+        Interface = 0x0200,
+        Abstract = 0x0400,
+        Synthetic = 0x1000,
+        Annotation = 0x2000,
+        Enum = 0x4000,
+        Module = 0x8000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `FieldAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.5-200-A.1>
+    FieldAccess;
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.5-200-A.1>
+    FieldAccessFlags {
+        Public = 0x0001,
+        Private = 0x0002,
+        Protected = 0x0004,
+        Static = 0x0008,
+        Final = 0x0010,
+        Volatile = 0x0040,
+        Transient = 0x0080,
+        Synthetic = 0x1000,
+        Enum = 0x4000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `MethodAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.6-200-A.1>
+    MethodAccess;
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.6-200-A.1>
+    MethodAccessFlags {
+        Public = 0x0001,
+        Private = 0x0002,
+        Protected = 0x0004,
+        Static = 0x0008,
+        Final = 0x0010,
+        Synchronized = 0x0020,
+        /// A bridge method generated by the compiler, e.g. for a covariant override.
+        Bridge = 0x0040,
+        Varargs = 0x0080,
+        Native = 0x0100,
+        Abstract = 0x0400,
+        /// Only meaningful in class files before version 61 (Java 17).
+        Strict = 0x0800,
+        Synthetic = 0x1000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `InnerClassAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.6>
+    InnerClassAccess;
+    /// The flags of a nested class as declared in the source code, from the InnerClasses
+    /// attribute.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.6>
+    InnerClassAccessFlags {
+        Public = 0x0001,
+        Private = 0x0002,
+        Protected = 0x0004,
+        Static = 0x0008,
+        Final = 0x0010,
+        Interface = 0x0200,
+        Abstract = 0x0400,
+        Synthetic = 0x1000,
+        Annotation = 0x2000,
+        Enum = 0x4000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `ParameterAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.24>
+    ParameterAccess;
+    /// The flags of a formal parameter, from the MethodParameters attribute.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.24>
+    ParameterAccessFlags {
+        Final = 0x0010,
+        Synthetic = 0x1000,
+        /// Implicitly declared, e.g. the outer instance parameter of an inner class constructor.
+        Mandated = 0x8000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `ModuleAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    ModuleAccess;
+    /// The `module_flags` of a Module attribute.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    ModuleAccessFlags {
+        Open = 0x0020,
+        Synthetic = 0x1000,
+        Mandated = 0x8000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `RequiresAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    RequiresAccess;
+    /// The `requires_flags` of a module dependency.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    RequiresAccessFlags {
+        Transitive = 0x0020,
+        /// The dependency is only needed at compile time (`requires static`).
+        StaticPhase = 0x0040,
+        Synthetic = 0x1000,
+        Mandated = 0x8000,
+    }
+}
+
+access_flags! {
+    /// A bitflag to check against `ExportsAccessFlags`.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    ExportsAccess;
+    /// The `exports_flags` or `opens_flags` of a package exported or opened by a module.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    ExportsAccessFlags {
+        Synthetic = 0x1000,
+        Mandated = 0x8000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug() {
+        assert_eq!(
+            "{ Public Super }",
+            format!("{:?}", ClassAccessFlags::new(0x0021))
+        );
+        assert_eq!(
+            "{ Public Synchronized }",
+            format!("{:?}", MethodAccessFlags::new(0x0021))
+        );
+        assert_eq!(
+            "{ Volatile }",
+            format!("{:?}", FieldAccessFlags::new(0x0040))
+        );
+        assert_eq!(
+            "{ Bridge }",
+            format!("{:?}", MethodAccessFlags::new(0x0040))
+        );
+        assert_eq!("{ }", format!("{:?}", ExportsAccessFlags::new(0)));
+    }
+
+    #[test]
+    fn test_collect() {
+        let flags: ClassAccessFlags = [ClassAccess::Public, ClassAccess::Super]
+            .into_iter()
+            .collect();
+        assert_eq!(0x0021, flags.bits());
+    }
+}
@@ -0,0 +1,1146 @@
+//! Computing `max_stack`, `max_locals` and the StackMapTable of a method's code, by following
+//! the types of the locals and the operand stack through every path in the code.
+//! <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.10.1>
+
+use crate::op::{Op, Wide};
+use crate::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+
+type Result<T> = std::result::Result<T, AnalysisError>;
+
+const OBJECT: &str = "java/lang/Object";
+const THROWABLE: &str = "java/lang/Throwable";
+
+/// What [`analyze_code`] needs to know about classes to merge reference types where paths
+/// meet.
+pub trait ClassHierarchy {
+    /// The superclass of `class`, or `None` for `java/lang/Object`. Classes that aren't known
+    /// are treated as direct subclasses of `java/lang/Object`.
+    fn super_class(&self, class: &Symbol) -> Option<Symbol>;
+
+    fn is_interface(&self, class: &Symbol) -> bool;
+
+    /// The most specific class that both `a` and `b` are subclasses of. The verifier treats
+    /// interfaces like `java/lang/Object`, so that's the result when either is an interface.
+    fn common_super_class(&self, a: &Symbol, b: &Symbol) -> Symbol {
+        if self.is_interface(a) || self.is_interface(b) {
+            return OBJECT.into();
+        }
+
+        let mut ancestors = HashSet::new();
+        let mut class = Some(a.clone());
+        while let Some(c) = class {
+            class = self.super_class(&c);
+            if !ancestors.insert(c) {
+                break;
+            }
+        }
+
+        let mut visited = HashSet::new();
+        let mut class = Some(b.clone());
+        while let Some(c) = class {
+            if ancestors.contains(&c) {
+                return c;
+            }
+            class = self.super_class(&c);
+            if !visited.insert(c) {
+                break;
+            }
+        }
+        OBJECT.into()
+    }
+}
+
+/// Looks classes up among a set of class files, e.g. the classes of an application that are
+/// being generated or rewritten together.
+impl ClassHierarchy for Vec<ClassFile> {
+    fn super_class(&self, class: &Symbol) -> Option<Symbol> {
+        self.iter()
+            .find(|c| c.this_class() == class)?
+            .super_class()
+            .cloned()
+    }
+
+    fn is_interface(&self, class: &Symbol) -> bool {
+        self.iter()
+            .any(|c| c.this_class() == class && c.access_flags().has(ClassAccess::Interface))
+    }
+}
+
+/// The results of [`analyze_code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeAnalysis {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    /// The frames of a StackMapTable for the code, each in its shortest form. There's one at
+    /// every branch target, exception handler and instruction after an unconditional branch.
+    pub frames: Vec<StackMapFrame>,
+    /// The pcs of the runs of instructions that can't be reached. The frames only describe the
+    /// code once each run is replaced with `nop`s and a final `athrow` and the exception handlers
+    /// no longer cover it, as [`ClassFile::compute_frames`] does.
+    pub unreachable: Vec<Range<u16>>,
+}
+
+/// Computes `max_stack`, `max_locals` and StackMapTable frames for `code`, ignoring the values
+/// it already has.
+///
+/// `initial_locals` are the locals on entry, as given by
+/// [`MethodInfo::initial_locals`](crate::MethodInfo::initial_locals), and `cp` has the catch
+/// types of the exception handlers. Where paths with different reference types meet, the types
+/// are merged with `hierarchy`.
+///
+/// The code must not have subroutines (`jsr` and `ret`), which class files from version 51 on
+/// can't have anyway. Unreachable code isn't followed; see [`CodeAnalysis::unreachable`].
+pub fn analyze_code(
+    code: &CodeAttribute,
+    initial_locals: &[VerificationTypeInfo],
+    this_class: &Symbol,
+    cp: &ConstantPool,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<CodeAnalysis> {
+    let mut pcs = Vec::with_capacity(code.code.len() + 1);
+    let mut pc = 0;
+    for op in &code.code {
+        pcs.push(pc);
+        pc += op.encoded_len(pc);
+    }
+    pcs.push(pc);
+    let indices = pcs.iter().enumerate().map(|(i, &pc)| (pc, i)).collect();
+
+    let mut analyzer = Analyzer {
+        ops: &code.code,
+        pcs,
+        indices,
+        handlers: Vec::with_capacity(code.exception_table.len()),
+        this_class,
+        hierarchy,
+        states: Vec::new(),
+        worklist: Vec::new(),
+        max_stack: 0,
+    };
+    for handler in &code.exception_table {
+        let catch_type = match handler.catch_type {
+            0 => THROWABLE.into(),
+            index => cp
+                .get_class(index)
+                .map_err(|_| AnalysisError {
+                    pc: handler.handler_pc.into(),
+                    kind: AnalysisErrorKind::BadCatchType(index),
+                })?
+                .clone(),
+        };
+        analyzer.handlers.push(Handler {
+            start: handler.start_pc.into(),
+            end: handler.end_pc.into(),
+            index: analyzer.index(handler.handler_pc.into(), handler.handler_pc.into())?,
+            catch_type: VerificationTypeInfo::Object(catch_type),
+        });
+    }
+
+    let mut locals = VerificationTypeInfo::to_slots(initial_locals);
+    let max_locals = code
+        .code
+        .iter()
+        .filter_map(local_slots)
+        .map(|(index, width)| usize::from(index) + width)
+        .fold(locals.len(), usize::max);
+    locals.resize(max_locals, VerificationTypeInfo::Top);
+
+    let (frame_targets, unreachable) = analyzer.run(Frame {
+        locals,
+        stack: Vec::new(),
+    })?;
+
+    Ok(CodeAnalysis {
+        max_stack: analyzer.max_stack as u16,
+        max_locals: max_locals as u16,
+        frames: analyzer.encode_frames(initial_locals, &frame_targets),
+        unreachable,
+    })
+}
+
+/// Replaces each of the `unreachable` runs of instructions in `code` with `nop`s and a final
+/// `athrow`, and removes them from the exception handlers, like ASM does. The pcs of the other
+/// instructions don't change.
+pub(crate) fn replace_unreachable(code: &mut CodeAttribute, unreachable: &[Range<u16>]) {
+    let mut ops = Vec::with_capacity(code.code.len());
+    let mut pc = 0;
+    for op in std::mem::take(&mut code.code) {
+        let len = op.encoded_len(pc);
+        match unreachable.iter().find(|run| run.contains(&(pc as u16))) {
+            Some(run) => ops.extend((pc..pc + len).map(|pc| {
+                if pc + 1 == usize::from(run.end) {
+                    Op::Athrow
+                } else {
+                    Op::Nop
+                }
+            })),
+            None => ops.push(op),
+        }
+        pc += len;
+    }
+    code.code = ops;
+
+    for run in unreachable {
+        code.exception_table = std::mem::take(&mut code.exception_table)
+            .into_iter()
+            .flat_map(|handler| {
+                [
+                    (handler.start_pc, handler.end_pc.min(run.start)),
+                    (handler.start_pc.max(run.end), handler.end_pc),
+                ]
+                .map(|(start_pc, end_pc)| ExceptionHandlerDescriptor {
+                    start_pc,
+                    end_pc,
+                    handler_pc: handler.handler_pc,
+                    catch_type: handler.catch_type,
+                })
+            })
+            .filter(|handler| handler.start_pc < handler.end_pc)
+            .collect();
+    }
+}
+
+#[derive(Debug)]
+struct Handler {
+    start: usize,
+    end: usize,
+    /// The index of the handler's first instruction.
+    index: usize,
+    catch_type: VerificationTypeInfo,
+}
+
+/// Where execution can go after an instruction, besides its exception handlers.
+#[derive(Debug)]
+struct Successors {
+    falls_through: bool,
+    /// The pcs of branch targets.
+    targets: Vec<usize>,
+}
+
+impl Successors {
+    fn next() -> Self {
+        Self {
+            falls_through: true,
+            targets: Vec::new(),
+        }
+    }
+
+    fn none() -> Self {
+        Self {
+            falls_through: false,
+            targets: Vec::new(),
+        }
+    }
+}
+
+struct Analyzer<'a> {
+    ops: &'a [Op],
+    /// The pc of each instruction, and then the length of the code.
+    pcs: Vec<usize>,
+    /// The index of the instruction at each pc.
+    indices: HashMap<usize, usize>,
+    handlers: Vec<Handler>,
+    this_class: &'a Symbol,
+    hierarchy: &'a dyn ClassHierarchy,
+    /// The frame on entry to each instruction, merged from every path to it so far.
+    states: Vec<Option<Frame>>,
+    /// The instructions whose frames have changed since they were last followed.
+    worklist: Vec<usize>,
+    max_stack: usize,
+}
+
+impl Analyzer<'_> {
+    fn error(&self, index: usize, kind: AnalysisErrorKind) -> AnalysisError {
+        AnalysisError {
+            pc: self.pcs[index],
+            kind,
+        }
+    }
+
+    /// The index of the instruction at `pc`, for a branch from the instruction at `from`.
+    fn index(&self, pc: usize, from: usize) -> Result<usize> {
+        match self.indices.get(&pc) {
+            Some(&index) if index < self.ops.len() => Ok(index),
+            _ => Err(AnalysisError {
+                pc: from,
+                kind: AnalysisErrorKind::BadBranchTarget(pc),
+            }),
+        }
+    }
+
+    /// Follows every path from the first instruction. Returns the instructions that need a
+    /// frame in the StackMapTable, and the pcs of the runs of unreachable instructions.
+    fn run(&mut self, initial: Frame) -> Result<(BTreeSet<usize>, Vec<Range<u16>>)> {
+        if self.ops.is_empty() {
+            return Err(AnalysisError {
+                pc: 0,
+                kind: AnalysisErrorKind::FallsOffEnd,
+            });
+        }
+
+        let mut frame_targets: BTreeSet<_> = self.handlers.iter().map(|h| h.index).collect();
+        self.states = vec![None; self.ops.len()];
+        self.merge_into(0, initial)?;
+
+        while let Some(index) = self.worklist.pop() {
+            let before = self.states[index].clone().unwrap();
+            let mut after = before.clone();
+            let successors = self.execute(index, &mut after)?;
+            self.max_stack = self.max_stack.max(after.stack.len());
+
+            let pc = self.pcs[index];
+            for i in 0..self.handlers.len() {
+                let handler = &self.handlers[i];
+                if !(handler.start..handler.end).contains(&pc) {
+                    continue;
+                }
+                let (handler_index, stack) = (handler.index, vec![handler.catch_type.clone()]);
+                self.max_stack = self.max_stack.max(1);
+                // Depending on the instruction, the verifier checks the handler against the
+                // locals from before or after it, so the handler's frame has to allow both.
+                for locals in [&before.locals, &after.locals] {
+                    let frame = Frame {
+                        locals: locals.clone(),
+                        stack: stack.clone(),
+                    };
+                    self.merge_into(handler_index, frame)?;
+                }
+            }
+
+            for &target in &successors.targets {
+                let target = self.index(target, pc)?;
+                frame_targets.insert(target);
+                self.merge_into(target, after.clone())?;
+            }
+            if successors.falls_through {
+                if index + 1 == self.ops.len() {
+                    return Err(self.error(index, AnalysisErrorKind::FallsOffEnd));
+                }
+                self.merge_into(index + 1, after)?;
+            } else if index + 1 < self.ops.len() {
+                frame_targets.insert(index + 1);
+            }
+        }
+
+        // Each unreachable run becomes `nop`s and an `athrow`, which only needs a Throwable on
+        // the stack.
+        let locals = self.states[0].as_ref().unwrap().locals.len();
+        let mut unreachable = Vec::new();
+        let mut index = 0;
+        while index < self.ops.len() {
+            if self.states[index].is_some() {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < self.ops.len() && self.states[index].is_none() {
+                frame_targets.remove(&index);
+                index += 1;
+            }
+            frame_targets.insert(start);
+            self.states[start] = Some(Frame {
+                locals: vec![VerificationTypeInfo::Top; locals],
+                stack: vec![VerificationTypeInfo::Object(THROWABLE.into())],
+            });
+            self.max_stack = self.max_stack.max(1);
+            unreachable.push(self.pcs[start] as u16..self.pcs[index] as u16);
+        }
+        Ok((frame_targets, unreachable))
+    }
+
+    /// Merges `frame` into the frame on entry to the instruction at `index`, and queues the
+    /// instruction if that changed it.
+    fn merge_into(&mut self, index: usize, frame: Frame) -> Result<()> {
+        let merged = match &self.states[index] {
+            None => frame,
+            Some(existing) => {
+                if existing.stack.len() != frame.stack.len() {
+                    return Err(self.error(index, AnalysisErrorKind::StackHeightMismatch));
+                }
+                let merge = |a: &[VerificationTypeInfo], b: &[VerificationTypeInfo]| {
+                    a.iter()
+                        .zip(b)
+                        .map(|(a, b)| self.merge_types(a, b))
+                        .collect::<std::result::Result<_, _>>()
+                        .map_err(|kind| self.error(index, kind))
+                };
+                let merged = Frame {
+                    locals: merge(&existing.locals, &frame.locals)?,
+                    stack: merge(&existing.stack, &frame.stack)?,
+                };
+                if merged == *existing {
+                    return Ok(());
+                }
+                merged
+            }
+        };
+
+        self.states[index] = Some(merged);
+        if !self.worklist.contains(&index) {
+            self.worklist.push(index);
+        }
+        Ok(())
+    }
+
+    /// The most specific type that both `a` and `b` can be used as.
+    fn merge_types(
+        &self,
+        a: &VerificationTypeInfo,
+        b: &VerificationTypeInfo,
+    ) -> std::result::Result<VerificationTypeInfo, AnalysisErrorKind> {
+        use VerificationTypeInfo::*;
+
+        Ok(match (a, b) {
+            _ if a == b => a.clone(),
+            (Null, Object(_)) => b.clone(),
+            (Object(_), Null) => a.clone(),
+            (Object(a), Object(b)) => Object(self.merge_classes(a, b)?),
+            _ => Top,
+        })
+    }
+
+    fn merge_classes(
+        &self,
+        a: &Symbol,
+        b: &Symbol,
+    ) -> std::result::Result<Symbol, AnalysisErrorKind> {
+        Ok(match (component_class(a)?, component_class(b)?) {
+            // Arrays of references are covariant, arrays of primitives aren't.
+            (Some(Some(a)), Some(Some(b))) => array_of(&self.merge_classes(&a, &b)?),
+            (None, None) => self.hierarchy.common_super_class(a, b),
+            _ => OBJECT.into(),
+        })
+    }
+
+    /// Applies the instruction at `index` to `frame`.
+    fn execute(&self, index: usize, frame: &mut Frame) -> Result<Successors> {
+        use Op::*;
+        use VerificationTypeInfo as T;
+
+        let pc = self.pcs[index];
+        let mut stack = Stack { pc, frame };
+        let branch = |offset: i32| Successors {
+            falls_through: true,
+            targets: vec![(pc as i64 + i64::from(offset)) as usize],
+        };
+        let jump = |offset: i32| Successors {
+            falls_through: false,
+            ..branch(offset)
+        };
+
+        match &self.ops[index] {
+            Nop | Iinc { .. } | Wide(self::Wide::Iinc { .. }) => {}
+            AconstNull => stack.push(T::Null),
+            Iconstm1 | Iconst0 | Iconst1 | Iconst2 | Iconst3 | Iconst4 | Iconst5 | Bipush(_)
+            | Sipush(_) => stack.push(T::Integer),
+            Lconst0 | Lconst1 => stack.push(T::Long),
+            Fconst0 | Fconst1 | Fconst2 => stack.push(T::Float),
+            Dconst0 | Dconst1 => stack.push(T::Double),
+            Ldc(constant) | LdcW(constant) | Ldc2W(constant) => stack.push(loadable_type(constant)),
+            Iload(_) | Iload0 | Iload1 | Iload2 | Iload3 | Wide(self::Wide::Iload(_)) => {
+                stack.push(T::Integer)
+            }
+            Lload(_) | Lload0 | Lload1 | Lload2 | Lload3 | Wide(self::Wide::Lload(_)) => {
+                stack.push(T::Long)
+            }
+            Fload(_) | Fload0 | Fload1 | Fload2 | Fload3 | Wide(self::Wide::Fload(_)) => {
+                stack.push(T::Float)
+            }
+            Dload(_) | Dload0 | Dload1 | Dload2 | Dload3 | Wide(self::Wide::Dload(_)) => {
+                stack.push(T::Double)
+            }
+            op @ (Aload(_) | Aload0 | Aload1 | Aload2 | Aload3 | Wide(self::Wide::Aload(_))) => {
+                let (local, _) = local_slots(op).unwrap();
+                let value = stack.frame.locals[usize::from(local)].clone();
+                stack.push(value);
+            }
+            Iaload | Baload | Caload | Saload => stack.apply(2, Some(T::Integer))?,
+            Laload => stack.apply(2, Some(T::Long))?,
+            Faload => stack.apply(2, Some(T::Float))?,
+            Daload => stack.apply(2, Some(T::Double))?,
+            Aaload => {
+                stack.pop(1)?;
+                let component = match stack.pop_value()? {
+                    T::Object(array) => match component_class(&array) {
+                        Ok(Some(Some(class))) => T::Object(class),
+                        Ok(_) => T::Object(OBJECT.into()),
+                        Err(kind) => return Err(self.error(index, kind)),
+                    },
+                    _ => T::Null,
+                };
+                stack.push(component);
+            }
+            op @ (Istore(_)
+            | Istore0
+            | Istore1
+            | Istore2
+            | Istore3
+            | Lstore(_)
+            | Lstore0
+            | Lstore1
+            | Lstore2
+            | Lstore3
+            | Fstore(_)
+            | Fstore0
+            | Fstore1
+            | Fstore2
+            | Fstore3
+            | Dstore(_)
+            | Dstore0
+            | Dstore1
+            | Dstore2
+            | Dstore3
+            | Astore(_)
+            | Astore0
+            | Astore1
+            | Astore2
+            | Astore3
+            | Wide(
+                self::Wide::Istore(_)
+                | self::Wide::Lstore(_)
+                | self::Wide::Fstore(_)
+                | self::Wide::Dstore(_)
+                | self::Wide::Astore(_),
+            )) => {
+                let (local, _) = local_slots(op).unwrap();
+                let value = stack.pop_value()?;
+                stack.store(local.into(), value);
+            }
+            Iastore | Fastore | Aastore | Bastore | Castore | Sastore => stack.pop(3)?,
+            Lastore | Dastore => stack.pop(4)?,
+            Pop => stack.pop(1)?,
+            Pop2 => stack.pop(2)?,
+            Dup => stack.dup(1, 0)?,
+            DupX1 => stack.dup(1, 1)?,
+            DupX2 => stack.dup(1, 2)?,
+            Dup2 => stack.dup(2, 0)?,
+            Dup2X1 => stack.dup(2, 1)?,
+            Dup2X2 => stack.dup(2, 2)?,
+            Swap => {
+                let len = stack.frame.stack.len();
+                if len < 2 {
+                    return Err(stack.underflow());
+                }
+                stack.frame.stack.swap(len - 1, len - 2);
+            }
+            Iadd | Isub | Imul | Idiv | Irem | Ishl | Ishr | Iushr | Iand | Ior | Ixor => {
+                stack.apply(2, Some(T::Integer))?
+            }
+            Ladd | Lsub | Lmul | Ldiv | Lrem | Land | Lor | Lxor => {
+                stack.apply(4, Some(T::Long))?
+            }
+            Lshl | Lshr | Lushr => stack.apply(3, Some(T::Long))?,
+            Fadd | Fsub | Fmul | Fdiv | Frem => stack.apply(2, Some(T::Float))?,
+            Dadd | Dsub | Dmul | Ddiv | Drem => stack.apply(4, Some(T::Double))?,
+            Ineg | F2i | I2b | I2c | I2s => stack.apply(1, Some(T::Integer))?,
+            Lneg | D2l => stack.apply(2, Some(T::Long))?,
+            Fneg | I2f => stack.apply(1, Some(T::Float))?,
+            Dneg | L2d => stack.apply(2, Some(T::Double))?,
+            I2l | F2l => stack.apply(1, Some(T::Long))?,
+            I2d | F2d => stack.apply(1, Some(T::Double))?,
+            L2i | D2i => stack.apply(2, Some(T::Integer))?,
+            L2f | D2f => stack.apply(2, Some(T::Float))?,
+            Lcmp | Dcmpl | Dcmpg => stack.apply(4, Some(T::Integer))?,
+            Fcmpl | Fcmpg => stack.apply(2, Some(T::Integer))?,
+            Ifeq(offset) | Ifne(offset) | Iflt(offset) | Ifge(offset) | Ifgt(offset)
+            | Ifle(offset) | Ifnull(offset) | Ifnonnull(offset) => {
+                stack.pop(1)?;
+                return Ok(branch((*offset).into()));
+            }
+            IfIcmpeq(offset) | IfIcmpne(offset) | IfIcmplt(offset) | IfIcmpge(offset)
+            | IfIcmpgt(offset) | IfIcmple(offset) | IfAcmpeq(offset) | IfAcmpne(offset) => {
+                stack.pop(2)?;
+                return Ok(branch((*offset).into()));
+            }
+            Goto(offset) => return Ok(jump((*offset).into())),
+            GotoW(offset) => return Ok(jump(*offset)),
+            Jsr(_) | JsrW(_) | Ret(_) | Wide(self::Wide::Ret(_)) => {
+                return Err(self.error(index, AnalysisErrorKind::Subroutine))
+            }
+            Tableswitch(switch) => {
+                stack.pop(1)?;
+                let offsets = switch.offsets.iter().chain([&switch.default]);
+                return Ok(Successors {
+                    falls_through: false,
+                    targets: offsets.flat_map(|&offset| jump(offset).targets).collect(),
+                });
+            }
+            Lookupswitch(switch) => {
+                stack.pop(1)?;
+                let offsets = switch.pairs.iter().map(|(_, offset)| offset);
+                return Ok(Successors {
+                    falls_through: false,
+                    targets: offsets
+                        .chain([&switch.default])
+                        .flat_map(|&offset| jump(offset).targets)
+                        .collect(),
+                });
+            }
+            Ireturn | Lreturn | Freturn | Dreturn | Areturn | Return | Athrow => {
+                return Ok(Successors::none())
+            }
+            Getstatic(field) => stack.push(T::from_descriptor(&field.descriptor)),
+            Putstatic(field) => stack.pop(slots(&field.descriptor))?,
+            Getfield(field) => stack.apply(1, Some(T::from_descriptor(&field.descriptor)))?,
+            Putfield(field) => stack.pop(slots(&field.descriptor) + 1)?,
+            op @ (Invokevirtual(method)
+            | Invokespecial(method)
+            | Invokestatic(method)
+            | Invokeinterface { method, .. }) => {
+                let descriptor = &method.descriptor;
+                stack.pop(descriptor.parameters.iter().map(slots).sum())?;
+                if !matches!(op, Invokestatic(_)) {
+                    let receiver = stack.pop_value()?;
+                    if method.name == "<init>" {
+                        let class = match receiver {
+                            T::UninitializedThis => self.this_class.clone(),
+                            _ => method.class.clone(),
+                        };
+                        stack.initialize(&receiver, T::Object(class));
+                    }
+                }
+                stack.push_return(&descriptor.return_descriptor);
+            }
+            Invokedynamic(call_site) => {
+                let descriptor = &call_site.descriptor;
+                stack.pop(descriptor.parameters.iter().map(slots).sum())?;
+                stack.push_return(&descriptor.return_descriptor);
+            }
+            New(_) => stack.push(T::Uninitialized(pc as u16)),
+            Newarray(atype) => {
+                let array = match atype {
+                    op::ArrayType::Boolean => "[Z",
+                    op::ArrayType::Char => "[C",
+                    op::ArrayType::Float => "[F",
+                    op::ArrayType::Double => "[D",
+                    op::ArrayType::Byte => "[B",
+                    op::ArrayType::Short => "[S",
+                    op::ArrayType::Int => "[I",
+                    op::ArrayType::Long => "[J",
+                };
+                stack.apply(1, Some(T::Object(array.into())))?
+            }
+            Anewarray(class) => stack.apply(1, Some(T::Object(array_of(class))))?,
+            Arraylength | Instanceof(_) => stack.apply(1, Some(T::Integer))?,
+            Checkcast(class) => stack.apply(1, Some(T::Object(class.clone())))?,
+            Monitorenter | Monitorexit => stack.pop(1)?,
+            Multianewarray { class, dimensions } => {
+                stack.apply((*dimensions).into(), Some(T::Object(class.clone())))?
+            }
+        }
+
+        Ok(Successors::next())
+    }
+
+    /// Encodes the frames on entry to each instruction in `targets` as StackMapTable frames.
+    fn encode_frames(
+        &self,
+        initial_locals: &[VerificationTypeInfo],
+        targets: &BTreeSet<usize>,
+    ) -> Vec<StackMapFrame> {
+        let mut frames = Vec::with_capacity(targets.len());
+        let mut previous_locals = initial_locals.to_vec();
+        let mut previous_pc = None;
+
+        for &index in targets {
+            let state = self.states[index].as_ref().unwrap();
+            let mut locals = from_slots(&state.locals);
+            while locals.last() == Some(&VerificationTypeInfo::Top) {
+                locals.pop();
+            }
+            let mut stack = from_slots(&state.stack);
+
+            let pc = self.pcs[index];
+            let offset_delta = match previous_pc {
+                None => pc,
+                Some(previous_pc) => pc - previous_pc - 1,
+            } as u16;
+            previous_pc = Some(pc);
+
+            let frame = if locals == previous_locals && stack.len() <= 1 {
+                match stack.pop() {
+                    None => StackMapFrame::Same {
+                        offset_delta,
+                        extended: false,
+                    },
+                    Some(stack) => StackMapFrame::SameLocals1StackItem {
+                        offset_delta,
+                        stack,
+                        extended: false,
+                    },
+                }
+            } else if stack.is_empty()
+                && locals.len() < previous_locals.len()
+                && previous_locals.len() - locals.len() <= 3
+                && previous_locals.starts_with(&locals)
+            {
+                StackMapFrame::Chop {
+                    offset_delta,
+                    k: (previous_locals.len() - locals.len()) as u8,
+                }
+            } else if stack.is_empty()
+                && locals.len() > previous_locals.len()
+                && locals.len() - previous_locals.len() <= 3
+                && locals.starts_with(&previous_locals)
+            {
+                StackMapFrame::Append {
+                    offset_delta,
+                    locals: locals[previous_locals.len()..].to_vec(),
+                }
+            } else {
+                StackMapFrame::Full {
+                    offset_delta,
+                    locals: locals.clone(),
+                    stack,
+                }
+            };
+
+            frames.push(frame);
+            previous_locals = locals;
+        }
+
+        frames
+    }
+}
+
+/// The operand stack and locals of a frame as an instruction is applied to them.
+struct Stack<'a> {
+    pc: usize,
+    frame: &'a mut Frame,
+}
+
+impl Stack<'_> {
+    fn push(&mut self, value: VerificationTypeInfo) {
+        let wide = value.is_wide();
+        self.frame.stack.push(value);
+        if wide {
+            self.frame.stack.push(VerificationTypeInfo::Top);
+        }
+    }
+
+    fn push_return(&mut self, descriptor: &ReturnDescriptor) {
+        if let ReturnDescriptor::NonVoid(descriptor) = descriptor {
+            self.push(VerificationTypeInfo::from_descriptor(descriptor));
+        }
+    }
+
+    /// Pops `slots` stack slots.
+    fn pop(&mut self, slots: usize) -> Result<()> {
+        let len = self.frame.stack.len();
+        let Some(new_len) = len.checked_sub(slots) else {
+            return Err(self.underflow());
+        };
+        self.frame.stack.truncate(new_len);
+        Ok(())
+    }
+
+    /// Pops a single value, which takes up two slots if it's a long or double.
+    fn pop_value(&mut self) -> Result<VerificationTypeInfo> {
+        let value = self.frame.stack.pop().ok_or_else(|| self.underflow())?;
+        if value == VerificationTypeInfo::Top
+            && self
+                .frame
+                .stack
+                .last()
+                .is_some_and(VerificationTypeInfo::is_wide)
+        {
+            return Ok(self.frame.stack.pop().unwrap());
+        }
+        Ok(value)
+    }
+
+    /// Pops `slots` stack slots and pushes `result`.
+    fn apply(&mut self, slots: usize, result: Option<VerificationTypeInfo>) -> Result<()> {
+        self.pop(slots)?;
+        if let Some(result) = result {
+            self.push(result);
+        }
+        Ok(())
+    }
+
+    /// Copies the top `count` slots and inserts them `depth` slots further down, as the `dup`
+    /// instructions do.
+    fn dup(&mut self, count: usize, depth: usize) -> Result<()> {
+        let len = self.frame.stack.len();
+        if len < count + depth {
+            return Err(self.underflow());
+        }
+        let copied = self.frame.stack[len - count..].to_vec();
+        let at = len - count - depth;
+        self.frame.stack.splice(at..at, copied);
+        Ok(())
+    }
+
+    fn store(&mut self, local: usize, value: VerificationTypeInfo) {
+        let locals = &mut self.frame.locals;
+        // Overwriting either half of a long or double leaves the other half unusable.
+        if local > 0 && locals[local - 1].is_wide() {
+            locals[local - 1] = VerificationTypeInfo::Top;
+        }
+        if value.is_wide() {
+            locals[local + 1] = VerificationTypeInfo::Top;
+        }
+        locals[local] = value;
+    }
+
+    /// Replaces every copy of an uninitialized object with its initialized type, once its
+    /// constructor is called.
+    fn initialize(
+        &mut self,
+        uninitialized: &VerificationTypeInfo,
+        initialized: VerificationTypeInfo,
+    ) {
+        for value in self.frame.locals.iter_mut().chain(&mut self.frame.stack) {
+            if value == uninitialized {
+                *value = initialized.clone();
+            }
+        }
+    }
+
+    fn underflow(&self) -> AnalysisError {
+        AnalysisError {
+            pc: self.pc,
+            kind: AnalysisErrorKind::StackUnderflow,
+        }
+    }
+}
+
+/// The local variable that a load, store or `iinc` accesses, and the number of slots it takes
+/// up.
+fn local_slots(op: &Op) -> Option<(u16, usize)> {
+    use Op::*;
+
+    Some(match op {
+        Iload(index)
+        | Fload(index)
+        | Aload(index)
+        | Istore(index)
+        | Fstore(index)
+        | Astore(index)
+        | Ret(index)
+        | Iinc { index, .. } => ((*index).into(), 1),
+        Lload(index) | Dload(index) | Lstore(index) | Dstore(index) => ((*index).into(), 2),
+        Iload0 | Fload0 | Aload0 | Istore0 | Fstore0 | Astore0 => (0, 1),
+        Iload1 | Fload1 | Aload1 | Istore1 | Fstore1 | Astore1 => (1, 1),
+        Iload2 | Fload2 | Aload2 | Istore2 | Fstore2 | Astore2 => (2, 1),
+        Iload3 | Fload3 | Aload3 | Istore3 | Fstore3 | Astore3 => (3, 1),
+        Lload0 | Dload0 | Lstore0 | Dstore0 => (0, 2),
+        Lload1 | Dload1 | Lstore1 | Dstore1 => (1, 2),
+        Lload2 | Dload2 | Lstore2 | Dstore2 => (2, 2),
+        Lload3 | Dload3 | Lstore3 | Dstore3 => (3, 2),
+        Wide(wide) => match wide {
+            self::Wide::Iload(index)
+            | self::Wide::Fload(index)
+            | self::Wide::Aload(index)
+            | self::Wide::Istore(index)
+            | self::Wide::Fstore(index)
+            | self::Wide::Astore(index)
+            | self::Wide::Ret(index)
+            | self::Wide::Iinc { index, .. } => (*index, 1),
+            self::Wide::Lload(index)
+            | self::Wide::Dload(index)
+            | self::Wide::Lstore(index)
+            | self::Wide::Dstore(index) => (*index, 2),
+        },
+        _ => return None,
+    })
+}
+
+/// The number of stack or local variable slots that a value of type `descriptor` takes up.
+fn slots(descriptor: &FieldDescriptor) -> usize {
+    match descriptor {
+        FieldDescriptor::Basic(BasicFieldType::Long | BasicFieldType::Double) => 2,
+        _ => 1,
+    }
+}
+
+fn loadable_type(constant: &LoadableConstant) -> VerificationTypeInfo {
+    use VerificationTypeInfo as T;
+
+    match constant {
+        LoadableConstant::Integer(_) => T::Integer,
+        LoadableConstant::Float(_) => T::Float,
+        LoadableConstant::Long(_) => T::Long,
+        LoadableConstant::Double(_) => T::Double,
+        LoadableConstant::Class(_) => T::Object("java/lang/Class".into()),
+        LoadableConstant::String(_) => T::Object("java/lang/String".into()),
+        LoadableConstant::MethodHandle(_) => T::Object("java/lang/invoke/MethodHandle".into()),
+        LoadableConstant::MethodType(_) => T::Object("java/lang/invoke/MethodType".into()),
+        LoadableConstant::Dynamic(dynamic) => T::from_descriptor(&dynamic.descriptor),
+    }
+}
+
+/// The name of the array class whose components are `class`.
+/// The class of the components of `class` if it's an array class, or `Some(None)` if they're
+/// primitives.
+fn component_class(
+    class: &Symbol,
+) -> std::result::Result<Option<Option<Symbol>>, AnalysisErrorKind> {
+    let Some(component) = class.strip_prefix('[') else {
+        return Ok(None);
+    };
+    match component.as_bytes() {
+        [b'[', ..] => Ok(Some(Some(component.into()))),
+        [b'L', .., b';'] if component.len() > 2 => {
+            Ok(Some(Some(component[1..component.len() - 1].into())))
+        }
+        [b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z'] => Ok(Some(None)),
+        _ => Err(AnalysisErrorKind::BadArrayClass(class.to_string())),
+    }
+}
+
+fn array_of(class: &Symbol) -> Symbol {
+    if class.starts_with('[') {
+        format!("[{class}").into()
+    } else {
+        format!("[L{class};").into()
+    }
+}
+
+/// The inverse of [`VerificationTypeInfo::to_slots`].
+fn from_slots(slots: &[VerificationTypeInfo]) -> Vec<VerificationTypeInfo> {
+    let mut types = Vec::with_capacity(slots.len());
+    let mut i = 0;
+    while i < slots.len() {
+        types.push(slots[i].clone());
+        i += if slots[i].is_wide() { 2 } else { 1 };
+    }
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fixtures;
+
+    fn analyze(
+        class: &ClassFile,
+        method: &MethodInfo,
+        hierarchy: &dyn ClassHierarchy,
+    ) -> CodeAnalysis {
+        let code = method.code().unwrap().unwrap();
+        analyze_code(
+            code,
+            &method.initial_locals(class.this_class()),
+            class.this_class(),
+            class.constant_pool(),
+            hierarchy,
+        )
+        .unwrap_or_else(|e| panic!("{}.{}: {e}", class.this_class(), method.name))
+    }
+
+    /// The pc and operand stack of each frame. javac's frames can have different locals, since
+    /// it drops locals that go out of scope.
+    fn stacks(frames: &[StackMapFrame]) -> Vec<(usize, &[VerificationTypeInfo])> {
+        let mut pc = None;
+        frames
+            .iter()
+            .map(|frame| {
+                let delta = usize::from(frame.offset_delta());
+                pc = Some(pc.map_or(delta, |pc| pc + delta + 1));
+                let stack = match frame {
+                    StackMapFrame::SameLocals1StackItem { stack, .. } => {
+                        std::slice::from_ref(stack)
+                    }
+                    StackMapFrame::Full { stack, .. } => stack,
+                    _ => &[],
+                };
+                (pc.unwrap(), stack)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixtures_match_javac() {
+        let classes: Vec<_> = fixtures()
+            .into_iter()
+            .map(|(_, raw)| ClassFile::parse_from_bytes(&raw).unwrap())
+            .collect();
+        let mut count = 0;
+        for class in &classes {
+            for method in class.methods() {
+                let Some(code) = method.code().unwrap() else {
+                    continue;
+                };
+                let analysis = analyze(class, method, &classes);
+                let name = format!("{}.{}", class.this_class(), method.name);
+                assert_eq!(analysis.max_stack, code.max_stack, "{name}");
+                assert_eq!(analysis.max_locals, code.max_locals, "{name}");
+
+                let expected = code.stack_map_table().unwrap_or_default();
+                assert_eq!(stacks(&analysis.frames), stacks(expected), "{name}");
+                count += 1;
+            }
+        }
+        assert!(count >= 30);
+    }
+
+    #[test]
+    fn test_merge_classes() {
+        use crate::builder::*;
+        use crate::descriptor::parse_method_descriptor;
+
+        let hierarchy = vec![
+            ClassBuilder::new("A").build().unwrap(),
+            ClassBuilder::new("B").super_class("A").build().unwrap(),
+            ClassBuilder::new("C").super_class("A").build().unwrap(),
+            ClassBuilder::new("I")
+                .access_flags(&[
+                    ClassAccess::Public,
+                    ClassAccess::Interface,
+                    ClassAccess::Abstract,
+                ])
+                .build()
+                .unwrap(),
+        ];
+        assert_eq!(
+            Symbol::from("A"),
+            hierarchy.common_super_class(&"B".into(), &"C".into())
+        );
+        assert_eq!(
+            Symbol::from("A"),
+            hierarchy.common_super_class(&"B".into(), &"A".into())
+        );
+        assert_eq!(
+            Symbol::from(OBJECT),
+            hierarchy.common_super_class(&"B".into(), &"I".into())
+        );
+        assert_eq!(
+            Symbol::from(OBJECT),
+            hierarchy.common_super_class(&"B".into(), &"X".into())
+        );
+
+        // static A[] pick(boolean b) { return b ? new B[1] : new C[1]; }
+        let mut code = CodeBuilder::new();
+        let (other, end) = (code.new_label(), code.new_label());
+        code.op(Op::Iload0).branch(Op::Ifeq, other);
+        code.op(Op::Iconst1).op(Op::Anewarray("B".into()));
+        code.branch(Op::Goto, end);
+        code.place(other)
+            .op(Op::Iconst1)
+            .op(Op::Anewarray("C".into()));
+        code.place(end).op(Op::Areturn);
+        let method = MethodBuilder::new(
+            &[MethodAccess::Static],
+            "pick",
+            parse_method_descriptor("(Z)[LA;").unwrap(),
+        );
+        let class = ClassBuilder::new("Picker")
+            .method(method.code(code))
+            .build_with_frames(&hierarchy)
+            .unwrap();
+
+        let code = class.methods()[0].code().unwrap().unwrap();
+        assert_eq!((1, 1), (code.max_stack, code.max_locals));
+        assert_eq!(
+            Some(
+                &[
+                    StackMapFrame::Same {
+                        offset_delta: 11,
+                        extended: false,
+                    },
+                    StackMapFrame::SameLocals1StackItem {
+                        offset_delta: 3,
+                        stack: VerificationTypeInfo::Object("[LA;".into()),
+                        extended: false,
+                    },
+                ][..]
+            ),
+            code.stack_map_table()
+        );
+    }
+
+    #[test]
+    fn test_bad_array_class() {
+        use crate::builder::*;
+        use crate::descriptor::parse_method_descriptor;
+
+        let build = |code: CodeBuilder| {
+            let method = MethodBuilder::new(
+                &[MethodAccess::Static],
+                "run",
+                parse_method_descriptor("(Z)Ljava/lang/Object;").unwrap(),
+            );
+            match ClassBuilder::new("Runner")
+                .method(method.code(code))
+                .build_with_frames(&Vec::new())
+            {
+                Err(ClassWriteError::Analysis(error)) => error.kind,
+                other => panic!("{other:?}"),
+            }
+        };
+
+        let mut code = CodeBuilder::new();
+        code.op(Op::AconstNull)
+            .op(Op::Checkcast("[L".into()))
+            .op(Op::Iconst0)
+            .op(Op::Aaload)
+            .op(Op::Areturn);
+        assert_eq!(AnalysisErrorKind::BadArrayClass("[L".into()), build(code));
+
+        let mut code = CodeBuilder::new();
+        let (other, end) = (code.new_label(), code.new_label());
+        code.op(Op::Iload0).branch(Op::Ifeq, other);
+        code.op(Op::AconstNull).op(Op::Checkcast("[LA;".into()));
+        code.branch(Op::Goto, end);
+        code.place(other)
+            .op(Op::AconstNull)
+            .op(Op::Checkcast("[".into()));
+        code.place(end).op(Op::Areturn);
+        assert_eq!(AnalysisErrorKind::BadArrayClass("[".into()), build(code));
+    }
+
+    #[test]
+    fn test_unreachable() {
+        use crate::builder::*;
+        use crate::descriptor::parse_method_descriptor;
+
+        let mut code = CodeBuilder::new();
+        let (start, handler) = (code.new_label(), code.new_label());
+        code.place(start).op(Op::Return);
+        code.op(Op::Iconst0).op(Op::Pop).branch(Op::Goto, start);
+        code.place(handler).op(Op::Athrow);
+        code.exception_handler(start, handler, handler, None);
+        let method = MethodBuilder::new(
+            &[MethodAccess::Static],
+            "run",
+            parse_method_descriptor("()V").unwrap(),
+        );
+        let class = ClassBuilder::new("Runner")
+            .method(method.code(code))
+            .build_with_frames(&Vec::new())
+            .unwrap();
+
+        let code = class.methods()[0].code().unwrap().unwrap();
+        assert_eq!(
+            "[Return, Nop, Nop, Nop, Nop, Athrow, Athrow]",
+            format!("{:?}", code.code)
+        );
+        let handlers: Vec<_> = code
+            .exception_table
+            .iter()
+            .map(|h| (h.start_pc, h.end_pc, h.handler_pc))
+            .collect();
+        assert_eq!(vec![(0, 1, 6)], handlers);
+        let throwable = VerificationTypeInfo::Object(THROWABLE.into());
+        assert_eq!(
+            Some(
+                &[
+                    StackMapFrame::SameLocals1StackItem {
+                        offset_delta: 1,
+                        stack: throwable.clone(),
+                        extended: false,
+                    },
+                    StackMapFrame::SameLocals1StackItem {
+                        offset_delta: 4,
+                        stack: throwable,
+                        extended: false,
+                    },
+                ][..]
+            ),
+            code.stack_map_table()
+        );
+    }
+}
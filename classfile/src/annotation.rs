@@ -0,0 +1,207 @@
+use crate::{FieldDescriptor, JavaString, ReturnDescriptor, Symbol};
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// The annotation interface, e.g. `Ljava/lang/Deprecated;`.
+    pub type_descriptor: FieldDescriptor,
+    pub elements: Vec<ElementValuePair>,
+}
+
+impl Annotation {
+    /// The value of the element called `name`, if it was given explicitly. Defaults aren't
+    /// included; they're in the AnnotationDefault attributes of the annotation interface.
+    pub fn element(&self, name: &str) -> Option<&ElementValue> {
+        self.elements
+            .iter()
+            .find(|pair| pair.name == name)
+            .map(|pair| &pair.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementValuePair {
+    pub name: Symbol,
+    pub value: ElementValue,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16.1>
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue {
+    Byte(i8),
+    Char(u16),
+    Double(f64),
+    Float(f32),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Boolean(bool),
+    /// A byte, char, short or boolean (going by `tag`) whose Integer constant is out of range
+    /// for its type. The JVM truncates it; it's kept as it is so that it's written back the same.
+    OutOfRange {
+        tag: u8,
+        value: i32,
+    },
+    String(JavaString),
+    Enum {
+        type_descriptor: FieldDescriptor,
+        const_name: Symbol,
+    },
+    /// A class literal. `void.class` is `ReturnDescriptor::Void`.
+    Class(ReturnDescriptor),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+/// An annotation on a use of a type.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20>
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation {
+    pub target_type: TargetType,
+    pub target_info: TargetInfo,
+    /// Which part of the type in `target_info` is annotated, outermost step first. Empty if
+    /// it's the whole type.
+    pub target_path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
+
+/// The kind of type that a [`TypeAnnotation`] is on.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20-400>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TargetType {
+    ClassTypeParameter = 0x00,
+    MethodTypeParameter = 0x01,
+    ClassExtends = 0x10,
+    ClassTypeParameterBound = 0x11,
+    MethodTypeParameterBound = 0x12,
+    Field = 0x13,
+    /// The return type of a method, or the type of a newly constructed object.
+    MethodReturn = 0x14,
+    MethodReceiver = 0x15,
+    MethodFormalParameter = 0x16,
+    Throws = 0x17,
+    LocalVariable = 0x40,
+    ResourceVariable = 0x41,
+    ExceptionParameter = 0x42,
+    Instanceof = 0x43,
+    New = 0x44,
+    ConstructorReference = 0x45,
+    MethodReference = 0x46,
+    Cast = 0x47,
+    ConstructorInvocationTypeArgument = 0x48,
+    MethodInvocationTypeArgument = 0x49,
+    ConstructorReferenceTypeArgument = 0x4A,
+    MethodReferenceTypeArgument = 0x4B,
+}
+
+impl TargetType {
+    pub(crate) fn from_u8(target_type: u8) -> Option<Self> {
+        use TargetType::*;
+
+        Some(match target_type {
+            0x00 => ClassTypeParameter,
+            0x01 => MethodTypeParameter,
+            0x10 => ClassExtends,
+            0x11 => ClassTypeParameterBound,
+            0x12 => MethodTypeParameterBound,
+            0x13 => Field,
+            0x14 => MethodReturn,
+            0x15 => MethodReceiver,
+            0x16 => MethodFormalParameter,
+            0x17 => Throws,
+            0x40 => LocalVariable,
+            0x41 => ResourceVariable,
+            0x42 => ExceptionParameter,
+            0x43 => Instanceof,
+            0x44 => New,
+            0x45 => ConstructorReference,
+            0x46 => MethodReference,
+            0x47 => Cast,
+            0x48 => ConstructorInvocationTypeArgument,
+            0x49 => MethodInvocationTypeArgument,
+            0x4A => ConstructorReferenceTypeArgument,
+            0x4B => MethodReferenceTypeArgument,
+            _ => return None,
+        })
+    }
+}
+
+/// Which type in a declaration or expression a [`TypeAnnotation`] is on. The variant is
+/// determined by the annotation's [`TargetType`].
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20.1>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetInfo {
+    TypeParameter {
+        type_parameter_index: u8,
+    },
+    Supertype {
+        /// An index into the class's interfaces, or 65535 for the superclass.
+        supertype_index: u16,
+    },
+    TypeParameterBound {
+        type_parameter_index: u8,
+        bound_index: u8,
+    },
+    /// For field types, return types and receiver types, which need no more information.
+    Empty,
+    FormalParameter {
+        formal_parameter_index: u8,
+    },
+    Throws {
+        /// An index into the method's Exceptions attribute.
+        throws_type_index: u16,
+    },
+    Localvar(Vec<LocalvarTargetEntry>),
+    Catch {
+        exception_table_index: u16,
+    },
+    /// The pc of the instruction that the annotated expression compiled to.
+    Offset(u16),
+    TypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+}
+
+/// A range of code in which a local variable has the annotated type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalvarTargetEntry {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+}
+
+/// One step into a type, towards the part that a [`TypeAnnotation`] is on.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20.2>
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TypePathEntry {
+    pub type_path_kind: TypePathKind,
+    /// Which type argument is stepped into, for `TypePathKind::TypeArgument`. Zero otherwise.
+    pub type_argument_index: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TypePathKind {
+    /// Deeper in an array type.
+    ArrayElement = 0,
+    /// Deeper in a nested type.
+    Nested = 1,
+    /// On the bound of a wildcard type argument.
+    WildcardBound = 2,
+    /// On a type argument of a parameterized type.
+    TypeArgument = 3,
+}
+
+impl TypePathKind {
+    pub(crate) fn from_u8(kind: u8) -> Option<Self> {
+        Some(match kind {
+            0 => Self::ArrayElement,
+            1 => Self::Nested,
+            2 => Self::WildcardBound,
+            3 => Self::TypeArgument,
+            _ => return None,
+        })
+    }
+}
@@ -1,11 +1,136 @@
+use crate::{
+    Annotation, BasicFieldType, ElementValue, ExportsAccessFlags, FieldDescriptor, FieldSignature,
+    InnerClassAccessFlags, JavaString, LoadableConstant, MethodHandle, ModuleAccessFlags,
+    NameAndType, ParameterAccessFlags, RequiresAccessFlags, Signature, SourceMap, Symbol,
+    TypeAnnotation,
+};
+
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7>
 #[derive(Debug)]
-pub enum Attribute<'cp> {
-    ConstantValue(ConstantValueAttribute<'cp>),
-    Code(CodeAttribute<'cp>),
+pub enum Attribute {
+    ConstantValue(ConstantValueAttribute),
+    Code(CodeAttribute),
     LineNumberTable(Vec<LineNumberTableEntry>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.13>
+    LocalVariableTable(Vec<LocalVariableTableEntry>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.14>
+    LocalVariableTypeTable(Vec<LocalVariableTypeTableEntry>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.4>
+    StackMapTable(Vec<StackMapFrame>),
+    /// The classes of the checked exceptions that a method declares it throws.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.5>
+    Exceptions(Vec<Symbol>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.6>
+    InnerClasses(Vec<InnerClass>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.7>
+    EnclosingMethod(EnclosingMethod),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9>
+    Signature(Signature),
     /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.10>
-    SourceFile(&'cp str),
+    SourceFile(Symbol),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.11>
+    SourceDebugExtension(SourceDebugExtension),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16>
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.17>
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+    /// The annotations on each formal parameter, in order.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.18>
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.19>
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20>
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.21>
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
+    /// The default value of an element of an annotation interface.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.22>
+    AnnotationDefault(ElementValue),
+    /// The bootstrap methods referred to by Dynamic and InvokeDynamic constants, in order.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.23>
+    BootstrapMethods(Vec<BootstrapMethod>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.24>
+    MethodParameters(Vec<MethodParameter>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    Module(ModuleAttribute),
+    /// Every package in the module, including ones that aren't exported or opened.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.26>
+    ModulePackages(Vec<Symbol>),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.27>
+    ModuleMainClass(Symbol),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.28>
+    NestHost(Symbol),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.29>
+    NestMembers(Vec<Symbol>),
+    /// The components of a record class, in order.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.30>
+    Record(Vec<RecordComponent>),
+    /// The classes and interfaces allowed to directly extend a sealed class or interface.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.31>
+    PermittedSubclasses(Vec<Symbol>),
+    /// An attribute that isn't parsed by this crate, with its contents kept as-is.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.1>
+    Unknown {
+        name: Symbol,
+        /// Everything after the `attribute_length` field.
+        bytes: Vec<u8>,
+    },
+}
+
+impl Attribute {
+    /// The attribute's name, as it appears in the class file.
+    pub fn name(&self) -> &str {
+        match self {
+            Attribute::ConstantValue(_) => "ConstantValue",
+            Attribute::Code(_) => "Code",
+            Attribute::LineNumberTable(_) => "LineNumberTable",
+            Attribute::LocalVariableTable(_) => "LocalVariableTable",
+            Attribute::LocalVariableTypeTable(_) => "LocalVariableTypeTable",
+            Attribute::StackMapTable(_) => "StackMapTable",
+            Attribute::Exceptions(_) => "Exceptions",
+            Attribute::InnerClasses(_) => "InnerClasses",
+            Attribute::EnclosingMethod(_) => "EnclosingMethod",
+            Attribute::Signature(_) => "Signature",
+            Attribute::SourceFile(_) => "SourceFile",
+            Attribute::SourceDebugExtension(_) => "SourceDebugExtension",
+            Attribute::RuntimeVisibleAnnotations(_) => "RuntimeVisibleAnnotations",
+            Attribute::RuntimeInvisibleAnnotations(_) => "RuntimeInvisibleAnnotations",
+            Attribute::RuntimeVisibleParameterAnnotations(_) => {
+                "RuntimeVisibleParameterAnnotations"
+            }
+            Attribute::RuntimeInvisibleParameterAnnotations(_) => {
+                "RuntimeInvisibleParameterAnnotations"
+            }
+            Attribute::RuntimeVisibleTypeAnnotations(_) => "RuntimeVisibleTypeAnnotations",
+            Attribute::RuntimeInvisibleTypeAnnotations(_) => "RuntimeInvisibleTypeAnnotations",
+            Attribute::AnnotationDefault(_) => "AnnotationDefault",
+            Attribute::BootstrapMethods(_) => "BootstrapMethods",
+            Attribute::MethodParameters(_) => "MethodParameters",
+            Attribute::Module(_) => "Module",
+            Attribute::ModulePackages(_) => "ModulePackages",
+            Attribute::ModuleMainClass(_) => "ModuleMainClass",
+            Attribute::NestHost(_) => "NestHost",
+            Attribute::NestMembers(_) => "NestMembers",
+            Attribute::Record(_) => "Record",
+            Attribute::PermittedSubclasses(_) => "PermittedSubclasses",
+            Attribute::Unknown { name, .. } => name,
+        }
+    }
+}
+
+/// The annotations from the RuntimeVisibleAnnotations and RuntimeInvisibleAnnotations attributes
+/// in `attributes`, visible ones first.
+pub(crate) fn annotations(attributes: &[Attribute]) -> impl Iterator<Item = &Annotation> {
+    let visible = attributes.iter().filter_map(|attr| match attr {
+        Attribute::RuntimeVisibleAnnotations(annotations) => Some(annotations),
+        _ => None,
+    });
+    let invisible = attributes.iter().filter_map(|attr| match attr {
+        Attribute::RuntimeInvisibleAnnotations(annotations) => Some(annotations),
+        _ => None,
+    });
+
+    visible.chain(invisible).flatten()
 }
 
 /// The spec defines this struct inline on the "Code" attribute: <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.3>
@@ -19,23 +144,275 @@ pub struct ExceptionHandlerDescriptor {
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.3>
 #[derive(Debug)]
-pub struct CodeAttribute<'cp> {
+pub struct CodeAttribute {
     pub max_stack: u16,
     pub max_locals: u16,
-    pub code: Vec<crate::op::Op<'cp>>,
+    pub code: Vec<crate::op::Op>,
     pub exception_table: Vec<ExceptionHandlerDescriptor>,
-    pub attributes: Vec<Attribute<'cp>>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl CodeAttribute {
+    /// The frames in this method's StackMapTable attribute, if it has one.
+    pub fn stack_map_table(&self) -> Option<&[StackMapFrame]> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::StackMapTable(frames) => Some(frames.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The source line that the instruction at `pc` was compiled from, according to the
+    /// LineNumberTable attributes.
+    pub fn line_number_at(&self, pc: u16) -> Option<u16> {
+        self.attributes
+            .iter()
+            .filter_map(|attr| match attr {
+                Attribute::LineNumberTable(entries) => Some(entries),
+                _ => None,
+            })
+            .flatten()
+            .filter(|entry| entry.start_pc <= pc)
+            .max_by_key(|entry| entry.start_pc)
+            .map(|entry| entry.line_number)
+    }
+
+    /// The LocalVariableTable entry for local variable `slot` at `pc`, if there is one.
+    pub fn local_at(&self, pc: u16, slot: u16) -> Option<&LocalVariableTableEntry> {
+        self.attributes
+            .iter()
+            .filter_map(|attr| match attr {
+                Attribute::LocalVariableTable(entries) => Some(entries),
+                _ => None,
+            })
+            .flatten()
+            .find(|entry| entry.index == slot && entry.covers(pc))
+    }
+
+    /// The LocalVariableTypeTable entry for local variable `slot` at `pc`, which only exists if
+    /// the variable has a generic type.
+    pub fn local_type_at(&self, pc: u16, slot: u16) -> Option<&LocalVariableTypeTableEntry> {
+        self.attributes
+            .iter()
+            .filter_map(|attr| match attr {
+                Attribute::LocalVariableTypeTable(entries) => Some(entries),
+                _ => None,
+            })
+            .flatten()
+            .find(|entry| entry.index == slot && entry.covers(pc))
+    }
+
+    /// Expands the StackMapTable into a list of `(pc, frame)`, where each frame holds the full
+    /// state of the locals and operand stack instead of a delta from the previous frame.
+    ///
+    /// `initial_locals` describes the implicit frame at pc 0 (see
+    /// [`MethodInfo::initial_locals`](crate::MethodInfo::initial_locals)), which isn't itself
+    /// included in the result. Returns an empty list if there is no StackMapTable, and `None`
+    /// if a frame chops more locals than there are or a pc doesn't fit in a `u16`.
+    pub fn expand_stack_map_frames(
+        &self,
+        initial_locals: &[VerificationTypeInfo],
+    ) -> Option<Vec<(u16, Frame)>> {
+        let Some(frames) = self.stack_map_table() else {
+            return Some(Vec::new());
+        };
+
+        // Locals as they're listed in frames, where long and double take up one entry.
+        let mut locals = initial_locals.to_vec();
+        let mut pc: Option<u16> = None;
+        let mut expanded = Vec::with_capacity(frames.len());
+
+        for frame in frames {
+            let offset_delta = frame.offset_delta();
+            let frame_pc = match pc {
+                None => offset_delta,
+                Some(pc) => pc.checked_add(offset_delta)?.checked_add(1)?,
+            };
+            pc = Some(frame_pc);
+
+            let stack = match frame {
+                StackMapFrame::Same { .. } => Vec::new(),
+                StackMapFrame::SameLocals1StackItem { stack, .. } => vec![stack.clone()],
+                StackMapFrame::Chop { k, .. } => {
+                    locals.truncate(locals.len().checked_sub((*k).into())?);
+                    Vec::new()
+                }
+                StackMapFrame::Append {
+                    locals: appended, ..
+                } => {
+                    locals.extend_from_slice(appended);
+                    Vec::new()
+                }
+                StackMapFrame::Full {
+                    locals: full_locals,
+                    stack,
+                    ..
+                } => {
+                    locals = full_locals.clone();
+                    stack.clone()
+                }
+            };
+
+            expanded.push((
+                frame_pc,
+                Frame {
+                    locals: VerificationTypeInfo::to_slots(&locals),
+                    stack: VerificationTypeInfo::to_slots(&stack),
+                },
+            ));
+        }
+
+        Some(expanded)
+    }
+}
+
+/// A frame of a StackMapTable. Each frame applies to the instruction `offset_delta + 1` bytes
+/// after the previous frame's, except the first, which applies at `offset_delta`.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.4>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackMapFrame {
+    /// same_frame or same_frame_extended: the same locals as the previous frame and an empty
+    /// stack.
+    Same {
+        offset_delta: u16,
+        /// Whether the frame uses the extended form even though `offset_delta` would fit in
+        /// the short one, so that it's written back the same way.
+        extended: bool,
+    },
+    /// same_locals_1_stack_item_frame or same_locals_1_stack_item_frame_extended: the same
+    /// locals as the previous frame and a single stack item.
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationTypeInfo,
+        /// As for [`Self::Same`].
+        extended: bool,
+    },
+    /// The previous frame's locals with the last `k` removed, and an empty stack.
+    Chop { offset_delta: u16, k: u8 },
+    /// The previous frame's locals with more added, and an empty stack.
+    Append {
+        offset_delta: u16,
+        locals: Vec<VerificationTypeInfo>,
+    },
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationTypeInfo>,
+        stack: Vec<VerificationTypeInfo>,
+    },
+}
+
+impl StackMapFrame {
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            Self::Same { offset_delta, .. }
+            | Self::SameLocals1StackItem { offset_delta, .. }
+            | Self::Chop { offset_delta, .. }
+            | Self::Append { offset_delta, .. }
+            | Self::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
+/// The type of a local variable or stack entry in a [`StackMapFrame`].
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.4>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationTypeInfo {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    /// An instance of the named class, or of an array type such as `[I`.
+    Object(Symbol),
+    /// An object created by the `new` instruction at this offset, whose constructor hasn't been
+    /// called yet.
+    Uninitialized(u16),
+}
+
+impl VerificationTypeInfo {
+    /// Whether the type takes up two local variable slots or two stack entries.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Self::Long | Self::Double)
+    }
+
+    /// The type of a value of the type `descriptor`, e.g. `Integer` for a `boolean`.
+    pub(crate) fn from_descriptor(descriptor: &FieldDescriptor) -> Self {
+        match descriptor {
+            FieldDescriptor::Basic(BasicFieldType::ClassInstance(class)) => {
+                Self::Object(class.clone())
+            }
+            FieldDescriptor::Basic(BasicFieldType::Float) => Self::Float,
+            FieldDescriptor::Basic(BasicFieldType::Long) => Self::Long,
+            FieldDescriptor::Basic(BasicFieldType::Double) => Self::Double,
+            FieldDescriptor::Basic(_) => Self::Integer,
+            FieldDescriptor::Arr(..) => Self::Object(descriptor.to_string().into()),
+        }
+    }
+
+    /// Converts a list of types as written in a frame into one entry per slot, with `Top` after
+    /// each long and double.
+    pub(crate) fn to_slots(types: &[Self]) -> Vec<Self> {
+        let mut slots = Vec::with_capacity(types.len());
+        for t in types {
+            slots.push(t.clone());
+            if t.is_wide() {
+                slots.push(Self::Top);
+            }
+        }
+        slots
+    }
+}
+
+/// The state of the locals and operand stack at some pc, as given by the StackMapTable (see
+/// [`CodeAttribute::expand_stack_map_frames`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// One entry per local variable slot. The slot after a long or double is `Top`.
+    pub locals: Vec<VerificationTypeInfo>,
+    /// One entry per stack slot, with the same layout as `locals`.
+    pub stack: Vec<VerificationTypeInfo>,
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.2>
 #[derive(Debug)]
-pub enum ConstantValueAttribute<'cp> {
+pub enum ConstantValueAttribute {
     /// int, short, char, byte, boolean
     Integer(i32),
     Float(f32),
     Long(i64),
     Double(f64),
-    String(&'cp str),
+    String(JavaString),
+}
+
+/// Spec defines this inline on "InnerClasses": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.6>
+#[derive(Debug)]
+pub struct InnerClass {
+    pub inner_class: Symbol,
+    /// `None` for top-level, local and anonymous classes.
+    pub outer_class: Option<Symbol>,
+    /// The simple name from the source code, or `None` for anonymous classes.
+    pub inner_name: Option<Symbol>,
+    /// The flags the class was declared with in the source code.
+    pub inner_class_access_flags: InnerClassAccessFlags,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.7>
+#[derive(Debug)]
+pub struct EnclosingMethod {
+    pub class: Symbol,
+    /// `None` if the class isn't immediately enclosed by a method or constructor, e.g. when
+    /// it's declared in a field initializer.
+    pub method: Option<NameAndType>,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.11>
+#[derive(Debug)]
+pub struct SourceDebugExtension {
+    /// The contents of the attribute, which the JVM doesn't interpret.
+    pub debug_extension: JavaString,
+    /// The decoded contents, if they're a well-formed JSR-45 source map (starting with `SMAP`).
+    pub source_map: Option<SourceMap>,
 }
 
 /// Spec defines this inline on "LineNumberTable": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.12>
@@ -44,3 +421,141 @@ pub struct LineNumberTableEntry {
     pub start_pc: u16,
     pub line_number: u16,
 }
+
+/// Spec defines this inline on "LocalVariableTable": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.13>
+#[derive(Debug)]
+pub struct LocalVariableTableEntry {
+    /// The variable is in scope from `start_pc` up to, but not including, `start_pc + length`.
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: Symbol,
+    pub descriptor: FieldDescriptor,
+    /// The local variable slot. Longs and doubles also take up `index + 1`.
+    pub index: u16,
+}
+
+impl LocalVariableTableEntry {
+    fn covers(&self, pc: u16) -> bool {
+        (u32::from(self.start_pc)..u32::from(self.start_pc) + u32::from(self.length))
+            .contains(&pc.into())
+    }
+}
+
+/// Spec defines this inline on "LocalVariableTypeTable": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.14>
+#[derive(Debug)]
+pub struct LocalVariableTypeTableEntry {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: Symbol,
+    pub signature: FieldSignature,
+    pub index: u16,
+}
+
+impl LocalVariableTypeTableEntry {
+    fn covers(&self, pc: u16) -> bool {
+        (u32::from(self.start_pc)..u32::from(self.start_pc) + u32::from(self.length))
+            .contains(&pc.into())
+    }
+}
+
+/// Spec defines this inline on "MethodParameters": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.24>
+#[derive(Debug)]
+pub struct MethodParameter {
+    /// `None` for a parameter without a name.
+    pub name: Option<Symbol>,
+    pub access_flags: ParameterAccessFlags,
+}
+
+/// Spec defines this inline on "BootstrapMethods": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.23>
+#[derive(Debug)]
+pub struct BootstrapMethod {
+    pub method: MethodHandle,
+    /// The static arguments passed to `method` after the lookup, name and type.
+    pub arguments: Vec<LoadableConstant>,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+#[derive(Debug)]
+pub struct ModuleAttribute {
+    /// The module's name, with `.` separators, e.g. `java.base`.
+    pub name: Symbol,
+    pub flags: ModuleAccessFlags,
+    pub version: Option<Symbol>,
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Opens>,
+    /// The services that the module uses, as class names.
+    pub uses: Vec<Symbol>,
+    pub provides: Vec<Provides>,
+}
+
+/// A dependency of a module.
+#[derive(Debug)]
+pub struct Requires {
+    pub module: Symbol,
+    pub flags: RequiresAccessFlags,
+    /// The version of `module` that this module was compiled against, if it was recorded.
+    pub version: Option<Symbol>,
+}
+
+/// A package that a module exports.
+#[derive(Debug)]
+pub struct Exports {
+    /// The package, with `/` separators.
+    pub package: Symbol,
+    pub flags: ExportsAccessFlags,
+    /// The modules that the package is exported to, or empty if it's exported to all modules.
+    pub to: Vec<Symbol>,
+}
+
+/// A package that a module opens for reflection.
+#[derive(Debug)]
+pub struct Opens {
+    /// The package, with `/` separators.
+    pub package: Symbol,
+    pub flags: ExportsAccessFlags,
+    /// The modules that the package is opened to, or empty if it's opened to all modules.
+    pub to: Vec<Symbol>,
+}
+
+/// A service implementation that a module provides.
+#[derive(Debug)]
+pub struct Provides {
+    /// The service interface or class.
+    pub service: Symbol,
+    /// The implementations of `service`.
+    pub with: Vec<Symbol>,
+}
+
+/// A module declaration, put together from the Module, ModulePackages and ModuleMainClass
+/// attributes of a `module-info.class`. See [`ClassFile::module_descriptor`](crate::ClassFile::module_descriptor).
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleDescriptor<'a> {
+    pub module: &'a ModuleAttribute,
+    /// Empty if the class file has no ModulePackages attribute.
+    pub packages: &'a [Symbol],
+    pub main_class: Option<&'a Symbol>,
+}
+
+/// Spec defines this inline on "Record": <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.30>
+#[derive(Debug)]
+pub struct RecordComponent {
+    pub name: Symbol,
+    pub descriptor: FieldDescriptor,
+    pub attributes: Vec<Attribute>,
+}
+
+impl RecordComponent {
+    /// The generic type of the component, if it has one.
+    pub fn signature(&self) -> Option<&FieldSignature> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Signature(Signature::Field(signature)) => Some(signature),
+            _ => None,
+        })
+    }
+
+    /// The component's annotations, both visible and invisible at runtime.
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        annotations(&self.attributes)
+    }
+}
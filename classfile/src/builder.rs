@@ -0,0 +1,1132 @@
+//! Building class files from scratch, as an alternative to parsing them.
+//!
+//! A [`ClassBuilder`] collects the parts of a class and [`ClassBuilder::build`] turns them into
+//! a [`ClassFile`], with a constant pool that has exactly one entry for each constant the class
+//! refers to, unless it started from another class's pool. Code is emitted with a [`CodeBuilder`], where branches jump to [`Label`]s instead
+//! of offsets.
+//!
+//! The builders are also visitors (see [`visitor`](crate::visitor)), so that a class can be
+//! rebuilt from the events of a transformed class.
+
+use std::sync::Arc;
+
+use crate::lazy::LazyCode;
+use crate::op::{LookupSwitch, Op, TableSwitch};
+use crate::visitor::{ClassVisitor, CodeVisitor, FieldVisitor, MethodVisitor};
+use crate::writer::{self, ConstantPoolWriter};
+use crate::*;
+
+type Result<T> = std::result::Result<T, ClassWriteError>;
+
+/// A class that's being built. See the [module documentation](self).
+#[derive(Debug)]
+pub struct ClassBuilder {
+    major_version: u16,
+    minor_version: u16,
+    access_flags: ClassAccessFlags,
+    this_class: Symbol,
+    super_class: Option<Symbol>,
+    interfaces: Vec<Symbol>,
+    fields: Vec<FieldInfo>,
+    methods: Vec<MethodBuilder>,
+    attributes: Vec<Attribute>,
+    /// The pool of the class that this one is rebuilt from, which its unknown attributes refer
+    /// to.
+    constant_pool: Option<Arc<ConstantPool>>,
+}
+
+impl ClassBuilder {
+    /// A public class called `name` that extends `java/lang/Object`, with version 61.0 (Java
+    /// 17).
+    pub fn new(name: impl Into<Symbol>) -> Self {
+        Self {
+            major_version: 61,
+            minor_version: 0,
+            access_flags: [ClassAccess::Public, ClassAccess::Super]
+                .into_iter()
+                .collect(),
+            this_class: name.into(),
+            super_class: Some("java/lang/Object".into()),
+            interfaces: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            attributes: Vec::new(),
+            constant_pool: None,
+        }
+    }
+
+    pub fn version(mut self, major_version: u16, minor_version: u16) -> Self {
+        self.major_version = major_version;
+        self.minor_version = minor_version;
+        self
+    }
+
+    /// Replaces the access flags, which are `ACC_PUBLIC` and `ACC_SUPER` by default.
+    pub fn access_flags(mut self, flags: &[ClassAccess]) -> Self {
+        self.access_flags = flags.iter().copied().collect();
+        self
+    }
+
+    pub fn super_class(mut self, name: impl Into<Symbol>) -> Self {
+        self.super_class = Some(name.into());
+        self
+    }
+
+    /// Removes the superclass, which only `java/lang/Object` and `module-info` go without.
+    pub fn without_super_class(mut self) -> Self {
+        self.super_class = None;
+        self
+    }
+
+    /// Adds an interface that the class implements, or that the interface extends.
+    pub fn interface(mut self, name: impl Into<Symbol>) -> Self {
+        self.interfaces.push(name.into());
+        self
+    }
+
+    pub fn field(mut self, field: FieldBuilder) -> Self {
+        self.fields.push(field.field);
+        self
+    }
+
+    pub fn method(mut self, method: MethodBuilder) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    pub fn attribute(mut self, attribute: Attribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    /// Resolves the labels in the code of each method and builds the constant pool.
+    ///
+    /// Writing the class gives the same bytes as writing the class that's parsed from them.
+    pub fn build(self) -> Result<ClassFile> {
+        self.build_class(None)
+    }
+
+    /// Like [`Self::build`], but computes the `max_stack`, `max_locals` and StackMapTable of
+    /// each method's code instead of using the ones that were given (see
+    /// [`ClassFile::compute_frames`]).
+    pub fn build_with_frames(self, hierarchy: &dyn ClassHierarchy) -> Result<ClassFile> {
+        self.build_class(Some(hierarchy))
+    }
+
+    fn build_class(self, hierarchy: Option<&dyn ClassHierarchy>) -> Result<ClassFile> {
+        // Constants keep their indices in the pool the class is rebuilt from, so that the bytes
+        // of unknown attributes still refer to the right ones.
+        let mut cp = match &self.constant_pool {
+            Some(source) => {
+                ConstantPoolWriter::new(source, &[]).with_major_version(self.major_version)
+            }
+            None => {
+                ConstantPoolWriter::new(&ConstantPool::new(Vec::new(), self.major_version), &[])
+            }
+        };
+        // `ldc` can only load the first 255 constants, so those constants go first.
+        for code in self
+            .methods
+            .iter()
+            .filter_map(|method| method.code.as_ref())
+        {
+            for op in &code.ops {
+                if let Op::Ldc(constant) = op {
+                    cp.loadable(constant)?;
+                }
+            }
+        }
+        let methods = self
+            .methods
+            .into_iter()
+            .map(|method| method.build(&mut cp))
+            .collect::<Result<_>>()?;
+
+        let mut class = ClassFile {
+            major_version: self.major_version,
+            minor_version: self.minor_version,
+            constant_pool: Arc::new(cp.to_constant_pool()),
+            fields: self.fields,
+            access_flags: self.access_flags,
+            methods,
+            attributes: self.attributes,
+            this_class: self.this_class,
+            super_class: self.super_class,
+            interfaces: self.interfaces,
+            warnings: Vec::new(),
+            cp_references: Vec::new(),
+        };
+        if let Some(hierarchy) = hierarchy {
+            class.compute_frames(hierarchy)?;
+        }
+        class.constant_pool = Arc::new(writer::complete_constant_pool(&class)?);
+        Ok(class)
+    }
+}
+
+/// A field that's being built, to be added with [`ClassBuilder::field`].
+#[derive(Debug)]
+pub struct FieldBuilder {
+    field: FieldInfo,
+}
+
+impl FieldBuilder {
+    pub fn new(
+        flags: &[FieldAccess],
+        name: impl Into<Symbol>,
+        descriptor: FieldDescriptor,
+    ) -> Self {
+        Self {
+            field: FieldInfo {
+                access_flags: flags.iter().copied().collect(),
+                name: name.into(),
+                descriptor,
+                attributes: Vec::new(),
+            },
+        }
+    }
+
+    pub fn attribute(mut self, attribute: Attribute) -> Self {
+        self.field.attributes.push(attribute);
+        self
+    }
+}
+
+/// A method that's being built, to be added with [`ClassBuilder::method`].
+#[derive(Debug)]
+pub struct MethodBuilder {
+    access_flags: MethodAccessFlags,
+    name: Symbol,
+    descriptor: MethodDescriptor,
+    code: Option<CodeBuilder>,
+    attributes: Vec<Attribute>,
+}
+
+impl MethodBuilder {
+    /// A method without code, as abstract and native methods are.
+    pub fn new(
+        flags: &[MethodAccess],
+        name: impl Into<Symbol>,
+        descriptor: MethodDescriptor,
+    ) -> Self {
+        Self {
+            access_flags: flags.iter().copied().collect(),
+            name: name.into(),
+            descriptor,
+            code: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn code(mut self, code: CodeBuilder) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Adds an attribute other than Code, which is set with [`Self::code`].
+    pub fn attribute(mut self, attribute: Attribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    fn build(self, cp: &mut ConstantPoolWriter) -> Result<MethodInfo> {
+        Ok(MethodInfo {
+            access_flags: self.access_flags,
+            name: self.name,
+            descriptor: self.descriptor,
+            code: self
+                .code
+                .map(|code| code.build(cp).map(LazyCode::decoded))
+                .transpose()?,
+            attributes: self.attributes,
+            code_position: 0,
+        })
+    }
+}
+
+/// A position in the code of a [`CodeBuilder`], which can be used before it's placed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+/// An instruction whose offsets are only known once every label is placed.
+#[derive(Debug)]
+enum Fixup {
+    Branch(fn(i16) -> Op, Label),
+    WideBranch(fn(i32) -> Op, Label),
+    TableSwitch {
+        low: i32,
+        default: Label,
+        targets: Vec<Label>,
+    },
+    LookupSwitch {
+        default: Label,
+        pairs: Vec<(i32, Label)>,
+    },
+}
+
+impl Fixup {
+    /// The fixup for a branch instruction like `op`, whose offset doesn't matter.
+    fn branch(op: &Op, target: Label) -> Option<Self> {
+        use Op::*;
+
+        let op: fn(i16) -> Op = match op {
+            Ifeq(_) => Ifeq,
+            Ifne(_) => Ifne,
+            Iflt(_) => Iflt,
+            Ifge(_) => Ifge,
+            Ifgt(_) => Ifgt,
+            Ifle(_) => Ifle,
+            IfIcmpeq(_) => IfIcmpeq,
+            IfIcmpne(_) => IfIcmpne,
+            IfIcmplt(_) => IfIcmplt,
+            IfIcmpge(_) => IfIcmpge,
+            IfIcmpgt(_) => IfIcmpgt,
+            IfIcmple(_) => IfIcmple,
+            IfAcmpeq(_) => IfAcmpeq,
+            IfAcmpne(_) => IfAcmpne,
+            Goto(_) => Goto,
+            Jsr(_) => Jsr,
+            Ifnull(_) => Ifnull,
+            Ifnonnull(_) => Ifnonnull,
+            GotoW(_) => return Some(Self::WideBranch(GotoW, target)),
+            JsrW(_) => return Some(Self::WideBranch(JsrW, target)),
+            _ => return None,
+        };
+        Some(Self::Branch(op, target))
+    }
+}
+
+#[derive(Debug)]
+struct ExceptionHandler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: Option<Symbol>,
+}
+
+/// The code of a method that's being built.
+///
+/// Instructions are appended in order. Branches, switches and exception handlers refer to
+/// [`Label`]s, which are created with [`Self::new_label`] and placed before the next
+/// instruction with [`Self::place`].
+#[derive(Debug, Default)]
+pub struct CodeBuilder {
+    max_stack: u16,
+    max_locals: u16,
+    ops: Vec<Op>,
+    /// The instructions in `ops` that still have placeholder offsets, by index.
+    fixups: Vec<(usize, Fixup)>,
+    /// For each label, the index in `ops` of the instruction that it was placed before.
+    labels: Vec<Option<usize>>,
+    exception_handlers: Vec<ExceptionHandler>,
+    line_numbers: Vec<(u16, Label)>,
+    local_variables: Vec<LocalVariable<FieldDescriptor>>,
+    local_variable_types: Vec<LocalVariable<FieldSignature>>,
+    attributes: Vec<Attribute>,
+}
+
+/// An entry of a LocalVariableTable, or of a LocalVariableTypeTable if `T` is a signature.
+#[derive(Debug)]
+struct LocalVariable<T> {
+    name: Symbol,
+    descriptor: T,
+    start: Label,
+    end: Label,
+    index: u16,
+}
+
+impl CodeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_stack(&mut self, max_stack: u16) -> &mut Self {
+        self.max_stack = max_stack;
+        self
+    }
+
+    pub fn max_locals(&mut self, max_locals: u16) -> &mut Self {
+        self.max_locals = max_locals;
+        self
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Places `label` before the next instruction, or at the end of the code if there isn't
+    /// one.
+    ///
+    /// Panics if the label was already placed.
+    pub fn place(&mut self, label: Label) -> &mut Self {
+        let position = &mut self.labels[label.0];
+        assert!(position.is_none(), "label placed twice");
+        *position = Some(self.ops.len());
+        self
+    }
+
+    /// Appends an instruction. Branches and switches should be added with [`Self::branch`],
+    /// [`Self::tableswitch`] and friends instead, so that their offsets are filled in.
+    pub fn op(&mut self, op: Op) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Appends a branch to `target`, e.g. `code.branch(Op::Goto, target)`.
+    pub fn branch(&mut self, op: fn(i16) -> Op, target: Label) -> &mut Self {
+        self.fixup(op(0), Fixup::Branch(op, target))
+    }
+
+    /// Appends a `goto_w` or `jsr_w` to `target`.
+    pub fn branch_wide(&mut self, op: fn(i32) -> Op, target: Label) -> &mut Self {
+        self.fixup(op(0), Fixup::WideBranch(op, target))
+    }
+
+    /// Appends a `tableswitch` that jumps to `targets[i]` for the value `low + i`, and to
+    /// `default` for any other value. There has to be at least one target, and `low + i` has to
+    /// fit in an `i32` for each of them.
+    pub fn tableswitch(&mut self, low: i32, default: Label, targets: Vec<Label>) -> &mut Self {
+        // `high` is only known to be valid once the code is built.
+        let placeholder = TableSwitch {
+            padding: [0; 3],
+            default: 0,
+            low,
+            high: low,
+            offsets: vec![0; targets.len()],
+        };
+        self.fixup(
+            Op::Tableswitch(placeholder),
+            Fixup::TableSwitch {
+                low,
+                default,
+                targets,
+            },
+        )
+    }
+
+    /// Appends a `lookupswitch` that jumps to the label paired with the value, or to `default`
+    /// if there isn't one. The pairs don't need to be sorted.
+    pub fn lookupswitch(&mut self, default: Label, mut pairs: Vec<(i32, Label)>) -> &mut Self {
+        pairs.sort_by_key(|&(value, _)| value);
+        let placeholder = LookupSwitch {
+            padding: [0; 3],
+            default: 0,
+            pairs: pairs.iter().map(|&(value, _)| (value, 0)).collect(),
+        };
+        self.fixup(
+            Op::Lookupswitch(placeholder),
+            Fixup::LookupSwitch { default, pairs },
+        )
+    }
+
+    fn fixup(&mut self, placeholder: Op, fixup: Fixup) -> &mut Self {
+        self.fixups.push((self.ops.len(), fixup));
+        self.op(placeholder)
+    }
+
+    /// Adds an exception handler at `handler` for the code from `start` up to `end`. It
+    /// catches `catch_type` and its subclasses, or anything if `catch_type` is `None`.
+    pub fn exception_handler(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<&str>,
+    ) -> &mut Self {
+        self.exception_handlers.push(ExceptionHandler {
+            start,
+            end,
+            handler,
+            catch_type: catch_type.map(Symbol::from),
+        });
+        self
+    }
+
+    /// Records that the instructions from `start` on come from `line` in the source file, for
+    /// the LineNumberTable.
+    pub fn line_number(&mut self, line: u16, start: Label) -> &mut Self {
+        self.line_numbers.push((line, start));
+        self
+    }
+
+    /// Adds a local variable in slot `index` that's in scope from `start` up to `end` to the
+    /// LocalVariableTable.
+    pub fn local_variable(
+        &mut self,
+        name: impl Into<Symbol>,
+        descriptor: FieldDescriptor,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) -> &mut Self {
+        self.local_variables.push(LocalVariable {
+            name: name.into(),
+            descriptor,
+            start,
+            end,
+            index,
+        });
+        self
+    }
+
+    /// Like [`Self::local_variable`], but for the LocalVariableTypeTable, which has the
+    /// generic types of local variables.
+    pub fn local_variable_type(
+        &mut self,
+        name: impl Into<Symbol>,
+        signature: FieldSignature,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) -> &mut Self {
+        self.local_variable_types.push(LocalVariable {
+            name: name.into(),
+            descriptor: signature,
+            start,
+            end,
+            index,
+        });
+        self
+    }
+
+    /// Adds an attribute of the Code attribute. LineNumberTable, LocalVariableTable and
+    /// LocalVariableTypeTable attributes are made from [`Self::line_number`] and friends
+    /// instead, and come before the attributes that are added here.
+    pub fn attribute(&mut self, attribute: Attribute) -> &mut Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    fn build(mut self, cp: &mut ConstantPoolWriter) -> Result<CodeAttribute> {
+        // Any `ldc` whose constant didn't make it into the first 255 becomes an `ldc_w`.
+        for op in &mut self.ops {
+            if let Op::Ldc(constant) = op {
+                if cp.loadable(constant)? > u8::MAX.into() {
+                    let Op::Ldc(constant) = std::mem::replace(op, Op::Nop) else {
+                        unreachable!()
+                    };
+                    *op = Op::LdcW(constant);
+                }
+            }
+        }
+
+        let mut pcs = Vec::with_capacity(self.ops.len() + 1);
+        let mut pc = 0;
+        for op in &self.ops {
+            pcs.push(pc);
+            pc += op.encoded_len(pc);
+        }
+        pcs.push(pc);
+
+        let label_pc = |label: Label| match self.labels[label.0] {
+            Some(index) => Ok(pcs[index]),
+            None => Err(ClassWriteError::UnplacedLabel),
+        };
+
+        let mut ops = self.ops;
+        for (index, fixup) in self.fixups {
+            let offset = |target: Label| {
+                let offset = label_pc(target)? as i64 - pcs[index] as i64;
+                i32::try_from(offset).map_err(|_| ClassWriteError::BranchTooFar)
+            };
+            ops[index] = match fixup {
+                Fixup::Branch(op, target) => {
+                    let offset = i16::try_from(offset(target)?)
+                        .map_err(|_| ClassWriteError::BranchTooFar)?;
+                    op(offset)
+                }
+                Fixup::WideBranch(op, target) => op(offset(target)?),
+                Fixup::TableSwitch {
+                    low,
+                    default,
+                    targets,
+                } => Op::Tableswitch(TableSwitch {
+                    padding: [0; 3],
+                    default: offset(default)?,
+                    low,
+                    high: targets
+                        .len()
+                        .checked_sub(1)
+                        .and_then(|last| low.checked_add(i32::try_from(last).ok()?))
+                        .ok_or(ClassWriteError::BadTableswitch)?,
+                    offsets: targets.into_iter().map(offset).collect::<Result<_>>()?,
+                }),
+                Fixup::LookupSwitch { default, pairs } => Op::Lookupswitch(LookupSwitch {
+                    padding: [0; 3],
+                    default: offset(default)?,
+                    pairs: pairs
+                        .into_iter()
+                        .map(|(value, target)| Ok((value, offset(target)?)))
+                        .collect::<Result<_>>()?,
+                }),
+            };
+        }
+
+        let code_pc = |label: Label| {
+            u16::try_from(label_pc(label)?).map_err(|_| ClassWriteError::TooLong("code"))
+        };
+        let exception_table = self
+            .exception_handlers
+            .into_iter()
+            .map(|handler| {
+                Ok(ExceptionHandlerDescriptor {
+                    start_pc: code_pc(handler.start)?,
+                    end_pc: code_pc(handler.end)?,
+                    handler_pc: code_pc(handler.handler)?,
+                    catch_type: match &handler.catch_type {
+                        Some(class) => cp.class(class)?,
+                        None => 0,
+                    },
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let mut attributes = Vec::with_capacity(self.attributes.len() + 3);
+        if !self.line_numbers.is_empty() {
+            let entries = self
+                .line_numbers
+                .into_iter()
+                .map(|(line_number, start)| {
+                    Ok(LineNumberTableEntry {
+                        start_pc: code_pc(start)?,
+                        line_number,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            attributes.push(Attribute::LineNumberTable(entries));
+        }
+        let range = |start: Label, end: Label| -> Result<(u16, u16)> {
+            let start_pc = code_pc(start)?;
+            // A local that ends before it starts is out of scope everywhere.
+            Ok((start_pc, code_pc(end)?.saturating_sub(start_pc)))
+        };
+        if !self.local_variables.is_empty() {
+            let entries = self
+                .local_variables
+                .into_iter()
+                .map(|local| {
+                    let (start_pc, length) = range(local.start, local.end)?;
+                    Ok(LocalVariableTableEntry {
+                        start_pc,
+                        length,
+                        name: local.name,
+                        descriptor: local.descriptor,
+                        index: local.index,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            attributes.push(Attribute::LocalVariableTable(entries));
+        }
+        if !self.local_variable_types.is_empty() {
+            let entries = self
+                .local_variable_types
+                .into_iter()
+                .map(|local| {
+                    let (start_pc, length) = range(local.start, local.end)?;
+                    Ok(LocalVariableTypeTableEntry {
+                        start_pc,
+                        length,
+                        name: local.name,
+                        signature: local.descriptor,
+                        index: local.index,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            attributes.push(Attribute::LocalVariableTypeTable(entries));
+        }
+        attributes.extend(self.attributes);
+
+        Ok(CodeAttribute {
+            max_stack: self.max_stack,
+            max_locals: self.max_locals,
+            code: ops,
+            exception_table,
+            attributes,
+        })
+    }
+}
+
+/// Rebuilds a class from the events of a visitor; see the [module documentation](self).
+/// `visit` replaces the name, version, access flags, superclass and interfaces.
+impl ClassVisitor for ClassBuilder {
+    fn visit(
+        &mut self,
+        major_version: u16,
+        minor_version: u16,
+        access_flags: ClassAccessFlags,
+        name: Symbol,
+        super_class: Option<Symbol>,
+        interfaces: Vec<Symbol>,
+    ) {
+        self.major_version = major_version;
+        self.minor_version = minor_version;
+        self.access_flags = access_flags;
+        self.this_class = name;
+        self.super_class = super_class;
+        self.interfaces = interfaces;
+    }
+
+    fn visit_constant_pool(&mut self, constant_pool: &Arc<ConstantPool>) {
+        self.constant_pool = Some(constant_pool.clone());
+    }
+
+    fn visit_field(
+        &mut self,
+        access_flags: FieldAccessFlags,
+        name: Symbol,
+        descriptor: FieldDescriptor,
+    ) -> Option<Box<dyn FieldVisitor + '_>> {
+        Some(Box::new(FieldEntry {
+            fields: &mut self.fields,
+            field: Some(FieldInfo {
+                access_flags,
+                name,
+                descriptor,
+                attributes: Vec::new(),
+            }),
+        }))
+    }
+
+    fn visit_method(
+        &mut self,
+        access_flags: MethodAccessFlags,
+        name: Symbol,
+        descriptor: MethodDescriptor,
+    ) -> Option<Box<dyn MethodVisitor + '_>> {
+        Some(Box::new(MethodEntry {
+            methods: &mut self.methods,
+            method: Some(MethodBuilder {
+                access_flags,
+                name,
+                descriptor,
+                code: None,
+                attributes: Vec::new(),
+            }),
+        }))
+    }
+
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        self.attributes.push(attribute);
+    }
+}
+
+/// A field that's added to a [`ClassBuilder`] when it's visited to the end.
+struct FieldEntry<'a> {
+    fields: &'a mut Vec<FieldInfo>,
+    field: Option<FieldInfo>,
+}
+
+impl FieldVisitor for FieldEntry<'_> {
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(field) = &mut self.field {
+            field.attributes.push(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        self.fields.extend(self.field.take());
+    }
+}
+
+/// A method that's added to a [`ClassBuilder`] when it's visited to the end.
+struct MethodEntry<'a> {
+    methods: &'a mut Vec<MethodBuilder>,
+    method: Option<MethodBuilder>,
+}
+
+impl MethodVisitor for MethodEntry<'_> {
+    fn visit_code(&mut self) -> Option<Box<dyn CodeVisitor + '_>> {
+        let method = self.method.as_mut()?;
+        Some(Box::new(method.code.insert(CodeBuilder::new())))
+    }
+
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(method) = &mut self.method {
+            method.attributes.push(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        self.methods.extend(self.method.take());
+    }
+}
+
+impl CodeVisitor for CodeBuilder {
+    fn new_label(&mut self) -> Label {
+        CodeBuilder::new_label(self)
+    }
+
+    fn visit_label(&mut self, label: Label) {
+        self.place(label);
+    }
+
+    fn visit_op(&mut self, op: Op) {
+        self.op(op);
+    }
+
+    /// Panics if `op` isn't a branch.
+    fn visit_branch(&mut self, op: Op, target: Label) {
+        let fixup = Fixup::branch(&op, target).expect("not a branch");
+        self.fixup(op, fixup);
+    }
+
+    fn visit_tableswitch(&mut self, low: i32, default: Label, targets: Vec<Label>) {
+        self.tableswitch(low, default, targets);
+    }
+
+    fn visit_lookupswitch(&mut self, default: Label, pairs: Vec<(i32, Label)>) {
+        self.lookupswitch(default, pairs);
+    }
+
+    fn visit_exception_handler(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<Symbol>,
+    ) {
+        self.exception_handlers.push(ExceptionHandler {
+            start,
+            end,
+            handler,
+            catch_type,
+        });
+    }
+
+    fn visit_line_number(&mut self, line: u16, start: Label) {
+        self.line_number(line, start);
+    }
+
+    fn visit_local_variable(
+        &mut self,
+        name: Symbol,
+        descriptor: FieldDescriptor,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) {
+        self.local_variable(name, descriptor, start, end, index);
+    }
+
+    fn visit_local_variable_type(
+        &mut self,
+        name: Symbol,
+        signature: FieldSignature,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) {
+        self.local_variable_type(name, signature, start, end, index);
+    }
+
+    fn visit_maxs(&mut self, max_stack: u16, max_locals: u16) {
+        self.max_stack(max_stack).max_locals(max_locals);
+    }
+
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        self.attribute(attribute);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{parse_field_descriptor, parse_method_descriptor};
+
+    fn println() -> Op {
+        Op::Invokevirtual(MethodRef {
+            is_interface: false,
+            class: "java/io/PrintStream".into(),
+            name: "println".into(),
+            descriptor: parse_method_descriptor("(Ljava/lang/String;)V").unwrap(),
+        })
+    }
+
+    fn system_out() -> Op {
+        Op::Getstatic(FieldRef {
+            class: "java/lang/System".into(),
+            name: "out".into(),
+            descriptor: parse_field_descriptor("Ljava/io/PrintStream;").unwrap(),
+        })
+    }
+
+    fn example_class() -> ClassBuilder {
+        let mut init = CodeBuilder::new();
+        init.max_stack(1)
+            .max_locals(1)
+            .op(Op::Aload0)
+            .op(Op::Invokespecial(MethodRef {
+                is_interface: false,
+                class: "java/lang/Object".into(),
+                name: "<init>".into(),
+                descriptor: parse_method_descriptor("()V").unwrap(),
+            }));
+        init.op(Op::Return);
+
+        // int sum(int n) { int sum = 0; while (n > 0) { sum += n; n--; } return sum; }
+        let mut sum = CodeBuilder::new();
+        let (head, end) = (sum.new_label(), sum.new_label());
+        sum.max_stack(2)
+            .max_locals(2)
+            .op(Op::Iconst0)
+            .op(Op::Istore1);
+        sum.place(head).op(Op::Iload0).branch(Op::Ifle, end);
+        sum.op(Op::Iload1)
+            .op(Op::Iload0)
+            .op(Op::Iadd)
+            .op(Op::Istore1);
+        sum.op(Op::Iinc {
+            index: 0,
+            constant: -1,
+        });
+        sum.branch(Op::Goto, head);
+        sum.place(end).op(Op::Iload1).op(Op::Ireturn);
+
+        // A switch, and a println that catches exceptions
+        let mut run = CodeBuilder::new();
+        let labels: Vec<_> = (0..5).map(|_| run.new_label()).collect();
+        let [one, other, start, end, handler] = labels[..] else {
+            unreachable!()
+        };
+        run.max_stack(2).max_locals(1).op(Op::Iload0);
+        run.tableswitch(1, other, vec![one, other, one]);
+        run.place(one)
+            .op(Op::Iload0)
+            .lookupswitch(other, vec![(10, one), (-5, other)]);
+        run.place(other).place(start).op(system_out());
+        run.op(Op::Ldc(LoadableConstant::String("hi".into())));
+        run.op(println()).place(end).op(Op::Return);
+        run.place(handler).op(Op::Astore0).op(Op::Return);
+        run.exception_handler(start, end, handler, Some("java/lang/Exception"));
+
+        ClassBuilder::new("com/example/Built")
+            .interface("java/lang/Runnable")
+            .field(FieldBuilder::new(
+                &[FieldAccess::Private, FieldAccess::Final],
+                "count",
+                parse_field_descriptor("I").unwrap(),
+            ))
+            .method(
+                MethodBuilder::new(
+                    &[MethodAccess::Public],
+                    "<init>",
+                    parse_method_descriptor("()V").unwrap(),
+                )
+                .code(init),
+            )
+            .method(
+                MethodBuilder::new(
+                    &[MethodAccess::Static],
+                    "sum",
+                    parse_method_descriptor("(I)I").unwrap(),
+                )
+                .code(sum),
+            )
+            .method(
+                MethodBuilder::new(
+                    &[MethodAccess::Static],
+                    "run",
+                    parse_method_descriptor("(I)V").unwrap(),
+                )
+                .code(run),
+            )
+            .method(MethodBuilder::new(
+                &[MethodAccess::Public, MethodAccess::Native],
+                "run",
+                parse_method_descriptor("()V").unwrap(),
+            ))
+            .attribute(Attribute::SourceFile("Built.java".into()))
+    }
+
+    #[test]
+    fn test_bad_tableswitch() {
+        let build = |low, targets| {
+            let mut code = CodeBuilder::new();
+            let label = code.new_label();
+            code.place(label).op(Op::Iconst0);
+            code.tableswitch(low, label, vec![label; targets]);
+            ClassBuilder::new("Foo")
+                .method(
+                    MethodBuilder::new(
+                        &[MethodAccess::Static],
+                        "run",
+                        parse_method_descriptor("()V").unwrap(),
+                    )
+                    .code(code),
+                )
+                .build()
+                .map(|_| ())
+        };
+
+        assert!(build(i32::MAX - 1, 2).is_ok());
+        assert!(matches!(
+            build(i32::MAX - 1, 3),
+            Err(ClassWriteError::BadTableswitch)
+        ));
+        assert!(matches!(build(0, 0), Err(ClassWriteError::BadTableswitch)));
+    }
+
+    #[test]
+    fn test_build() {
+        let class = example_class().build().unwrap();
+        let bytes = class.to_bytes().unwrap();
+        assert!(check(&bytes).unwrap().is_empty());
+
+        let parsed = ClassFile::parse_from_bytes(&bytes).unwrap();
+        assert_eq!(bytes, parsed.to_bytes().unwrap());
+        for (built, parsed) in [
+            (
+                format!("{:?}", class.constant_pool()),
+                format!("{:?}", parsed.constant_pool()),
+            ),
+            (
+                format!("{:?}", class.fields()),
+                format!("{:?}", parsed.fields()),
+            ),
+            (
+                format!("{:?}", class.methods()),
+                format!("{:?}", parsed.methods()),
+            ),
+            (
+                format!("{:?}", class.attributes()),
+                format!("{:?}", parsed.attributes()),
+            ),
+        ] {
+            assert_eq!(built, parsed);
+        }
+
+        let run = &parsed.methods()[2];
+        let code = run.code().unwrap().unwrap();
+        let Op::Tableswitch(switch) = &code.code[1] else {
+            panic!("{:?}", code.code[1]);
+        };
+        // The tableswitch starts at 1 and is 27 bytes long with its padding, and so is the
+        // lookupswitch after the iload_0 that follows it.
+        assert_eq!(
+            (1, 3, 55, vec![27, 55, 27]),
+            (
+                switch.low,
+                switch.high,
+                switch.default,
+                switch.offsets.clone()
+            )
+        );
+        let handler = &code.exception_table[0];
+        assert_eq!(
+            Ok(&"java/lang/Exception".into()),
+            parsed.constant_pool().get_class(handler.catch_type)
+        );
+    }
+
+    #[test]
+    fn test_constants_deduplicated() {
+        let mut code = CodeBuilder::new();
+        code.max_stack(2);
+        for _ in 0..2 {
+            code.op(system_out());
+            code.op(Op::Ldc(LoadableConstant::String("hi".into())));
+            code.op(println());
+        }
+        code.op(Op::Return);
+        let class = ClassBuilder::new("Twice")
+            .method(
+                MethodBuilder::new(
+                    &[MethodAccess::Static],
+                    "twice",
+                    parse_method_descriptor("()V").unwrap(),
+                )
+                .code(code),
+            )
+            .build()
+            .unwrap();
+
+        let cp = class.constant_pool();
+        let count = |f: fn(&Constant) -> bool| cp.iter().filter(|(_, c)| f(c)).count();
+        assert_eq!(1, count(|c| matches!(c, Constant::Fieldref(_))));
+        assert_eq!(1, count(|c| matches!(c, Constant::Methodref(_))));
+        assert_eq!(
+            1,
+            count(|c| matches!(c, Constant::Utf8(s) if s.as_str() == Some("hi")))
+        );
+    }
+
+    #[test]
+    fn test_build_with_frames() {
+        let class = example_class().build_with_frames(&Vec::new()).unwrap();
+        let bytes = class.to_bytes().unwrap();
+        assert!(check(&bytes).unwrap().is_empty());
+
+        let sum = class.methods()[1].code().unwrap().unwrap();
+        assert_eq!((2, 2), (sum.max_stack, sum.max_locals));
+        assert_eq!(
+            Some(
+                &[
+                    StackMapFrame::Append {
+                        offset_delta: 2,
+                        locals: vec![VerificationTypeInfo::Integer],
+                    },
+                    StackMapFrame::Same {
+                        offset_delta: 13,
+                        extended: false,
+                    },
+                ][..]
+            ),
+            sum.stack_map_table()
+        );
+
+        let run = class.methods()[2].code().unwrap().unwrap();
+        let frames = run.stack_map_table().unwrap();
+        assert_eq!(
+            &StackMapFrame::SameLocals1StackItem {
+                offset_delta: 8,
+                stack: VerificationTypeInfo::Object("java/lang/Exception".into()),
+                extended: false,
+            },
+            frames.last().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ldc_widened() {
+        // Each string takes up two constants, so only the first 127 fit below 256.
+        let mut code = CodeBuilder::new();
+        code.max_stack(1);
+        for i in 0..200 {
+            code.op(Op::Ldc(LoadableConstant::String(i.to_string().into())));
+            code.op(Op::Pop);
+        }
+        code.op(Op::Return);
+        let method = MethodBuilder::new(
+            &[MethodAccess::Static],
+            "strings",
+            parse_method_descriptor("()V").unwrap(),
+        );
+        let class = ClassBuilder::new("Strings")
+            .method(method.code(code))
+            .build()
+            .unwrap();
+        let bytes = class.to_bytes().unwrap();
+        assert!(check(&bytes).unwrap().is_empty());
+
+        let code = class.methods()[0].code().unwrap().unwrap();
+        let ldc_ws = code
+            .code
+            .iter()
+            .filter(|op| matches!(op, Op::LdcW(_)))
+            .count();
+        assert_eq!(73, ldc_ws);
+    }
+
+    #[test]
+    fn test_unplaced_label() {
+        let mut code = CodeBuilder::new();
+        let nowhere = code.new_label();
+        code.branch(Op::Goto, nowhere);
+        let method = MethodBuilder::new(&[], "m", parse_method_descriptor("()V").unwrap());
+        let result = ClassBuilder::new("Broken")
+            .method(method.code(code))
+            .build();
+        assert!(matches!(result, Err(ClassWriteError::UnplacedLabel)));
+    }
+}
@@ -0,0 +1,1047 @@
+//! The format checks of JVMS 4.8, which go beyond what's needed to parse a class file.
+//! <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.8>
+
+use crate::descriptor;
+use crate::parser::Parser;
+use crate::*;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Parses the class file in `raw` and checks it against the format rules of JVMS 4.8: constant
+/// pool entries refer to constants of the right kinds, names and descriptors are well-formed,
+/// access flags are legal for where they appear, attributes are allowed where they appear and
+/// in the class file's version, and nothing comes after the end of the class file.
+///
+/// Returns an error if the class file can't be parsed at all, and otherwise every violation that
+/// was found.
+pub fn check(raw: &[u8]) -> Result<Vec<Violation>, ClassParseError> {
+    let mut parser = Parser::new(raw);
+    let class = parser.run()?;
+
+    let mut checker = Checker {
+        class: &class,
+        violations: Vec::new(),
+    };
+    checker.check_constant_pool();
+    checker.check_class();
+    for (i, field) in class.fields().iter().enumerate() {
+        checker.check_field(i, field);
+    }
+    for (i, method) in class.methods().iter().enumerate() {
+        checker.check_method(i, method);
+    }
+    checker.check_duplicate_members();
+
+    // The parser only warns about malformed signatures, keeping their attributes as unknown ones.
+    for warning in class.warnings() {
+        if let ClassParseErrorKind::BadSignature(signature) = &warning.kind {
+            checker.report(
+                Location::Offset(warning.offset),
+                ViolationKind::BadSignature(signature.clone()),
+            );
+        }
+    }
+
+    let trailing = parser.remaining();
+    if trailing != 0 {
+        checker.report(
+            Location::Offset(raw.len() - trailing),
+            ViolationKind::TrailingData(trailing),
+        );
+    }
+
+    Ok(checker.violations)
+}
+
+/// A broken format rule, found by [`check`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub location: Location,
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (in {})", self.kind, self.location)
+    }
+}
+
+/// The part of a class file that a [`Violation`] is in. Fields, methods and record components
+/// are identified by their index in the class file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// The class itself: its flags, names, superclass, interfaces and attributes.
+    Class,
+    Constant(u16),
+    Field(usize),
+    Method(usize),
+    /// The Code attribute of a method.
+    Code(usize),
+    RecordComponent(usize),
+    /// A byte offset into the class file.
+    Offset(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Class => f.write_str("class"),
+            Location::Constant(index) => write!(f, "constant {index}"),
+            Location::Field(index) => write!(f, "field {index}"),
+            Location::Method(index) => write!(f, "method {index}"),
+            Location::Code(index) => write!(f, "code of method {index}"),
+            Location::RecordComponent(index) => write!(f, "record component {index}"),
+            Location::Offset(offset) => write!(f, "offset {offset:#x}"),
+        }
+    }
+}
+
+/// What rule a [`Violation`] breaks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    /// A constant can't be resolved, e.g. because it refers to a constant of the wrong kind.
+    Constant(ClassParseErrorKind),
+    /// A class or interface name isn't a binary name in internal form, or an array descriptor
+    /// where one is allowed.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.1>
+    BadClassName(String),
+    /// A field name, or another name with the same rules, isn't a legal unqualified name.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.2>
+    BadUnqualifiedName(String),
+    /// A method name isn't a legal unqualified name, `<init>` or `<clinit>`.
+    BadMethodName(String),
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.3>
+    BadModuleName(String),
+    BadPackageName(String),
+    /// A descriptor is well-formed but not allowed where it's used, e.g. a non-void `<init>`.
+    BadDescriptor(String),
+    /// A Signature or LocalVariableTypeTable attribute has a malformed signature.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+    BadSignature(String),
+    /// An illegal combination of access flags.
+    IllegalFlags(&'static str),
+    /// Some other structural rule is broken.
+    IllegalStructure(&'static str),
+    /// A field or method with the same name and descriptor as an earlier one.
+    DuplicateMember {
+        name: Symbol,
+        descriptor: String,
+    },
+    /// A predefined attribute where it isn't allowed.
+    MisplacedAttribute(String),
+    /// More than one of an attribute that's allowed at most once.
+    DuplicateAttribute(String),
+    /// Something that needs a newer class file version than this one.
+    RequiresVersion {
+        /// What needs the newer version, e.g. `"MethodHandle constant"`.
+        feature: String,
+        major_version: u16,
+    },
+    /// There are bytes after the end of the class file. Holds how many.
+    TrailingData(usize),
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ViolationKind::*;
+
+        match self {
+            Constant(kind) => kind.fmt(f),
+            BadClassName(name) => write!(f, "bad class name {name:?}"),
+            BadUnqualifiedName(name) => write!(f, "bad name {name:?}"),
+            BadMethodName(name) => write!(f, "bad method name {name:?}"),
+            BadModuleName(name) => write!(f, "bad module name {name:?}"),
+            BadPackageName(name) => write!(f, "bad package name {name:?}"),
+            BadDescriptor(descriptor) => write!(f, "descriptor {descriptor:?} isn't allowed here"),
+            BadSignature(signature) => write!(f, "bad signature {signature:?}"),
+            IllegalFlags(rule) | IllegalStructure(rule) => f.write_str(rule),
+            DuplicateMember { name, descriptor } => {
+                write!(f, "duplicate member {name} {descriptor}")
+            }
+            MisplacedAttribute(name) => write!(f, "{name} attribute isn't allowed here"),
+            DuplicateAttribute(name) => write!(f, "more than one {name} attribute"),
+            RequiresVersion {
+                feature,
+                major_version,
+            } => write!(f, "{feature} requires class file version {major_version}"),
+            TrailingData(len) => write!(f, "{len} bytes after the end of the class file"),
+        }
+    }
+}
+
+/// Where an attribute is attached.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Context {
+    Class,
+    Field,
+    Method,
+    Code,
+    RecordComponent,
+}
+
+/// The predefined attributes, and where they may appear. The versions that define them are in
+/// [`version::attribute_major_version`].
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7-320>
+const PREDEFINED_ATTRIBUTES: &[(&str, &[Context])] = {
+    use Context::*;
+
+    &[
+        ("ConstantValue", &[Field]),
+        ("Code", &[Method]),
+        ("StackMapTable", &[Code]),
+        ("Exceptions", &[Method]),
+        ("InnerClasses", &[Class]),
+        ("EnclosingMethod", &[Class]),
+        ("Synthetic", &[Class, Field, Method]),
+        ("Signature", &[Class, Field, Method, RecordComponent]),
+        ("SourceFile", &[Class]),
+        ("SourceDebugExtension", &[Class]),
+        ("LineNumberTable", &[Code]),
+        ("LocalVariableTable", &[Code]),
+        ("LocalVariableTypeTable", &[Code]),
+        ("Deprecated", &[Class, Field, Method]),
+        (
+            "RuntimeVisibleAnnotations",
+            &[Class, Field, Method, RecordComponent],
+        ),
+        (
+            "RuntimeInvisibleAnnotations",
+            &[Class, Field, Method, RecordComponent],
+        ),
+        ("RuntimeVisibleParameterAnnotations", &[Method]),
+        ("RuntimeInvisibleParameterAnnotations", &[Method]),
+        (
+            "RuntimeVisibleTypeAnnotations",
+            &[Class, Field, Method, Code, RecordComponent],
+        ),
+        (
+            "RuntimeInvisibleTypeAnnotations",
+            &[Class, Field, Method, Code, RecordComponent],
+        ),
+        ("AnnotationDefault", &[Method]),
+        ("BootstrapMethods", &[Class]),
+        ("MethodParameters", &[Method]),
+        ("Module", &[Class]),
+        ("ModulePackages", &[Class]),
+        ("ModuleMainClass", &[Class]),
+        ("NestHost", &[Class]),
+        ("NestMembers", &[Class]),
+        ("Record", &[Class]),
+        ("PermittedSubclasses", &[Class]),
+    ]
+};
+
+/// Predefined attributes that may appear more than once in the same place.
+const REPEATABLE_ATTRIBUTES: &[&str] = &[
+    "LineNumberTable",
+    "LocalVariableTable",
+    "LocalVariableTypeTable",
+];
+
+struct Checker<'a> {
+    class: &'a ClassFile,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, location: Location, kind: ViolationKind) {
+        self.violations.push(Violation { location, kind });
+    }
+
+    fn major_version(&self) -> u16 {
+        self.class.version().0
+    }
+
+    fn is_interface(&self) -> bool {
+        self.class.access_flags().has(ClassAccess::Interface)
+    }
+
+    fn require_version(&mut self, location: Location, feature: String, major_version: u16) {
+        if self.major_version() < major_version {
+            self.report(
+                location,
+                ViolationKind::RequiresVersion {
+                    feature,
+                    major_version,
+                },
+            );
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4>
+    ///
+    /// The parser already rejects constants that aren't allowed in the class file's version.
+    fn check_constant_pool(&mut self) {
+        let cp = self.class.constant_pool();
+
+        for (index, constant) in cp.iter() {
+            let location = Location::Constant(index);
+            if let Err(kind) = self.check_constant(location, index, constant) {
+                self.report(location, ViolationKind::Constant(kind));
+            }
+        }
+    }
+
+    fn check_constant(
+        &mut self,
+        location: Location,
+        index: u16,
+        constant: &Constant,
+    ) -> Result<(), ClassParseErrorKind> {
+        let cp = self.class.constant_pool();
+
+        match constant {
+            Constant::Class(_) => {
+                let name = cp.get_class(index)?;
+                if !is_class_name_or_array(name) {
+                    self.report(location, ViolationKind::BadClassName(name.to_string()));
+                }
+            }
+            Constant::Fieldref(_) => {
+                let field = cp.get_fieldref(index)?;
+                self.check_unqualified_name(location, &field.name);
+                self.check_field_descriptor(location, &field.descriptor);
+            }
+            Constant::Methodref(_) | Constant::InterfaceMethodref(_) => {
+                let method = cp.get_methodref(index)?;
+                self.check_method_ref(location, &method);
+                if method.name == "<clinit>" {
+                    self.report(
+                        location,
+                        ViolationKind::BadMethodName(method.name.to_string()),
+                    );
+                }
+            }
+            Constant::NameAndType(_) => {
+                cp.get_name_and_type(index)?;
+            }
+            Constant::ConstantValue(ConstantValueKind::String(_)) => {
+                cp.get_string(index)?;
+            }
+            Constant::MethodHandle(_) => {
+                let handle = cp.get_method_handle(index)?;
+                self.check_method_handle(location, &handle);
+            }
+            Constant::MethodType(_) => {
+                let descriptor = cp.get_method_type(index)?;
+                self.check_method_descriptor(location, &descriptor, false);
+            }
+            Constant::Dynamic(_) => {
+                let dynamic = cp.get_dynamic(index)?;
+                self.check_unqualified_name(location, &dynamic.name);
+                self.check_field_descriptor(location, &dynamic.descriptor);
+            }
+            Constant::InvokeDynamic(_) => {
+                let dynamic = cp.get_invoke_dynamic(index)?;
+                self.check_unqualified_name(location, &dynamic.name);
+                self.check_method_descriptor(location, &dynamic.descriptor, false);
+            }
+            Constant::Module(_) => {
+                let name = cp.get_module(index)?;
+                if !is_module_name(name) {
+                    self.report(location, ViolationKind::BadModuleName(name.to_string()));
+                }
+            }
+            Constant::Package(_) => {
+                let name = cp.get_package(index)?;
+                if !is_binary_name(name) {
+                    self.report(location, ViolationKind::BadPackageName(name.to_string()));
+                }
+            }
+            Constant::Utf8(_) | Constant::ConstantValue(_) | Constant::Unusable => {}
+        }
+
+        Ok(())
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.2>
+    fn check_method_ref(&mut self, location: Location, method: &MethodRef) {
+        self.check_method_name(location, &method.name);
+        // Only constructors are known to be instance methods, since a reference doesn't say
+        // whether the method is static.
+        self.check_method_descriptor(location, &method.descriptor, method.name == "<init>");
+        if method.name == "<init>" && method.descriptor.return_descriptor != ReturnDescriptor::Void
+        {
+            self.report(
+                location,
+                ViolationKind::BadDescriptor(method.descriptor.to_string()),
+            );
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.8>
+    fn check_method_handle(&mut self, location: Location, handle: &MethodHandle) {
+        let MethodHandleRef::Method(method) = &handle.reference else {
+            return;
+        };
+
+        let is_init = method.name == "<init>";
+        let (legal, rule) = match handle.kind {
+            ReferenceKind::NewInvokeSpecial => {
+                (is_init, "a newInvokeSpecial handle must refer to <init>")
+            }
+            _ => (
+                !is_init && method.name != "<clinit>",
+                "only newInvokeSpecial handles may refer to <init>",
+            ),
+        };
+        if !legal {
+            self.report(location, ViolationKind::IllegalStructure(rule));
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1>
+    fn check_class(&mut self) {
+        let class = self.class;
+        let flags = class.access_flags();
+
+        if !is_binary_name(class.this_class()) {
+            self.report(
+                Location::Class,
+                ViolationKind::BadClassName(class.this_class().to_string()),
+            );
+        }
+        for interface in class.interfaces() {
+            if !is_binary_name(interface) {
+                self.report(
+                    Location::Class,
+                    ViolationKind::BadClassName(interface.to_string()),
+                );
+            }
+        }
+
+        if flags.has(ClassAccess::Module) {
+            self.check_module_class();
+        } else {
+            if flags.has(ClassAccess::Interface) {
+                if !flags.has(ClassAccess::Abstract) {
+                    self.report(
+                        Location::Class,
+                        ViolationKind::IllegalFlags("an interface must be abstract"),
+                    );
+                }
+                if flags.has(ClassAccess::Final)
+                    || flags.has(ClassAccess::Super)
+                    || flags.has(ClassAccess::Enum)
+                {
+                    self.report(
+                        Location::Class,
+                        ViolationKind::IllegalFlags(
+                            "an interface can't be final, super or an enum",
+                        ),
+                    );
+                }
+                if class.super_class().map(Symbol::as_str) != Some("java/lang/Object") {
+                    self.report(
+                        Location::Class,
+                        ViolationKind::IllegalStructure(
+                            "the superclass of an interface must be java/lang/Object",
+                        ),
+                    );
+                }
+            } else {
+                if flags.has(ClassAccess::Annotation) {
+                    self.report(
+                        Location::Class,
+                        ViolationKind::IllegalFlags("an annotation interface must be an interface"),
+                    );
+                }
+                if flags.has(ClassAccess::Final) && flags.has(ClassAccess::Abstract) {
+                    self.report(
+                        Location::Class,
+                        ViolationKind::IllegalFlags("a class can't be both final and abstract"),
+                    );
+                }
+            }
+
+            match class.super_class() {
+                Some(super_class) if !is_binary_name(super_class) => self.report(
+                    Location::Class,
+                    ViolationKind::BadClassName(super_class.to_string()),
+                ),
+                None if class.this_class() != "java/lang/Object" => self.report(
+                    Location::Class,
+                    ViolationKind::IllegalStructure("only java/lang/Object has no superclass"),
+                ),
+                _ => {}
+            }
+        }
+
+        self.check_attributes(Location::Class, Context::Class, class.attributes());
+        if let Some(components) = class.record_components() {
+            for (i, component) in components.iter().enumerate() {
+                let location = Location::RecordComponent(i);
+                self.check_unqualified_name(location, &component.name);
+                self.check_field_descriptor(location, &component.descriptor);
+                self.check_attributes(location, Context::RecordComponent, &component.attributes);
+            }
+        }
+    }
+
+    /// A class with ACC_MODULE set is a module-info.class, which has nothing but a header and
+    /// attributes.
+    fn check_module_class(&mut self) {
+        let class = self.class;
+
+        self.require_version(Location::Class, "ACC_MODULE".to_owned(), 53);
+        if class.access_flags() != ClassAccessFlags::new(ClassAccess::Module as u16) {
+            self.report(
+                Location::Class,
+                ViolationKind::IllegalFlags("a module can't have flags other than ACC_MODULE"),
+            );
+        }
+        if class.this_class() != "module-info" {
+            self.report(
+                Location::Class,
+                ViolationKind::IllegalStructure("a module must be named module-info"),
+            );
+        }
+        if class.super_class().is_some()
+            || !class.interfaces().is_empty()
+            || !class.fields().is_empty()
+            || !class.methods().is_empty()
+        {
+            self.report(
+                Location::Class,
+                ViolationKind::IllegalStructure(
+                    "a module can't have a superclass, interfaces, fields or methods",
+                ),
+            );
+        }
+        if class.module_descriptor().is_none() {
+            self.report(
+                Location::Class,
+                ViolationKind::IllegalStructure("a module must have a Module attribute"),
+            );
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.5>
+    fn check_field(&mut self, index: usize, field: &FieldInfo) {
+        let location = Location::Field(index);
+        let flags = field.access_flags;
+
+        self.check_unqualified_name(location, &field.name);
+        self.check_field_descriptor(location, &field.descriptor);
+
+        if self.is_interface() {
+            let required = [FieldAccess::Public, FieldAccess::Static, FieldAccess::Final];
+            let forbidden = [
+                FieldAccess::Private,
+                FieldAccess::Protected,
+                FieldAccess::Volatile,
+                FieldAccess::Transient,
+                FieldAccess::Enum,
+            ];
+            if !required.into_iter().all(|flag| flags.has(flag))
+                || forbidden.into_iter().any(|flag| flags.has(flag))
+            {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags(
+                        "a field of an interface must be public, static and final",
+                    ),
+                );
+            }
+        } else {
+            let visibility = [
+                FieldAccess::Public,
+                FieldAccess::Private,
+                FieldAccess::Protected,
+            ];
+            if visibility
+                .into_iter()
+                .filter(|&flag| flags.has(flag))
+                .count()
+                > 1
+            {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags(
+                        "a field can only be one of public, private or protected",
+                    ),
+                );
+            }
+            if flags.has(FieldAccess::Final) && flags.has(FieldAccess::Volatile) {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags("a field can't be both final and volatile"),
+                );
+            }
+        }
+
+        self.check_attributes(location, Context::Field, &field.attributes);
+        let constant_value = field.attributes.iter().find_map(|attr| match attr {
+            Attribute::ConstantValue(value) => Some(value),
+            _ => None,
+        });
+        if let Some(value) = constant_value {
+            if !constant_value_matches(value, &field.descriptor) {
+                self.report(
+                    location,
+                    ViolationKind::IllegalStructure(
+                        "the type of a ConstantValue doesn't match its field",
+                    ),
+                );
+            }
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.6>
+    fn check_method(&mut self, index: usize, method: &MethodInfo) {
+        use MethodAccess::*;
+
+        let location = Location::Method(index);
+        let flags = method.access_flags;
+        let is_init = method.name == "<init>";
+        let is_clinit = method.name == "<clinit>";
+
+        self.check_method_name(location, &method.name);
+        self.check_method_descriptor(location, &method.descriptor, !flags.has(Static));
+
+        if is_clinit {
+            // Other flags of class initializers are ignored.
+            if self.major_version() >= 51 && !flags.has(Static) {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags("a class initializer must be static"),
+                );
+            }
+            if !method.descriptor.parameters.is_empty()
+                || method.descriptor.return_descriptor != ReturnDescriptor::Void
+            {
+                self.report(
+                    location,
+                    ViolationKind::BadDescriptor(method.descriptor.to_string()),
+                );
+            }
+        } else {
+            if [Public, Private, Protected]
+                .into_iter()
+                .filter(|&flag| flags.has(flag))
+                .count()
+                > 1
+            {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags(
+                        "a method can only be one of public, private or protected",
+                    ),
+                );
+            }
+
+            if self.is_interface() {
+                if is_init {
+                    self.report(
+                        location,
+                        ViolationKind::IllegalStructure("an interface can't have constructors"),
+                    );
+                }
+                if self.major_version() < 52 {
+                    if !flags.has(Public) || !flags.has(Abstract) {
+                        self.report(
+                            location,
+                            ViolationKind::IllegalFlags(
+                                "before version 52, interface methods must be public and abstract",
+                            ),
+                        );
+                    }
+                } else if [Protected, Final, Synchronized, Native]
+                    .into_iter()
+                    .any(|flag| flags.has(flag))
+                    || flags.has(Public) == flags.has(Private)
+                {
+                    self.report(
+                        location,
+                        ViolationKind::IllegalFlags("illegal flags for an interface method"),
+                    );
+                }
+            }
+
+            let strict_is_abstract = (46..=60).contains(&self.major_version());
+            if flags.has(Abstract)
+                && ([Private, Static, Final, Synchronized, Native]
+                    .into_iter()
+                    .any(|flag| flags.has(flag))
+                    || (strict_is_abstract && flags.has(Strict)))
+            {
+                self.report(
+                    location,
+                    ViolationKind::IllegalFlags("illegal flags for an abstract method"),
+                );
+            }
+
+            if is_init {
+                if [Static, Final, Synchronized, Bridge, Native, Abstract]
+                    .into_iter()
+                    .any(|flag| flags.has(flag))
+                {
+                    self.report(
+                        location,
+                        ViolationKind::IllegalFlags("illegal flags for a constructor"),
+                    );
+                }
+                if method.descriptor.return_descriptor != ReturnDescriptor::Void {
+                    self.report(
+                        location,
+                        ViolationKind::BadDescriptor(method.descriptor.to_string()),
+                    );
+                }
+            }
+        }
+
+        let has_code = method.has_code();
+        if has_code && (flags.has(Abstract) || flags.has(Native)) {
+            self.report(
+                location,
+                ViolationKind::IllegalStructure("abstract and native methods can't have code"),
+            );
+        } else if !has_code && !flags.has(Abstract) && !flags.has(Native) {
+            self.report(
+                location,
+                ViolationKind::IllegalStructure("methods that aren't abstract or native need code"),
+            );
+        }
+
+        self.check_attributes(location, Context::Method, method.attributes());
+        // The class was parsed eagerly, so its code has already been decoded.
+        if let Ok(Some(code)) = method.code() {
+            self.check_attributes(Location::Code(index), Context::Code, &code.attributes);
+        }
+    }
+
+    /// No two fields, and no two methods, may have the same name and descriptor.
+    fn check_duplicate_members(&mut self) {
+        let mut fields = HashSet::new();
+        for (i, field) in self.class.fields().iter().enumerate() {
+            if !fields.insert((&field.name, field.descriptor.to_string())) {
+                self.report(
+                    Location::Field(i),
+                    ViolationKind::DuplicateMember {
+                        name: field.name.clone(),
+                        descriptor: field.descriptor.to_string(),
+                    },
+                );
+            }
+        }
+
+        let mut methods = HashSet::new();
+        for (i, method) in self.class.methods().iter().enumerate() {
+            if !methods.insert((&method.name, method.descriptor.to_string())) {
+                self.report(
+                    Location::Method(i),
+                    ViolationKind::DuplicateMember {
+                        name: method.name.clone(),
+                        descriptor: method.descriptor.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Checks that the predefined attributes in `attributes` are allowed in `context` and this
+    /// class file's version, and that there's at most one of each. Unknown attributes are
+    /// always allowed.
+    fn check_attributes(&mut self, location: Location, context: Context, attributes: &[Attribute]) {
+        let mut seen = HashSet::new();
+
+        for attribute in attributes {
+            let name = attribute.name();
+            let Some(&(_, contexts)) = PREDEFINED_ATTRIBUTES
+                .iter()
+                .find(|(predefined, _)| *predefined == name)
+            else {
+                continue;
+            };
+
+            if !contexts.contains(&context) {
+                self.report(location, ViolationKind::MisplacedAttribute(name.to_owned()));
+                continue;
+            }
+            if let Some(major_version) = version::attribute_major_version(name) {
+                self.require_version(location, format!("{name} attribute"), major_version);
+            }
+
+            // A method's Code attribute isn't in its attribute list, so a Code attribute in a
+            // method's attributes is always a second one.
+            let duplicate = !seen.insert(name) || (context == Context::Method && name == "Code");
+            if duplicate && !REPEATABLE_ATTRIBUTES.contains(&name) {
+                self.report(location, ViolationKind::DuplicateAttribute(name.to_owned()));
+            }
+        }
+    }
+
+    fn check_unqualified_name(&mut self, location: Location, name: &Symbol) {
+        if !is_unqualified_name(name) {
+            self.report(
+                location,
+                ViolationKind::BadUnqualifiedName(name.to_string()),
+            );
+        }
+    }
+
+    fn check_method_name(&mut self, location: Location, name: &Symbol) {
+        let legal = matches!(name.as_str(), "<init>" | "<clinit>")
+            || (is_unqualified_name(name) && !name.contains(['<', '>']));
+        if !legal {
+            self.report(location, ViolationKind::BadMethodName(name.to_string()));
+        }
+    }
+
+    fn check_field_descriptor(&mut self, location: Location, descriptor: &FieldDescriptor) {
+        let (FieldDescriptor::Basic(BasicFieldType::ClassInstance(name))
+        | FieldDescriptor::Arr(_, BasicFieldType::ClassInstance(name))) = descriptor
+        else {
+            return;
+        };
+        if !is_binary_name(name) {
+            self.report(location, ViolationKind::BadClassName(name.to_string()));
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.3>
+    ///
+    /// `is_instance` is whether the descriptor is of an instance method, which takes `this` as
+    /// an extra parameter.
+    fn check_method_descriptor(
+        &mut self,
+        location: Location,
+        descriptor: &MethodDescriptor,
+        is_instance: bool,
+    ) {
+        for parameter in &descriptor.parameters {
+            self.check_field_descriptor(location, parameter);
+        }
+        if let ReturnDescriptor::NonVoid(return_type) = &descriptor.return_descriptor {
+            self.check_field_descriptor(location, return_type);
+        }
+
+        let slots: usize = usize::from(is_instance)
+            + descriptor
+                .parameters
+                .iter()
+                .map(|parameter| match parameter {
+                    FieldDescriptor::Basic(BasicFieldType::Long | BasicFieldType::Double) => 2,
+                    _ => 1,
+                })
+                .sum::<usize>();
+        if slots > 255 {
+            self.report(
+                location,
+                ViolationKind::IllegalStructure(
+                    "a method can't take more than 255 slots of parameters",
+                ),
+            );
+        }
+    }
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.2>
+fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+/// A binary name in internal form, e.g. `java/lang/Object`.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.1>
+fn is_binary_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+/// What a CONSTANT_Class can name: a class or interface, or an array type.
+fn is_class_name_or_array(name: &str) -> bool {
+    if name.starts_with('[') {
+        descriptor::parse_field_descriptor(name).is_ok_and(|descriptor| match descriptor {
+            FieldDescriptor::Arr(_, BasicFieldType::ClassInstance(class)) => is_binary_name(&class),
+            _ => true,
+        })
+    } else {
+        is_binary_name(name)
+    }
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.2.3>
+fn is_module_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{0}'..='\u{1F}' | ':' | '@' => return false,
+            '\\' if !matches!(chars.next(), Some('\\' | ':' | '@')) => return false,
+            _ => {}
+        }
+    }
+    !name.is_empty()
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.2-300-C.1>
+fn constant_value_matches(value: &ConstantValueAttribute, descriptor: &FieldDescriptor) -> bool {
+    use BasicFieldType::*;
+
+    let FieldDescriptor::Basic(field_type) = descriptor else {
+        return false;
+    };
+    match value {
+        ConstantValueAttribute::Integer(_) => {
+            matches!(field_type, Int | Short | Char | Byte | Boolean)
+        }
+        ConstantValueAttribute::Float(_) => *field_type == Float,
+        ConstantValueAttribute::Long(_) => *field_type == Long,
+        ConstantValueAttribute::Double(_) => *field_type == Double,
+        ConstantValueAttribute::String(_) => {
+            matches!(field_type, ClassInstance(class) if class == "java/lang/String")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::utf8;
+
+    #[test]
+    fn test_fixtures_are_valid() {
+        for raw in [
+            &include_bytes!("../test_data/Annotated.class")[..],
+            include_bytes!("../test_data/Branches.class"),
+            include_bytes!("../test_data/Generics.class"),
+            include_bytes!("../test_data/Info.class"),
+            include_bytes!("../test_data/Lambdas.class"),
+            include_bytes!("../test_data/Locals.class"),
+            include_bytes!("../test_data/Nested$1.class"),
+            include_bytes!("../test_data/Nested.class"),
+            include_bytes!("../test_data/Shape$Polygon.class"),
+            include_bytes!("../test_data/Shape.class"),
+            include_bytes!("../test_data/Simple.class"),
+            include_bytes!("../test_data/Strings.class"),
+            include_bytes!("../test_data/Wide.class"),
+            include_bytes!("../test_data/module-info.class"),
+        ] {
+            assert_eq!(Ok(vec![]), check(raw));
+        }
+    }
+
+    #[test]
+    fn test_parameter_slots() {
+        let descriptor = format!("({})V", "I".repeat(255));
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 9],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("a"),                // #5
+            utf8(&descriptor),        // #6
+            vec![16, 0, 6],           // #7 MethodType
+            utf8("b"),                // #8
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+            // `static native void a(int...)` and `native void b(int...)`, each with 255 ints
+            vec![0, 2],
+            vec![0x01, 0x08, 0, 5, 0, 6, 0, 0],
+            vec![0x01, 0x00, 0, 8, 0, 6, 0, 0],
+            vec![0, 0],
+        ]
+        .concat();
+
+        // Only `b` goes over the limit, with `this`.
+        assert_eq!(
+            Ok(vec![Violation {
+                location: Location::Method(1),
+                kind: ViolationKind::IllegalStructure(
+                    "a method can't take more than 255 slots of parameters"
+                ),
+            }]),
+            check(&raw)
+        );
+    }
+
+    #[test]
+    fn test_violations() {
+        let raw = [
+            // Version 50
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 50, 0, 14],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("a.b"),              // #5
+            utf8("()V"),              // #6
+            vec![12, 0, 5, 0, 6],     // #7 NameAndType
+            vec![10, 0, 4, 0, 7],     // #8 Methodref
+            utf8("BootstrapMethods"), // #9
+            utf8("x"),                // #10
+            utf8("SourceFile"),       // #11
+            utf8("I"),                // #12
+            utf8("Signature"),        // #13
+            // A final abstract class
+            vec![0x04, 0x11, 0, 2, 0, 4, 0, 0],
+            // Two `private public int x` fields, with a SourceFile attribute
+            vec![0, 2],
+            vec![0, 3, 0, 10, 0, 12, 0, 1, 0, 11, 0, 0, 0, 2, 0, 10],
+            vec![0, 3, 0, 10, 0, 12, 0, 0],
+            // An abstract method `()V` with a bad name, and a Signature attribute that isn't a
+            // method signature
+            vec![0, 1, 0x04, 0x01, 0, 5, 0, 6, 0, 1, 0, 13, 0, 0, 0, 2, 0, 12],
+            // An empty BootstrapMethods attribute, which is too new for version 50
+            vec![0, 1, 0, 9, 0, 0, 0, 2, 0, 0],
+            // Trailing data
+            vec![0xCA, 0xFE],
+        ]
+        .concat();
+
+        let violation = |location, kind| Violation { location, kind };
+        assert_eq!(
+            Ok(vec![
+                violation(
+                    Location::Constant(8),
+                    ViolationKind::BadMethodName("a.b".to_owned())
+                ),
+                violation(
+                    Location::Class,
+                    ViolationKind::IllegalFlags("a class can't be both final and abstract")
+                ),
+                violation(
+                    Location::Class,
+                    ViolationKind::RequiresVersion {
+                        feature: "BootstrapMethods attribute".to_owned(),
+                        major_version: 51
+                    }
+                ),
+                violation(
+                    Location::Field(0),
+                    ViolationKind::IllegalFlags(
+                        "a field can only be one of public, private or protected"
+                    )
+                ),
+                violation(
+                    Location::Field(0),
+                    ViolationKind::MisplacedAttribute("SourceFile".to_owned())
+                ),
+                violation(
+                    Location::Field(1),
+                    ViolationKind::IllegalFlags(
+                        "a field can only be one of public, private or protected"
+                    )
+                ),
+                violation(
+                    Location::Method(0),
+                    ViolationKind::BadMethodName("a.b".to_owned())
+                ),
+                violation(
+                    Location::Field(1),
+                    ViolationKind::DuplicateMember {
+                        name: "x".into(),
+                        descriptor: "I".to_owned()
+                    }
+                ),
+                violation(
+                    Location::Offset(raw.len() - 14),
+                    ViolationKind::BadSignature("I".to_owned())
+                ),
+                violation(
+                    Location::Offset(raw.len() - 2),
+                    ViolationKind::TrailingData(2)
+                ),
+            ]),
+            check(&raw)
+        );
+    }
+}
@@ -1,97 +1,404 @@
 use crate::descriptor;
-use crate::{FieldRef, MethodRef};
+use crate::{
+    BasicFieldType, ClassParseErrorKind, DynamicRef, FieldDescriptor, FieldRef, InvokeDynamicRef,
+    JavaString, LoadableConstant, MethodDescriptor, MethodHandle, MethodHandleRef, MethodRef,
+    NameAndType, ReferenceKind, Symbol,
+};
 
+/// The constant pool of a class file.
+///
+/// The typed `get_*` lookups resolve any indices inside the constant (e.g. the name of a
+/// `Class`), and return an error if an index is out of bounds or points at the wrong kind of
+/// constant.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4>
 #[derive(Debug)]
-pub(crate) struct ConstantPool {
+pub struct ConstantPool {
     storage: Vec<Constant>,
+    /// The major version of the class file, which decides what some constants may refer to.
+    major_version: u16,
 }
 
 impl ConstantPool {
-    pub fn new(storage: Vec<Constant>) -> Self {
-        Self { storage }
+    pub(crate) fn new(storage: Vec<Constant>, major_version: u16) -> Self {
+        Self {
+            storage,
+            major_version,
+        }
+    }
+
+    pub(crate) fn major_version(&self) -> u16 {
+        self.major_version
+    }
+
+    /// The number of entries, including the unusable ones after Long and Double constants.
+    /// The `constant_pool_count` in the class file is one more than this.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
     }
 
-    /// Returns the constant at the (one-based) index `idx`, or `None` if out of bounds.
-    /// The indices are one-based: `get(0)` returns None.
-    pub fn get(&self, idx: u16) -> Option<&Constant> {
-        if idx == 0 {
-            None
-        } else {
-            self.storage.get((idx - 1) as usize)
+    /// Iterates over the constants along with their indices, skipping the unusable entries
+    /// after Long and Double constants.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Constant)> + '_ {
+        self.storage
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !matches!(c, Constant::Unusable))
+            .map(|(i, c)| (i as u16 + 1, c))
+    }
+
+    /// Returns the constant at the (one-based) index `idx`.
+    /// The indices are one-based: `get(0)` is an error. It is also an error to get the
+    /// unusable entry after a Long or Double constant.
+    pub fn get(&self, idx: u16) -> Result<&Constant, ClassParseErrorKind> {
+        match idx
+            .checked_sub(1)
+            .and_then(|i| self.storage.get(i as usize))
+        {
+            None | Some(Constant::Unusable) => Err(ClassParseErrorKind::BadConstantIndex(idx)),
+            Some(constant) => Ok(constant),
         }
     }
 
-    pub(crate) fn get_class(&self, index: u16) -> &str {
-        let Constant::Class(ClassConstant { name_index }) = self.get(index).unwrap() else {
-            panic!("expected classconst");
+    /// Gets the name of the Class constant at `index`.
+    pub fn get_class(&self, index: u16) -> Result<&Symbol, ClassParseErrorKind> {
+        let Constant::Class(ClassConstant { name_index }) = self.get(index)? else {
+            return Err(wrong_kind(index, "Class"));
         };
 
         self.get_utf8(*name_index)
     }
 
-    pub(crate) fn get_utf8(&self, index: u16) -> &str {
-        let Constant::Utf8(s) = self.get(index).unwrap() else {
-            panic!("expected utf8");
+    /// Gets a Utf8 constant that's used as a name or descriptor, which has to be valid Unicode.
+    /// Use [`Self::get_java_string`] for arbitrary strings.
+    pub fn get_utf8(&self, index: u16) -> Result<&Symbol, ClassParseErrorKind> {
+        self.get_java_string(index)?
+            .as_symbol()
+            .ok_or(ClassParseErrorKind::NonUnicodeUtf8(index))
+    }
+
+    /// Gets a Utf8 constant, which may contain unpaired surrogates.
+    pub fn get_java_string(&self, index: u16) -> Result<&JavaString, ClassParseErrorKind> {
+        let Constant::Utf8(s) = self.get(index)? else {
+            return Err(wrong_kind(index, "Utf8"));
         };
-        s
+        Ok(s)
+    }
+
+    /// Gets the String constant at `index`.
+    pub fn get_string(&self, index: u16) -> Result<&JavaString, ClassParseErrorKind> {
+        let Constant::ConstantValue(ConstantValueKind::String(string_index)) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "String"));
+        };
+
+        self.get_java_string(*string_index)
+    }
+
+    pub fn get_integer(&self, index: u16) -> Result<i32, ClassParseErrorKind> {
+        match self.get(index)? {
+            Constant::ConstantValue(ConstantValueKind::Integer(x)) => Ok(*x),
+            _ => Err(wrong_kind(index, "Integer")),
+        }
+    }
+
+    pub fn get_float(&self, index: u16) -> Result<f32, ClassParseErrorKind> {
+        match self.get(index)? {
+            Constant::ConstantValue(ConstantValueKind::Float(x)) => Ok(*x),
+            _ => Err(wrong_kind(index, "Float")),
+        }
+    }
+
+    pub fn get_long(&self, index: u16) -> Result<i64, ClassParseErrorKind> {
+        match self.get(index)? {
+            Constant::ConstantValue(ConstantValueKind::Long(x)) => Ok(*x),
+            _ => Err(wrong_kind(index, "Long")),
+        }
+    }
+
+    pub fn get_double(&self, index: u16) -> Result<f64, ClassParseErrorKind> {
+        match self.get(index)? {
+            Constant::ConstantValue(ConstantValueKind::Double(x)) => Ok(*x),
+            _ => Err(wrong_kind(index, "Double")),
+        }
+    }
+
+    /// Resolves the Methodref or InterfaceMethodref at `index`.
+    pub fn get_methodref(&self, index: u16) -> Result<MethodRef, ClassParseErrorKind> {
+        match self.get(index)? {
+            Constant::Methodref(x) => self.resolve_methodref(*x, false),
+            Constant::InterfaceMethodref(x) => self.resolve_methodref(*x, true),
+            _ => Err(wrong_kind(index, "Methodref or InterfaceMethodref")),
+        }
     }
 
-    pub(crate) fn get_methodref(&self, cnst: MethodrefConstant, is_interface: bool) -> MethodRef {
+    fn resolve_methodref(
+        &self,
+        cnst: MethodrefConstant,
+        is_interface: bool,
+    ) -> Result<MethodRef, ClassParseErrorKind> {
         let MethodrefConstant {
             class_index,
             name_and_type_index,
         } = cnst;
 
-        let classname = self.get_class(class_index);
-        let Constant::NameAndType(NameAndTypeConstant { name_index, descriptor_index }) = self.get(name_and_type_index).unwrap() else {
-            panic!("expected utf8");
-        };
-        let descriptor_string = self.get_utf8(*descriptor_index);
+        let classname = self.get_class(class_index)?.clone();
+        let NameAndType { name, descriptor } = self.get_name_and_type(name_and_type_index)?;
 
-        MethodRef {
+        Ok(MethodRef {
             is_interface,
             class: classname,
-            name: self.get_utf8(*name_index),
-            descriptor: descriptor::parse_method_descriptor(descriptor_string),
-        }
+            name,
+            descriptor: descriptor::parse_method_descriptor(&descriptor)?,
+        })
     }
 
-    pub(crate) fn get_fieldref(&self, index: u16) -> FieldRef {
-        let Constant::Fieldref(FieldrefConstant { class_index, name_and_type_index }) = self.get(index).unwrap() else {
-            panic!("expected fieldref");
-        };
-        let classname = self.get_class(*class_index);
-        let Constant::NameAndType(NameAndTypeConstant { name_index, descriptor_index }) = self.get(*name_and_type_index).unwrap() else {
-            panic!("expected utf8");
+    /// Resolves the Fieldref at `index`.
+    pub fn get_fieldref(&self, index: u16) -> Result<FieldRef, ClassParseErrorKind> {
+        let Constant::Fieldref(FieldrefConstant {
+            class_index,
+            name_and_type_index,
+        }) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "Fieldref"));
         };
-        let field_name = self.get_utf8(*name_index);
-        let descriptor = self.get_utf8(*descriptor_index);
+        let classname = self.get_class(*class_index)?.clone();
+        let NameAndType { name, descriptor } = self.get_name_and_type(*name_and_type_index)?;
 
-        FieldRef {
+        Ok(FieldRef {
             class: classname,
-            name: field_name,
-            descriptor: descriptor::parse_field_descriptor(descriptor),
+            name,
+            descriptor: descriptor::parse_field_descriptor(&descriptor)?,
+        })
+    }
+
+    /// Gets the name and (unparsed) descriptor of the NameAndType constant at `index`.
+    pub fn get_name_and_type(&self, index: u16) -> Result<NameAndType, ClassParseErrorKind> {
+        let Constant::NameAndType(NameAndTypeConstant {
+            name_index,
+            descriptor_index,
+        }) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "NameAndType"));
+        };
+
+        Ok(NameAndType {
+            name: self.get_utf8(*name_index)?.clone(),
+            descriptor: self.get_utf8(*descriptor_index)?.clone(),
+        })
+    }
+
+    /// Resolves the MethodHandle at `index`, checking that the kind of its reference
+    /// matches its `ReferenceKind`.
+    pub fn get_method_handle(&self, index: u16) -> Result<MethodHandle, ClassParseErrorKind> {
+        let Constant::MethodHandle(MethodHandleConstant {
+            reference_kind,
+            reference_index,
+        }) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "MethodHandle"));
+        };
+
+        use ReferenceKind::*;
+        let reference = match reference_kind {
+            GetField | GetStatic | PutField | PutStatic => {
+                MethodHandleRef::Field(self.get_fieldref(*reference_index)?)
+            }
+            InvokeStatic | InvokeSpecial if self.major_version >= 52 => {
+                MethodHandleRef::Method(self.get_methodref(*reference_index)?)
+            }
+            // Before version 52, only invokeInterface handles can refer to interface methods.
+            InvokeVirtual | NewInvokeSpecial | InvokeStatic | InvokeSpecial => {
+                match self.get(*reference_index)? {
+                    Constant::Methodref(x) => {
+                        MethodHandleRef::Method(self.resolve_methodref(*x, false)?)
+                    }
+                    _ => return Err(wrong_kind(*reference_index, "Methodref")),
+                }
+            }
+            InvokeInterface => match self.get(*reference_index)? {
+                Constant::InterfaceMethodref(x) => {
+                    MethodHandleRef::Method(self.resolve_methodref(*x, true)?)
+                }
+                _ => return Err(wrong_kind(*reference_index, "InterfaceMethodref")),
+            },
+        };
+
+        Ok(MethodHandle {
+            kind: *reference_kind,
+            reference,
+        })
+    }
+
+    /// Gets the parsed descriptor of the MethodType at `index`.
+    pub fn get_method_type(&self, index: u16) -> Result<MethodDescriptor, ClassParseErrorKind> {
+        let Constant::MethodType(MethodTypeConstant { descriptor_index }) = self.get(index)? else {
+            return Err(wrong_kind(index, "MethodType"));
+        };
+
+        descriptor::parse_method_descriptor(self.get_utf8(*descriptor_index)?)
+    }
+
+    pub fn get_dynamic(&self, index: u16) -> Result<DynamicRef, ClassParseErrorKind> {
+        let Constant::Dynamic(DynamicConstant {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        }) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "Dynamic"));
+        };
+        let NameAndType { name, descriptor } = self.get_name_and_type(*name_and_type_index)?;
+
+        Ok(DynamicRef {
+            bootstrap_method_attr_index: *bootstrap_method_attr_index,
+            name,
+            descriptor: descriptor::parse_field_descriptor(&descriptor)?,
+        })
+    }
+
+    pub fn get_invoke_dynamic(&self, index: u16) -> Result<InvokeDynamicRef, ClassParseErrorKind> {
+        let Constant::InvokeDynamic(DynamicConstant {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        }) = self.get(index)?
+        else {
+            return Err(wrong_kind(index, "InvokeDynamic"));
+        };
+        let NameAndType { name, descriptor } = self.get_name_and_type(*name_and_type_index)?;
+
+        Ok(InvokeDynamicRef {
+            bootstrap_method_attr_index: *bootstrap_method_attr_index,
+            name,
+            descriptor: descriptor::parse_method_descriptor(&descriptor)?,
+        })
+    }
+
+    /// Resolves a constant that can be loaded by `ldc` and friends.
+    pub fn get_loadable(&self, index: u16) -> Result<LoadableConstant, ClassParseErrorKind> {
+        Ok(match self.get(index)? {
+            Constant::ConstantValue(value) => match value {
+                ConstantValueKind::Integer(x) => LoadableConstant::Integer(*x),
+                ConstantValueKind::Float(x) => LoadableConstant::Float(*x),
+                ConstantValueKind::Long(x) => LoadableConstant::Long(*x),
+                ConstantValueKind::Double(x) => LoadableConstant::Double(*x),
+                ConstantValueKind::String(string_idx) => {
+                    LoadableConstant::String(self.get_java_string(*string_idx)?.clone())
+                }
+            },
+            Constant::Class(_) => LoadableConstant::Class(self.get_class(index)?.clone()),
+            Constant::MethodHandle(_) => {
+                LoadableConstant::MethodHandle(self.get_method_handle(index)?)
+            }
+            Constant::MethodType(_) => LoadableConstant::MethodType(self.get_method_type(index)?),
+            Constant::Dynamic(_) => LoadableConstant::Dynamic(self.get_dynamic(index)?),
+            _ => return Err(wrong_kind(index, "loadable")),
+        })
+    }
+
+    /// Resolves a constant that can be loaded by `ldc` or `ldc_w`, which can't load longs or
+    /// doubles.
+    pub fn get_category1_loadable(
+        &self,
+        index: u16,
+    ) -> Result<LoadableConstant, ClassParseErrorKind> {
+        match self.get_loadable(index)? {
+            constant if constant.is_wide() => {
+                Err(ClassParseErrorKind::WrongConstantCategory { index, category: 1 })
+            }
+            constant => Ok(constant),
+        }
+    }
+
+    /// Resolves a constant that can be loaded by `ldc2_w`, which only loads longs and doubles.
+    pub fn get_category2_loadable(
+        &self,
+        index: u16,
+    ) -> Result<LoadableConstant, ClassParseErrorKind> {
+        match self.get_loadable(index)? {
+            constant if !constant.is_wide() => {
+                Err(ClassParseErrorKind::WrongConstantCategory { index, category: 2 })
+            }
+            constant => Ok(constant),
         }
     }
+
+    /// Gets the name of the Module constant at `index`.
+    pub fn get_module(&self, index: u16) -> Result<&Symbol, ClassParseErrorKind> {
+        let Constant::Module(ModuleConstant { name_index }) = self.get(index)? else {
+            return Err(wrong_kind(index, "Module"));
+        };
+
+        self.get_utf8(*name_index)
+    }
+
+    /// Gets the name of the Package constant at `index`.
+    pub fn get_package(&self, index: u16) -> Result<&Symbol, ClassParseErrorKind> {
+        let Constant::Package(PackageConstant { name_index }) = self.get(index)? else {
+            return Err(wrong_kind(index, "Package"));
+        };
+
+        self.get_utf8(*name_index)
+    }
+}
+
+fn wrong_kind(index: u16, expected: &'static str) -> ClassParseErrorKind {
+    ClassParseErrorKind::WrongConstantKind { index, expected }
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4-140>
 #[derive(Debug)]
-pub(crate) enum Constant {
+#[allow(clippy::enum_variant_names)]
+pub enum Constant {
     Class(ClassConstant),
     Methodref(MethodrefConstant),
     InterfaceMethodref(MethodrefConstant),
     NameAndType(NameAndTypeConstant),
     Fieldref(FieldrefConstant),
     /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.7>
-    Utf8(String),
+    Utf8(JavaString),
     ConstantValue(ConstantValueKind),
+    MethodHandle(MethodHandleConstant),
+    MethodType(MethodTypeConstant),
+    Dynamic(DynamicConstant),
+    InvokeDynamic(DynamicConstant),
+    Module(ModuleConstant),
+    Package(PackageConstant),
+    /// The entry following a Long or Double constant, which can't be referenced.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.5>
+    Unusable,
+}
+
+impl LoadableConstant {
+    /// Whether the constant is a long or a double, including a dynamically-computed one, which
+    /// takes up two stack entries.
+    pub fn is_wide(&self) -> bool {
+        match self {
+            LoadableConstant::Long(_) | LoadableConstant::Double(_) => true,
+            LoadableConstant::Dynamic(dynamic) => matches!(
+                dynamic.descriptor,
+                FieldDescriptor::Basic(BasicFieldType::Long | BasicFieldType::Double)
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl Constant {
+    /// Whether this constant takes up two entries in the constant pool.
+    pub fn is_wide(&self) -> bool {
+        matches!(
+            self,
+            Constant::ConstantValue(ConstantValueKind::Long(_) | ConstantValueKind::Double(_))
+        )
+    }
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.2>
 #[derive(Debug)]
-pub(crate) enum ConstantValueKind {
+pub enum ConstantValueKind {
     /// int, short, char, byte, boolean
     Integer(i32),
     Float(f32),
@@ -103,27 +410,169 @@ pub(crate) enum ConstantValueKind {
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.1>
 #[derive(Debug)]
-pub(crate) struct ClassConstant {
+pub struct ClassConstant {
     pub name_index: u16,
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.6>
 #[derive(Debug)]
-pub(crate) struct NameAndTypeConstant {
+pub struct NameAndTypeConstant {
     pub name_index: u16,
     pub descriptor_index: u16,
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.2>
 #[derive(Debug, Copy, Clone)]
-pub(crate) struct MethodrefConstant {
+pub struct MethodrefConstant {
     pub class_index: u16,
     pub name_and_type_index: u16,
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.2>
 #[derive(Debug)]
-pub(crate) struct FieldrefConstant {
+pub struct FieldrefConstant {
     pub class_index: u16,
     pub name_and_type_index: u16,
 }
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.8>
+#[derive(Debug)]
+pub struct MethodHandleConstant {
+    pub reference_kind: ReferenceKind,
+    pub reference_index: u16,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.9>
+#[derive(Debug)]
+pub struct MethodTypeConstant {
+    pub descriptor_index: u16,
+}
+
+/// Used for both CONSTANT_Dynamic and CONSTANT_InvokeDynamic, which only differ in
+/// the kind of descriptor that `name_and_type_index` points to.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.10>
+#[derive(Debug)]
+pub struct DynamicConstant {
+    pub bootstrap_method_attr_index: u16,
+    pub name_and_type_index: u16,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.11>
+#[derive(Debug)]
+pub struct ModuleConstant {
+    pub name_index: u16,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.12>
+#[derive(Debug)]
+pub struct PackageConstant {
+    pub name_index: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_constant_slots() {
+        let cp = ConstantPool::new(
+            vec![
+                Constant::ConstantValue(ConstantValueKind::Long(7)),
+                Constant::Unusable,
+                Constant::Utf8("after".into()),
+            ],
+            52,
+        );
+
+        assert!(matches!(
+            cp.get(1),
+            Ok(Constant::ConstantValue(ConstantValueKind::Long(7)))
+        ));
+        assert!(matches!(
+            cp.get(2),
+            Err(ClassParseErrorKind::BadConstantIndex(2))
+        ));
+        assert_eq!(Ok("after"), cp.get_utf8(3).map(Symbol::as_str));
+        assert!(matches!(
+            cp.get(4),
+            Err(ClassParseErrorKind::BadConstantIndex(4))
+        ));
+    }
+
+    #[test]
+    fn test_public_lookups() {
+        let class =
+            crate::ClassFile::parse_from_bytes(include_bytes!("../test_data/Wide.class")).unwrap();
+        let cp = class.constant_pool();
+
+        // The Long at #12 and the Double at #16 each hide the next index
+        let indices: Vec<u16> = cp.iter().map(|(i, _)| i).collect();
+        assert_eq!(&indices[9..14], &[10, 11, 12, 14, 15]);
+        assert!(!indices.contains(&17));
+
+        assert_eq!(Ok("Wide"), cp.get_class(7).map(Symbol::as_str));
+        assert_eq!(Ok(1234567890123), cp.get_long(12));
+        assert_eq!(Ok(3.5), cp.get_double(16));
+        assert_eq!(Ok(100000), cp.get_integer(22));
+        assert_eq!(Ok(1.25), cp.get_float(28));
+        assert!(matches!(
+            cp.get_category2_loadable(12),
+            Ok(LoadableConstant::Long(1234567890123))
+        ));
+        assert!(matches!(
+            cp.get_category1_loadable(22),
+            Ok(LoadableConstant::Integer(100000))
+        ));
+        assert_eq!(
+            Some(ClassParseErrorKind::WrongConstantCategory {
+                index: 16,
+                category: 1
+            }),
+            cp.get_category1_loadable(16).err()
+        );
+        assert_eq!(
+            Some(ClassParseErrorKind::WrongConstantCategory {
+                index: 28,
+                category: 2
+            }),
+            cp.get_category2_loadable(28).err()
+        );
+        assert_eq!(
+            Some("after the wide constants"),
+            cp.get_string(25).unwrap().as_str()
+        );
+        assert_eq!(
+            Ok(NameAndType {
+                name: "<init>".into(),
+                descriptor: "()V".into()
+            }),
+            cp.get_name_and_type(3)
+        );
+
+        let methodref = cp.get_methodref(1).unwrap();
+        assert_eq!(
+            ("java/lang/Object", "<init>"),
+            (methodref.class.as_str(), methodref.name.as_str())
+        );
+
+        assert_eq!(
+            Err(ClassParseErrorKind::WrongConstantKind {
+                index: 8,
+                expected: "Class"
+            }),
+            cp.get_class(8)
+        );
+        assert_eq!(
+            Err(ClassParseErrorKind::BadConstantIndex(13)),
+            cp.get_long(13)
+        );
+        assert_eq!(
+            Err(ClassParseErrorKind::BadConstantIndex(0)),
+            cp.get_utf8(0)
+        );
+        assert!(matches!(
+            cp.get_fieldref(1000),
+            Err(ClassParseErrorKind::BadConstantIndex(1000))
+        ));
+    }
+}
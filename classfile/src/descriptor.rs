@@ -1,46 +1,88 @@
+use crate::{ClassParseErrorKind, Symbol};
+use std::fmt;
+
 /// Non-recursive part of a `FieldDescriptor`.
 ///
 /// It is a union of "BaseType" and "ObjectType"
 /// as defined in <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.2>.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum BasicFieldType<'cp> {
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum BasicFieldType {
     Byte,
     Char,
     Double,
     Float,
     Int,
     Long,
-    ClassInstance(&'cp str),
+    ClassInstance(Symbol),
     Short,
     Boolean,
 }
 
 /// A field descriptor.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.2>
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum FieldDescriptor<'cp> {
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum FieldDescriptor {
     /// BaseType | ObjectType
-    Basic(BasicFieldType<'cp>),
+    Basic(BasicFieldType),
     /// ArrayType
     Arr(
         /// Dimension
         std::num::NonZeroU8,
-        BasicFieldType<'cp>,
+        BasicFieldType,
     ),
 }
 
+/// Writes the type in descriptor form, e.g. `I` or `Ljava/lang/String;`.
+impl fmt::Display for BasicFieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BasicFieldType::*;
+
+        match self {
+            Byte => f.write_str("B"),
+            Char => f.write_str("C"),
+            Double => f.write_str("D"),
+            Float => f.write_str("F"),
+            Int => f.write_str("I"),
+            Long => f.write_str("J"),
+            ClassInstance(class_name) => write!(f, "L{class_name};"),
+            Short => f.write_str("S"),
+            Boolean => f.write_str("Z"),
+        }
+    }
+}
+
+/// Writes the descriptor as it would appear in a class file.
+impl fmt::Display for FieldDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldDescriptor::Basic(t) => t.fmt(f),
+            FieldDescriptor::Arr(dim, t) => {
+                for _ in 0..dim.get() {
+                    f.write_str("[")?;
+                }
+                t.fmt(f)
+            }
+        }
+    }
+}
+
 /// Parses a `FieldDescriptor` out of the given field descriptor in `string`.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.2>
-pub(crate) fn parse_field_descriptor(string: &str) -> FieldDescriptor {
-    parse_field_descriptor_rest(string).0
+pub(crate) fn parse_field_descriptor(string: &str) -> Result<FieldDescriptor, ClassParseErrorKind> {
+    match parse_field_descriptor_rest(string) {
+        Some((desc, "")) => Ok(desc),
+        _ => Err(bad_descriptor(string)),
+    }
 }
 
-fn parse_field_descriptor_rest(string: &str) -> (FieldDescriptor, &str) {
+/// Parses one `FieldDescriptor` off the front of `string`, returning it and the rest of the
+/// string. Returns `None` if `string` doesn't start with a valid descriptor.
+fn parse_field_descriptor_rest(string: &str) -> Option<(FieldDescriptor, &str)> {
     let mut chars = string.chars();
 
     use BasicFieldType::*;
     use FieldDescriptor::*;
-    let desc = match chars.next().unwrap() {
+    let desc = match chars.next()? {
         'B' => Basic(Byte),
         'C' => Basic(Char),
         'D' => Basic(Double),
@@ -48,28 +90,24 @@ fn parse_field_descriptor_rest(string: &str) -> (FieldDescriptor, &str) {
         'I' => Basic(Int),
         'J' => Basic(Long),
         'L' => {
-            let before = chars.as_str();
-            loop {
-                match chars.next() {
-                    None => panic!("Reached end of string while looking for ';'"),
-                    Some(';') => break,
-                    Some(_) => {}
-                }
+            let (class_name, rest) = chars.as_str().split_once(';')?;
+            if class_name.is_empty() {
+                return None;
             }
-            let len_diff = before.len() - chars.as_str().len();
+            chars = rest.chars();
 
-            Basic(ClassInstance(&before[..len_diff - 1]))
+            Basic(ClassInstance(class_name.into()))
         }
         'S' => Basic(Short),
         'Z' => Basic(Boolean),
         '[' => {
-            let mut dim = 1;
-            while let Some('[') = chars.as_str().chars().next() {
+            let mut dim: u8 = 1;
+            while chars.as_str().starts_with('[') {
                 chars.next();
-                dim += 1;
+                dim = dim.checked_add(1)?;
             }
 
-            let (Basic(descriptor), rest) = parse_field_descriptor_rest(chars.as_str()) else {
+            let (Basic(descriptor), rest) = parse_field_descriptor_rest(chars.as_str())? else {
                 // We parsed out the '[' characters already, so the next parsed descriptor
                 // wont be an `Arr`.
                 unreachable!();
@@ -77,54 +115,92 @@ fn parse_field_descriptor_rest(string: &str) -> (FieldDescriptor, &str) {
             chars = rest.chars();
             Arr(std::num::NonZeroU8::new(dim).unwrap(), descriptor)
         }
-        x => panic!("Unknown field spec {x}"),
+        _ => return None,
     };
 
-    (desc, chars.as_str())
+    Some((desc, chars.as_str()))
+}
+
+fn bad_descriptor(string: &str) -> ClassParseErrorKind {
+    ClassParseErrorKind::BadDescriptor(string.to_owned())
 }
 
 /// See <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.3>.
-#[derive(Debug, Eq, PartialEq)]
-pub enum ReturnDescriptor<'cp> {
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ReturnDescriptor {
     Void,
-    NonVoid(FieldDescriptor<'cp>),
+    NonVoid(FieldDescriptor),
 }
 
 /// A method descriptor.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.3>
-#[derive(Debug, Eq, PartialEq)]
-pub struct MethodDescriptor<'cp> {
-    pub parameters: Vec<FieldDescriptor<'cp>>,
-    pub return_descriptor: ReturnDescriptor<'cp>,
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldDescriptor>,
+    pub return_descriptor: ReturnDescriptor,
+}
+
+/// Writes the descriptor as it would appear in a class file.
+impl fmt::Display for ReturnDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReturnDescriptor::Void => f.write_str("V"),
+            ReturnDescriptor::NonVoid(t) => t.fmt(f),
+        }
+    }
+}
+
+/// Writes the descriptor as it would appear in a class file.
+impl fmt::Display for MethodDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for parameter in &self.parameters {
+            parameter.fmt(f)?;
+        }
+        f.write_str(")")?;
+        self.return_descriptor.fmt(f)
+    }
+}
+
+/// Parses a `ReturnDescriptor`, which is either `V` or a field descriptor.
+pub(crate) fn parse_return_descriptor(
+    string: &str,
+) -> Result<ReturnDescriptor, ClassParseErrorKind> {
+    if string == "V" {
+        Ok(ReturnDescriptor::Void)
+    } else {
+        parse_field_descriptor(string).map(ReturnDescriptor::NonVoid)
+    }
 }
 
 /// Parses a `MethodDescriptor` from the specified string.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.3.3>
-pub(crate) fn parse_method_descriptor(string: &str) -> MethodDescriptor {
-    let mut chars = string.chars();
-    assert!(chars.next().unwrap() == '(');
+pub(crate) fn parse_method_descriptor(
+    string: &str,
+) -> Result<MethodDescriptor, ClassParseErrorKind> {
+    let Some(mut rest) = string.strip_prefix('(') else {
+        return Err(bad_descriptor(string));
+    };
 
     let mut parameters = Vec::new();
     loop {
-        if chars.as_str().chars().next().unwrap() == ')' {
-            chars.next();
+        if let Some(after) = rest.strip_prefix(')') {
+            rest = after;
             break;
         } else {
-            let (desc, rest) = parse_field_descriptor_rest(chars.as_str());
-            chars = rest.chars();
+            let Some((desc, after)) = parse_field_descriptor_rest(rest) else {
+                return Err(bad_descriptor(string));
+            };
+            rest = after;
             parameters.push(desc);
         }
     }
-    let return_descriptor = if chars.as_str().chars().next().unwrap() == 'V' {
-        ReturnDescriptor::Void
-    } else {
-        ReturnDescriptor::NonVoid(parse_field_descriptor(chars.as_str()))
-    };
+    let return_descriptor = parse_return_descriptor(rest).map_err(|_| bad_descriptor(string))?;
 
-    MethodDescriptor {
+    Ok(MethodDescriptor {
         parameters,
         return_descriptor,
-    }
+    })
 }
 
 #[cfg(test)]
@@ -140,13 +216,13 @@ mod tests {
                 parameters: vec![
                     Basic(Int),
                     Basic(Double),
-                    Basic(ClassInstance("java/lang/Thread"))
+                    Basic(ClassInstance("java/lang/Thread".into()))
                 ],
                 return_descriptor: ReturnDescriptor::NonVoid(FieldDescriptor::Basic(
-                    ClassInstance("java/lang/Object")
+                    ClassInstance("java/lang/Object".into())
                 ))
             },
-            parse_method_descriptor("(IDLjava/lang/Thread;)Ljava/lang/Object;")
+            parse_method_descriptor("(IDLjava/lang/Thread;)Ljava/lang/Object;").unwrap()
         );
 
         assert_eq!(
@@ -154,7 +230,7 @@ mod tests {
                 parameters: Vec::new(),
                 return_descriptor: ReturnDescriptor::Void
             },
-            parse_method_descriptor("()V")
+            parse_method_descriptor("()V").unwrap()
         );
 
         assert_eq!(
@@ -162,7 +238,7 @@ mod tests {
                 parameters: vec![Basic(Float)],
                 return_descriptor: ReturnDescriptor::Void
             },
-            parse_method_descriptor("(F)V")
+            parse_method_descriptor("(F)V").unwrap()
         );
     }
 
@@ -172,18 +248,59 @@ mod tests {
             std::num::NonZeroU8::new(n).unwrap()
         }
 
-        assert_eq!((Basic(Byte), ""), parse_field_descriptor_rest("B"));
+        assert_eq!(Some((Basic(Byte), "")), parse_field_descriptor_rest("B"));
 
         assert_eq!(
-            (Basic(ClassInstance("thing/other/FooBar")), ""),
+            Some((Basic(ClassInstance("thing/other/FooBar".into())), "")),
             parse_field_descriptor_rest("Lthing/other/FooBar;")
         );
 
-        assert_eq!((Arr(nz(1), Short), "Z"), parse_field_descriptor_rest("[SZ"));
+        assert_eq!(
+            Some((Arr(nz(1), Short), "Z")),
+            parse_field_descriptor_rest("[SZ")
+        );
 
         assert_eq!(
-            (Arr(nz(3), ClassInstance("Something")), "[BSZLHello;"),
+            Some((Arr(nz(3), ClassInstance("Something".into())), "[BSZLHello;")),
             parse_field_descriptor_rest("[[[LSomething;[BSZLHello;")
         );
     }
+
+    #[test]
+    fn test_descriptor_display() {
+        for descriptor in ["I", "Ljava/lang/String;", "[[J", "[Ljava/lang/Object;"] {
+            assert_eq!(
+                descriptor,
+                parse_field_descriptor(descriptor).unwrap().to_string()
+            );
+        }
+
+        for descriptor in [
+            "()V",
+            "(IDLjava/lang/Thread;)Ljava/lang/Object;",
+            "([[BZ)[I",
+        ] {
+            assert_eq!(
+                descriptor,
+                parse_method_descriptor(descriptor).unwrap().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_descriptors() {
+        for bad in ["", "X", "Ljava/lang/Object", "L;", "[", "II"] {
+            assert_eq!(
+                Err(ClassParseErrorKind::BadDescriptor(bad.to_owned())),
+                parse_field_descriptor(bad)
+            );
+        }
+
+        for bad in ["", "V", "(", "(I", "()", "(V)V", "()VV", "(L;)V"] {
+            assert_eq!(
+                Err(ClassParseErrorKind::BadDescriptor(bad.to_owned())),
+                parse_method_descriptor(bad)
+            );
+        }
+    }
 }
@@ -0,0 +1,377 @@
+use std::fmt;
+
+/// An error encountered while parsing a .class file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassParseError {
+    /// Byte offset into the class file at which the problem was detected.
+    pub offset: usize,
+    pub kind: ClassParseErrorKind,
+}
+
+impl ClassParseError {
+    pub(crate) fn new(offset: usize, kind: ClassParseErrorKind) -> Self {
+        Self { offset, kind }
+    }
+}
+
+impl fmt::Display for ClassParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at offset {:#x})", self.kind, self.offset)
+    }
+}
+
+impl std::error::Error for ClassParseError {}
+
+/// What went wrong while parsing; see [`ClassParseError`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassParseErrorKind {
+    /// The file doesn't start with `0xCAFEBABE`. Holds the magic that was found instead.
+    BadMagic(u32),
+    /// The class file's version isn't supported by the [`ParseOptions`](crate::ParseOptions)
+    /// it was parsed with.
+    UnsupportedVersion {
+        major_version: u16,
+        minor_version: u16,
+    },
+    /// The input ended in the middle of a structure.
+    UnexpectedEof,
+    /// A constant pool entry has a tag that isn't defined by the spec.
+    BadConstantTag(u8),
+    /// A constant pool entry has a tag that isn't allowed in the class file's version.
+    ConstantNotInVersion { tag: u8, major_version: u16 },
+    /// A constant pool index is zero, past the end of the pool, or refers to the unusable
+    /// entry after a Long or Double constant.
+    BadConstantIndex(u16),
+    /// A Long or Double constant is the last entry of the constant pool, so there's no room for
+    /// the unusable entry after it. Holds the constant's index.
+    WideConstantOverflowsPool(u16),
+    /// A constant pool index points at an entry of the wrong kind.
+    WrongConstantKind {
+        index: u16,
+        /// The kind of constant that was expected, e.g. `"Utf8"`.
+        expected: &'static str,
+    },
+    /// An `ldc` or `ldc_w` refers to a long or double constant, or an `ldc2_w` to a constant
+    /// that isn't one.
+    WrongConstantCategory {
+        index: u16,
+        /// The computational type category that the instruction loads, 1 or 2.
+        category: u8,
+    },
+    /// A CONSTANT_MethodHandle has a `reference_kind` that isn't defined by the spec.
+    BadReferenceKind(u8),
+    /// A Dynamic or InvokeDynamic constant refers to a bootstrap method that isn't in the
+    /// class's BootstrapMethods attribute.
+    BadBootstrapMethodIndex {
+        /// The index of the constant.
+        index: u16,
+        bootstrap_method_attr_index: u16,
+    },
+    /// A field or method descriptor is malformed. Holds the offending descriptor.
+    BadDescriptor(String),
+    /// A generic signature is malformed. Holds the offending signature.
+    BadSignature(String),
+    /// A CONSTANT_Utf8 entry or a SourceDebugExtension attribute isn't valid modified UTF-8. Only
+    /// a warning for SourceDebugExtension, which is then kept as an unknown attribute.
+    BadUtf8,
+    /// A CONSTANT_Utf8 entry that's used as a name or descriptor contains unpaired surrogates.
+    NonUnicodeUtf8(u16),
+    /// The body of an attribute wasn't the length that its header claimed.
+    AttributeLengthMismatch {
+        name: String,
+        /// `attribute_length` from the attribute's header.
+        declared: u32,
+        /// Number of bytes the attribute body actually took up.
+        actual: usize,
+    },
+    /// An instruction in a Code attribute has an unknown opcode.
+    BadOpcode(u8),
+    /// An instruction in a Code attribute isn't allowed in the class file's version.
+    OpcodeNotInVersion { opcode: u8, major_version: u16 },
+    /// A predefined attribute is in a class file that's older than the attribute, so it's
+    /// treated as an unknown attribute. Only reported as a warning; see
+    /// [`ClassFile::warnings`](crate::ClassFile::warnings).
+    AttributeNotInVersion { name: String, major_version: u16 },
+    /// A `newarray` instruction has an unknown element type.
+    BadArrayType(u8),
+    /// A `tableswitch` has `low` greater than `high`, or a `lookupswitch` has a negative number
+    /// of pairs.
+    BadSwitch,
+    /// A byte of an instruction that has to be zero isn't, e.g. the last byte of
+    /// `invokeinterface`, or the padding of a switch before version 51.
+    NonZeroReservedByte(u8),
+    /// An annotation element value has a tag that isn't defined by the spec.
+    BadElementValueTag(u8),
+    /// Annotation element values are nested more deeply than the parser supports.
+    ElementValueTooDeep,
+    /// A type annotation has a `target_type` that isn't defined by the spec.
+    BadTargetType(u8),
+    /// A type annotation's `type_path` has a `type_path_kind` that isn't defined by the spec.
+    BadTypePathKind(u8),
+    /// A SourceDebugExtension attribute starts with `SMAP` but isn't a well-formed source map.
+    /// Holds the 1-based number of the offending line. Only reported as a warning, and the
+    /// attribute is kept without a source map.
+    BadSourceMap(usize),
+    /// A StackMapTable frame has a `frame_type` that isn't defined by the spec.
+    BadFrameType(u8),
+    /// A StackMapTable entry has a verification type tag that isn't defined by the spec.
+    BadVerificationType(u8),
+}
+
+impl fmt::Display for ClassParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ClassParseErrorKind::*;
+
+        match self {
+            BadMagic(magic) => write!(f, "bad magic {magic:#010X}"),
+            UnexpectedEof => f.write_str("unexpected end of input"),
+            UnsupportedVersion {
+                major_version,
+                minor_version: crate::PREVIEW_MINOR_VERSION,
+            } if *major_version >= 56 => write!(
+                f,
+                "unsupported class file version {major_version} with preview features"
+            ),
+            UnsupportedVersion {
+                major_version,
+                minor_version,
+            } => write!(
+                f,
+                "unsupported class file version {major_version}.{minor_version}"
+            ),
+            BadConstantTag(tag) => write!(f, "unknown constant tag {tag}"),
+            ConstantNotInVersion { tag, major_version } => write!(
+                f,
+                "constant tag {tag} isn't allowed in class file version {major_version}"
+            ),
+            BadConstantIndex(index) => write!(f, "bad constant pool index {index}"),
+            WideConstantOverflowsPool(index) => write!(
+                f,
+                "wide constant at index {index} doesn't fit in the constant pool"
+            ),
+            WrongConstantKind { index, expected } => {
+                write!(f, "expected {expected} constant at index {index}")
+            }
+            WrongConstantCategory { index, category } => {
+                write!(f, "expected category {category} constant at index {index}")
+            }
+            BadReferenceKind(kind) => write!(f, "unknown method handle reference kind {kind}"),
+            BadBootstrapMethodIndex {
+                index,
+                bootstrap_method_attr_index,
+            } => write!(
+                f,
+                "constant at index {index} refers to missing bootstrap method {bootstrap_method_attr_index}"
+            ),
+            BadDescriptor(descriptor) => write!(f, "bad descriptor {descriptor:?}"),
+            BadSignature(signature) => write!(f, "bad signature {signature:?}"),
+            BadUtf8 => f.write_str("malformed modified utf8"),
+            NonUnicodeUtf8(index) => {
+                write!(
+                    f,
+                    "utf8 constant at index {index} contains unpaired surrogates"
+                )
+            }
+            AttributeLengthMismatch {
+                name,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "{name} attribute declared length {declared} but has length {actual}"
+            ),
+            BadOpcode(opcode) => write!(f, "unknown opcode {opcode:#04X}"),
+            OpcodeNotInVersion {
+                opcode,
+                major_version,
+            } => write!(
+                f,
+                "opcode {opcode:#04X} isn't allowed in class file version {major_version}"
+            ),
+            AttributeNotInVersion {
+                name,
+                major_version,
+            } => write!(
+                f,
+                "{name} attribute isn't recognized in class file version {major_version}"
+            ),
+            BadArrayType(atype) => write!(f, "unknown newarray type {atype}"),
+            BadSwitch => f.write_str("malformed switch instruction"),
+            NonZeroReservedByte(byte) => write!(f, "reserved byte is {byte:#x} instead of zero"),
+            BadElementValueTag(tag) => {
+                write!(f, "unknown element value tag {:?}", char::from(*tag))
+            }
+            ElementValueTooDeep => f.write_str("element values are nested too deeply"),
+            BadTargetType(target_type) => {
+                write!(f, "unknown type annotation target type {target_type:#04X}")
+            }
+            BadTypePathKind(kind) => write!(f, "unknown type path kind {kind}"),
+            BadSourceMap(line) => write!(f, "malformed source map at line {line}"),
+            BadFrameType(frame_type) => write!(f, "unknown stack map frame type {frame_type}"),
+            BadVerificationType(tag) => write!(f, "unknown verification type tag {tag}"),
+        }
+    }
+}
+
+/// An error encountered while reading a class file, e.g. with
+/// [`ClassFile::parse_from_reader`](crate::ClassFile::parse_from_reader).
+#[derive(Debug)]
+pub enum ClassReadError {
+    Io(std::io::Error),
+    Parse(ClassParseError),
+}
+
+impl From<std::io::Error> for ClassReadError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ClassParseError> for ClassReadError {
+    fn from(error: ClassParseError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl fmt::Display for ClassReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassReadError::Io(error) => error.fmt(f),
+            ClassReadError::Parse(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ClassReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClassReadError::Io(error) => Some(error),
+            ClassReadError::Parse(error) => Some(error),
+        }
+    }
+}
+
+/// An error encountered while writing a class file; see
+/// [`ClassFile::write_to`](crate::ClassFile::write_to).
+#[derive(Debug)]
+pub enum ClassWriteError {
+    Io(std::io::Error),
+    /// The constant pool would need more than 65535 entries.
+    TooManyConstants,
+    /// An `ldc` refers to a constant whose index doesn't fit in a byte, so it has to be an
+    /// `ldc_w` instead. Holds the index.
+    LdcIndexTooLarge(u16),
+    /// A table, attribute or code array is too long for its length field. Holds what it is,
+    /// e.g. `"code"`.
+    TooLong(&'static str),
+    /// A StackMapTable frame can't be encoded, e.g. a chop frame with a `k` greater than 3.
+    BadStackMapFrame,
+    /// Code from a [`CodeBuilder`](crate::builder::CodeBuilder) refers to a label that was
+    /// never placed.
+    UnplacedLabel,
+    /// A branch target is too far away for the branch's offset, e.g. more than 32767 bytes
+    /// after a `goto`.
+    BranchTooFar,
+    /// A `tableswitch` from a [`CodeBuilder`](crate::builder::CodeBuilder) has no targets, or
+    /// so many that the last one's value doesn't fit in an `i32`.
+    BadTableswitch,
+    /// The frames of generated code couldn't be computed.
+    Analysis(AnalysisError),
+}
+
+impl From<AnalysisError> for ClassWriteError {
+    fn from(error: AnalysisError) -> Self {
+        Self::Analysis(error)
+    }
+}
+
+impl From<std::io::Error> for ClassWriteError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl fmt::Display for ClassWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ClassWriteError::*;
+
+        match self {
+            Io(error) => error.fmt(f),
+            TooManyConstants => f.write_str("too many constants"),
+            LdcIndexTooLarge(index) => write!(f, "constant index {index} is too large for ldc"),
+            TooLong(what) => write!(f, "{what} is too long"),
+            BadStackMapFrame => f.write_str("stack map frame can't be encoded"),
+            UnplacedLabel => f.write_str("label was never placed"),
+            BranchTooFar => f.write_str("branch target is too far away"),
+            BadTableswitch => f.write_str("tableswitch has no targets or too many of them"),
+            Analysis(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ClassWriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClassWriteError::Io(error) => Some(error),
+            ClassWriteError::Analysis(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// An error computing the frames of a method's code; see
+/// [`analyze_code`](crate::analyze_code).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisError {
+    /// The offset in the code of the instruction at which the problem was detected.
+    pub pc: usize,
+    pub kind: AnalysisErrorKind,
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at pc {})", self.kind, self.pc)
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+/// What went wrong while computing frames; see [`AnalysisError`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisErrorKind {
+    /// An instruction pops more values than there are on the operand stack.
+    StackUnderflow,
+    /// Two paths reach an instruction with operand stacks of different sizes.
+    StackHeightMismatch,
+    /// A branch, switch or exception handler targets an offset that isn't the start of an
+    /// instruction.
+    BadBranchTarget(usize),
+    /// Execution can continue past the last instruction.
+    FallsOffEnd,
+    /// A `jsr` or `ret`, whose subroutines can't be described by frames.
+    Subroutine,
+    /// The catch type of an exception handler isn't a Class constant. Holds its index.
+    BadCatchType(u16),
+    /// A class name starts with `[` but isn't a valid array type. Holds the name.
+    BadArrayClass(String),
+    /// The method's code couldn't be decoded, in a class parsed with
+    /// [`ParseOptions::lazy_code`](crate::ParseOptions::lazy_code).
+    BadCode(ClassParseError),
+}
+
+impl fmt::Display for AnalysisErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AnalysisErrorKind::*;
+
+        match self {
+            StackUnderflow => f.write_str("operand stack underflow"),
+            StackHeightMismatch => f.write_str("operand stacks of different sizes meet"),
+            BadBranchTarget(target) => write!(f, "branch target {target} isn't an instruction"),
+            FallsOffEnd => f.write_str("execution falls off the end of the code"),
+            Subroutine => f.write_str("subroutines aren't supported"),
+            BadCatchType(index) => write!(f, "catch type {index} isn't a Class constant"),
+            BadArrayClass(name) => write!(f, "{name} isn't a valid array class"),
+            BadCode(error) => write!(f, "code can't be decoded: {error}"),
+        }
+    }
+}
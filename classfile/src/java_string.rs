@@ -0,0 +1,349 @@
+use crate::Symbol;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+/// The contents of a CONSTANT_Utf8.
+///
+/// Java strings are sequences of UTF-16 code units, which may include unpaired surrogates that a
+/// `str` can't hold. Well-formed strings are stored as a `Symbol`; anything else is stored as
+/// [WTF-8](https://simonsapin.github.io/wtf-8/), which is UTF-8 extended to allow surrogate code
+/// points.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct JavaString(Repr);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Unicode(Symbol),
+    /// Contains at least one unpaired surrogate.
+    Wtf8(Arc<[u8]>),
+}
+
+/// Error returned when decoding malformed modified UTF-8.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ModifiedUtf8Error {
+    /// Index of the first byte of the bad sequence.
+    pub position: usize,
+}
+
+impl fmt::Display for ModifiedUtf8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed modified utf8 at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for ModifiedUtf8Error {}
+
+impl JavaString {
+    /// Decodes the "modified UTF-8" used by CONSTANT_Utf8.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.7>
+    pub fn from_modified_utf8(bytes: &[u8]) -> Result<Self, ModifiedUtf8Error> {
+        let mut wtf8 = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let error = ModifiedUtf8Error { position: i };
+            let continuation = |n: usize| match bytes.get(i + n) {
+                Some(&b) if b & 0xC0 == 0x80 => Ok(u32::from(b & 0x3F)),
+                _ => Err(error),
+            };
+
+            match bytes[i] {
+                0 => return Err(error),
+                b @ 0x01..=0x7F => {
+                    wtf8.push(b);
+                    i += 1;
+                }
+                b @ 0xC0..=0xDF => {
+                    let c = u32::from(b & 0x1F) << 6 | continuation(1)?;
+                    // NUL is the only character allowed to have an overlong encoding.
+                    if c != 0 && c < 0x80 {
+                        return Err(error);
+                    }
+                    push_code_point(&mut wtf8, c);
+                    i += 2;
+                }
+                b @ 0xE0..=0xEF => {
+                    let c = u32::from(b & 0x0F) << 12 | continuation(1)? << 6 | continuation(2)?;
+                    if c < 0x800 {
+                        return Err(error);
+                    }
+                    i += 3;
+
+                    // Supplementary characters are stored as a surrogate pair, each encoded
+                    // separately.
+                    if is_high_surrogate(c) {
+                        if let [0xED, b2 @ 0xB0..=0xBF, b3 @ 0x80..=0xBF, ..] = bytes[i..] {
+                            let low = 0xD000 | u32::from(b2 & 0x3F) << 6 | u32::from(b3 & 0x3F);
+                            push_code_point(
+                                &mut wtf8,
+                                0x10000 + ((c - 0xD800) << 10 | (low - 0xDC00)),
+                            );
+                            i += 3;
+                            continue;
+                        }
+                    }
+                    push_code_point(&mut wtf8, c);
+                }
+                _ => return Err(error),
+            }
+        }
+
+        Ok(Self::from_wtf8(wtf8))
+    }
+
+    /// Encodes this string as modified UTF-8, the inverse of [`Self::from_modified_utf8`].
+    pub fn to_modified_utf8(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.wtf8().len());
+
+        for c in self.code_points() {
+            match c {
+                0 => out.extend([0xC0, 0x80]),
+                0x10000.. => {
+                    let c = c - 0x10000;
+                    push_code_point(&mut out, 0xD800 | c >> 10);
+                    push_code_point(&mut out, 0xDC00 | (c & 0x3FF));
+                }
+                _ => push_code_point(&mut out, c),
+            }
+        }
+
+        out
+    }
+
+    /// Builds a `JavaString` out of UTF-16 code units. Never fails: unpaired surrogates are kept.
+    pub fn from_utf16(units: &[u16]) -> Self {
+        let mut wtf8 = Vec::with_capacity(units.len());
+        for c in char::decode_utf16(units.iter().copied()) {
+            match c {
+                Ok(c) => push_code_point(&mut wtf8, c.into()),
+                Err(e) => push_code_point(&mut wtf8, e.unpaired_surrogate().into()),
+            }
+        }
+
+        Self::from_wtf8(wtf8)
+    }
+
+    /// Returns the string as a `str`, or `None` if it contains unpaired surrogates.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_symbol().map(Symbol::as_str)
+    }
+
+    /// Returns the string as a `Symbol`, or `None` if it contains unpaired surrogates.
+    pub fn as_symbol(&self) -> Option<&Symbol> {
+        match &self.0 {
+            Repr::Unicode(s) => Some(s),
+            Repr::Wtf8(_) => None,
+        }
+    }
+
+    /// Returns the string, with each unpaired surrogate replaced by U+FFFD.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        match &self.0 {
+            Repr::Unicode(s) => Cow::Borrowed(s),
+            Repr::Wtf8(_) => Cow::Owned(
+                self.code_points()
+                    .map(|c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns the UTF-16 code units of the string, as Java would see them.
+    pub fn encode_utf16(&self) -> impl Iterator<Item = u16> + '_ {
+        self.code_points().flat_map(|c| {
+            if c >= 0x10000 {
+                let c = c - 0x10000;
+                [
+                    Some(0xD800 | (c >> 10) as u16),
+                    Some(0xDC00 | (c & 0x3FF) as u16),
+                ]
+            } else {
+                [Some(c as u16), None]
+            }
+            .into_iter()
+            .flatten()
+        })
+    }
+
+    fn from_wtf8(wtf8: Vec<u8>) -> Self {
+        match String::from_utf8(wtf8) {
+            Ok(s) => Self(Repr::Unicode(s.into())),
+            Err(e) => Self(Repr::Wtf8(e.into_bytes().into())),
+        }
+    }
+
+    fn wtf8(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Unicode(s) => s.as_bytes(),
+            Repr::Wtf8(v) => v,
+        }
+    }
+
+    /// Iterates over the code points (including surrogates) of the string.
+    fn code_points(&self) -> impl Iterator<Item = u32> + '_ {
+        let mut bytes = self.wtf8();
+
+        std::iter::from_fn(move || {
+            let &first = bytes.first()?;
+            let (len, mask) = match first {
+                0x00..=0x7F => (1, 0x7F),
+                0xC0..=0xDF => (2, 0x1F),
+                0xE0..=0xEF => (3, 0x0F),
+                _ => (4, 0x07),
+            };
+
+            let mut c = u32::from(first & mask);
+            for &b in &bytes[1..len] {
+                c = c << 6 | u32::from(b & 0x3F);
+            }
+            bytes = &bytes[len..];
+
+            Some(c)
+        })
+    }
+}
+
+fn is_high_surrogate(c: u32) -> bool {
+    (0xD800..0xDC00).contains(&c)
+}
+
+/// Appends `c` to `out` using the standard UTF-8 encoding, which WTF-8 also uses for surrogates.
+fn push_code_point(out: &mut Vec<u8>, c: u32) {
+    match c {
+        0..=0x7F => out.push(c as u8),
+        0x80..=0x7FF => out.extend([0xC0 | (c >> 6) as u8, 0x80 | (c & 0x3F) as u8]),
+        0x800..=0xFFFF => out.extend([
+            0xE0 | (c >> 12) as u8,
+            0x80 | (c >> 6 & 0x3F) as u8,
+            0x80 | (c & 0x3F) as u8,
+        ]),
+        _ => out.extend([
+            0xF0 | (c >> 18) as u8,
+            0x80 | (c >> 12 & 0x3F) as u8,
+            0x80 | (c >> 6 & 0x3F) as u8,
+            0x80 | (c & 0x3F) as u8,
+        ]),
+    }
+}
+
+impl From<&str> for JavaString {
+    fn from(s: &str) -> Self {
+        Self(Repr::Unicode(s.into()))
+    }
+}
+
+impl From<String> for JavaString {
+    fn from(s: String) -> Self {
+        Self(Repr::Unicode(s.into()))
+    }
+}
+
+impl From<Symbol> for JavaString {
+    fn from(s: Symbol) -> Self {
+        Self(Repr::Unicode(s))
+    }
+}
+
+impl PartialEq<str> for JavaString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == Some(other)
+    }
+}
+
+impl PartialEq<&str> for JavaString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == Some(*other)
+    }
+}
+
+impl fmt::Display for JavaString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+impl fmt::Debug for JavaString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::Unicode(s) => fmt::Debug::fmt(s, f),
+            Repr::Wtf8(_) => {
+                f.write_str("\"")?;
+                for c in self.code_points() {
+                    match char::from_u32(c) {
+                        Some(c) => write!(f, "{}", c.escape_debug())?,
+                        None => write!(f, "\\u{{{c:x}}}")?,
+                    }
+                }
+                f.write_str("\"")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_modified_utf8() {
+        let decode = |b: &[u8]| JavaString::from_modified_utf8(b).unwrap();
+
+        assert_eq!(Some("hello"), decode(b"hello").as_str());
+        assert_eq!(Some("a\0b"), decode(&[b'a', 0xC0, 0x80, b'b']).as_str());
+        assert_eq!(
+            Some("\u{e9}\u{20ac}"),
+            decode("\u{e9}\u{20ac}".as_bytes()).as_str()
+        );
+        // U+1F600 as the surrogate pair D83D DE00
+        assert_eq!(
+            Some("\u{1F600}"),
+            decode(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).as_str()
+        );
+
+        let lone = decode(&[b'x', 0xED, 0xA0, 0x80, b'y']);
+        assert_eq!(None, lone.as_str());
+        assert_eq!("x\u{FFFD}y", lone.to_string_lossy());
+        assert_eq!(
+            vec![0x78, 0xD800, 0x79],
+            lone.encode_utf16().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_bad_modified_utf8() {
+        let error = |b: &[u8]| JavaString::from_modified_utf8(b).unwrap_err().position;
+
+        // NUL has to be encoded as two bytes
+        assert_eq!(1, error(b"a\0"));
+        // Supplementary characters can't use the standard four-byte form
+        assert_eq!(0, error("\u{1F600}".as_bytes()));
+        // Overlong encodings
+        assert_eq!(0, error(&[0xC1, 0x81]));
+        assert_eq!(0, error(&[0xE0, 0x81, 0x81]));
+        // Missing and stray continuation bytes
+        assert_eq!(1, error(&[b'a', 0xE2, 0x82]));
+        assert_eq!(0, error(&[0x80]));
+    }
+
+    #[test]
+    fn test_modified_utf8_round_trip() {
+        let cases: &[&[u8]] = &[
+            b"",
+            &[0xC0, 0x80],
+            &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80],
+            // Unpaired high and low surrogates, and a low followed by a high
+            &[0xED, 0xA0, 0x80],
+            &[0xED, 0xB0, 0x80, b'z'],
+            &[0xED, 0xB8, 0x80, 0xED, 0xA0, 0xBD],
+        ];
+
+        for &bytes in cases {
+            let decoded = JavaString::from_modified_utf8(bytes).unwrap();
+            assert_eq!(bytes, decoded.to_modified_utf8());
+
+            let units: Vec<u16> = decoded.encode_utf16().collect();
+            assert_eq!(decoded, JavaString::from_utf16(&units));
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Method code that's decoded the first time it's accessed; see [`ParseOptions::lazy_code`].
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::*;
+
+/// A method's Code attribute, which may not have been decoded yet.
+pub(crate) struct LazyCode {
+    decoded: OnceLock<Result<Decoded, ClassParseError>>,
+    /// The attribute as it was parsed, kept until the code is modified so that it can be
+    /// written back as is. Always present if the code hasn't been decoded.
+    raw: Option<RawCode>,
+}
+
+/// The body of a Code attribute, with everything needed to decode it later.
+pub(crate) struct RawCode {
+    pub bytes: Box<[u8]>,
+    /// Offset of `bytes` in the class file, for errors.
+    pub offset: usize,
+    pub constant_pool: Arc<ConstantPool>,
+    pub major_version: u16,
+    pub options: ParseOptions,
+}
+
+struct Decoded {
+    code: CodeAttribute,
+    /// The constant pool indices that the code refers to, if it was decoded lazily. Those
+    /// aren't among the class's references, so they're kept here for the writer.
+    references: Option<Vec<u16>>,
+}
+
+impl Decoded {
+    fn lazily(raw: &RawCode) -> Result<Self, ClassParseError> {
+        let (code, references) = parser::decode_code(raw)?;
+        Ok(Self {
+            code,
+            references: Some(references),
+        })
+    }
+}
+
+impl LazyCode {
+    pub fn decoded(code: CodeAttribute) -> Self {
+        Self {
+            decoded: OnceLock::from(Ok(Decoded {
+                code,
+                references: None,
+            })),
+            raw: None,
+        }
+    }
+
+    pub fn raw(raw: RawCode) -> Self {
+        Self {
+            decoded: OnceLock::new(),
+            raw: Some(raw),
+        }
+    }
+
+    /// The decoded code, decoding it first if needed.
+    pub fn get(&self) -> Result<&CodeAttribute, ClassParseError> {
+        self.decoded
+            .get_or_init(|| Decoded::lazily(self.raw.as_ref().expect("code wasn't kept")))
+            .as_ref()
+            .map(|decoded| &decoded.code)
+            .map_err(Clone::clone)
+    }
+
+    /// The decoded code, for modifying it. The raw attribute is dropped, since it may no longer
+    /// match.
+    pub fn get_mut(&mut self) -> Result<&mut CodeAttribute, ClassParseError> {
+        self.get()?;
+        self.raw = None;
+        Ok(&mut self.decoded.get_mut().unwrap().as_mut().unwrap().code)
+    }
+
+    pub fn into_inner(self) -> Result<CodeAttribute, ClassParseError> {
+        let decoded = match self.decoded.into_inner() {
+            Some(decoded) => decoded,
+            None => Decoded::lazily(self.raw.as_ref().expect("code wasn't kept")),
+        };
+        decoded.map(|decoded| decoded.code)
+    }
+
+    /// The constant pool indices that the code referred to when it was decoded, if it was
+    /// parsed lazily.
+    pub fn references(&self) -> Option<&[u16]> {
+        match self.decoded.get() {
+            Some(Ok(decoded)) => decoded.references.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The body of the attribute as it was parsed, if the code hasn't been modified since.
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        self.raw.as_ref().map(|raw| &*raw.bytes)
+    }
+}
+
+impl fmt::Debug for LazyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.decoded.get(), &self.raw) {
+            (Some(Ok(decoded)), _) => decoded.code.fmt(f),
+            (Some(Err(error)), _) => error.fmt(f),
+            (None, Some(raw)) => f
+                .debug_struct("RawCode")
+                .field("len", &raw.bytes.len())
+                .finish_non_exhaustive(),
+            (None, None) => unreachable!("code wasn't kept"),
+        }
+    }
+}
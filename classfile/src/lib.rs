@@ -2,64 +2,173 @@
 //!
 //! The main type is [`ClassFile`].
 
+// Mapping a file is the only thing that needs unsafe code.
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
+
+use std::sync::Arc;
+
+use lazy::LazyCode;
+
+pub mod builder;
 pub mod op;
+pub mod visitor;
 
+pub use access::*;
+pub use analysis::*;
+pub use annotation::*;
 pub use attribute::*;
+pub use check::*;
+pub use constant_pool::*;
 pub use descriptor::*;
+pub use error::*;
+pub use java_string::*;
+pub use signature::*;
+pub use smap::*;
+pub use symbol::*;
+pub use version::*;
 
+mod access;
+mod analysis;
+mod annotation;
 mod attribute;
+mod check;
 mod constant_pool;
 mod descriptor;
+mod error;
+mod java_string;
+mod lazy;
 mod parser;
-
-use constant_pool::ConstantPool;
-use std::mem;
+mod read;
+mod signature;
+mod smap;
+mod symbol;
+#[cfg(test)]
+mod test_util;
+mod version;
+mod writer;
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1>
 #[derive(Debug)]
 pub struct ClassFile {
-    // UNSOUND: All the 'static lifetimes in here aren't actually static.
-    // Rather, they should be tied to the lifetime of the owned ConstantPool.
-    // tldr: this is really cursed
     major_version: u16,
     minor_version: u16,
-    _constant_pool: ConstantPool,
-    fields: Vec<FieldInfo<'static>>,
-    access_flags: AccessFlags,
-    methods: Vec<MethodInfo<'static>>,
-    attributes: Vec<Attribute<'static>>,
-    this_class: &'static str,
-    super_class: &'static str,
-    interfaces: Vec<&'static str>,
+    /// Shared with the code of methods that haven't been decoded yet.
+    constant_pool: Arc<ConstantPool>,
+    fields: Vec<FieldInfo>,
+    access_flags: ClassAccessFlags,
+    methods: Vec<MethodInfo>,
+    attributes: Vec<Attribute>,
+    this_class: Symbol,
+    super_class: Option<Symbol>,
+    interfaces: Vec<Symbol>,
+    warnings: Vec<ClassParseError>,
+    /// The constant pool indices that the class file refers to, in the order they were parsed.
+    /// If the pool has duplicate entries, these let [`Self::write_to`] pick the same ones.
+    cp_references: Vec<u16>,
 }
 
 impl ClassFile {
-    /// Parses a `ClassFile` from the provided bytes of a .class file
-    pub fn parse_from_bytes(raw: &[u8]) -> Self {
-        parser::Parser::new(raw).run()
+    /// Parses a `ClassFile` from the provided bytes of a .class file, with the default
+    /// [`ParseOptions`].
+    pub fn parse_from_bytes(raw: &[u8]) -> Result<Self, ClassParseError> {
+        Self::parse_with_options(raw, ParseOptions::default())
+    }
+
+    /// Parses a `ClassFile` from the provided bytes of a .class file.
+    pub fn parse_with_options(raw: &[u8], options: ParseOptions) -> Result<Self, ClassParseError> {
+        parser::Parser::with_options(raw, options).run()
+    }
+
+    /// Writes the class in the class file format.
+    ///
+    /// Constants are looked up in the class's constant pool, and any that aren't there are
+    /// added to the end of it. Writing a class that was parsed and not modified gives back the
+    /// same bytes, even if its constant pool has duplicate entries.
+    pub fn write_to(&self, w: &mut impl std::io::Write) -> Result<(), ClassWriteError> {
+        writer::write_class(self, w)
+    }
+
+    /// Writes the class to a new buffer; see [`Self::write_to`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ClassWriteError> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Recomputes `max_stack`, `max_locals` and the StackMapTable of every method with code, e.g.
+    /// after its code was generated or modified; see [`analyze_code`]. Class files before
+    /// version 50 don't have StackMapTables, so only the maximums are updated in those. In the
+    /// others, unreachable code is replaced with `nop`s and an `athrow`, which a frame can
+    /// describe.
+    pub fn compute_frames(&mut self, hierarchy: &dyn ClassHierarchy) -> Result<(), AnalysisError> {
+        for method in &mut self.methods {
+            let initial_locals = method.initial_locals(&self.this_class);
+            let code = method.code_mut().map_err(|error| AnalysisError {
+                pc: 0,
+                kind: AnalysisErrorKind::BadCode(error),
+            })?;
+            let Some(code) = code else {
+                continue;
+            };
+            let analysis = analyze_code(
+                code,
+                &initial_locals,
+                &self.this_class,
+                &self.constant_pool,
+                hierarchy,
+            )?;
+            code.max_stack = analysis.max_stack;
+            code.max_locals = analysis.max_locals;
+            if self.major_version >= 50 {
+                analysis::replace_unreachable(code, &analysis.unreachable);
+            }
+
+            let position = code
+                .attributes
+                .iter()
+                .position(|attr| matches!(attr, Attribute::StackMapTable(_)));
+            let frames = (self.major_version >= 50 && !analysis.frames.is_empty())
+                .then_some(Attribute::StackMapTable(analysis.frames));
+            match (position, frames) {
+                (Some(position), Some(frames)) => code.attributes[position] = frames,
+                (Some(position), None) => {
+                    code.attributes.remove(position);
+                }
+                (None, Some(frames)) => code.attributes.push(frames),
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn constant_pool(&self) -> &ConstantPool {
+        &self.constant_pool
     }
 
-    pub fn access_flags(&self) -> AccessFlags {
+    pub fn access_flags(&self) -> ClassAccessFlags {
         self.access_flags
     }
 
     pub fn fields(&self) -> &[FieldInfo] {
-        let ret: &[FieldInfo<'static>] = &self.fields;
-        unsafe { mem::transmute(ret) }
+        &self.fields
     }
 
     pub fn methods(&self) -> &[MethodInfo] {
-        let ret: &[MethodInfo<'static>] = &self.methods;
-        unsafe { mem::transmute(ret) }
+        &self.methods
+    }
+
+    /// The methods, for modifying them, e.g. through [`MethodInfo::code_mut`].
+    pub fn methods_mut(&mut self) -> &mut [MethodInfo] {
+        &mut self.methods
     }
 
     pub fn attributes(&self) -> &[Attribute] {
-        let ret: &[Attribute<'static>] = &self.attributes;
-        unsafe { mem::transmute(ret) }
+        &self.attributes
     }
 
-    pub fn this_class(&self) -> &str {
-        unsafe { mem::transmute(self.this_class) }
+    pub fn this_class(&self) -> &Symbol {
+        &self.this_class
     }
 
     /// (major, minor)
@@ -67,103 +176,513 @@ impl ClassFile {
         (self.major_version, self.minor_version)
     }
 
-    pub fn super_class(&self) -> &str {
-        unsafe { mem::transmute(self.super_class) }
+    /// Whether the class file depends on the preview features of its Java SE release.
+    pub fn is_preview(&self) -> bool {
+        self.major_version >= 56 && self.minor_version == PREVIEW_MINOR_VERSION
+    }
+
+    /// Problems that didn't stop the class file from being parsed, e.g. an unsupported version
+    /// under [`VersionPolicy::Warn`].
+    pub fn warnings(&self) -> &[ClassParseError] {
+        &self.warnings
+    }
+
+    /// The superclass, or `None` for `java/lang/Object` and `module-info`.
+    pub fn super_class(&self) -> Option<&Symbol> {
+        self.super_class.as_ref()
+    }
+
+    pub fn interfaces(&self) -> &[Symbol] {
+        &self.interfaces
+    }
+
+    /// The generic signature of the class, if it has one.
+    pub fn signature(&self) -> Option<&ClassSignature> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Signature(Signature::Class(signature)) => Some(signature),
+            _ => None,
+        })
+    }
+
+    /// The class's annotations, both visible and invisible at runtime.
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        attribute::annotations(&self.attributes)
+    }
+
+    /// The entries of the BootstrapMethods attribute, or an empty slice if there isn't one.
+    pub fn bootstrap_methods(&self) -> &[BootstrapMethod] {
+        self.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                Attribute::BootstrapMethods(methods) => Some(methods.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The bootstrap method that an `invokedynamic` call site is linked with.
+    pub fn invokedynamic_bootstrap(
+        &self,
+        call_site: &InvokeDynamicRef,
+    ) -> Option<&BootstrapMethod> {
+        self.bootstrap_methods()
+            .get(usize::from(call_site.bootstrap_method_attr_index))
     }
 
-    pub fn interfaces(&self) -> &[&str] {
-        let ret: &[&'static str] = &self.interfaces;
-        unsafe { mem::transmute(ret) }
+    /// The bootstrap method that computes a dynamically-computed constant.
+    pub fn dynamic_bootstrap(&self, constant: &DynamicRef) -> Option<&BootstrapMethod> {
+        self.bootstrap_methods()
+            .get(usize::from(constant.bootstrap_method_attr_index))
+    }
+
+    /// The module declared by a `module-info.class`, or `None` for other classes.
+    pub fn module_descriptor(&self) -> Option<ModuleDescriptor<'_>> {
+        let mut module = None;
+        let mut packages: &[Symbol] = &[];
+        let mut main_class = None;
+        for attr in &self.attributes {
+            match attr {
+                Attribute::Module(m) => module = Some(m),
+                Attribute::ModulePackages(p) => packages = p,
+                Attribute::ModuleMainClass(c) => main_class = Some(c),
+                _ => {}
+            }
+        }
+
+        Some(ModuleDescriptor {
+            module: module?,
+            packages,
+            main_class,
+        })
+    }
+
+    /// Whether this is a record class, i.e. it has a Record attribute.
+    pub fn is_record(&self) -> bool {
+        self.record_components().is_some()
+    }
+
+    /// The components of a record class, or `None` if this isn't a record.
+    pub fn record_components(&self) -> Option<&[RecordComponent]> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Record(components) => Some(components.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The classes that may extend this sealed class or interface, or an empty slice if it
+    /// isn't sealed.
+    pub fn permitted_subclasses(&self) -> &[Symbol] {
+        self.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                Attribute::PermittedSubclasses(classes) => Some(classes.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The host of the nest this class belongs to, if it isn't the host itself.
+    pub fn nest_host(&self) -> Option<&Symbol> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::NestHost(host) => Some(host),
+            _ => None,
+        })
+    }
+
+    /// The other members of the nest that this class hosts.
+    pub fn nest_members(&self) -> &[Symbol] {
+        self.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                Attribute::NestMembers(members) => Some(members.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The entries of the InnerClasses attribute, or an empty slice if there isn't one.
+    pub fn inner_classes(&self) -> &[InnerClass] {
+        self.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                Attribute::InnerClasses(classes) => Some(classes.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The EnclosingMethod attribute, which local and anonymous classes have.
+    pub fn enclosing_method(&self) -> Option<&EnclosingMethod> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::EnclosingMethod(enclosing) => Some(enclosing),
+            _ => None,
+        })
+    }
+
+    /// The name of the source file that the class was compiled from, e.g. `Foo.java`.
+    pub fn source_file(&self) -> Option<&Symbol> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::SourceFile(file) => Some(file),
+            _ => None,
+        })
+    }
+
+    /// The JSR-45 source map in the SourceDebugExtension attribute, if there is one.
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::SourceDebugExtension(extension) => extension.source_map.as_ref(),
+            _ => None,
+        })
+    }
+
+    /// The source file and line that the instruction at `pc` in `method` was compiled from, as
+    /// seen in `stratum`, or in the source map's default stratum if `stratum` is `None`.
+    ///
+    /// The "Java" stratum, which is the default without a source map, is the class's own
+    /// LineNumberTable and SourceFile. Other strata remap those lines through the source map.
+    pub fn source_location(
+        &self,
+        method: &MethodInfo,
+        pc: u16,
+        stratum: Option<&str>,
+    ) -> Option<SourceLocation<'_>> {
+        let line = method.code().ok()??.line_number_at(pc)?.into();
+        let source_map = self.source_map();
+        let stratum = stratum
+            .or(source_map.map(|smap| smap.default_stratum.as_str()))
+            .unwrap_or("Java");
+
+        match source_map.and_then(|smap| smap.stratum(stratum)) {
+            Some(stratum) => stratum.map_line(line),
+            None if stratum == "Java" => Some(SourceLocation {
+                file: self.source_file()?.as_str(),
+                path: None,
+                line,
+            }),
+            None => None,
+        }
     }
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.5>
 #[derive(Debug)]
-pub struct FieldInfo<'cp> {
-    pub access_flags: AccessFlags,
-    pub name: &'cp str,
-    pub descriptor: FieldDescriptor<'cp>,
-    pub attributes: Vec<Attribute<'cp>>,
+pub struct FieldInfo {
+    pub access_flags: FieldAccessFlags,
+    pub name: Symbol,
+    pub descriptor: FieldDescriptor,
+    pub attributes: Vec<Attribute>,
+}
+
+impl FieldInfo {
+    /// The field's annotations, both visible and invisible at runtime.
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        attribute::annotations(&self.attributes)
+    }
+
+    /// The generic type of the field, if it has one.
+    pub fn signature(&self) -> Option<&FieldSignature> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Signature(Signature::Field(signature)) => Some(signature),
+            _ => None,
+        })
+    }
 }
 
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.6>
 #[derive(Debug)]
-pub struct MethodInfo<'cp> {
-    pub access_flags: AccessFlags,
-    pub name: &'cp str,
-    pub descriptor: MethodDescriptor<'cp>,
-    pub code: Option<CodeAttribute<'cp>>,
-    attributes: Vec<Attribute<'cp>>,
+pub struct MethodInfo {
+    pub access_flags: MethodAccessFlags,
+    pub name: Symbol,
+    pub descriptor: MethodDescriptor,
+    code: Option<LazyCode>,
+    attributes: Vec<Attribute>,
+    /// Where the Code attribute was among the method's attributes, so that it can be written
+    /// back in the same place.
+    code_position: usize,
+}
+
+impl MethodInfo {
+    /// The method's Code attribute, or `None` if it's abstract or native. In a class parsed with
+    /// [`ParseOptions::lazy_code`], the code is decoded the first time it's accessed, and
+    /// errors in it are reported here instead of by the parser.
+    pub fn code(&self) -> Result<Option<&CodeAttribute>, ClassParseError> {
+        self.code.as_ref().map(LazyCode::get).transpose()
+    }
+
+    /// Like [`Self::code`], but for modifying the code. This decodes the code if it hasn't been
+    /// yet, and drops the attribute's original bytes, so that the class is written from the
+    /// decoded code from then on.
+    pub fn code_mut(&mut self) -> Result<Option<&mut CodeAttribute>, ClassParseError> {
+        self.code.as_mut().map(LazyCode::get_mut).transpose()
+    }
+
+    /// Whether the method has a Code attribute. Unlike [`Self::code`], this doesn't decode it.
+    pub fn has_code(&self) -> bool {
+        self.code.is_some()
+    }
+
+    pub(crate) fn lazy_code(&self) -> Option<&LazyCode> {
+        self.code.as_ref()
+    }
+
+    /// The method's attributes, not including its `Code` attribute (see `Self::code`).
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// The index that the Code attribute had among the method's attributes.
+    pub(crate) fn code_position(&self) -> usize {
+        self.code_position
+    }
+
+    /// The method's annotations, both visible and invisible at runtime.
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        attribute::annotations(&self.attributes)
+    }
+
+    /// The annotations on the formal parameter at `index`, both visible and invisible at
+    /// runtime.
+    pub fn parameter_annotations(&self, index: usize) -> impl Iterator<Item = &Annotation> {
+        self.attributes
+            .iter()
+            .filter_map(move |attr| match attr {
+                Attribute::RuntimeVisibleParameterAnnotations(parameters)
+                | Attribute::RuntimeInvisibleParameterAnnotations(parameters) => {
+                    parameters.get(index)
+                }
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// The names and flags of the method's formal parameters, from its MethodParameters
+    /// attribute. Class files only have one if they were compiled with `javac -parameters`.
+    pub fn parameters(&self) -> Option<&[MethodParameter]> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::MethodParameters(parameters) => Some(parameters.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The default value of this annotation interface element, if it has one.
+    pub fn annotation_default(&self) -> Option<&ElementValue> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::AnnotationDefault(value) => Some(value),
+            _ => None,
+        })
+    }
+
+    /// The generic signature of the method, if it has one.
+    pub fn signature(&self) -> Option<&MethodSignature> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Signature(Signature::Method(signature)) => Some(signature),
+            _ => None,
+        })
+    }
+
+    /// The classes listed in the method's `throws` clause, from its Exceptions attribute.
+    pub fn declared_exceptions(&self) -> &[Symbol] {
+        self.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                Attribute::Exceptions(exceptions) => Some(exceptions.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The types of the locals on entry to the method, in `this_class`, as the implicit first
+    /// frame of a StackMapTable would list them.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.10.1.6>
+    pub fn initial_locals(&self, this_class: &Symbol) -> Vec<VerificationTypeInfo> {
+        let mut locals = Vec::with_capacity(self.descriptor.parameters.len() + 1);
+
+        if !self.access_flags.has(MethodAccess::Static) {
+            locals.push(
+                if self.name == "<init>" && this_class != "java/lang/Object" {
+                    VerificationTypeInfo::UninitializedThis
+                } else {
+                    VerificationTypeInfo::Object(this_class.clone())
+                },
+            );
+        }
+
+        for parameter in &self.descriptor.parameters {
+            locals.push(VerificationTypeInfo::from_descriptor(parameter));
+        }
+
+        locals
+    }
 }
 
 #[derive(Debug)]
-pub struct FieldRef<'cp> {
-    pub class: &'cp str,
-    pub name: &'cp str,
-    pub descriptor: FieldDescriptor<'cp>,
+pub struct FieldRef {
+    pub class: Symbol,
+    pub name: Symbol,
+    pub descriptor: FieldDescriptor,
 }
 
 #[derive(Debug)]
-pub struct MethodRef<'cp> {
+pub struct MethodRef {
     /// if `false`: this is a class method. If `true`: this is an interface method.
     pub is_interface: bool,
-    pub class: &'cp str,
-    pub name: &'cp str,
-    pub descriptor: MethodDescriptor<'cp>,
-}
-
-/// A bitflag to check against `AccessFlags`.
-/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-E.1>
-#[derive(Debug, Copy, Clone)]
-#[repr(u16)]
-pub enum Access {
-    Public = 0x001,
-    Final = 0x0010,
-    Super = 0x0020,
-    Interface = 0x0200,
-    Abstract = 0x0400,
-    Synthetic = 0x1000,
-    Annotation = 0x2000,
-    Enum = 0x4000,
-    Module = 0x8000,
-}
-
-/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-E.1>
-#[derive(Copy, Clone)]
-pub struct AccessFlags(u16);
-
-impl AccessFlags {
-    pub fn has(self, flag: Access) -> bool {
-        self.0 & (flag as u16) != 0
-    }
-
-    fn new(bits: u16) -> Self {
-        Self(bits)
-    }
-}
-
-impl std::fmt::Debug for AccessFlags {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Access::*;
-
-        f.write_str("{ ")?;
-
-        for (string, access) in [
-            ("Public", Public),
-            ("Final", Final),
-            ("Super", Super),
-            ("Interface", Interface),
-            ("Abstract", Abstract),
-            ("Synthetic", Synthetic),
-            ("Annotation", Annotation),
-            ("Enum", Enum),
-            ("Module", Module),
-        ] {
-            if self.has(access) {
-                f.write_fmt(format_args!("{string} "))?;
-            }
+    pub class: Symbol,
+    pub name: Symbol,
+    pub descriptor: MethodDescriptor,
+}
+
+/// A resolved CONSTANT_NameAndType.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.6>
+#[derive(Debug, Eq, PartialEq)]
+pub struct NameAndType {
+    pub name: Symbol,
+    /// An unparsed field or method descriptor.
+    pub descriptor: Symbol,
+}
+
+/// The kind of a method handle, which says how its reference is used.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-5.html#jvms-5.4.3.5>
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u8)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+impl ReferenceKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        use ReferenceKind::*;
+
+        Some(match kind {
+            1 => GetField,
+            2 => GetStatic,
+            3 => PutField,
+            4 => PutStatic,
+            5 => InvokeVirtual,
+            6 => InvokeStatic,
+            7 => InvokeSpecial,
+            8 => NewInvokeSpecial,
+            9 => InvokeInterface,
+            _ => return None,
+        })
+    }
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.8>
+#[derive(Debug)]
+pub struct MethodHandle {
+    pub kind: ReferenceKind,
+    pub reference: MethodHandleRef,
+}
+
+/// What a `MethodHandle` refers to. Which variant this is follows from the handle's
+/// `ReferenceKind`: the `Get*`/`Put*` kinds refer to fields, and the rest to methods.
+#[derive(Debug)]
+pub enum MethodHandleRef {
+    Field(FieldRef),
+    Method(MethodRef),
+}
+
+/// A dynamically-computed constant.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.10>
+#[derive(Debug)]
+pub struct DynamicRef {
+    /// Index into the class's `BootstrapMethods` attribute.
+    pub bootstrap_method_attr_index: u16,
+    pub name: Symbol,
+    pub descriptor: FieldDescriptor,
+}
+
+/// A dynamically-computed call site.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.10>
+#[derive(Debug)]
+pub struct InvokeDynamicRef {
+    /// Index into the class's `BootstrapMethods` attribute.
+    pub bootstrap_method_attr_index: u16,
+    pub name: Symbol,
+    pub descriptor: MethodDescriptor,
+}
+
+/// A constant that can be pushed onto the operand stack by `ldc`, `ldc_w` or `ldc2_w`.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4-310>
+#[derive(Debug)]
+pub enum LoadableConstant {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    /// A class name, for loading a `java.lang.Class`.
+    Class(Symbol),
+    String(JavaString),
+    MethodHandle(MethodHandle),
+    MethodType(MethodDescriptor),
+    Dynamic(DynamicRef),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::Op;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_class_file_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<ClassFile>();
+    }
+
+    #[test]
+    fn test_share_across_threads() {
+        let class = Arc::new(
+            ClassFile::parse_from_bytes(include_bytes!("../test_data/Simple.class")).unwrap(),
+        );
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let class = Arc::clone(&class);
+                std::thread::spawn(move || class.methods()[1].name.clone())
+            })
+            .collect();
+        drop(class);
+
+        // The names are still usable after the ClassFile itself is gone.
+        for handle in handles {
+            assert_eq!("get", handle.join().unwrap());
         }
+    }
+
+    #[test]
+    fn test_modify_code() {
+        let raw = include_bytes!("../test_data/Simple.class");
+        let options = ParseOptions {
+            lazy_code: true,
+            ..ParseOptions::default()
+        };
+        let mut class = ClassFile::parse_with_options(raw, options).unwrap();
+
+        // Make the constructor set `x` to 4 instead of 5.
+        let init = &mut class.methods_mut()[0];
+        assert_eq!("<init>", init.name);
+        let code = init.code_mut().unwrap().unwrap();
+        let iconst = code
+            .code
+            .iter_mut()
+            .find(|op| matches!(op, Op::Iconst5))
+            .unwrap();
+        *iconst = Op::Iconst4;
 
-        f.write_str("}")
+        let written = class.to_bytes().unwrap();
+        assert_ne!(&raw[..], written);
+        let class = ClassFile::parse_from_bytes(&written).unwrap();
+        let code = class.methods()[0].code().unwrap().unwrap();
+        assert!(code.code.iter().any(|op| matches!(op, Op::Iconst4)));
+        assert!(!code.code.iter().any(|op| matches!(op, Op::Iconst5)));
     }
 }
@@ -1,47 +1,478 @@
 mod parser;
+mod writer;
 
-use crate::{ConstantPool, FieldRef, MethodRef};
+use crate::writer::ConstantPoolWriter;
+use crate::{
+    ClassParseError, ClassWriteError, ConstantPool, FieldRef, InvokeDynamicRef, LoadableConstant,
+    MethodRef, Symbol,
+};
 
 /// A VM instruction, with the opcode and all its operands.
 /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-6.html#jvms-6.5>
+///
+/// Branch offsets are kept as they appear in the bytecode: relative to the offset of the branch
+/// instruction itself.
 #[derive(Debug)]
-pub enum Op<'cp> {
-    Aload0,
-    Aload1,
-    Aload2,
-    Aload3,
-    Astore0,
-    Astore1,
-    Astore2,
-    Astore3,
+pub enum Op {
+    Nop,
+    AconstNull,
+    Iconstm1,
+    Iconst0,
+    Iconst1,
+    Iconst2,
+    Iconst3,
+    Iconst4,
+    Iconst5,
+    Lconst0,
+    Lconst1,
+    Fconst0,
+    Fconst1,
+    Fconst2,
+    Dconst0,
+    Dconst1,
+    Bipush(i8),
+    Sipush(i16),
+    Ldc(LoadableConstant),
+    LdcW(LoadableConstant),
+    Ldc2W(LoadableConstant),
+    Iload(u8),
+    Lload(u8),
+    Fload(u8),
+    Dload(u8),
+    Aload(u8),
     Iload0,
     Iload1,
     Iload2,
     Iload3,
-    Invokespecial(MethodRef<'cp>),
-    Return,
-    Ireturn,
-    Bipush(i8),
+    Lload0,
+    Lload1,
+    Lload2,
+    Lload3,
+    Fload0,
+    Fload1,
+    Fload2,
+    Fload3,
+    Dload0,
+    Dload1,
+    Dload2,
+    Dload3,
+    Aload0,
+    Aload1,
+    Aload2,
+    Aload3,
+    Iaload,
+    Laload,
+    Faload,
+    Daload,
+    Aaload,
+    Baload,
+    Caload,
+    Saload,
+    Istore(u8),
+    Lstore(u8),
+    Fstore(u8),
+    Dstore(u8),
+    Astore(u8),
     Istore0,
     Istore1,
     Istore2,
     Istore3,
-    Iconstm1,
-    Iconst0,
-    Iconst1,
-    Iconst2,
-    Iconst3,
-    Iconst4,
-    Iconst5,
-    Iadd,
-    New(&'cp str),
+    Lstore0,
+    Lstore1,
+    Lstore2,
+    Lstore3,
+    Fstore0,
+    Fstore1,
+    Fstore2,
+    Fstore3,
+    Dstore0,
+    Dstore1,
+    Dstore2,
+    Dstore3,
+    Astore0,
+    Astore1,
+    Astore2,
+    Astore3,
+    Iastore,
+    Lastore,
+    Fastore,
+    Dastore,
+    Aastore,
+    Bastore,
+    Castore,
+    Sastore,
+    Pop,
+    Pop2,
     Dup,
-    Putfield(FieldRef<'cp>),
-    Getfield(FieldRef<'cp>),
-    Invokevirtual(MethodRef<'cp>),
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+    Iadd,
+    Ladd,
+    Fadd,
+    Dadd,
+    Isub,
+    Lsub,
+    Fsub,
+    Dsub,
+    Imul,
+    Lmul,
+    Fmul,
+    Dmul,
+    Idiv,
+    Ldiv,
+    Fdiv,
+    Ddiv,
+    Irem,
+    Lrem,
+    Frem,
+    Drem,
+    Ineg,
+    Lneg,
+    Fneg,
+    Dneg,
+    Ishl,
+    Lshl,
+    Ishr,
+    Lshr,
+    Iushr,
+    Lushr,
+    Iand,
+    Land,
+    Ior,
+    Lor,
+    Ixor,
+    Lxor,
+    Iinc {
+        index: u8,
+        constant: i8,
+    },
+    I2l,
+    I2f,
+    I2d,
+    L2i,
+    L2f,
+    L2d,
+    F2i,
+    F2l,
+    F2d,
+    D2i,
+    D2l,
+    D2f,
+    I2b,
+    I2c,
+    I2s,
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+    Ifeq(i16),
+    Ifne(i16),
+    Iflt(i16),
+    Ifge(i16),
+    Ifgt(i16),
+    Ifle(i16),
+    IfIcmpeq(i16),
+    IfIcmpne(i16),
+    IfIcmplt(i16),
+    IfIcmpge(i16),
+    IfIcmpgt(i16),
+    IfIcmple(i16),
+    IfAcmpeq(i16),
+    IfAcmpne(i16),
+    Goto(i16),
+    Jsr(i16),
+    Ret(u8),
+    Tableswitch(TableSwitch),
+    Lookupswitch(LookupSwitch),
+    Ireturn,
+    Lreturn,
+    Freturn,
+    Dreturn,
+    Areturn,
+    Return,
+    Getstatic(FieldRef),
+    Putstatic(FieldRef),
+    Getfield(FieldRef),
+    Putfield(FieldRef),
+    Invokevirtual(MethodRef),
+    Invokespecial(MethodRef),
+    Invokestatic(MethodRef),
+    Invokeinterface {
+        method: MethodRef,
+        /// The redundant `count` operand: the size of the arguments, in local variable slots,
+        /// including the receiver.
+        count: u8,
+    },
+    Invokedynamic(InvokeDynamicRef),
+    New(Symbol),
+    Newarray(ArrayType),
+    Anewarray(Symbol),
+    Arraylength,
+    Athrow,
+    Checkcast(Symbol),
+    Instanceof(Symbol),
+    Monitorenter,
+    Monitorexit,
+    /// An instruction prefixed by `wide`.
+    Wide(Wide),
+    Multianewarray {
+        class: Symbol,
+        dimensions: u8,
+    },
+    Ifnull(i16),
+    Ifnonnull(i16),
+    GotoW(i32),
+    JsrW(i32),
+}
+
+impl Op {
+    /// The number of bytes the instruction takes up in the code array when it starts at offset
+    /// `pc`. Only switches depend on `pc`, because of the padding after their opcode.
+    pub fn encoded_len(&self, pc: usize) -> usize {
+        use Op::*;
+
+        let padding = 3 - pc % 4;
+        match self {
+            Bipush(_) | Ldc(_) | Iload(_) | Lload(_) | Fload(_) | Dload(_) | Aload(_)
+            | Istore(_) | Lstore(_) | Fstore(_) | Dstore(_) | Astore(_) | Ret(_) | Newarray(_) => 2,
+            Sipush(_)
+            | LdcW(_)
+            | Ldc2W(_)
+            | Iinc { .. }
+            | Ifeq(_)
+            | Ifne(_)
+            | Iflt(_)
+            | Ifge(_)
+            | Ifgt(_)
+            | Ifle(_)
+            | IfIcmpeq(_)
+            | IfIcmpne(_)
+            | IfIcmplt(_)
+            | IfIcmpge(_)
+            | IfIcmpgt(_)
+            | IfIcmple(_)
+            | IfAcmpeq(_)
+            | IfAcmpne(_)
+            | Goto(_)
+            | Jsr(_)
+            | Getstatic(_)
+            | Putstatic(_)
+            | Getfield(_)
+            | Putfield(_)
+            | Invokevirtual(_)
+            | Invokespecial(_)
+            | Invokestatic(_)
+            | New(_)
+            | Anewarray(_)
+            | Checkcast(_)
+            | Instanceof(_)
+            | Ifnull(_)
+            | Ifnonnull(_) => 3,
+            Multianewarray { .. } => 4,
+            Invokeinterface { .. } | Invokedynamic(_) | GotoW(_) | JsrW(_) => 5,
+            Wide(self::Wide::Iinc { .. }) => 6,
+            Wide(_) => 4,
+            Tableswitch(switch) => 1 + padding + 12 + 4 * switch.offsets.len(),
+            Lookupswitch(switch) => 1 + padding + 8 + 8 * switch.pairs.len(),
+            _ => 1,
+        }
+    }
+
+    /// The offset of the target of a branch, relative to the branch. `None` for switches,
+    /// which have several targets, and for instructions that don't branch.
+    pub fn branch_offset(&self) -> Option<i32> {
+        use Op::*;
+
+        match self {
+            Ifeq(offset) | Ifne(offset) | Iflt(offset) | Ifge(offset) | Ifgt(offset)
+            | Ifle(offset) | IfIcmpeq(offset) | IfIcmpne(offset) | IfIcmplt(offset)
+            | IfIcmpge(offset) | IfIcmpgt(offset) | IfIcmple(offset) | IfAcmpeq(offset)
+            | IfAcmpne(offset) | Goto(offset) | Jsr(offset) | Ifnull(offset)
+            | Ifnonnull(offset) => Some((*offset).into()),
+            GotoW(offset) | JsrW(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+/// The operands of a `tableswitch`. Offsets are relative to the `tableswitch` instruction.
+#[derive(Debug)]
+pub struct TableSwitch {
+    /// See [`LookupSwitch::padding`].
+    pub padding: [u8; 3],
+    pub default: i32,
+    pub low: i32,
+    pub high: i32,
+    /// One jump offset for each value from `low` to `high` inclusive.
+    pub offsets: Vec<i32>,
 }
 
-/// Parses a series of instructions out of the code given by `raw`.
-pub(crate) fn parse_ops_from_code_bytes(raw: &[u8], cp: &ConstantPool) -> Vec<Op<'static>> {
-    parser::Parser::new(raw, cp).run()
+/// The operands of a `lookupswitch`. Offsets are relative to the `lookupswitch` instruction.
+#[derive(Debug)]
+pub struct LookupSwitch {
+    /// The 0-3 bytes between the opcode and `default` as they were in the class file, followed
+    /// by zeros. As many as the switch's offset needs are written back. Only version 51 and
+    /// later allow them to be non-zero.
+    pub padding: [u8; 3],
+    pub default: i32,
+    /// `(match, offset)` pairs, sorted by `match`.
+    pub pairs: Vec<(i32, i32)>,
+}
+
+/// The element type operand of `newarray`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+impl ArrayType {
+    fn from_u8(atype: u8) -> Option<Self> {
+        use ArrayType::*;
+
+        Some(match atype {
+            4 => Boolean,
+            5 => Char,
+            6 => Float,
+            7 => Double,
+            8 => Byte,
+            9 => Short,
+            10 => Int,
+            11 => Long,
+            _ => return None,
+        })
+    }
+}
+
+/// An instruction that can be modified by `wide`, with its widened operands.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-6.html#jvms-6.5.wide>
+#[derive(Debug)]
+pub enum Wide {
+    Iload(u16),
+    Lload(u16),
+    Fload(u16),
+    Dload(u16),
+    Aload(u16),
+    Istore(u16),
+    Lstore(u16),
+    Fstore(u16),
+    Dstore(u16),
+    Astore(u16),
+    Ret(u16),
+    Iinc { index: u16, constant: i16 },
+}
+
+/// Parses a series of instructions out of the code given by `raw`, which starts at `offset` in
+/// the class file. Instructions that aren't allowed in `major_version` are errors. The constant
+/// pool indices that the instructions refer to are added to `cp_references`.
+pub(crate) fn parse_ops_from_code_bytes(
+    raw: &[u8],
+    cp: &ConstantPool,
+    offset: usize,
+    major_version: u16,
+    cp_references: &mut Vec<u16>,
+) -> Result<Vec<Op>, ClassParseError> {
+    parser::Parser::new(raw, cp, offset, major_version, cp_references).run()
+}
+
+/// Encodes `ops` as the contents of a code array, looking up or adding the constants they refer
+/// to in `cp`. The inverse of [`parse_ops_from_code_bytes`].
+pub(crate) fn write_ops(
+    ops: &[Op],
+    cp: &mut ConstantPoolWriter,
+) -> Result<Vec<u8>, ClassWriteError> {
+    writer::Writer::new(cp).run(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClassFile, ClassParseErrorKind};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_encoded_len() {
+        for raw in [
+            &include_bytes!("../../test_data/Branches.class")[..],
+            include_bytes!("../../test_data/Lambdas.class"),
+            include_bytes!("../../test_data/Strings.class"),
+            include_bytes!("../../test_data/Wide.class"),
+        ] {
+            let class = ClassFile::parse_from_bytes(raw).unwrap();
+            for method in class.methods() {
+                let Some(code) = method.code().unwrap() else {
+                    continue;
+                };
+                let mut cp = ConstantPoolWriter::new(class.constant_pool(), &[]);
+                let written = write_ops(&code.code, &mut cp).unwrap();
+                let len = code.code.iter().fold(0, |pc, op| pc + op.encoded_len(pc));
+                assert_eq!(written.len(), len, "{}.{}", class.this_class(), method.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_zero_reserved_bytes() {
+        let mut kinds = HashSet::new();
+        for raw in [
+            &include_bytes!("../../test_data/Branches.class")[..],
+            include_bytes!("../../test_data/Generics.class"),
+            include_bytes!("../../test_data/Lambdas.class"),
+        ] {
+            let class = ClassFile::parse_from_bytes(raw).unwrap();
+            let cp = class.constant_pool();
+            for method in class.methods() {
+                let Some(code) = method.code().unwrap() else {
+                    continue;
+                };
+                let bytes = write_ops(&code.code, &mut ConstantPoolWriter::new(cp, &[])).unwrap();
+                let mut pc = 0;
+                for op in &code.code {
+                    let (reserved, is_switch) = match op {
+                        Op::Tableswitch(_) | Op::Lookupswitch(_) if pc % 4 != 3 => (pc + 1, true),
+                        Op::Invokeinterface { .. } => (pc + 4, false),
+                        Op::Invokedynamic(_) => (pc + 3, false),
+                        _ => {
+                            pc += op.encoded_len(pc);
+                            continue;
+                        }
+                    };
+                    kinds.insert(std::mem::discriminant(op));
+                    let mut bytes = bytes.clone();
+                    bytes[reserved] = 7;
+                    let parse = |major_version| {
+                        parse_ops_from_code_bytes(&bytes, cp, 0, major_version, &mut Vec::new())
+                    };
+                    // Switch padding only has to be zero before version 51.
+                    assert_eq!(
+                        Err(ClassParseError::new(
+                            reserved,
+                            ClassParseErrorKind::NonZeroReservedByte(7)
+                        )),
+                        parse(if is_switch { 50 } else { 61 }).map(|_| ())
+                    );
+                    if is_switch {
+                        // Later, it's written back as it was.
+                        let ops = parse(51).unwrap();
+                        let mut cp = ConstantPoolWriter::new(cp, &[]);
+                        assert_eq!(bytes, write_ops(&ops, &mut cp).unwrap());
+                    }
+                    pc += op.encoded_len(pc);
+                }
+            }
+        }
+        assert_eq!(3, kinds.len());
+    }
 }
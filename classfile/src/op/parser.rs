@@ -1,107 +1,460 @@
-use super::Op;
-use crate::constant_pool::*;
+use super::{ArrayType, LookupSwitch, Op, TableSwitch, Wide};
+use crate::{ClassParseError, ClassParseErrorKind, ConstantPool, MethodRef};
 
 /// Parser state for parsing `Op`s
 #[derive(Debug)]
 pub(crate) struct Parser<'a> {
     raw: &'a [u8],
     cp: &'a ConstantPool,
+    /// Offset of the start of `raw` in the class file, used to compute switch padding.
+    start_offset: usize,
+    /// Offset of the end of `raw` in the class file, used for error reporting.
+    end_offset: usize,
+    major_version: u16,
+    cp_references: &'a mut Vec<u16>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(raw: &'a [u8], cp: &'a ConstantPool) -> Self {
-        Self { raw, cp }
+    /// `offset` is the position of `raw` within the class file.
+    pub fn new(
+        raw: &'a [u8],
+        cp: &'a ConstantPool,
+        offset: usize,
+        major_version: u16,
+        cp_references: &'a mut Vec<u16>,
+    ) -> Self {
+        Self {
+            raw,
+            cp,
+            start_offset: offset,
+            end_offset: offset + raw.len(),
+            major_version,
+            cp_references,
+        }
     }
 
     /// Parses `Op`s from the parser's `raw` content (see `Self::new`).
-    pub fn run(mut self) -> Vec<Op<'static>> {
+    pub fn run(mut self) -> Result<Vec<Op>, ClassParseError> {
         let mut ops = Vec::new();
 
         while !self.raw.is_empty() {
             use Op::*;
 
-            let opcode = self.next_u8();
+            let op_offset = self.offset();
+            let opcode = self.next_u8()?;
+            if !crate::version::opcode_allowed(opcode, self.major_version) {
+                return Err(ClassParseError::new(
+                    op_offset,
+                    ClassParseErrorKind::OpcodeNotInVersion {
+                        opcode,
+                        major_version: self.major_version,
+                    },
+                ));
+            }
             let op = match opcode {
-                0x2a => Aload0,
-                0x2b => Aload1,
-                0x2c => Aload2,
-                0x2d => Aload3,
+                0x00 => Nop,
+                0x01 => AconstNull,
+                0x02 => Iconstm1,
+                0x03 => Iconst0,
+                0x04 => Iconst1,
+                0x05 => Iconst2,
+                0x06 => Iconst3,
+                0x07 => Iconst4,
+                0x08 => Iconst5,
+                0x09 => Lconst0,
+                0x0a => Lconst1,
+                0x0b => Fconst0,
+                0x0c => Fconst1,
+                0x0d => Fconst2,
+                0x0e => Dconst0,
+                0x0f => Dconst1,
+                0x10 => Bipush(self.next_i8()?),
+                0x11 => Sipush(self.next_i16()?),
+                0x12 => {
+                    let offset = self.offset();
+                    let idx = self.next_u8()?;
+                    self.cp_references.push(idx.into());
+                    Ldc(self
+                        .cp
+                        .get_category1_loadable(idx.into())
+                        .map_err(|kind| ClassParseError::new(offset, kind))?)
+                }
+                0x13 => LdcW(self.next_cp(ConstantPool::get_category1_loadable)?),
+                0x14 => Ldc2W(self.next_cp(ConstantPool::get_category2_loadable)?),
+                0x15 => Iload(self.next_u8()?),
+                0x16 => Lload(self.next_u8()?),
+                0x17 => Fload(self.next_u8()?),
+                0x18 => Dload(self.next_u8()?),
+                0x19 => Aload(self.next_u8()?),
                 0x1a => Iload0,
                 0x1b => Iload1,
                 0x1c => Iload2,
                 0x1d => Iload3,
-                0xb7 => {
-                    let methodref_index = self.next_be_u16();
-                    let r = match self.cp.get(methodref_index).unwrap() {
-                        Constant::InterfaceMethodref(x) => self.cp.get_methodref(*x, true),
-                        Constant::Methodref(x) => self.cp.get_methodref(*x, false),
-                        _ => panic!("expected [interface]methodref"),
-                    };
-
-                    Invokespecial(r)
-                }
-                0xb1 => Return,
-                0x10 => Bipush(self.next_i8()),
+                0x1e => Lload0,
+                0x1f => Lload1,
+                0x20 => Lload2,
+                0x21 => Lload3,
+                0x22 => Fload0,
+                0x23 => Fload1,
+                0x24 => Fload2,
+                0x25 => Fload3,
+                0x26 => Dload0,
+                0x27 => Dload1,
+                0x28 => Dload2,
+                0x29 => Dload3,
+                0x2a => Aload0,
+                0x2b => Aload1,
+                0x2c => Aload2,
+                0x2d => Aload3,
+                0x2e => Iaload,
+                0x2f => Laload,
+                0x30 => Faload,
+                0x31 => Daload,
+                0x32 => Aaload,
+                0x33 => Baload,
+                0x34 => Caload,
+                0x35 => Saload,
+                0x36 => Istore(self.next_u8()?),
+                0x37 => Lstore(self.next_u8()?),
+                0x38 => Fstore(self.next_u8()?),
+                0x39 => Dstore(self.next_u8()?),
+                0x3a => Astore(self.next_u8()?),
                 0x3b => Istore0,
                 0x3c => Istore1,
                 0x3d => Istore2,
                 0x3e => Istore3,
-                0x2 => Iconstm1,
-                0x3 => Iconst0,
-                0x4 => Iconst1,
-                0x5 => Iconst2,
-                0x6 => Iconst3,
-                0x7 => Iconst4,
-                0x8 => Iconst5,
-                0x60 => Iadd,
-                0xbb => {
-                    let idx = self.next_be_u16();
-                    New(self.cp.get_class(idx))
-                }
-                0x59 => Dup,
-                0xb5 => {
-                    let idx = self.next_be_u16();
-                    Putfield(self.cp.get_fieldref(idx))
-                }
-                0xb4 => {
-                    let idx = self.next_be_u16();
-                    Getfield(self.cp.get_fieldref(idx))
-                }
-                0xac => Ireturn,
+                0x3f => Lstore0,
+                0x40 => Lstore1,
+                0x41 => Lstore2,
+                0x42 => Lstore3,
+                0x43 => Fstore0,
+                0x44 => Fstore1,
+                0x45 => Fstore2,
+                0x46 => Fstore3,
+                0x47 => Dstore0,
+                0x48 => Dstore1,
+                0x49 => Dstore2,
+                0x4a => Dstore3,
                 0x4b => Astore0,
                 0x4c => Astore1,
                 0x4d => Astore2,
                 0x4e => Astore3,
-                0xb6 => {
-                    let idx = self.next_be_u16();
-                    let r = match self.cp.get(idx).unwrap() {
-                        Constant::Methodref(x) => self.cp.get_methodref(*x, false),
-                        _ => panic!("expected methodref"),
-                    };
-
-                    Invokevirtual(r)
+                0x4f => Iastore,
+                0x50 => Lastore,
+                0x51 => Fastore,
+                0x52 => Dastore,
+                0x53 => Aastore,
+                0x54 => Bastore,
+                0x55 => Castore,
+                0x56 => Sastore,
+                0x57 => Pop,
+                0x58 => Pop2,
+                0x59 => Dup,
+                0x5a => DupX1,
+                0x5b => DupX2,
+                0x5c => Dup2,
+                0x5d => Dup2X1,
+                0x5e => Dup2X2,
+                0x5f => Swap,
+                0x60 => Iadd,
+                0x61 => Ladd,
+                0x62 => Fadd,
+                0x63 => Dadd,
+                0x64 => Isub,
+                0x65 => Lsub,
+                0x66 => Fsub,
+                0x67 => Dsub,
+                0x68 => Imul,
+                0x69 => Lmul,
+                0x6a => Fmul,
+                0x6b => Dmul,
+                0x6c => Idiv,
+                0x6d => Ldiv,
+                0x6e => Fdiv,
+                0x6f => Ddiv,
+                0x70 => Irem,
+                0x71 => Lrem,
+                0x72 => Frem,
+                0x73 => Drem,
+                0x74 => Ineg,
+                0x75 => Lneg,
+                0x76 => Fneg,
+                0x77 => Dneg,
+                0x78 => Ishl,
+                0x79 => Lshl,
+                0x7a => Ishr,
+                0x7b => Lshr,
+                0x7c => Iushr,
+                0x7d => Lushr,
+                0x7e => Iand,
+                0x7f => Land,
+                0x80 => Ior,
+                0x81 => Lor,
+                0x82 => Ixor,
+                0x83 => Lxor,
+                0x84 => Iinc {
+                    index: self.next_u8()?,
+                    constant: self.next_i8()?,
+                },
+                0x85 => I2l,
+                0x86 => I2f,
+                0x87 => I2d,
+                0x88 => L2i,
+                0x89 => L2f,
+                0x8a => L2d,
+                0x8b => F2i,
+                0x8c => F2l,
+                0x8d => F2d,
+                0x8e => D2i,
+                0x8f => D2l,
+                0x90 => D2f,
+                0x91 => I2b,
+                0x92 => I2c,
+                0x93 => I2s,
+                0x94 => Lcmp,
+                0x95 => Fcmpl,
+                0x96 => Fcmpg,
+                0x97 => Dcmpl,
+                0x98 => Dcmpg,
+                0x99 => Ifeq(self.next_i16()?),
+                0x9a => Ifne(self.next_i16()?),
+                0x9b => Iflt(self.next_i16()?),
+                0x9c => Ifge(self.next_i16()?),
+                0x9d => Ifgt(self.next_i16()?),
+                0x9e => Ifle(self.next_i16()?),
+                0x9f => IfIcmpeq(self.next_i16()?),
+                0xa0 => IfIcmpne(self.next_i16()?),
+                0xa1 => IfIcmplt(self.next_i16()?),
+                0xa2 => IfIcmpge(self.next_i16()?),
+                0xa3 => IfIcmpgt(self.next_i16()?),
+                0xa4 => IfIcmple(self.next_i16()?),
+                0xa5 => IfAcmpeq(self.next_i16()?),
+                0xa6 => IfAcmpne(self.next_i16()?),
+                0xa7 => Goto(self.next_i16()?),
+                0xa8 => Jsr(self.next_i16()?),
+                0xa9 => Ret(self.next_u8()?),
+                0xaa => {
+                    let padding = self.switch_padding()?;
+                    let default = self.next_i32()?;
+                    let low = self.next_i32()?;
+                    let high = self.next_i32()?;
+                    if low > high {
+                        return Err(ClassParseError::new(
+                            op_offset,
+                            ClassParseErrorKind::BadSwitch,
+                        ));
+                    }
+                    let offsets = (low..=high)
+                        .map(|_| self.next_i32())
+                        .collect::<Result<_, _>>()?;
+                    Tableswitch(TableSwitch {
+                        padding,
+                        default,
+                        low,
+                        high,
+                        offsets,
+                    })
+                }
+                0xab => {
+                    let padding = self.switch_padding()?;
+                    let default = self.next_i32()?;
+                    let npairs = self.next_i32()?;
+                    if npairs < 0 {
+                        return Err(ClassParseError::new(
+                            op_offset,
+                            ClassParseErrorKind::BadSwitch,
+                        ));
+                    }
+                    let pairs = (0..npairs)
+                        .map(|_| Ok((self.next_i32()?, self.next_i32()?)))
+                        .collect::<Result<_, _>>()?;
+                    Lookupswitch(LookupSwitch {
+                        padding,
+                        default,
+                        pairs,
+                    })
+                }
+                0xac => Ireturn,
+                0xad => Lreturn,
+                0xae => Freturn,
+                0xaf => Dreturn,
+                0xb0 => Areturn,
+                0xb1 => Return,
+                0xb2 => Getstatic(self.next_cp(ConstantPool::get_fieldref)?),
+                0xb3 => Putstatic(self.next_cp(ConstantPool::get_fieldref)?),
+                0xb4 => Getfield(self.next_cp(ConstantPool::get_fieldref)?),
+                0xb5 => Putfield(self.next_cp(ConstantPool::get_fieldref)?),
+                0xb6 => Invokevirtual(self.next_cp(|cp, idx| match cp.get_methodref(idx)? {
+                    MethodRef {
+                        is_interface: true, ..
+                    } => Err(ClassParseErrorKind::WrongConstantKind {
+                        index: idx,
+                        expected: "Methodref",
+                    }),
+                    methodref => Ok(methodref),
+                })?),
+                0xb7 => Invokespecial(self.next_cp(ConstantPool::get_methodref)?),
+                0xb8 => Invokestatic(self.next_cp(ConstantPool::get_methodref)?),
+                0xb9 => {
+                    let method = self.next_cp(|cp, idx| match cp.get_methodref(idx)? {
+                        MethodRef {
+                            is_interface: false,
+                            ..
+                        } => Err(ClassParseErrorKind::WrongConstantKind {
+                            index: idx,
+                            expected: "InterfaceMethodref",
+                        }),
+                        methodref => Ok(methodref),
+                    })?;
+                    let count = self.next_u8()?;
+                    self.next_zero_byte()?;
+                    Invokeinterface { method, count }
+                }
+                0xba => {
+                    let invokedynamic = self.next_cp(ConstantPool::get_invoke_dynamic)?;
+                    self.next_zero_byte()?;
+                    self.next_zero_byte()?;
+                    Invokedynamic(invokedynamic)
+                }
+                0xbb => New(self.next_cp(|cp, idx| cp.get_class(idx).cloned())?),
+                0xbc => {
+                    let offset = self.offset();
+                    let atype = self.next_u8()?;
+                    Newarray(ArrayType::from_u8(atype).ok_or_else(|| {
+                        ClassParseError::new(offset, ClassParseErrorKind::BadArrayType(atype))
+                    })?)
+                }
+                0xbd => Anewarray(self.next_cp(|cp, idx| cp.get_class(idx).cloned())?),
+                0xbe => Arraylength,
+                0xbf => Athrow,
+                0xc0 => Checkcast(self.next_cp(|cp, idx| cp.get_class(idx).cloned())?),
+                0xc1 => Instanceof(self.next_cp(|cp, idx| cp.get_class(idx).cloned())?),
+                0xc2 => Monitorenter,
+                0xc3 => Monitorexit,
+                0xc4 => Wide(self.parse_wide()?),
+                0xc5 => Multianewarray {
+                    class: self.next_cp(|cp, idx| cp.get_class(idx).cloned())?,
+                    dimensions: self.next_u8()?,
+                },
+                0xc6 => Ifnull(self.next_i16()?),
+                0xc7 => Ifnonnull(self.next_i16()?),
+                0xc8 => GotoW(self.next_i32()?),
+                0xc9 => JsrW(self.next_i32()?),
+                x => {
+                    return Err(ClassParseError::new(
+                        op_offset,
+                        ClassParseErrorKind::BadOpcode(x),
+                    ))
                 }
-                x => panic!("Unknown opcode 0x{x:X}"),
             };
-            ops.push(unsafe { std::mem::transmute::<Op<'_>, Op<'static>>(op) });
+            ops.push(op);
+        }
+
+        Ok(ops)
+    }
+
+    /// Parses the instruction following a `wide` opcode.
+    fn parse_wide(&mut self) -> Result<Wide, ClassParseError> {
+        use Wide::*;
+
+        let op_offset = self.offset();
+        Ok(match self.next_u8()? {
+            0x15 => Iload(self.next_be_u16()?),
+            0x16 => Lload(self.next_be_u16()?),
+            0x17 => Fload(self.next_be_u16()?),
+            0x18 => Dload(self.next_be_u16()?),
+            0x19 => Aload(self.next_be_u16()?),
+            0x36 => Istore(self.next_be_u16()?),
+            0x37 => Lstore(self.next_be_u16()?),
+            0x38 => Fstore(self.next_be_u16()?),
+            0x39 => Dstore(self.next_be_u16()?),
+            0x3a => Astore(self.next_be_u16()?),
+            0x84 => Iinc {
+                index: self.next_be_u16()?,
+                constant: self.next_i16()?,
+            },
+            0xa9 => Ret(self.next_be_u16()?),
+            x => {
+                return Err(ClassParseError::new(
+                    op_offset,
+                    ClassParseErrorKind::BadOpcode(x),
+                ))
+            }
+        })
+    }
+
+    fn offset(&self) -> usize {
+        self.end_offset - self.raw.len()
+    }
+
+    /// Reads the 0-3 bytes after a switch opcode that align its operands to a multiple of four
+    /// bytes from the start of the code. They only have to be zero before version 51.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.10.1>
+    fn switch_padding(&mut self) -> Result<[u8; 3], ClassParseError> {
+        let mut padding = [0; 3];
+        for byte in &mut padding {
+            if (self.offset() - self.start_offset).is_multiple_of(4) {
+                break;
+            }
+            if self.major_version < 51 {
+                self.next_zero_byte()?;
+            } else {
+                *byte = self.next_u8()?;
+            }
+        }
+        Ok(padding)
+    }
+
+    /// Consumes a byte that the spec requires to be zero.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.9.1>
+    fn next_zero_byte(&mut self) -> Result<(), ClassParseError> {
+        let offset = self.offset();
+        match self.next_u8()? {
+            0 => Ok(()),
+            byte => Err(ClassParseError::new(
+                offset,
+                ClassParseErrorKind::NonZeroReservedByte(byte),
+            )),
         }
+    }
+
+    fn next_i8(&mut self) -> Result<i8, ClassParseError> {
+        Ok(self.next_u8()? as i8)
+    }
+
+    fn next_u8(&mut self) -> Result<u8, ClassParseError> {
+        let (&it, rest) = self.raw.split_first().ok_or_else(|| {
+            ClassParseError::new(self.end_offset, ClassParseErrorKind::UnexpectedEof)
+        })?;
+        self.raw = rest;
+        Ok(it)
+    }
 
-        ops
+    fn next_be_u16(&mut self) -> Result<u16, ClassParseError> {
+        let index1: u16 = self.next_u8()?.into();
+        let index2: u16 = self.next_u8()?.into();
+        Ok(index1 << 8 | index2)
     }
 
-    fn next_i8(&mut self) -> i8 {
-        unsafe { std::mem::transmute::<u8, i8>(self.next_u8()) }
+    fn next_i16(&mut self) -> Result<i16, ClassParseError> {
+        Ok(self.next_be_u16()? as i16)
     }
 
-    fn next_u8(&mut self) -> u8 {
-        let it = self.raw[0];
-        self.raw = &self.raw[1..];
-        it
+    fn next_i32(&mut self) -> Result<i32, ClassParseError> {
+        let high: u32 = self.next_be_u16()?.into();
+        let low: u32 = self.next_be_u16()?.into();
+        Ok((high << 16 | low) as i32)
     }
 
-    fn next_be_u16(&mut self) -> u16 {
-        let index1: u16 = self.next_u8().into();
-        let index2: u16 = self.next_u8().into();
-        index1 << 8 | index2
+    /// Reads a constant pool index and looks it up with `lookup`. Errors from the lookup are
+    /// reported at the offset of the index.
+    fn next_cp<T>(
+        &mut self,
+        lookup: impl FnOnce(&'a ConstantPool, u16) -> Result<T, ClassParseErrorKind>,
+    ) -> Result<T, ClassParseError> {
+        let offset = self.offset();
+        let idx = self.next_be_u16()?;
+        self.cp_references.push(idx);
+        lookup(self.cp, idx).map_err(|kind| ClassParseError::new(offset, kind))
     }
 }
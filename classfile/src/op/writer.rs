@@ -0,0 +1,405 @@
+use super::{Op, Wide};
+use crate::writer::ConstantPoolWriter;
+use crate::ClassWriteError;
+
+/// Writer state for encoding `Op`s
+pub(crate) struct Writer<'a> {
+    out: Vec<u8>,
+    cp: &'a mut ConstantPoolWriter,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(cp: &'a mut ConstantPoolWriter) -> Self {
+        Self {
+            out: Vec::new(),
+            cp,
+        }
+    }
+
+    /// Encodes `ops` as the contents of a code array.
+    pub fn run(mut self, ops: &[Op]) -> Result<Vec<u8>, ClassWriteError> {
+        for op in ops {
+            self.write_op(op)?;
+        }
+        Ok(self.out)
+    }
+
+    fn write_op(&mut self, op: &Op) -> Result<(), ClassWriteError> {
+        use Op::*;
+
+        let opcode = match op {
+            Nop => 0x00,
+            AconstNull => 0x01,
+            Iconstm1 => 0x02,
+            Iconst0 => 0x03,
+            Iconst1 => 0x04,
+            Iconst2 => 0x05,
+            Iconst3 => 0x06,
+            Iconst4 => 0x07,
+            Iconst5 => 0x08,
+            Lconst0 => 0x09,
+            Lconst1 => 0x0a,
+            Fconst0 => 0x0b,
+            Fconst1 => 0x0c,
+            Fconst2 => 0x0d,
+            Dconst0 => 0x0e,
+            Dconst1 => 0x0f,
+            Bipush(x) => {
+                self.u8(0x10);
+                self.u8(*x as u8);
+                return Ok(());
+            }
+            Sipush(x) => {
+                self.u8(0x11);
+                self.u16(*x as u16);
+                return Ok(());
+            }
+            Ldc(constant) => {
+                let index = self.cp.loadable(constant)?;
+                let index = self.cp.hinted(index);
+                let index =
+                    u8::try_from(index).map_err(|_| ClassWriteError::LdcIndexTooLarge(index))?;
+                self.u8(0x12);
+                self.u8(index);
+                return Ok(());
+            }
+            LdcW(constant) => {
+                let index = self.cp.loadable(constant)?;
+                self.u8(0x13);
+                self.index(index);
+                return Ok(());
+            }
+            Ldc2W(constant) => {
+                let index = self.cp.loadable(constant)?;
+                self.u8(0x14);
+                self.index(index);
+                return Ok(());
+            }
+            Iload(index) => return self.with_u8(0x15, *index),
+            Lload(index) => return self.with_u8(0x16, *index),
+            Fload(index) => return self.with_u8(0x17, *index),
+            Dload(index) => return self.with_u8(0x18, *index),
+            Aload(index) => return self.with_u8(0x19, *index),
+            Iload0 => 0x1a,
+            Iload1 => 0x1b,
+            Iload2 => 0x1c,
+            Iload3 => 0x1d,
+            Lload0 => 0x1e,
+            Lload1 => 0x1f,
+            Lload2 => 0x20,
+            Lload3 => 0x21,
+            Fload0 => 0x22,
+            Fload1 => 0x23,
+            Fload2 => 0x24,
+            Fload3 => 0x25,
+            Dload0 => 0x26,
+            Dload1 => 0x27,
+            Dload2 => 0x28,
+            Dload3 => 0x29,
+            Aload0 => 0x2a,
+            Aload1 => 0x2b,
+            Aload2 => 0x2c,
+            Aload3 => 0x2d,
+            Iaload => 0x2e,
+            Laload => 0x2f,
+            Faload => 0x30,
+            Daload => 0x31,
+            Aaload => 0x32,
+            Baload => 0x33,
+            Caload => 0x34,
+            Saload => 0x35,
+            Istore(index) => return self.with_u8(0x36, *index),
+            Lstore(index) => return self.with_u8(0x37, *index),
+            Fstore(index) => return self.with_u8(0x38, *index),
+            Dstore(index) => return self.with_u8(0x39, *index),
+            Astore(index) => return self.with_u8(0x3a, *index),
+            Istore0 => 0x3b,
+            Istore1 => 0x3c,
+            Istore2 => 0x3d,
+            Istore3 => 0x3e,
+            Lstore0 => 0x3f,
+            Lstore1 => 0x40,
+            Lstore2 => 0x41,
+            Lstore3 => 0x42,
+            Fstore0 => 0x43,
+            Fstore1 => 0x44,
+            Fstore2 => 0x45,
+            Fstore3 => 0x46,
+            Dstore0 => 0x47,
+            Dstore1 => 0x48,
+            Dstore2 => 0x49,
+            Dstore3 => 0x4a,
+            Astore0 => 0x4b,
+            Astore1 => 0x4c,
+            Astore2 => 0x4d,
+            Astore3 => 0x4e,
+            Iastore => 0x4f,
+            Lastore => 0x50,
+            Fastore => 0x51,
+            Dastore => 0x52,
+            Aastore => 0x53,
+            Bastore => 0x54,
+            Castore => 0x55,
+            Sastore => 0x56,
+            Pop => 0x57,
+            Pop2 => 0x58,
+            Dup => 0x59,
+            DupX1 => 0x5a,
+            DupX2 => 0x5b,
+            Dup2 => 0x5c,
+            Dup2X1 => 0x5d,
+            Dup2X2 => 0x5e,
+            Swap => 0x5f,
+            Iadd => 0x60,
+            Ladd => 0x61,
+            Fadd => 0x62,
+            Dadd => 0x63,
+            Isub => 0x64,
+            Lsub => 0x65,
+            Fsub => 0x66,
+            Dsub => 0x67,
+            Imul => 0x68,
+            Lmul => 0x69,
+            Fmul => 0x6a,
+            Dmul => 0x6b,
+            Idiv => 0x6c,
+            Ldiv => 0x6d,
+            Fdiv => 0x6e,
+            Ddiv => 0x6f,
+            Irem => 0x70,
+            Lrem => 0x71,
+            Frem => 0x72,
+            Drem => 0x73,
+            Ineg => 0x74,
+            Lneg => 0x75,
+            Fneg => 0x76,
+            Dneg => 0x77,
+            Ishl => 0x78,
+            Lshl => 0x79,
+            Ishr => 0x7a,
+            Lshr => 0x7b,
+            Iushr => 0x7c,
+            Lushr => 0x7d,
+            Iand => 0x7e,
+            Land => 0x7f,
+            Ior => 0x80,
+            Lor => 0x81,
+            Ixor => 0x82,
+            Lxor => 0x83,
+            Iinc { index, constant } => {
+                self.u8(0x84);
+                self.u8(*index);
+                self.u8(*constant as u8);
+                return Ok(());
+            }
+            I2l => 0x85,
+            I2f => 0x86,
+            I2d => 0x87,
+            L2i => 0x88,
+            L2f => 0x89,
+            L2d => 0x8a,
+            F2i => 0x8b,
+            F2l => 0x8c,
+            F2d => 0x8d,
+            D2i => 0x8e,
+            D2l => 0x8f,
+            D2f => 0x90,
+            I2b => 0x91,
+            I2c => 0x92,
+            I2s => 0x93,
+            Lcmp => 0x94,
+            Fcmpl => 0x95,
+            Fcmpg => 0x96,
+            Dcmpl => 0x97,
+            Dcmpg => 0x98,
+            Ifeq(offset) => return self.with_i16(0x99, *offset),
+            Ifne(offset) => return self.with_i16(0x9a, *offset),
+            Iflt(offset) => return self.with_i16(0x9b, *offset),
+            Ifge(offset) => return self.with_i16(0x9c, *offset),
+            Ifgt(offset) => return self.with_i16(0x9d, *offset),
+            Ifle(offset) => return self.with_i16(0x9e, *offset),
+            IfIcmpeq(offset) => return self.with_i16(0x9f, *offset),
+            IfIcmpne(offset) => return self.with_i16(0xa0, *offset),
+            IfIcmplt(offset) => return self.with_i16(0xa1, *offset),
+            IfIcmpge(offset) => return self.with_i16(0xa2, *offset),
+            IfIcmpgt(offset) => return self.with_i16(0xa3, *offset),
+            IfIcmple(offset) => return self.with_i16(0xa4, *offset),
+            IfAcmpeq(offset) => return self.with_i16(0xa5, *offset),
+            IfAcmpne(offset) => return self.with_i16(0xa6, *offset),
+            Goto(offset) => return self.with_i16(0xa7, *offset),
+            Jsr(offset) => return self.with_i16(0xa8, *offset),
+            Ret(index) => return self.with_u8(0xa9, *index),
+            Tableswitch(switch) => {
+                self.u8(0xaa);
+                self.switch_padding(&switch.padding);
+                self.i32(switch.default);
+                self.i32(switch.low);
+                self.i32(switch.high);
+                for offset in &switch.offsets {
+                    self.i32(*offset);
+                }
+                return Ok(());
+            }
+            Lookupswitch(switch) => {
+                self.u8(0xab);
+                self.switch_padding(&switch.padding);
+                self.i32(switch.default);
+                let npairs = i32::try_from(switch.pairs.len())
+                    .map_err(|_| ClassWriteError::TooLong("lookupswitch"))?;
+                self.i32(npairs);
+                for (value, offset) in &switch.pairs {
+                    self.i32(*value);
+                    self.i32(*offset);
+                }
+                return Ok(());
+            }
+            Ireturn => 0xac,
+            Lreturn => 0xad,
+            Freturn => 0xae,
+            Dreturn => 0xaf,
+            Areturn => 0xb0,
+            Return => 0xb1,
+            Getstatic(field) | Putstatic(field) | Getfield(field) | Putfield(field) => {
+                let index = self.cp.fieldref(field)?;
+                self.u8(match op {
+                    Getstatic(_) => 0xb2,
+                    Putstatic(_) => 0xb3,
+                    Getfield(_) => 0xb4,
+                    _ => 0xb5,
+                });
+                self.index(index);
+                return Ok(());
+            }
+            Invokevirtual(method) | Invokespecial(method) | Invokestatic(method) => {
+                let index = self.cp.methodref(method)?;
+                self.u8(match op {
+                    Invokevirtual(_) => 0xb6,
+                    Invokespecial(_) => 0xb7,
+                    _ => 0xb8,
+                });
+                self.index(index);
+                return Ok(());
+            }
+            Invokeinterface { method, count } => {
+                let index = self.cp.methodref(method)?;
+                self.u8(0xb9);
+                self.index(index);
+                self.u8(*count);
+                self.u8(0);
+                return Ok(());
+            }
+            Invokedynamic(call_site) => {
+                let index = self.cp.invoke_dynamic(call_site)?;
+                self.u8(0xba);
+                self.index(index);
+                self.u16(0);
+                return Ok(());
+            }
+            New(class) => return self.with_class(0xbb, class),
+            Newarray(atype) => return self.with_u8(0xbc, *atype as u8),
+            Anewarray(class) => return self.with_class(0xbd, class),
+            Arraylength => 0xbe,
+            Athrow => 0xbf,
+            Checkcast(class) => return self.with_class(0xc0, class),
+            Instanceof(class) => return self.with_class(0xc1, class),
+            Monitorenter => 0xc2,
+            Monitorexit => 0xc3,
+            Wide(wide) => {
+                self.u8(0xc4);
+                self.write_wide(wide);
+                return Ok(());
+            }
+            Multianewarray { class, dimensions } => {
+                self.with_class(0xc5, class)?;
+                self.u8(*dimensions);
+                return Ok(());
+            }
+            Ifnull(offset) => return self.with_i16(0xc6, *offset),
+            Ifnonnull(offset) => return self.with_i16(0xc7, *offset),
+            GotoW(offset) => {
+                self.u8(0xc8);
+                self.i32(*offset);
+                return Ok(());
+            }
+            JsrW(offset) => {
+                self.u8(0xc9);
+                self.i32(*offset);
+                return Ok(());
+            }
+        };
+
+        self.u8(opcode);
+        Ok(())
+    }
+
+    /// Writes the instruction following a `wide` opcode.
+    fn write_wide(&mut self, wide: &Wide) {
+        use Wide::*;
+
+        let (opcode, index) = match wide {
+            Iload(index) => (0x15, index),
+            Lload(index) => (0x16, index),
+            Fload(index) => (0x17, index),
+            Dload(index) => (0x18, index),
+            Aload(index) => (0x19, index),
+            Istore(index) => (0x36, index),
+            Lstore(index) => (0x37, index),
+            Fstore(index) => (0x38, index),
+            Dstore(index) => (0x39, index),
+            Astore(index) => (0x3a, index),
+            Ret(index) => (0xa9, index),
+            Iinc { index, constant } => {
+                self.u8(0x84);
+                self.u16(*index);
+                self.u16(*constant as u16);
+                return;
+            }
+        };
+        self.u8(opcode);
+        self.u16(*index);
+    }
+
+    /// Pads after a switch opcode with the start of `padding`, so that its operands start at a
+    /// multiple of four bytes from the start of the code.
+    fn switch_padding(&mut self, padding: &[u8; 3]) {
+        let len = (4 - self.out.len() % 4) % 4;
+        self.out.extend_from_slice(&padding[..len]);
+    }
+
+    fn with_u8(&mut self, opcode: u8, operand: u8) -> Result<(), ClassWriteError> {
+        self.u8(opcode);
+        self.u8(operand);
+        Ok(())
+    }
+
+    fn with_i16(&mut self, opcode: u8, offset: i16) -> Result<(), ClassWriteError> {
+        self.u8(opcode);
+        self.u16(offset as u16);
+        Ok(())
+    }
+
+    fn with_class(&mut self, opcode: u8, class: &str) -> Result<(), ClassWriteError> {
+        let index = self.cp.class(class)?;
+        self.u8(opcode);
+        self.index(index);
+        Ok(())
+    }
+
+    fn u8(&mut self, x: u8) {
+        self.out.push(x);
+    }
+
+    /// Writes a reference to the constant at `index`.
+    fn index(&mut self, index: u16) {
+        let index = self.cp.hinted(index);
+        self.u16(index);
+    }
+
+    fn u16(&mut self, x: u16) {
+        self.out.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn i32(&mut self, x: i32) {
+        self.out.extend_from_slice(&x.to_be_bytes());
+    }
+}
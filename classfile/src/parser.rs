@@ -1,316 +1,2438 @@
-use crate::constant_pool::*;
+use std::sync::Arc;
+
+use crate::lazy::{LazyCode, RawCode};
 use crate::*;
-use std::mem;
+
+type Result<T> = std::result::Result<T, ClassParseError>;
+
+/// How many annotations and arrays an element value may be nested in.
+const MAX_ELEMENT_VALUE_DEPTH: usize = 255;
+
+/// Decodes a Code attribute that was skipped over by a parser with
+/// [`ParseOptions::lazy_code`], along with the constant pool indices it refers to. Warnings were
+/// already reported when the class was parsed.
+pub(crate) fn decode_code(raw: &RawCode) -> Result<(CodeAttribute, Vec<u16>)> {
+    let mut parser = Parser {
+        raw: &raw.bytes,
+        len: raw.offset + raw.bytes.len(),
+        options: raw.options,
+        major_version: raw.major_version,
+        warnings: Vec::new(),
+        cp_references: Vec::new(),
+    };
+    let code = parser.parse_code(&raw.constant_pool)?;
+    Ok((code, parser.cp_references))
+}
+
+/// Checks that every Dynamic and InvokeDynamic constant refers to an entry of the
+/// BootstrapMethods attribute.
+fn check_bootstrap_method_indices(
+    cp: &ConstantPool,
+    attributes: &[Attribute],
+) -> std::result::Result<(), ClassParseErrorKind> {
+    let num_bootstrap_methods = attributes
+        .iter()
+        .find_map(|attr| match attr {
+            Attribute::BootstrapMethods(methods) => Some(methods.len()),
+            _ => None,
+        })
+        .unwrap_or(0);
+
+    for (index, constant) in cp.iter() {
+        if let Constant::Dynamic(dynamic) | Constant::InvokeDynamic(dynamic) = constant {
+            if usize::from(dynamic.bootstrap_method_attr_index) >= num_bootstrap_methods {
+                return Err(ClassParseErrorKind::BadBootstrapMethodIndex {
+                    index,
+                    bootstrap_method_attr_index: dynamic.bootstrap_method_attr_index,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What an attribute is attached to, for attributes whose contents depend on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AttributeContext {
+    ClassFile,
+    Field,
+    Method,
+    Code,
+    RecordComponent,
+}
 
 /// Parser state for parsing .class files.
 #[derive(Debug)]
 pub struct Parser<'a> {
     raw: &'a [u8],
+    /// Length of the whole class file, used to compute offsets for errors.
+    len: usize,
+    options: ParseOptions,
+    /// The class file's major version. Nothing is restricted by version until it's been parsed.
+    major_version: u16,
+    warnings: Vec<ClassParseError>,
+    /// Every constant pool index that's been read, in order.
+    cp_references: Vec<u16>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(raw: &'a [u8]) -> Self {
-        Self { raw }
+        Self::with_options(raw, ParseOptions::default())
+    }
+
+    pub fn with_options(raw: &'a [u8], options: ParseOptions) -> Self {
+        Self {
+            raw,
+            len: raw.len(),
+            options,
+            major_version: u16::MAX,
+            warnings: Vec::new(),
+            cp_references: Vec::new(),
+        }
     }
 
-    /// Run the parser on the bytes it was constructed with (see `Self::new`).
-    pub fn run(mut self) -> ClassFile {
-        self.raw = self
-            .raw
-            .strip_prefix(&u32::to_be_bytes(0xCAFEBABEu32))
-            .expect("bad magic");
+    /// Run the parser on the bytes it was constructed with (see `Self::new`). Anything after
+    /// the end of the class file is left unparsed; see [`Self::remaining`].
+    pub fn run(&mut self) -> Result<ClassFile> {
+        let magic = self.next_u32()?;
+        if magic != 0xCAFEBABE {
+            return Err(self.error_at(0, ClassParseErrorKind::BadMagic(magic)));
+        }
 
-        let minor_version = self.next_u16();
-        let major_version = self.next_u16();
+        let minor_version = self.next_u16()?;
+        let major_version = self.next_u16()?;
+        self.major_version = major_version;
+        if !self.options.supports_version(major_version, minor_version) {
+            let error = self.error_at(
+                4,
+                ClassParseErrorKind::UnsupportedVersion {
+                    major_version,
+                    minor_version,
+                },
+            );
+            match self.options.unsupported_version {
+                VersionPolicy::Reject => return Err(error),
+                VersionPolicy::Warn => self.warnings.push(error),
+            }
+        }
 
-        let cp_cnt = self.next_u16();
-        let constant_pool = self.parse_sized_table(cp_cnt - 1, Self::parse_constant);
-        let constant_pool = ConstantPool::new(constant_pool);
+        let cp_cnt = self.next_u16()?;
+        let constant_pool = Arc::new(self.parse_constant_pool(cp_cnt)?);
 
-        let access_flags = self.next_u16();
-        let this_class = constant_pool.get_class(self.next_u16());
-        let super_class = constant_pool.get_class(self.next_u16());
+        let access_flags = self.next_u16()?;
+        let this_class = self.next_cp(|i| constant_pool.get_class(i).cloned())?;
+        let super_class = self.next_optional_cp(|i| constant_pool.get_class(i).cloned())?;
 
-        let interfaces_count = self.next_u16();
-        let interfaces = self
-            .parse_sized_table(interfaces_count, Self::next_u16)
-            .into_iter()
-            .map(|x| unsafe { mem::transmute::<&'_ str, &'static str>(constant_pool.get_utf8(x)) })
-            .collect();
+        let interfaces_count = self.next_u16()?;
+        let interfaces = self.parse_sized_table(interfaces_count, |p| {
+            p.next_cp(|i| constant_pool.get_class(i).cloned())
+        })?;
 
-        let fields_count = self.next_u16();
-        let fields = self.parse_sized_table(fields_count, |p| p.parse_field_info(&constant_pool));
+        let fields_count = self.next_u16()?;
+        let fields =
+            self.parse_sized_table(fields_count, |p| p.parse_field_info(&constant_pool))?;
 
-        let methods_count = self.next_u16();
+        let methods_count = self.next_u16()?;
         let methods =
-            self.parse_sized_table(methods_count, |p| p.parse_method_info(&constant_pool));
-
-        let attributes_count = self.next_u16();
-        let attributes =
-            self.parse_sized_table(attributes_count, |p| p.parse_attribute(&constant_pool));
-
-        unsafe {
-            ClassFile {
-                major_version,
-                minor_version,
-                access_flags: super::AccessFlags::new(access_flags),
-                this_class: mem::transmute::<&'_ str, &'static str>(this_class),
-                super_class: mem::transmute::<&'_ str, &'static str>(super_class),
-                _constant_pool: constant_pool,
-                interfaces,
-                fields,
-                methods,
-                attributes,
-            }
-        }
+            self.parse_sized_table(methods_count, |p| p.parse_method_info(&constant_pool))?;
+
+        let attributes_offset = self.offset();
+        let attributes_count = self.next_u16()?;
+        let attributes = self.parse_sized_table(attributes_count, |p| {
+            p.parse_attribute(&constant_pool, AttributeContext::ClassFile)
+        })?;
+        check_bootstrap_method_indices(&constant_pool, &attributes)
+            .map_err(|kind| self.error_at(attributes_offset, kind))?;
+
+        Ok(ClassFile {
+            major_version,
+            minor_version,
+            access_flags: ClassAccessFlags::new(access_flags),
+            this_class,
+            super_class,
+            constant_pool,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+            warnings: std::mem::take(&mut self.warnings),
+            cp_references: std::mem::take(&mut self.cp_references),
+        })
     }
 
-    fn next_u16(&mut self) -> u16 {
-        type Ret = u16;
-        let (bytes, raw) = self.raw.split_at(std::mem::size_of::<Ret>());
-        self.raw = raw;
-        // SAFETY: split_at returns a pointer to size_of<Ret> bytes
-        let bytes = unsafe { *(bytes.as_ptr() as *const [u8; std::mem::size_of::<Ret>()]) };
-        Ret::from_be_bytes(bytes)
+    /// The number of bytes that haven't been parsed yet.
+    pub fn remaining(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Offset of the next unparsed byte in the class file.
+    fn offset(&self) -> usize {
+        self.len - self.raw.len()
+    }
+
+    fn error_at(&self, offset: usize, kind: ClassParseErrorKind) -> ClassParseError {
+        ClassParseError::new(offset, kind)
     }
 
-    fn next_u32(&mut self) -> u32 {
-        type Ret = u32;
-        let (bytes, raw) = self.raw.split_at(std::mem::size_of::<Ret>());
+    /// Consumes the next `n` bytes.
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.raw.len() {
+            return Err(self.error_at(self.len, ClassParseErrorKind::UnexpectedEof));
+        }
+        let (bytes, raw) = self.raw.split_at(n);
         self.raw = raw;
-        // SAFETY: split_at returns a pointer to size_of<Ret> bytes
-        let bytes = unsafe { *(bytes.as_ptr() as *const [u8; std::mem::size_of::<Ret>()]) };
-        Ret::from_be_bytes(bytes)
+        Ok(bytes)
+    }
+
+    fn next_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn next_u8(&mut self) -> Result<u8> {
+        Ok(u8::from_be_bytes(self.next_array()?))
     }
 
-    fn parse_sized_table<Len, F, T>(&mut self, len: Len, parse_fn: F) -> Vec<T>
+    fn next_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.next_array()?))
+    }
+
+    fn next_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.next_array()?))
+    }
+
+    /// Reads a constant pool index and looks it up with `lookup`. Errors from the lookup are
+    /// reported at the offset of the index.
+    fn next_cp<T>(
+        &mut self,
+        lookup: impl FnOnce(u16) -> std::result::Result<T, ClassParseErrorKind>,
+    ) -> Result<T> {
+        let offset = self.offset();
+        let index = self.next_u16()?;
+        self.cp_references.push(index);
+        lookup(index).map_err(|kind| self.error_at(offset, kind))
+    }
+
+    /// Like `Self::next_cp`, but an index of zero means `None`.
+    fn next_optional_cp<T>(
+        &mut self,
+        lookup: impl FnOnce(u16) -> std::result::Result<T, ClassParseErrorKind>,
+    ) -> Result<Option<T>> {
+        let offset = self.offset();
+        match self.next_u16()? {
+            0 => Ok(None),
+            index => {
+                self.cp_references.push(index);
+                lookup(index)
+                    .map(Some)
+                    .map_err(|kind| self.error_at(offset, kind))
+            }
+        }
+    }
+
+    fn parse_sized_table<Len, F, T>(&mut self, len: Len, parse_fn: F) -> Result<Vec<T>>
     where
         Len: Copy + Into<usize>,
-        F: Fn(&mut Self) -> T,
+        F: Fn(&mut Self) -> Result<T>,
     {
         let mut v = Vec::with_capacity(len.into());
         for _ in 0..len.into() {
-            v.push(parse_fn(self));
+            v.push(parse_fn(self)?);
         }
-        v
+        Ok(v)
     }
 
-    fn parse_constant(&mut self) -> Constant {
-        let tag = self.raw[0];
-        self.raw = &self.raw[1..];
+    /// Parses the constant pool, given its `constant_pool_count`.
+    fn parse_constant_pool(&mut self, cp_cnt: u16) -> Result<ConstantPool> {
+        let len = usize::from(cp_cnt.saturating_sub(1));
+        let mut storage = Vec::with_capacity(len);
 
-        use Constant::*;
+        while storage.len() < len {
+            let offset = self.offset();
+            let constant = self.parse_constant()?;
+            let is_wide = constant.is_wide();
+            storage.push(constant);
+
+            // Long and Double constants take up two entries in the table,
+            // see <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.5>
+            if is_wide {
+                if storage.len() == len {
+                    return Err(self.error_at(
+                        offset,
+                        ClassParseErrorKind::WideConstantOverflowsPool(storage.len() as u16),
+                    ));
+                }
+                storage.push(Constant::Unusable);
+            }
+        }
+
+        Ok(ConstantPool::new(storage, self.major_version))
+    }
 
-        match tag {
-            7 => Class(self.parse_class_constant()),
-            9 => Fieldref(self.parse_fieldref_constant()),
-            10 => Methodref(self.parse_methodref_constant()),
-            11 => InterfaceMethodref(self.parse_methodref_constant()),
-            12 => NameAndType(self.parse_name_and_type_constant()),
-            1 => Utf8(self.parse_utf8_constant()),
-            3 => {
-                let (bytes, raw) = self.raw.split_at(4);
-                self.raw = raw;
-                ConstantValue(ConstantValueKind::Integer(i32::from_be_bytes(
-                    bytes.try_into().unwrap(),
-                )))
-            }
-            4 => {
-                let (bytes, raw) = self.raw.split_at(4);
-                self.raw = raw;
-                ConstantValue(ConstantValueKind::Float(f32::from_be_bytes(
-                    bytes.try_into().unwrap(),
-                )))
-            }
-            5 => {
-                let (bytes, raw) = self.raw.split_at(8);
-                self.raw = raw;
-                ConstantValue(ConstantValueKind::Long(i64::from_be_bytes(
-                    bytes.try_into().unwrap(),
-                )))
-            }
-            6 => {
-                let (bytes, raw) = self.raw.split_at(8);
-                self.raw = raw;
-                ConstantValue(ConstantValueKind::Double(f64::from_be_bytes(
-                    bytes.try_into().unwrap(),
-                )))
-            }
-            8 => ConstantValue(ConstantValueKind::String(self.next_u16())),
-            x => panic!("unknown constant tag {x}"),
+    fn parse_constant(&mut self) -> Result<Constant> {
+        let tag_offset = self.offset();
+        let tag = self.next_u8()?;
+        if version::constant_major_version(tag).is_some_and(|since| since > self.major_version) {
+            return Err(self.error_at(
+                tag_offset,
+                ClassParseErrorKind::ConstantNotInVersion {
+                    tag,
+                    major_version: self.major_version,
+                },
+            ));
         }
+
+        use Constant::*;
+
+        Ok(match tag {
+            7 => Class(self.parse_class_constant()?),
+            9 => Fieldref(self.parse_fieldref_constant()?),
+            10 => Methodref(self.parse_methodref_constant()?),
+            11 => InterfaceMethodref(self.parse_methodref_constant()?),
+            12 => NameAndType(self.parse_name_and_type_constant()?),
+            1 => Utf8(self.parse_utf8_constant()?),
+            3 => ConstantValue(ConstantValueKind::Integer(i32::from_be_bytes(
+                self.next_array()?,
+            ))),
+            4 => ConstantValue(ConstantValueKind::Float(f32::from_be_bytes(
+                self.next_array()?,
+            ))),
+            5 => ConstantValue(ConstantValueKind::Long(i64::from_be_bytes(
+                self.next_array()?,
+            ))),
+            6 => ConstantValue(ConstantValueKind::Double(f64::from_be_bytes(
+                self.next_array()?,
+            ))),
+            8 => ConstantValue(ConstantValueKind::String(self.next_u16()?)),
+            15 => MethodHandle(self.parse_method_handle_constant()?),
+            16 => MethodType(MethodTypeConstant {
+                descriptor_index: self.next_u16()?,
+            }),
+            17 => Dynamic(self.parse_dynamic_constant()?),
+            18 => InvokeDynamic(self.parse_dynamic_constant()?),
+            19 => Module(ModuleConstant {
+                name_index: self.next_u16()?,
+            }),
+            20 => Package(PackageConstant {
+                name_index: self.next_u16()?,
+            }),
+            x => return Err(self.error_at(tag_offset, ClassParseErrorKind::BadConstantTag(x))),
+        })
     }
 
-    fn parse_class_constant(&mut self) -> ClassConstant {
-        let name_index = self.next_u16();
+    fn parse_class_constant(&mut self) -> Result<ClassConstant> {
+        let name_index = self.next_u16()?;
 
-        ClassConstant { name_index }
+        Ok(ClassConstant { name_index })
     }
 
-    fn parse_methodref_constant(&mut self) -> MethodrefConstant {
-        let class_index = self.next_u16();
-        let name_and_type_index = self.next_u16();
+    fn parse_methodref_constant(&mut self) -> Result<MethodrefConstant> {
+        let class_index = self.next_u16()?;
+        let name_and_type_index = self.next_u16()?;
 
-        MethodrefConstant {
+        Ok(MethodrefConstant {
             class_index,
             name_and_type_index,
-        }
+        })
     }
 
-    fn parse_fieldref_constant(&mut self) -> FieldrefConstant {
-        let class_index = self.next_u16();
-        let name_and_type_index = self.next_u16();
+    fn parse_fieldref_constant(&mut self) -> Result<FieldrefConstant> {
+        let class_index = self.next_u16()?;
+        let name_and_type_index = self.next_u16()?;
 
-        FieldrefConstant {
+        Ok(FieldrefConstant {
             class_index,
             name_and_type_index,
-        }
+        })
     }
 
-    fn parse_name_and_type_constant(&mut self) -> NameAndTypeConstant {
-        let name_index = self.next_u16();
-        let descriptor_index = self.next_u16();
+    fn parse_name_and_type_constant(&mut self) -> Result<NameAndTypeConstant> {
+        let name_index = self.next_u16()?;
+        let descriptor_index = self.next_u16()?;
 
-        NameAndTypeConstant {
+        Ok(NameAndTypeConstant {
             name_index,
             descriptor_index,
-        }
+        })
     }
 
-    fn parse_utf8_constant(&mut self) -> String {
-        let len = self.next_u16();
-        let (bytes, raw) = self.raw.split_at(len as usize);
-        self.raw = raw;
+    fn parse_method_handle_constant(&mut self) -> Result<MethodHandleConstant> {
+        let kind_offset = self.offset();
+        let kind = self.next_u8()?;
+        let reference_kind = ReferenceKind::from_u8(kind).ok_or_else(|| {
+            self.error_at(kind_offset, ClassParseErrorKind::BadReferenceKind(kind))
+        })?;
+        let reference_index = self.next_u16()?;
 
-        String::from_utf8(Vec::from(bytes)).unwrap()
+        Ok(MethodHandleConstant {
+            reference_kind,
+            reference_index,
+        })
     }
 
-    fn parse_field_info(&mut self, cp: &ConstantPool) -> FieldInfo<'static> {
-        let access_flags = self.next_u16();
-        let name_string = cp.get_utf8(self.next_u16());
-        let descriptor_string = cp.get_utf8(self.next_u16());
-        let attribute_count = self.next_u16();
-        let attributes = self.parse_sized_table(attribute_count, |p| p.parse_attribute(cp));
+    fn parse_dynamic_constant(&mut self) -> Result<DynamicConstant> {
+        let bootstrap_method_attr_index = self.next_u16()?;
+        let name_and_type_index = self.next_u16()?;
 
-        FieldInfo {
-            access_flags: AccessFlags::new(access_flags),
-            name: unsafe { mem::transmute::<&'_ str, &'static str>(name_string) },
-            descriptor: unsafe {
-                mem::transmute::<FieldDescriptor<'_>, FieldDescriptor<'static>>(
-                    crate::descriptor::parse_field_descriptor(descriptor_string),
-                )
-            },
+        Ok(DynamicConstant {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        })
+    }
+
+    fn parse_utf8_constant(&mut self) -> Result<JavaString> {
+        let len = self.next_u16()?;
+        let offset = self.offset();
+        let bytes = self.take(len as usize)?;
+
+        JavaString::from_modified_utf8(bytes)
+            .map_err(|e| self.error_at(offset + e.position, ClassParseErrorKind::BadUtf8))
+    }
+
+    fn parse_field_info(&mut self, cp: &ConstantPool) -> Result<FieldInfo> {
+        let access_flags = self.next_u16()?;
+        let name = self.next_cp(|i| cp.get_utf8(i).cloned())?;
+        let descriptor = self.next_cp(|i| descriptor::parse_field_descriptor(cp.get_utf8(i)?))?;
+        let attribute_count = self.next_u16()?;
+        let attributes = self.parse_sized_table(attribute_count, |p| {
+            p.parse_attribute(cp, AttributeContext::Field)
+        })?;
+
+        Ok(FieldInfo {
+            access_flags: FieldAccessFlags::new(access_flags),
+            name,
+            descriptor,
             attributes,
-        }
+        })
     }
 
-    fn parse_method_info(&mut self, cp: &ConstantPool) -> MethodInfo<'static> {
-        let access_flags = self.next_u16();
-        let name_string = cp.get_utf8(self.next_u16());
-        let descriptor_string = cp.get_utf8(self.next_u16());
-        let descriptor = crate::descriptor::parse_method_descriptor(descriptor_string);
-        let attributes_count = self.next_u16();
-        let mut attributes = self.parse_sized_table(attributes_count, |p| p.parse_attribute(cp));
+    fn parse_method_info(&mut self, cp: &Arc<ConstantPool>) -> Result<MethodInfo> {
+        let access_flags = self.next_u16()?;
+        let name = self.next_cp(|i| cp.get_utf8(i).cloned())?;
+        let descriptor = self.next_cp(|i| descriptor::parse_method_descriptor(cp.get_utf8(i)?))?;
+        let attributes_count = self.next_u16()?;
 
+        let mut attributes = Vec::with_capacity(attributes_count.into());
         let mut code = None;
-        for i in 0..attributes.len() {
-            if let Attribute::Code(_) = attributes[i] {
-                code = Some(match attributes.remove(i) {
-                    Attribute::Code(x) => x,
-                    _ => unreachable!(),
-                });
+        let mut code_position = None;
+        for _ in 0..attributes_count {
+            if self.options.lazy_code && code.is_none() && self.next_attribute_is(cp, "Code") {
+                code_position = Some(attributes.len());
+                code = Some(LazyCode::raw(self.skim_code(cp)?));
+            } else {
+                attributes.push(self.parse_attribute(cp, AttributeContext::Method)?);
             }
         }
 
-        unsafe {
-            MethodInfo {
-                code,
-                access_flags: AccessFlags::new(access_flags),
-                name: mem::transmute::<&'_ str, &'static str>(name_string),
-                descriptor: mem::transmute::<MethodDescriptor<'_>, MethodDescriptor<'static>>(
-                    descriptor,
-                ),
-                attributes,
-            }
+        if code.is_none() {
+            code_position = attributes
+                .iter()
+                .position(|x| matches!(x, Attribute::Code(_)));
+            code = code_position.map(|i| match attributes.remove(i) {
+                Attribute::Code(x) => LazyCode::decoded(x),
+                _ => unreachable!(),
+            });
         }
+
+        Ok(MethodInfo {
+            code,
+            access_flags: MethodAccessFlags::new(access_flags),
+            name,
+            descriptor,
+            attributes,
+            code_position: code_position.unwrap_or(0),
+        })
+    }
+
+    /// Whether the next attribute is called `name`. Errors are left for
+    /// [`Self::parse_attribute`] to report.
+    fn next_attribute_is(&self, cp: &ConstantPool, name: &str) -> bool {
+        self.raw
+            .first_chunk()
+            .and_then(|&index| cp.get_utf8(u16::from_be_bytes(index)).ok())
+            .is_some_and(|attribute_name| attribute_name.as_str() == name)
+    }
+
+    /// Skips over a Code attribute without decoding it, for [`ParseOptions::lazy_code`]. Its
+    /// structure is still checked, so that the attribute lengths are known to be right and
+    /// version warnings are reported as if it had been parsed.
+    fn skim_code(&mut self, cp: &Arc<ConstantPool>) -> Result<RawCode> {
+        let name_offset = self.offset();
+        let attribute_name = self.next_cp(|i| cp.get_utf8(i))?;
+        let attribute_length = self.next_u32()?;
+        let offset = self.offset();
+        let body = self.raw;
+
+        self.take(4)?; // max_stack and max_locals
+        let code_length = self.next_u32()?;
+        self.take(code_length as usize)?;
+        let exception_table_length = self.next_u16()?;
+        self.take(usize::from(exception_table_length) * 8)?;
+
+        let attributes_count = self.next_u16()?;
+        for _ in 0..attributes_count {
+            // The name isn't added to the constant pool references, since the writer doesn't
+            // see it unless the code is decoded.
+            let name_offset = self.offset();
+            let index = self.next_u16()?;
+            let name = cp
+                .get_utf8(index)
+                .map_err(|kind| self.error_at(name_offset, kind))?;
+            self.check_attribute_version(name, name_offset);
+            let length = self.next_u32()?;
+            self.take(length as usize)?;
+        }
+
+        self.check_attribute_length(attribute_name, name_offset, attribute_length, body.len())?;
+
+        Ok(RawCode {
+            bytes: body[..attribute_length as usize].into(),
+            offset,
+            constant_pool: Arc::clone(cp),
+            major_version: self.major_version,
+            options: self.options,
+        })
     }
 
-    fn parse_attribute(&mut self, cp: &ConstantPool) -> Attribute<'static> {
-        let attribute_name = cp.get_utf8(self.next_u16());
-        let attribute_length = self.next_u32() as usize;
+    fn parse_attribute(
+        &mut self,
+        cp: &ConstantPool,
+        context: AttributeContext,
+    ) -> Result<Attribute> {
+        let name_offset = self.offset();
+        let attribute_name = self.next_cp(|i| cp.get_utf8(i))?;
+        let attribute_length = self.next_u32()?;
         let len_before_attribute = self.raw.len();
 
-        let attribute = match attribute_name {
-            "SourceFile" => Attribute::SourceFile(cp.get_utf8(self.next_u16())),
-            "LineNumberTable" => {
-                let len = self.next_u16();
+        let recognized = self.check_attribute_version(attribute_name, name_offset);
+
+        let attribute = match (attribute_name.as_str(), context) {
+            _ if !recognized => Attribute::Unknown {
+                name: attribute_name.clone(),
+                bytes: self.take(attribute_length as usize)?.to_vec(),
+            },
+            ("SourceFile", _) => Attribute::SourceFile(self.next_cp(|i| cp.get_utf8(i).cloned())?),
+            ("SourceDebugExtension", AttributeContext::ClassFile) => {
+                // The JVM doesn't interpret the contents, so a malformed one doesn't make the
+                // class invalid. It's kept as it is, with a warning.
+                let offset = self.offset();
+                let bytes = self.take(attribute_length as usize)?;
+                match JavaString::from_modified_utf8(bytes) {
+                    Ok(debug_extension) => {
+                        let source_map = match debug_extension.as_str() {
+                            Some(smap) if smap.starts_with("SMAP") => {
+                                match smap::parse_source_map(smap) {
+                                    Ok(source_map) => Some(source_map),
+                                    Err(line) => {
+                                        self.warnings.push(self.error_at(
+                                            offset,
+                                            ClassParseErrorKind::BadSourceMap(line),
+                                        ));
+                                        None
+                                    }
+                                }
+                            }
+                            _ => None,
+                        };
+                        Attribute::SourceDebugExtension(SourceDebugExtension {
+                            debug_extension,
+                            source_map,
+                        })
+                    }
+                    Err(e) => {
+                        self.warnings
+                            .push(self.error_at(offset + e.position, ClassParseErrorKind::BadUtf8));
+                        Attribute::Unknown {
+                            name: attribute_name.clone(),
+                            bytes: bytes.to_vec(),
+                        }
+                    }
+                }
+            }
+            ("LineNumberTable", _) => {
+                let len = self.next_u16()?;
                 let entries = self.parse_sized_table(len, |p| {
-                    let start_pc = p.next_u16();
-                    let line_number = p.next_u16();
-                    LineNumberTableEntry {
+                    let start_pc = p.next_u16()?;
+                    let line_number = p.next_u16()?;
+                    Ok(LineNumberTableEntry {
                         start_pc,
                         line_number,
-                    }
-                });
+                    })
+                })?;
 
                 Attribute::LineNumberTable(entries)
             }
-            "ConstantValue" => {
-                let cv_idx = self.next_u16();
-                let Constant::ConstantValue(value) = cp.get(cv_idx).unwrap() else {
-                    panic!();
+            ("Signature", AttributeContext::ClassFile) => {
+                self.parse_signature_attribute(attribute_name, attribute_length, |p| {
+                    Ok(Attribute::Signature(Signature::Class(p.next_cp(|i| {
+                        signature::parse_class_signature(cp.get_utf8(i)?)
+                    })?)))
+                })?
+            }
+            ("Signature", AttributeContext::Field | AttributeContext::RecordComponent) => self
+                .parse_signature_attribute(attribute_name, attribute_length, |p| {
+                    Ok(Attribute::Signature(Signature::Field(p.next_cp(|i| {
+                        signature::parse_field_signature(cp.get_utf8(i)?)
+                    })?)))
+                })?,
+            ("Signature", AttributeContext::Method) => {
+                self.parse_signature_attribute(attribute_name, attribute_length, |p| {
+                    Ok(Attribute::Signature(Signature::Method(p.next_cp(|i| {
+                        signature::parse_method_signature(cp.get_utf8(i)?)
+                    })?)))
+                })?
+            }
+            ("RuntimeVisibleAnnotations", _) => {
+                Attribute::RuntimeVisibleAnnotations(self.parse_annotations(cp)?)
+            }
+            ("RuntimeInvisibleAnnotations", _) => {
+                Attribute::RuntimeInvisibleAnnotations(self.parse_annotations(cp)?)
+            }
+            ("RuntimeVisibleParameterAnnotations", _) => {
+                let num_parameters = self.next_u8()?;
+                Attribute::RuntimeVisibleParameterAnnotations(
+                    self.parse_sized_table(num_parameters, |p| p.parse_annotations(cp))?,
+                )
+            }
+            ("RuntimeInvisibleParameterAnnotations", _) => {
+                let num_parameters = self.next_u8()?;
+                Attribute::RuntimeInvisibleParameterAnnotations(
+                    self.parse_sized_table(num_parameters, |p| p.parse_annotations(cp))?,
+                )
+            }
+            ("RuntimeVisibleTypeAnnotations", _) => {
+                let len = self.next_u16()?;
+                Attribute::RuntimeVisibleTypeAnnotations(
+                    self.parse_sized_table(len, |p| p.parse_type_annotation(cp))?,
+                )
+            }
+            ("RuntimeInvisibleTypeAnnotations", _) => {
+                let len = self.next_u16()?;
+                Attribute::RuntimeInvisibleTypeAnnotations(
+                    self.parse_sized_table(len, |p| p.parse_type_annotation(cp))?,
+                )
+            }
+            ("AnnotationDefault", _) => {
+                Attribute::AnnotationDefault(self.parse_element_value(cp, 0)?)
+            }
+            ("BootstrapMethods", AttributeContext::ClassFile) => {
+                let num_bootstrap_methods = self.next_u16()?;
+                let methods = self.parse_sized_table(num_bootstrap_methods, |p| {
+                    let method = p.next_cp(|i| cp.get_method_handle(i))?;
+                    let num_bootstrap_arguments = p.next_u16()?;
+                    let arguments = p.parse_sized_table(num_bootstrap_arguments, |p| {
+                        p.next_cp(|i| cp.get_loadable(i))
+                    })?;
+                    Ok(BootstrapMethod { method, arguments })
+                })?;
+
+                Attribute::BootstrapMethods(methods)
+            }
+            ("Module", AttributeContext::ClassFile) => {
+                Attribute::Module(self.parse_module_attribute(cp)?)
+            }
+            ("ModulePackages", AttributeContext::ClassFile) => {
+                let package_count = self.next_u16()?;
+                Attribute::ModulePackages(self.parse_sized_table(package_count, |p| {
+                    p.next_cp(|i| cp.get_package(i).cloned())
+                })?)
+            }
+            ("ModuleMainClass", AttributeContext::ClassFile) => {
+                Attribute::ModuleMainClass(self.next_cp(|i| cp.get_class(i).cloned())?)
+            }
+            ("NestHost", AttributeContext::ClassFile) => {
+                Attribute::NestHost(self.next_cp(|i| cp.get_class(i).cloned())?)
+            }
+            ("NestMembers", AttributeContext::ClassFile) => {
+                let number_of_classes = self.next_u16()?;
+                Attribute::NestMembers(self.parse_sized_table(number_of_classes, |p| {
+                    p.next_cp(|i| cp.get_class(i).cloned())
+                })?)
+            }
+            ("Record", AttributeContext::ClassFile) => {
+                let components_count = self.next_u16()?;
+                Attribute::Record(self.parse_sized_table(components_count, |p| {
+                    let name = p.next_cp(|i| cp.get_utf8(i).cloned())?;
+                    let descriptor =
+                        p.next_cp(|i| descriptor::parse_field_descriptor(cp.get_utf8(i)?))?;
+                    let attributes_count = p.next_u16()?;
+                    let attributes = p.parse_sized_table(attributes_count, |p| {
+                        p.parse_attribute(cp, AttributeContext::RecordComponent)
+                    })?;
+                    Ok(RecordComponent {
+                        name,
+                        descriptor,
+                        attributes,
+                    })
+                })?)
+            }
+            ("PermittedSubclasses", AttributeContext::ClassFile) => {
+                let number_of_classes = self.next_u16()?;
+                Attribute::PermittedSubclasses(self.parse_sized_table(number_of_classes, |p| {
+                    p.next_cp(|i| cp.get_class(i).cloned())
+                })?)
+            }
+            ("Exceptions", _) => {
+                let len = self.next_u16()?;
+                Attribute::Exceptions(
+                    self.parse_sized_table(len, |p| p.next_cp(|i| cp.get_class(i).cloned()))?,
+                )
+            }
+            ("InnerClasses", _) => {
+                let len = self.next_u16()?;
+                let classes = self.parse_sized_table(len, |p| {
+                    let inner_class = p.next_cp(|i| cp.get_class(i).cloned())?;
+                    let outer_class = p.next_optional_cp(|i| cp.get_class(i).cloned())?;
+                    let inner_name = p.next_optional_cp(|i| cp.get_utf8(i).cloned())?;
+                    let inner_class_access_flags = InnerClassAccessFlags::new(p.next_u16()?);
+                    Ok(InnerClass {
+                        inner_class,
+                        outer_class,
+                        inner_name,
+                        inner_class_access_flags,
+                    })
+                })?;
+
+                Attribute::InnerClasses(classes)
+            }
+            ("EnclosingMethod", _) => {
+                let class = self.next_cp(|i| cp.get_class(i).cloned())?;
+                let method = self.next_optional_cp(|i| cp.get_name_and_type(i))?;
+                Attribute::EnclosingMethod(EnclosingMethod { class, method })
+            }
+            ("LocalVariableTable", _) => {
+                let len = self.next_u16()?;
+                let entries = self.parse_sized_table(len, |p| {
+                    Ok(LocalVariableTableEntry {
+                        start_pc: p.next_u16()?,
+                        length: p.next_u16()?,
+                        name: p.next_cp(|i| cp.get_utf8(i).cloned())?,
+                        descriptor: p
+                            .next_cp(|i| descriptor::parse_field_descriptor(cp.get_utf8(i)?))?,
+                        index: p.next_u16()?,
+                    })
+                })?;
+
+                Attribute::LocalVariableTable(entries)
+            }
+            ("LocalVariableTypeTable", _) => {
+                self.parse_signature_attribute(attribute_name, attribute_length, |p| {
+                    let len = p.next_u16()?;
+                    let entries = p.parse_sized_table(len, |p| {
+                        Ok(LocalVariableTypeTableEntry {
+                            start_pc: p.next_u16()?,
+                            length: p.next_u16()?,
+                            name: p.next_cp(|i| cp.get_utf8(i).cloned())?,
+                            signature: p
+                                .next_cp(|i| signature::parse_field_signature(cp.get_utf8(i)?))?,
+                            index: p.next_u16()?,
+                        })
+                    })?;
+
+                    Ok(Attribute::LocalVariableTypeTable(entries))
+                })?
+            }
+            ("MethodParameters", _) => {
+                let parameters_count = self.next_u8()?;
+                let parameters = self.parse_sized_table(parameters_count, |p| {
+                    Ok(MethodParameter {
+                        name: p.next_optional_cp(|i| cp.get_utf8(i).cloned())?,
+                        access_flags: ParameterAccessFlags::new(p.next_u16()?),
+                    })
+                })?;
+
+                Attribute::MethodParameters(parameters)
+            }
+            ("StackMapTable", _) => {
+                let len = self.next_u16()?;
+                Attribute::StackMapTable(
+                    self.parse_sized_table(len, |p| p.parse_stack_map_frame(cp))?,
+                )
+            }
+            ("ConstantValue", _) => Attribute::ConstantValue(self.next_cp(|cv_idx| {
+                let Constant::ConstantValue(value) = cp.get(cv_idx)? else {
+                    return Err(ClassParseErrorKind::WrongConstantKind {
+                        index: cv_idx,
+                        expected: "Integer, Float, Long, Double or String",
+                    });
                 };
 
-                let att = match value {
+                Ok(match value {
                     ConstantValueKind::Integer(x) => ConstantValueAttribute::Integer(*x),
                     ConstantValueKind::Float(x) => ConstantValueAttribute::Float(*x),
                     ConstantValueKind::Long(x) => ConstantValueAttribute::Long(*x),
                     ConstantValueKind::Double(x) => ConstantValueAttribute::Double(*x),
                     ConstantValueKind::String(string_idx) => {
-                        ConstantValueAttribute::String(cp.get_utf8(*string_idx))
+                        ConstantValueAttribute::String(cp.get_java_string(*string_idx)?.clone())
                     }
-                };
+                })
+            })?),
+            ("Code", _) => Attribute::Code(self.parse_code(cp)?),
+            _ => Attribute::Unknown {
+                name: attribute_name.clone(),
+                bytes: self.take(attribute_length as usize)?.to_vec(),
+            },
+        };
 
-                Attribute::ConstantValue(att)
-            }
-            "Code" => {
-                let max_stack = self.next_u16();
-                let max_locals = self.next_u16();
-                let code_length = self.next_u32();
-                let (code, raw) = self.raw.split_at(code_length as usize);
-                self.raw = raw;
-
-                let exception_table_length = self.next_u16();
-                let exception_table = self.parse_sized_table(exception_table_length, |p| {
-                    let start_pc = p.next_u16();
-                    let end_pc = p.next_u16();
-                    let handler_pc = p.next_u16();
-                    let catch_type = p.next_u16();
-                    ExceptionHandlerDescriptor {
-                        start_pc,
-                        end_pc,
-                        handler_pc,
-                        catch_type,
-                    }
-                });
+        self.check_attribute_length(
+            attribute_name,
+            name_offset,
+            attribute_length,
+            len_before_attribute,
+        )?;
 
-                let attributes_count = self.next_u16();
-                let attributes =
-                    self.parse_sized_table(attributes_count, |p| p.parse_attribute(cp));
+        Ok(attribute)
+    }
 
-                Attribute::Code(CodeAttribute {
-                    max_stack,
-                    max_locals,
-                    code: crate::op::parse_ops_from_code_bytes(code, cp),
-                    exception_table,
-                    attributes,
+    /// Parses the body of a Signature or LocalVariableTypeTable attribute with `parse`. The JVM
+    /// doesn't check signatures, so a malformed one doesn't stop the class from being parsed:
+    /// the attribute is kept as an unknown one with a warning instead, and [`check`] reports it.
+    fn parse_signature_attribute(
+        &mut self,
+        name: &Symbol,
+        attribute_length: u32,
+        parse: impl FnOnce(&mut Self) -> Result<Attribute>,
+    ) -> Result<Attribute> {
+        let body = self.raw;
+        let references = self.cp_references.len();
+        match parse(self) {
+            Err(error) if matches!(error.kind, ClassParseErrorKind::BadSignature(_)) => {
+                self.warnings.push(error);
+                self.raw = body;
+                self.cp_references.truncate(references);
+                Ok(Attribute::Unknown {
+                    name: name.clone(),
+                    bytes: self.take(attribute_length as usize)?.to_vec(),
                 })
             }
-            c => panic!("Bad attribute name: {c:?}"),
+            result => result,
+        }
+    }
+
+    /// Whether the attribute called `name` is defined in the class file's version. If it isn't,
+    /// a warning is recorded and its contents should be left unparsed.
+    fn check_attribute_version(&mut self, name: &str, name_offset: usize) -> bool {
+        let recognized =
+            version::attribute_major_version(name).is_none_or(|since| since <= self.major_version);
+        if !recognized {
+            self.warnings.push(self.error_at(
+                name_offset,
+                ClassParseErrorKind::AttributeNotInVersion {
+                    name: name.to_string(),
+                    major_version: self.major_version,
+                },
+            ));
+        }
+        recognized
+    }
+
+    /// Checks that the body of the attribute that started when `len_before_attribute` bytes
+    /// were left had the length its header declared.
+    fn check_attribute_length(
+        &self,
+        name: &str,
+        name_offset: usize,
+        declared: u32,
+        len_before_attribute: usize,
+    ) -> Result<()> {
+        let actual = len_before_attribute - self.raw.len();
+        if actual != declared as usize {
+            return Err(self.error_at(
+                name_offset,
+                ClassParseErrorKind::AttributeLengthMismatch {
+                    name: name.to_string(),
+                    declared,
+                    actual,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parses the body of a Code attribute.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.3>
+    fn parse_code(&mut self, cp: &ConstantPool) -> Result<CodeAttribute> {
+        let max_stack = self.next_u16()?;
+        let max_locals = self.next_u16()?;
+        let code_length = self.next_u32()?;
+        let code_offset = self.offset();
+        let code = crate::op::parse_ops_from_code_bytes(
+            self.take(code_length as usize)?,
+            cp,
+            code_offset,
+            self.major_version,
+            &mut self.cp_references,
+        )?;
+
+        let exception_table_length = self.next_u16()?;
+        let exception_table = self.parse_sized_table(exception_table_length, |p| {
+            let start_pc = p.next_u16()?;
+            let end_pc = p.next_u16()?;
+            let handler_pc = p.next_u16()?;
+            let catch_type = p.next_u16()?;
+            Ok(ExceptionHandlerDescriptor {
+                start_pc,
+                end_pc,
+                handler_pc,
+                catch_type,
+            })
+        })?;
+
+        let attributes_count = self.next_u16()?;
+        let attributes = self.parse_sized_table(attributes_count, |p| {
+            p.parse_attribute(cp, AttributeContext::Code)
+        })?;
+
+        Ok(CodeAttribute {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            attributes,
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    fn parse_module_attribute(&mut self, cp: &ConstantPool) -> Result<ModuleAttribute> {
+        let name = self.next_cp(|i| cp.get_module(i).cloned())?;
+        let flags = ModuleAccessFlags::new(self.next_u16()?);
+        let version = self.next_optional_cp(|i| cp.get_utf8(i).cloned())?;
+
+        let requires_count = self.next_u16()?;
+        let requires = self.parse_sized_table(requires_count, |p| {
+            Ok(Requires {
+                module: p.next_cp(|i| cp.get_module(i).cloned())?,
+                flags: RequiresAccessFlags::new(p.next_u16()?),
+                version: p.next_optional_cp(|i| cp.get_utf8(i).cloned())?,
+            })
+        })?;
+
+        let exports_count = self.next_u16()?;
+        let exports = self.parse_sized_table(exports_count, |p| {
+            let package = p.next_cp(|i| cp.get_package(i).cloned())?;
+            let flags = ExportsAccessFlags::new(p.next_u16()?);
+            let exports_to_count = p.next_u16()?;
+            let to = p.parse_sized_table(exports_to_count, |p| {
+                p.next_cp(|i| cp.get_module(i).cloned())
+            })?;
+            Ok(Exports { package, flags, to })
+        })?;
+
+        let opens_count = self.next_u16()?;
+        let opens = self.parse_sized_table(opens_count, |p| {
+            let package = p.next_cp(|i| cp.get_package(i).cloned())?;
+            let flags = ExportsAccessFlags::new(p.next_u16()?);
+            let opens_to_count = p.next_u16()?;
+            let to =
+                p.parse_sized_table(opens_to_count, |p| p.next_cp(|i| cp.get_module(i).cloned()))?;
+            Ok(Opens { package, flags, to })
+        })?;
+
+        let uses_count = self.next_u16()?;
+        let uses =
+            self.parse_sized_table(uses_count, |p| p.next_cp(|i| cp.get_class(i).cloned()))?;
+
+        let provides_count = self.next_u16()?;
+        let provides = self.parse_sized_table(provides_count, |p| {
+            let service = p.next_cp(|i| cp.get_class(i).cloned())?;
+            let provides_with_count = p.next_u16()?;
+            let with = p.parse_sized_table(provides_with_count, |p| {
+                p.next_cp(|i| cp.get_class(i).cloned())
+            })?;
+            Ok(Provides { service, with })
+        })?;
+
+        Ok(ModuleAttribute {
+            name,
+            flags,
+            version,
+            requires,
+            exports,
+            opens,
+            uses,
+            provides,
+        })
+    }
+
+    /// Parses a `num_annotations` followed by that many annotations.
+    fn parse_annotations(&mut self, cp: &ConstantPool) -> Result<Vec<Annotation>> {
+        let num_annotations = self.next_u16()?;
+        self.parse_sized_table(num_annotations, |p| p.parse_annotation(cp, 0))
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16>
+    ///
+    /// `depth` is how many annotations and arrays the annotation is nested in.
+    fn parse_annotation(&mut self, cp: &ConstantPool, depth: usize) -> Result<Annotation> {
+        let type_descriptor =
+            self.next_cp(|i| descriptor::parse_field_descriptor(cp.get_utf8(i)?))?;
+        let num_element_value_pairs = self.next_u16()?;
+        let elements = self.parse_sized_table(num_element_value_pairs, |p| {
+            let name = p.next_cp(|i| cp.get_utf8(i).cloned())?;
+            let value = p.parse_element_value(cp, depth)?;
+            Ok(ElementValuePair { name, value })
+        })?;
+
+        Ok(Annotation {
+            type_descriptor,
+            elements,
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16.1>
+    ///
+    /// `depth` is how many annotations and arrays the value is nested in. Nested values are
+    /// parsed recursively, so the depth is limited to keep malicious input from overflowing the
+    /// stack.
+    fn parse_element_value(&mut self, cp: &ConstantPool, depth: usize) -> Result<ElementValue> {
+        let offset = self.offset();
+        let tag = self.next_u8()?;
+        if matches!(tag, b'@' | b'[') && depth == MAX_ELEMENT_VALUE_DEPTH {
+            return Err(self.error_at(offset, ClassParseErrorKind::ElementValueTooDeep));
+        }
+
+        // The narrower types are stored as Integer constants. One that's out of range is kept as
+        // it is, so that it's written back as the same constant.
+        let narrow_integer = |value: i32| {
+            match tag {
+                b'B' => i8::try_from(value).ok().map(ElementValue::Byte),
+                b'C' => u16::try_from(value).ok().map(ElementValue::Char),
+                b'S' => i16::try_from(value).ok().map(ElementValue::Short),
+                _ => matches!(value, 0 | 1).then_some(ElementValue::Boolean(value == 1)),
+            }
+            .unwrap_or(ElementValue::OutOfRange { tag, value })
+        };
+
+        Ok(match tag {
+            b'B' | b'C' | b'S' | b'Z' => narrow_integer(self.next_cp(|i| cp.get_integer(i))?),
+            b'D' => ElementValue::Double(self.next_cp(|i| cp.get_double(i))?),
+            b'F' => ElementValue::Float(self.next_cp(|i| cp.get_float(i))?),
+            b'I' => ElementValue::Int(self.next_cp(|i| cp.get_integer(i))?),
+            b'J' => ElementValue::Long(self.next_cp(|i| cp.get_long(i))?),
+            b's' => ElementValue::String(self.next_cp(|i| cp.get_java_string(i).cloned())?),
+            b'e' => ElementValue::Enum {
+                type_descriptor: self
+                    .next_cp(|i| descriptor::parse_field_descriptor(cp.get_utf8(i)?))?,
+                const_name: self.next_cp(|i| cp.get_utf8(i).cloned())?,
+            },
+            b'c' => ElementValue::Class(
+                self.next_cp(|i| descriptor::parse_return_descriptor(cp.get_utf8(i)?))?,
+            ),
+            b'@' => ElementValue::Annotation(self.parse_annotation(cp, depth + 1)?),
+            b'[' => {
+                let num_values = self.next_u16()?;
+                ElementValue::Array(
+                    self.parse_sized_table(num_values, |p| p.parse_element_value(cp, depth + 1))?,
+                )
+            }
+            _ => return Err(self.error_at(offset, ClassParseErrorKind::BadElementValueTag(tag))),
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20>
+    fn parse_type_annotation(&mut self, cp: &ConstantPool) -> Result<TypeAnnotation> {
+        use TargetType::*;
+
+        let offset = self.offset();
+        let raw_target_type = self.next_u8()?;
+        let target_type = TargetType::from_u8(raw_target_type).ok_or_else(|| {
+            self.error_at(offset, ClassParseErrorKind::BadTargetType(raw_target_type))
+        })?;
+
+        let target_info = match target_type {
+            ClassTypeParameter | MethodTypeParameter => TargetInfo::TypeParameter {
+                type_parameter_index: self.next_u8()?,
+            },
+            ClassExtends => TargetInfo::Supertype {
+                supertype_index: self.next_u16()?,
+            },
+            ClassTypeParameterBound | MethodTypeParameterBound => TargetInfo::TypeParameterBound {
+                type_parameter_index: self.next_u8()?,
+                bound_index: self.next_u8()?,
+            },
+            Field | MethodReturn | MethodReceiver => TargetInfo::Empty,
+            MethodFormalParameter => TargetInfo::FormalParameter {
+                formal_parameter_index: self.next_u8()?,
+            },
+            Throws => TargetInfo::Throws {
+                throws_type_index: self.next_u16()?,
+            },
+            LocalVariable | ResourceVariable => {
+                let table_length = self.next_u16()?;
+                TargetInfo::Localvar(self.parse_sized_table(table_length, |p| {
+                    Ok(LocalvarTargetEntry {
+                        start_pc: p.next_u16()?,
+                        length: p.next_u16()?,
+                        index: p.next_u16()?,
+                    })
+                })?)
+            }
+            ExceptionParameter => TargetInfo::Catch {
+                exception_table_index: self.next_u16()?,
+            },
+            Instanceof | New | ConstructorReference | MethodReference => {
+                TargetInfo::Offset(self.next_u16()?)
+            }
+            Cast
+            | ConstructorInvocationTypeArgument
+            | MethodInvocationTypeArgument
+            | ConstructorReferenceTypeArgument
+            | MethodReferenceTypeArgument => TargetInfo::TypeArgument {
+                offset: self.next_u16()?,
+                type_argument_index: self.next_u8()?,
+            },
         };
 
-        debug_assert!(len_before_attribute - self.raw.len() == attribute_length);
+        let path_length = self.next_u8()?;
+        let target_path = self.parse_sized_table(path_length, |p| {
+            let offset = p.offset();
+            let kind = p.next_u8()?;
+            let type_path_kind = TypePathKind::from_u8(kind)
+                .ok_or_else(|| p.error_at(offset, ClassParseErrorKind::BadTypePathKind(kind)))?;
+            Ok(TypePathEntry {
+                type_path_kind,
+                type_argument_index: p.next_u8()?,
+            })
+        })?;
+
+        Ok(TypeAnnotation {
+            target_type,
+            target_info,
+            target_path,
+            annotation: self.parse_annotation(cp, 0)?,
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.4>
+    fn parse_stack_map_frame(&mut self, cp: &ConstantPool) -> Result<StackMapFrame> {
+        let offset = self.offset();
+        let frame_type = self.next_u8()?;
+
+        Ok(match frame_type {
+            0..=63 => StackMapFrame::Same {
+                offset_delta: frame_type.into(),
+                extended: false,
+            },
+            64..=127 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: (frame_type - 64).into(),
+                stack: self.parse_verification_type(cp)?,
+                extended: false,
+            },
+            247 => {
+                let offset_delta = self.next_u16()?;
+                StackMapFrame::SameLocals1StackItem {
+                    offset_delta,
+                    stack: self.parse_verification_type(cp)?,
+                    extended: offset_delta <= 63,
+                }
+            }
+            248..=250 => StackMapFrame::Chop {
+                offset_delta: self.next_u16()?,
+                k: 251 - frame_type,
+            },
+            251 => {
+                let offset_delta = self.next_u16()?;
+                StackMapFrame::Same {
+                    offset_delta,
+                    extended: offset_delta <= 63,
+                }
+            }
+            252..=254 => StackMapFrame::Append {
+                offset_delta: self.next_u16()?,
+                locals: self
+                    .parse_sized_table(frame_type - 251, |p| p.parse_verification_type(cp))?,
+            },
+            255 => {
+                let offset_delta = self.next_u16()?;
+                let number_of_locals = self.next_u16()?;
+                let locals =
+                    self.parse_sized_table(number_of_locals, |p| p.parse_verification_type(cp))?;
+                let number_of_stack_items = self.next_u16()?;
+                let stack = self
+                    .parse_sized_table(number_of_stack_items, |p| p.parse_verification_type(cp))?;
+
+                StackMapFrame::Full {
+                    offset_delta,
+                    locals,
+                    stack,
+                }
+            }
+            _ => return Err(self.error_at(offset, ClassParseErrorKind::BadFrameType(frame_type))),
+        })
+    }
+
+    fn parse_verification_type(&mut self, cp: &ConstantPool) -> Result<VerificationTypeInfo> {
+        let offset = self.offset();
+
+        Ok(match self.next_u8()? {
+            0 => VerificationTypeInfo::Top,
+            1 => VerificationTypeInfo::Integer,
+            2 => VerificationTypeInfo::Float,
+            3 => VerificationTypeInfo::Double,
+            4 => VerificationTypeInfo::Long,
+            5 => VerificationTypeInfo::Null,
+            6 => VerificationTypeInfo::UninitializedThis,
+            7 => VerificationTypeInfo::Object(self.next_cp(|i| cp.get_class(i).cloned())?),
+            8 => VerificationTypeInfo::Uninitialized(self.next_u16()?),
+            tag => return Err(self.error_at(offset, ClassParseErrorKind::BadVerificationType(tag))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::utf8;
+
+    const SHAPE: &[u8] = include_bytes!("../test_data/Shape.class");
+    const SHAPE_POLYGON: &[u8] = include_bytes!("../test_data/Shape$Polygon.class");
+    const SIMPLE: &[u8] = include_bytes!("../test_data/Simple.class");
+    const WIDE: &[u8] = include_bytes!("../test_data/Wide.class");
+    const STRINGS: &[u8] = include_bytes!("../test_data/Strings.class");
+    const ANNOTATED: &[u8] = include_bytes!("../test_data/Annotated.class");
+    const ANNOTATION_INTERFACE: &[u8] = include_bytes!("../test_data/Info.class");
+    const BRANCHES: &[u8] = include_bytes!("../test_data/Branches.class");
+    const GENERICS: &[u8] = include_bytes!("../test_data/Generics.class");
+    const LAMBDAS: &[u8] = include_bytes!("../test_data/Lambdas.class");
+    const LOCALS: &[u8] = include_bytes!("../test_data/Locals.class");
+    const MODULE_INFO: &[u8] = include_bytes!("../test_data/module-info.class");
+    const NESTED: &[u8] = include_bytes!("../test_data/Nested.class");
+    const NESTED_ANONYMOUS: &[u8] = include_bytes!("../test_data/Nested$1.class");
+
+    #[test]
+    fn test_parse_simple() {
+        let class = ClassFile::parse_from_bytes(SIMPLE).unwrap();
+        assert_eq!("Simple", class.this_class());
+        assert_eq!(
+            Some("java/lang/Object"),
+            class.super_class().map(Symbol::as_str)
+        );
+        assert_eq!(2, class.methods().len());
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut raw = SIMPLE.to_vec();
+        raw[0] = 0xCB;
+        assert_eq!(
+            Err(ClassParseError::new(
+                0,
+                ClassParseErrorKind::BadMagic(0xCBFEBABE)
+            )),
+            ClassFile::parse_from_bytes(&raw).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        for len in 0..SIMPLE.len() {
+            assert_eq!(
+                Err(ClassParseError::new(
+                    len,
+                    ClassParseErrorKind::UnexpectedEof
+                )),
+                ClassFile::parse_from_bytes(&SIMPLE[..len]).map(|_| ()),
+                "truncated to {len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_constant_tag() {
+        let mut raw = SIMPLE.to_vec();
+        // The first constant's tag comes right after the magic, version, and constant_pool_count.
+        raw[10] = 2;
+        assert_eq!(
+            Err(ClassParseError::new(
+                10,
+                ClassParseErrorKind::BadConstantTag(2)
+            )),
+            ClassFile::parse_from_bytes(&raw).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_wide_constants() {
+        let class = ClassFile::parse_from_bytes(WIDE).unwrap();
+
+        let values: Vec<_> = class
+            .fields()
+            .iter()
+            .map(|f| {
+                let [Attribute::ConstantValue(value)] = &f.attributes[..] else {
+                    panic!("{} should have exactly one ConstantValue", f.name);
+                };
+                (f.name.as_str(), value)
+            })
+            .collect();
+
+        use ConstantValueAttribute::*;
+        assert!(matches!(values[0], ("A", Long(1234567890123))));
+        assert!(matches!(values[1], ("B", Double(x)) if *x == 3.5));
+        assert!(matches!(values[2], ("C", Long(-1099511627776))));
+        assert!(matches!(values[3], ("D", Integer(100000))));
+        assert!(matches!(values[4], ("E", String(s)) if *s == "after the wide constants"));
+        assert!(matches!(values[5], ("F", Float(x)) if *x == 1.25));
+    }
+
+    #[test]
+    fn test_modified_utf8_constants() {
+        let class = ClassFile::parse_from_bytes(STRINGS).unwrap();
+
+        let strings: Vec<_> = class
+            .fields()
+            .iter()
+            .map(|f| {
+                let [Attribute::ConstantValue(ConstantValueAttribute::String(s))] =
+                    &f.attributes[..]
+                else {
+                    panic!("{} should have a String ConstantValue", f.name);
+                };
+                s
+            })
+            .collect();
+
+        assert_eq!(Some("a\0b"), strings[0].as_str());
+        assert_eq!(Some("smile \u{1F600}"), strings[1].as_str());
+        assert_eq!(None, strings[2].as_str());
+        assert_eq!(
+            vec![0x78, 0xD800, 0x79],
+            strings[2].encode_utf16().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_wide_constant_at_end_of_pool() {
+        let raw = [
+            0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, // magic, version
+            0, 2, // constant_pool_count
+            5, 0, 0, 0, 0, 0, 0, 0, 1, // Long #1, which would need #2 as well
+        ];
+        assert_eq!(
+            Err(ClassParseError::new(
+                10,
+                ClassParseErrorKind::WideConstantOverflowsPool(1)
+            )),
+            ClassFile::parse_from_bytes(&raw).map(|_| ())
+        );
+    }
+
+    /// Encodes a CONSTANT_Utf8 entry.
+    #[test]
+    fn test_dynamic_constants() {
+        let raw = [
+            utf8("java/lang/Object"),     // #1
+            vec![7, 0, 1],                // #2 Class
+            utf8("toString"),             // #3
+            utf8("()Ljava/lang/String;"), // #4
+            vec![12, 0, 3, 0, 4],         // #5 NameAndType
+            vec![10, 0, 2, 0, 5],         // #6 Methodref
+            vec![15, 5, 0, 6],            // #7 MethodHandle REF_invokeVirtual
+            vec![16, 0, 4],               // #8 MethodType
+            vec![18, 0, 0, 0, 5],         // #9 InvokeDynamic
+            utf8("x"),                    // #10
+            utf8("I"),                    // #11
+            vec![12, 0, 10, 0, 11],       // #12 NameAndType
+            vec![17, 0, 1, 0, 12],        // #13 Dynamic
+            utf8("java.base"),            // #14
+            vec![19, 0, 14],              // #15 Module
+            utf8("java/util"),            // #16
+            vec![20, 0, 16],              // #17 Package
+            vec![15, 9, 0, 6],            // #18 MethodHandle REF_invokeInterface
+        ]
+        .concat();
+        let cp = Parser::new(&raw).parse_constant_pool(19).unwrap();
+
+        let handle = cp.get_method_handle(7).unwrap();
+        assert_eq!(ReferenceKind::InvokeVirtual, handle.kind);
+        let MethodHandleRef::Method(method) = handle.reference else {
+            panic!("expected a method reference");
+        };
+        assert_eq!(
+            ("java/lang/Object", "toString"),
+            (method.class.as_str(), method.name.as_str())
+        );
+        assert!(!method.is_interface);
+
+        let method_type = cp.get_method_type(8).unwrap();
+        assert!(method_type.parameters.is_empty());
+
+        let indy = cp.get_invoke_dynamic(9).unwrap();
+        assert_eq!(
+            (0, "toString"),
+            (indy.bootstrap_method_attr_index, indy.name.as_str())
+        );
+
+        let condy = cp.get_dynamic(13).unwrap();
+        assert_eq!(
+            (1, "x"),
+            (condy.bootstrap_method_attr_index, condy.name.as_str())
+        );
+        assert_eq!(
+            FieldDescriptor::Basic(BasicFieldType::Int),
+            condy.descriptor
+        );
+
+        assert_eq!(Ok("java.base"), cp.get_module(15).map(Symbol::as_str));
+        assert_eq!(Ok("java/util"), cp.get_package(17).map(Symbol::as_str));
+
+        assert!(matches!(
+            cp.get_method_handle(18),
+            Err(ClassParseErrorKind::WrongConstantKind { index: 6, .. })
+        ));
+        assert!(matches!(
+            cp.get_dynamic(9),
+            Err(ClassParseErrorKind::WrongConstantKind { index: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_method_handle_to_interface_method() {
+        let raw = [
+            utf8("java/util/List"),     // #1
+            vec![7, 0, 1],              // #2 Class
+            utf8("of"),                 // #3
+            utf8("()Ljava/util/List;"), // #4
+            vec![12, 0, 3, 0, 4],       // #5 NameAndType
+            vec![11, 0, 2, 0, 5],       // #6 InterfaceMethodref
+            vec![15, 6, 0, 6],          // #7 MethodHandle REF_invokeStatic
+        ]
+        .concat();
+        let parse = |major_version| {
+            let mut parser = Parser::new(&raw);
+            parser.major_version = major_version;
+            parser.parse_constant_pool(8).unwrap()
+        };
+
+        let handle = parse(52).get_method_handle(7).unwrap();
+        let MethodHandleRef::Method(method) = handle.reference else {
+            panic!("expected a method reference");
+        };
+        assert!(method.is_interface);
+
+        // Before version 52, only invokeInterface handles can refer to interface methods.
+        assert_eq!(
+            Err(ClassParseErrorKind::WrongConstantKind {
+                index: 6,
+                expected: "Methodref"
+            }),
+            parse(51).get_method_handle(7).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_bad_reference_kind() {
+        assert_eq!(
+            Err(ClassParseError::new(
+                1,
+                ClassParseErrorKind::BadReferenceKind(10)
+            )),
+            Parser::new(&[15, 10, 0, 1]).parse_constant().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_unknown_attribute() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 6],
+            utf8("Foo"),                // #1
+            vec![7, 0, 1],              // #2 Class
+            utf8("java/lang/Object"),   // #3
+            vec![7, 0, 3],              // #4 Class
+            utf8("com.example.Vendor"), // #5
+            // access_flags, this_class, super_class, and no interfaces, fields or methods
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0],
+            vec![0, 1, 0, 5, 0, 0, 0, 3, 1, 2, 3],
+        ]
+        .concat();
+        let class = Parser::new(&raw).run().unwrap();
+
+        let [Attribute::Unknown { name, bytes }] = class.attributes() else {
+            panic!("expected a single unknown attribute");
+        };
+        assert_eq!("com.example.Vendor", name.as_str());
+        assert_eq!(&[1, 2, 3], bytes.as_slice());
+    }
+
+    fn with_version(raw: &[u8], major_version: u16, minor_version: u16) -> Vec<u8> {
+        let mut raw = raw.to_vec();
+        raw[4..6].copy_from_slice(&minor_version.to_be_bytes());
+        raw[6..8].copy_from_slice(&major_version.to_be_bytes());
+        raw
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let unsupported = |major_version, minor_version| {
+            Err(ClassParseError::new(
+                4,
+                ClassParseErrorKind::UnsupportedVersion {
+                    major_version,
+                    minor_version,
+                },
+            ))
+        };
+        let parse = |raw: &[u8], options| {
+            ClassFile::parse_with_options(raw, options).map(|class| class.version())
+        };
+
+        let too_new = with_version(SIMPLE, 66, 0);
+        assert_eq!(unsupported(66, 0), parse(&too_new, ParseOptions::default()));
+        let older_runtime = ParseOptions {
+            max_major_version: 51,
+            ..ParseOptions::default()
+        };
+        assert_eq!(unsupported(52, 0), parse(SIMPLE, older_runtime));
+
+        let warn = ParseOptions {
+            unsupported_version: VersionPolicy::Warn,
+            ..ParseOptions::default()
+        };
+        let class = ClassFile::parse_with_options(&too_new, warn).unwrap();
+        assert_eq!(unsupported(66, 0).unwrap_err(), class.warnings()[0]);
+
+        // Since version 56, the only valid minor versions are 0 and the preview marker.
+        assert_eq!(
+            unsupported(60, 3),
+            parse(&with_version(SIMPLE, 60, 3), ParseOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_preview_version() {
+        let preview = with_version(SIMPLE, 65, PREVIEW_MINOR_VERSION);
+        assert!(matches!(
+            ClassFile::parse_from_bytes(&preview).unwrap_err().kind,
+            ClassParseErrorKind::UnsupportedVersion { .. }
+        ));
+
+        let allow_preview = ParseOptions {
+            allow_preview: true,
+            ..ParseOptions::default()
+        };
+        let class = ClassFile::parse_with_options(&preview, allow_preview).unwrap();
+        assert!(class.is_preview());
+        assert!(class.warnings().is_empty());
+        assert!(!ClassFile::parse_from_bytes(SIMPLE).unwrap().is_preview());
+
+        // Preview features are tied to one release.
+        let old_preview = with_version(SIMPLE, 61, PREVIEW_MINOR_VERSION);
+        assert!(ClassFile::parse_with_options(&old_preview, allow_preview).is_err());
+    }
+
+    #[test]
+    fn test_version_gated_constants_and_opcodes() {
+        let header = |major_version: u8, cp_count| {
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, major_version, 0, cp_count]
+        };
+
+        let method_type = [header(50, 3), utf8("()V"), vec![16, 0, 1]].concat();
+        assert_eq!(
+            Err(ClassParseError::new(
+                16,
+                ClassParseErrorKind::ConstantNotInVersion {
+                    tag: 16,
+                    major_version: 50
+                }
+            )),
+            Parser::new(&method_type).run().map(|_| ())
+        );
+
+        let jsr = [
+            header(52, 8),
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("run"),              // #5
+            utf8("()V"),              // #6
+            utf8("Code"),             // #7
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+            // `static void run()` with code that's just `jsr 0`
+            vec![0, 1, 0, 0x08, 0, 5, 0, 6, 0, 1, 0, 7, 0, 0, 0, 15],
+            vec![0, 0, 0, 0, 0, 0, 0, 3, 0xA8, 0, 0, 0, 0, 0, 0],
+            vec![0, 0],
+        ]
+        .concat();
+        let error = Parser::new(&jsr).run().unwrap_err();
+        assert_eq!(
+            ClassParseErrorKind::OpcodeNotInVersion {
+                opcode: 0xA8,
+                major_version: 52
+            },
+            error.kind
+        );
+        assert_eq!(0xA8, jsr[error.offset]);
+    }
+
+    #[test]
+    fn test_lazy_code() {
+        let lazy = ParseOptions {
+            lazy_code: true,
+            ..ParseOptions::default()
+        };
+        for raw in [
+            SIMPLE, WIDE, STRINGS, BRANCHES, GENERICS, LAMBDAS, LOCALS, NESTED,
+        ] {
+            let expected = Parser::new(raw).run().unwrap();
+            let mut class = ClassFile::parse_with_options(raw, lazy).unwrap();
+            assert!(format!("{class:?}").contains("RawCode"));
+            assert_eq!(expected.warnings(), class.warnings());
+            assert_eq!(raw, class.to_bytes().unwrap());
+
+            // Once the code is decoded, the class is the same as if it had been parsed eagerly.
+            for method in class.methods() {
+                assert_eq!(method.has_code(), method.code().unwrap().is_some());
+            }
+            assert_eq!(
+                format!("{:?}", expected.methods()),
+                format!("{:?}", class.methods())
+            );
+
+            // Code that's been accessed mutably is written from the decoded instructions.
+            for method in &mut class.methods {
+                method.code_mut().unwrap();
+            }
+            assert_eq!(raw, class.to_bytes().unwrap());
+        }
+
+        // Errors in the code are only found when it's decoded, at the same offset.
+        let header = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 8],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("run"),              // #5
+            utf8("()V"),              // #6
+            utf8("Code"),             // #7
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+        ]
+        .concat();
+        // `static void run()` with code that's just an unknown opcode
+        let bad_opcode = [
+            &header[..],
+            &[0, 1, 0, 0x08, 0, 5, 0, 6, 0, 1, 0, 7, 0, 0, 0, 13],
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0xFF, 0, 0, 0, 0],
+            &[0, 0],
+        ]
+        .concat();
+        let error = Parser::new(&bad_opcode).run().unwrap_err();
+        let class = ClassFile::parse_with_options(&bad_opcode, lazy).unwrap();
+        assert_eq!(error, class.methods()[0].code().unwrap_err());
+        assert_eq!(bad_opcode, class.to_bytes().unwrap());
+
+        // The structure of the attribute is still checked up front.
+        let mut bad_length = bad_opcode.clone();
+        bad_length[header.len() + 15] = 14;
+        assert!(matches!(
+            ClassFile::parse_with_options(&bad_length, lazy)
+                .unwrap_err()
+                .kind,
+            ClassParseErrorKind::AttributeLengthMismatch { declared: 14, .. }
+        ));
+    }
+
+    #[test]
+    fn test_attribute_too_new_for_version() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 48, 0, 7],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("Signature"),        // #5
+            utf8("not a signature"),  // #6
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0],
+            vec![0, 1, 0, 5, 0, 0, 0, 2, 0, 6],
+        ]
+        .concat();
+        let class = Parser::new(&raw).run().unwrap();
+
+        assert!(matches!(
+            class.attributes(),
+            [Attribute::Unknown { name, .. }] if name == "Signature"
+        ));
+        assert_eq!(
+            [ClassParseError::new(
+                raw.len() - 8,
+                ClassParseErrorKind::AttributeNotInVersion {
+                    name: "Signature".to_owned(),
+                    major_version: 48
+                }
+            )],
+            class.warnings()
+        );
+    }
+
+    #[test]
+    fn test_source_debug_extension() {
+        let smap = "SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*F\n+ 1 Foo.kt\ncom/example/Foo.kt\n\
+                    + 2 Util.kt\ncom/example/Util.kt\n*L\n1#1,11:1\n5#2,2:12\n*E\n";
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 12],
+            utf8("Foo"),                  // #1
+            vec![7, 0, 1],                // #2 Class
+            utf8("java/lang/Object"),     // #3
+            vec![7, 0, 3],                // #4 Class
+            utf8("run"),                  // #5
+            utf8("()V"),                  // #6
+            utf8("Code"),                 // #7
+            utf8("LineNumberTable"),      // #8
+            utf8("SourceFile"),           // #9
+            utf8("Foo.kt"),               // #10
+            utf8("SourceDebugExtension"), // #11
+            // access_flags, this_class, super_class, and no interfaces or fields
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+            // One method, `static void run()`, with a Code attribute
+            vec![0, 1, 0, 0x08, 0, 5, 0, 6, 0, 1, 0, 7, 0, 0, 0, 32],
+            // max_stack, max_locals, and code: nop, nop, nop, return
+            vec![0, 0, 0, 0, 0, 0, 0, 4, 0x00, 0x00, 0x00, 0xB1],
+            // No exception handlers, and a LineNumberTable: pc 0 is line 10, pc 2 is line 12
+            vec![
+                0, 0, 0, 1, 0, 8, 0, 0, 0, 10, 0, 2, 0, 0, 0, 10, 0, 2, 0, 12,
+            ],
+            // SourceFile and SourceDebugExtension
+            vec![0, 2, 0, 9, 0, 0, 0, 2, 0, 10, 0, 11],
+            (smap.len() as u32).to_be_bytes().to_vec(),
+            smap.as_bytes().to_vec(),
+        ]
+        .concat();
+        let class = Parser::new(&raw).run().unwrap();
+        let run = method(&class, "run");
+
+        let smap = class.source_map().unwrap();
+        assert_eq!("Kotlin", smap.default_stratum);
+        assert_eq!(Some(12), run.code().unwrap().unwrap().line_number_at(3));
+
+        let at = |pc, stratum| {
+            class
+                .source_location(run, pc, stratum)
+                .map(|loc| (loc.file, loc.path, loc.line))
+        };
+        assert_eq!(
+            Some(("Foo.kt", Some("com/example/Foo.kt"), 10)),
+            at(1, None)
+        );
+        assert_eq!(
+            Some(("Util.kt", Some("com/example/Util.kt"), 5)),
+            at(2, Some("Kotlin"))
+        );
+        assert_eq!(Some(("Foo.kt", None, 12)), at(3, Some("Java")));
+        assert_eq!(None, at(0, Some("KotlinDebug")));
+    }
+
+    #[test]
+    fn test_bad_source_map() {
+        let smap = "SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*L\n1:x\n*E\n";
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 6],
+            utf8("Foo"),                  // #1
+            vec![7, 0, 1],                // #2 Class
+            utf8("java/lang/Object"),     // #3
+            vec![7, 0, 3],                // #4 Class
+            utf8("SourceDebugExtension"), // #5
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0],
+            vec![0, 1, 0, 5],
+            (smap.len() as u32).to_be_bytes().to_vec(),
+            smap.as_bytes().to_vec(),
+        ]
+        .concat();
+
+        let class = Parser::new(&raw).run().unwrap();
+        assert_eq!(
+            [ClassParseError::new(
+                raw.len() - smap.len(),
+                ClassParseErrorKind::BadSourceMap(6)
+            )],
+            class.warnings()
+        );
+        assert!(class.source_map().is_none());
+        assert!(matches!(
+            &class.attributes[..],
+            [Attribute::SourceDebugExtension(SourceDebugExtension {
+                debug_extension,
+                source_map: None,
+            })] if debug_extension.as_str() == Some(smap)
+        ));
+        assert_eq!(raw, class.to_bytes().unwrap());
+
+        // Not modified UTF-8, so it's kept as an unknown attribute.
+        let mut raw = raw;
+        let len = raw.len();
+        raw[len - 2] = 0xFF;
+        let class = Parser::new(&raw).run().unwrap();
+        assert_eq!(
+            [ClassParseError::new(len - 2, ClassParseErrorKind::BadUtf8)],
+            class.warnings()
+        );
+        assert!(matches!(
+            &class.attributes[..],
+            [Attribute::Unknown { name, bytes }]
+                if name == "SourceDebugExtension" && bytes[..] == raw[len - smap.len()..]
+        ));
+        assert_eq!(raw, class.to_bytes().unwrap());
+    }
+
+    fn method<'a>(class: &'a ClassFile, name: &str) -> &'a MethodInfo {
+        class.methods().iter().find(|m| m.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_stack_map_table() {
+        use VerificationTypeInfo::*;
+
+        let class = Parser::new(BRANCHES).run().unwrap();
+
+        let sum = method(&class, "sum").code().unwrap().unwrap();
+        assert_eq!(
+            Some(
+                &[
+                    StackMapFrame::Append {
+                        offset_delta: 4,
+                        locals: vec![Integer, Integer],
+                    },
+                    StackMapFrame::Chop {
+                        offset_delta: 17,
+                        k: 1,
+                    },
+                ][..]
+            ),
+            sum.stack_map_table()
+        );
+
+        let create = method(&class, "create").code().unwrap().unwrap();
+        assert_eq!(
+            Some(&StackMapFrame::Full {
+                offset_delta: 1,
+                locals: vec![Object("Branches".into()), Integer],
+                stack: vec![Uninitialized(0), Uninitialized(0), Integer],
+            }),
+            create.stack_map_table().unwrap().last()
+        );
+
+        let describe = method(&class, "describe").code().unwrap().unwrap();
+        assert!(matches!(
+            describe.stack_map_table().unwrap()[6],
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta: 10,
+                stack: Object(ref class),
+                extended: false,
+            } if class == "java/lang/NumberFormatException"
+        ));
+    }
+
+    #[test]
+    fn test_expand_stack_map_frames() {
+        use VerificationTypeInfo::*;
+
+        let class = Parser::new(BRANCHES).run().unwrap();
+        let expand = |name| {
+            let method = method(&class, name);
+            let initial_locals = method.initial_locals(class.this_class());
+            method
+                .code()
+                .unwrap()
+                .unwrap()
+                .expand_stack_map_frames(&initial_locals)
+                .unwrap()
+        };
+
+        let sum = expand("sum");
+        let array = Object("[I".into());
+        assert_eq!(
+            vec![
+                (
+                    4,
+                    Frame {
+                        locals: vec![array.clone(), Integer, Integer],
+                        stack: vec![],
+                    }
+                ),
+                (
+                    22,
+                    Frame {
+                        locals: vec![array, Integer],
+                        stack: vec![],
+                    }
+                ),
+            ],
+            sum
+        );
+
+        let describe = expand("describe");
+        assert_eq!(
+            vec![21, 64, 71, 80, 89, 93, 104, 110, 118, 120],
+            describe.iter().map(|(pc, _)| *pc).collect::<Vec<_>>()
+        );
+        let string = Object("java/lang/String".into());
+        assert_eq!(
+            (
+                104,
+                Frame {
+                    locals: vec![Integer, string.clone(), Long, Top],
+                    stack: vec![Object("java/lang/NumberFormatException".into())],
+                }
+            ),
+            describe[6]
+        );
+        assert_eq!(
+            (
+                120,
+                Frame {
+                    locals: vec![Integer, string, Long, Top],
+                    stack: vec![Long, Top],
+                }
+            ),
+            describe[9]
+        );
+
+        let chop_too_many = CodeAttribute {
+            max_stack: 0,
+            max_locals: 0,
+            code: Vec::new(),
+            exception_table: Vec::new(),
+            attributes: vec![Attribute::StackMapTable(vec![StackMapFrame::Chop {
+                offset_delta: 0,
+                k: 1,
+            }])],
+        };
+        assert_eq!(None, chop_too_many.expand_stack_map_frames(&[]));
+    }
+
+    #[test]
+    fn test_switches() {
+        let class = Parser::new(BRANCHES).run().unwrap();
+
+        let letter = method(&class, "letter").code().unwrap().unwrap();
+        let op::Op::Tableswitch(table) = &letter.code[1] else {
+            panic!("expected a tableswitch");
+        };
+        assert_eq!((0, 2, 36), (table.low, table.high, table.default));
+        assert_eq!(vec![27, 30, 33], table.offsets);
+
+        let describe = method(&class, "describe").code().unwrap().unwrap();
+        let Some(op::Op::Lookupswitch(lookup)) = describe
+            .code
+            .iter()
+            .find(|op| matches!(op, op::Op::Lookupswitch(_)))
+        else {
+            panic!("expected a lookupswitch");
+        };
+        assert_eq!(71, lookup.default);
+        assert_eq!(vec![(1, 42), (2, 49), (3, 58), (1000, 67)], lookup.pairs);
+    }
+
+    #[test]
+    fn test_bad_frame_type() {
+        let cp = Parser::new(&[]).parse_constant_pool(1).unwrap();
+        assert_eq!(
+            Err(ClassParseError::new(
+                0,
+                ClassParseErrorKind::BadFrameType(128)
+            )),
+            Parser::new(&[128]).parse_stack_map_frame(&cp)
+        );
+        assert_eq!(
+            Err(ClassParseError::new(
+                1,
+                ClassParseErrorKind::BadVerificationType(9)
+            )),
+            Parser::new(&[64, 9]).parse_stack_map_frame(&cp)
+        );
+    }
+
+    #[test]
+    fn test_exceptions_and_inner_classes() {
+        let class = Parser::new(NESTED).run().unwrap();
+
+        assert_eq!(
+            ["java/io/IOException", "java/lang/InterruptedException"],
+            method(&class, "anonymous").declared_exceptions()
+        );
+        assert!(method(&class, "<init>").declared_exceptions().is_empty());
+        assert!(class.enclosing_method().is_none());
+
+        let inner_classes: Vec<_> = class
+            .inner_classes()
+            .iter()
+            .map(|c| {
+                (
+                    c.inner_class.as_str(),
+                    c.outer_class.as_ref().map(Symbol::as_str),
+                    c.inner_name.as_ref().map(Symbol::as_str),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("Nested$1", None, None),
+                ("Nested$StaticNested", Some("Nested"), Some("StaticNested")),
+                ("Nested$Inner", Some("Nested"), Some("Inner")),
+            ],
+            inner_classes
+        );
+        assert!(class.inner_classes()[1]
+            .inner_class_access_flags
+            .has(InnerClassAccess::Final));
+
+        let anonymous = Parser::new(NESTED_ANONYMOUS).run().unwrap();
+        let enclosing = anonymous.enclosing_method().unwrap();
+        assert_eq!("Nested", enclosing.class);
+        assert_eq!(
+            Some(NameAndType {
+                name: "anonymous".into(),
+                descriptor: "()Ljava/lang/Runnable;".into(),
+            }),
+            enclosing.method
+        );
+    }
+
+    #[test]
+    fn test_signatures() {
+        let class = Parser::new(GENERICS).run().unwrap();
+
+        assert_eq!(
+            "<K extends java.lang.Comparable<K>, V> extends java.lang.Object implements java.lang.Comparable<Generics<K, V>>",
+            class.signature().unwrap().to_string()
+        );
+        assert_eq!(
+            "java.util.Map<K, java.util.List<? super V>>",
+            class.fields()[0].signature().unwrap().to_string()
+        );
+        assert_eq!(
+            "<E extends java.lang.Exception> V (K, java.util.List<? extends V>[]) throws E",
+            method(&class, "get").signature().unwrap().to_string()
+        );
+        assert!(method(&class, "<init>").signature().is_none());
+
+        assert!(Parser::new(SIMPLE).run().unwrap().signature().is_none());
+    }
+
+    #[test]
+    fn test_bad_signature_attribute() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 7],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("Signature"),        // #5
+            utf8("()V"),              // #6
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0],
+            vec![0, 1, 0, 5, 0, 0, 0, 2, 0, 6],
+        ]
+        .concat();
+
+        let class = Parser::new(&raw).run().unwrap();
+        assert_eq!(
+            [ClassParseError::new(
+                raw.len() - 2,
+                ClassParseErrorKind::BadSignature("()V".to_owned())
+            )],
+            class.warnings()
+        );
+        assert!(class.signature().is_none());
+        assert!(matches!(
+            class.attributes(),
+            [Attribute::Unknown { name, bytes }] if name == "Signature" && bytes[..] == [0, 6]
+        ));
+        assert_eq!(raw, class.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_bad_local_variable_type_table() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 11],
+            utf8("Foo"),                    // #1
+            vec![7, 0, 1],                  // #2 Class
+            utf8("java/lang/Object"),       // #3
+            vec![7, 0, 3],                  // #4 Class
+            utf8("run"),                    // #5
+            utf8("(I)V"),                   // #6
+            utf8("Code"),                   // #7
+            utf8("LocalVariableTypeTable"), // #8
+            utf8("x"),                      // #9
+            utf8("Ljava/util/List<I>;"),    // #10
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+            // One method, `static void run(int)`, with a Code attribute
+            vec![0, 1, 0, 0x08, 0, 5, 0, 6, 0, 1, 0, 7, 0, 0, 0, 31],
+            // max_stack, max_locals, and code: return
+            vec![0, 0, 0, 1, 0, 0, 0, 1, 0xB1],
+            // No exception handlers, and a LocalVariableTypeTable with a bad signature
+            vec![0, 0, 0, 1, 0, 8, 0, 0, 0, 12, 0, 1],
+            vec![0, 0, 0, 1, 0, 9, 0, 10, 0, 0],
+            // No class attributes
+            vec![0, 0],
+        ]
+        .concat();
+
+        let class = Parser::new(&raw).run().unwrap();
+        assert_eq!(
+            [ClassParseError::new(
+                raw.len() - 6,
+                ClassParseErrorKind::BadSignature("Ljava/util/List<I>;".to_owned())
+            )],
+            class.warnings()
+        );
+        assert!(matches!(
+            &method(&class, "run").code().unwrap().unwrap().attributes[..],
+            [Attribute::Unknown { name, .. }] if name == "LocalVariableTypeTable"
+        ));
+        assert_eq!(raw, class.to_bytes().unwrap());
+    }
+
+    fn class_descriptor(name: &str) -> FieldDescriptor {
+        FieldDescriptor::Basic(BasicFieldType::ClassInstance(name.into()))
+    }
+
+    #[test]
+    fn test_annotations() {
+        let class = Parser::new(ANNOTATED).run().unwrap();
+
+        let types: Vec<_> = class
+            .annotations()
+            .map(|a| a.type_descriptor.to_string())
+            .collect();
+        assert_eq!(
+            vec!["Ljava/lang/Deprecated;", "LInfo;", "LInternal;"],
+            types
+        );
+
+        let info = class.annotations().nth(1).unwrap();
+        assert_eq!(
+            Some(&ElementValue::String("annotated".into())),
+            info.element("name")
+        );
+        assert_eq!(Some(&ElementValue::Array(vec![])), info.element("numbers"));
+        assert_eq!(
+            Some(&ElementValue::Class(ReturnDescriptor::NonVoid(
+                class_descriptor("java/lang/String")
+            ))),
+            info.element("type")
+        );
+        assert_eq!(None, info.element("kind"));
+
+        let field = &class.fields()[0];
+        let field_info = field.annotations().next().unwrap();
+        assert_eq!(
+            Some(&ElementValue::Enum {
+                type_descriptor: class_descriptor("java/lang/annotation/ElementType"),
+                const_name: "METHOD".into(),
+            }),
+            field_info.element("kind")
+        );
+        assert_eq!(
+            Some(&ElementValue::Boolean(false)),
+            field_info.element("flag")
+        );
+
+        let Some(Attribute::RuntimeInvisibleTypeAnnotations(type_annotations)) = field
+            .attributes
+            .iter()
+            .find(|a| matches!(a, Attribute::RuntimeInvisibleTypeAnnotations(_)))
+        else {
+            panic!("expected type annotations on the field");
+        };
+        assert_eq!(
+            vec![TypeAnnotation {
+                target_type: TargetType::Field,
+                target_info: TargetInfo::Empty,
+                target_path: vec![TypePathEntry {
+                    type_path_kind: TypePathKind::TypeArgument,
+                    type_argument_index: 0,
+                }],
+                annotation: Annotation {
+                    type_descriptor: class_descriptor("NotNull"),
+                    elements: vec![],
+                },
+            }],
+            *type_annotations
+        );
+
+        let run = method(&class, "run");
+        let parameter_types = |index| {
+            run.parameter_annotations(index)
+                .map(|a| a.type_descriptor.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["LInternal;"], parameter_types(0));
+        assert_eq!(vec!["LInfo;"], parameter_types(1));
+        assert!(parameter_types(2).is_empty());
+    }
+
+    #[test]
+    fn test_annotation_defaults() {
+        let class = Parser::new(ANNOTATION_INTERFACE).run().unwrap();
+        let default = |name| method(&class, name).annotation_default().unwrap();
+
+        assert_eq!(&ElementValue::String("none".into()), default("name"));
+        assert_eq!(
+            &ElementValue::Array(vec![ElementValue::Int(1), ElementValue::Int(2)]),
+            default("numbers")
+        );
+        assert_eq!(
+            &ElementValue::Class(ReturnDescriptor::Void),
+            default("type")
+        );
+        assert_eq!(&ElementValue::Char(u16::from(b'x')), default("letter"));
+        assert_eq!(&ElementValue::Boolean(true), default("flag"));
+        assert_eq!(&ElementValue::Long(1 << 40), default("big"));
+
+        let ElementValue::Annotation(nested) = default("nested") else {
+            panic!("expected a nested annotation");
+        };
+        assert_eq!(
+            class_descriptor("java/lang/annotation/Retention"),
+            nested.type_descriptor
+        );
+        assert!(matches!(
+            nested.element("value"),
+            Some(ElementValue::Enum { const_name, .. }) if const_name == "SOURCE"
+        ));
+
+        let runtime = class.annotations().next().unwrap();
+        assert_eq!(
+            class_descriptor("java/lang/annotation/Retention"),
+            runtime.type_descriptor
+        );
+    }
+
+    #[test]
+    fn test_bad_element_value_tag() {
+        let cp = Parser::new(&[]).parse_constant_pool(1).unwrap();
+        assert_eq!(
+            Err(ClassParseError::new(
+                0,
+                ClassParseErrorKind::BadElementValueTag(b'x')
+            )),
+            Parser::new(b"x").parse_element_value(&cp, 0)
+        );
+    }
+
+    #[test]
+    fn test_element_value_depth() {
+        let cp = Parser::new(&[]).parse_constant_pool(1).unwrap();
+        // Arrays nested `depth` deep, with an empty array innermost
+        let nested = |depth| [&b"[\0\x01"[..]].repeat(depth).concat();
+        let parse = |raw: &[u8]| Parser::new(raw).parse_element_value(&cp, 0).map(|_| ());
+
+        let deepest = [nested(MAX_ELEMENT_VALUE_DEPTH - 1), b"[\0\0".to_vec()].concat();
+        assert_eq!(Ok(()), parse(&deepest));
+        for depth in [MAX_ELEMENT_VALUE_DEPTH, 100000] {
+            assert_eq!(
+                Err(ClassParseError::new(
+                    3 * MAX_ELEMENT_VALUE_DEPTH,
+                    ClassParseErrorKind::ElementValueTooDeep
+                )),
+                parse(&[nested(depth), b"[\0\0".to_vec()].concat())
+            );
+        }
+    }
+
+    #[test]
+    fn test_narrow_element_value_out_of_range() {
+        // #1 Integer 300, #2 Integer 2
+        let raw = [3, 0, 0, 0x01, 0x2C, 3, 0, 0, 0, 2];
+        let cp = Parser::new(&raw).parse_constant_pool(3).unwrap();
+        let parse = |raw: &[u8]| Parser::new(raw).parse_element_value(&cp, 0);
+
+        assert_eq!(Ok(ElementValue::Byte(2)), parse(b"B\0\x02"));
+        assert_eq!(Ok(ElementValue::Char(300)), parse(b"C\0\x01"));
+        assert_eq!(
+            Ok(ElementValue::OutOfRange {
+                tag: b'B',
+                value: 300
+            }),
+            parse(b"B\0\x01")
+        );
+        assert_eq!(
+            Ok(ElementValue::OutOfRange {
+                tag: b'Z',
+                value: 2
+            }),
+            parse(b"Z\0\x02")
+        );
+    }
+
+    #[test]
+    fn test_local_variables() {
+        let class = Parser::new(LOCALS).run().unwrap();
+        let repeat = method(&class, "repeat");
+        let code = repeat.code().unwrap().unwrap();
+
+        let local = |pc, slot| code.local_at(pc, slot).map(|l| l.name.as_str());
+        assert_eq!(Some("item"), local(0, 0));
+        assert_eq!(Some("i"), local(20, 3));
+        assert_eq!(None, local(30, 3));
+        assert_eq!(Some("done"), local(32, 3));
+        assert_eq!(None, local(34, 3));
+        assert_eq!(None, local(0, 4));
+
+        let done = code.local_at(33, 3).unwrap();
+        assert_eq!(
+            FieldDescriptor::Basic(BasicFieldType::Long),
+            done.descriptor
+        );
+
+        assert_eq!(
+            Some("java.util.List<T>".to_owned()),
+            code.local_type_at(10, 2).map(|l| l.signature.to_string())
+        );
+        assert!(code.local_type_at(10, 1).is_none());
+
+        let parameters: Vec<_> = repeat
+            .parameters()
+            .unwrap()
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_ref().unwrap().as_str(),
+                    p.access_flags.has(ParameterAccess::Final),
+                )
+            })
+            .collect();
+        assert_eq!(vec![("item", true), ("times", false)], parameters);
+        assert!(Parser::new(SIMPLE).run().unwrap().methods()[0]
+            .parameters()
+            .is_none());
+    }
+
+    #[test]
+    fn test_bootstrap_methods() {
+        let class = Parser::new(LAMBDAS).run().unwrap();
+        assert_eq!(2, class.bootstrap_methods().len());
+
+        let greeter = method(&class, "greeter").code().unwrap().unwrap();
+        let Some(op::Op::Invokedynamic(call_site)) = greeter.code.get(1) else {
+            panic!("expected an invokedynamic");
+        };
+        assert_eq!("get", call_site.name);
+
+        let bootstrap = class.invokedynamic_bootstrap(call_site).unwrap();
+        assert_eq!(ReferenceKind::InvokeStatic, bootstrap.method.kind);
+        let MethodHandleRef::Method(metafactory) = &bootstrap.method.reference else {
+            panic!("expected a method reference");
+        };
+        assert_eq!(
+            ("java/lang/invoke/LambdaMetafactory", "metafactory"),
+            (metafactory.class.as_str(), metafactory.name.as_str())
+        );
+        assert_eq!(3, bootstrap.arguments.len());
+        assert!(matches!(
+            &bootstrap.arguments[1],
+            LoadableConstant::MethodHandle(MethodHandle {
+                reference: MethodHandleRef::Method(MethodRef { name, .. }),
+                ..
+            }) if name == "lambda$greeter$0"
+        ));
+
+        let concat = &class.bootstrap_methods()[1];
+        assert!(matches!(
+            &concat.arguments[..],
+            [LoadableConstant::String(recipe)] if *recipe == "Hello, \u{1}"
+        ));
+    }
+
+    #[test]
+    fn test_missing_bootstrap_method() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 9],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("run"),              // #5
+            utf8("()V"),              // #6
+            vec![12, 0, 5, 0, 6],     // #7 NameAndType
+            vec![18, 0, 0, 0, 7],     // #8 InvokeDynamic
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0],
+        ]
+        .concat();
+
+        assert_eq!(
+            Err(ClassParseError::new(
+                raw.len() - 2,
+                ClassParseErrorKind::BadBootstrapMethodIndex {
+                    index: 8,
+                    bootstrap_method_attr_index: 0,
+                }
+            )),
+            Parser::new(&raw).run().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_module_info() {
+        let class = Parser::new(MODULE_INFO).run().unwrap();
+        assert!(class.access_flags().has(ClassAccess::Module));
+        assert_eq!("module-info", class.this_class());
+        assert_eq!(None, class.super_class());
+        assert!(Parser::new(SIMPLE)
+            .run()
+            .unwrap()
+            .module_descriptor()
+            .is_none());
+
+        let descriptor = class.module_descriptor().unwrap();
+        let module = descriptor.module;
+        assert_eq!("com.example.app", module.name);
+        assert_eq!(Some("1.2"), module.version.as_deref());
+
+        let requires: Vec<_> = module.requires.iter().map(|r| r.module.as_str()).collect();
+        assert_eq!(vec!["java.base", "java.logging", "java.sql"], requires);
+        assert!(module.requires.iter().all(|r| r.version.is_some()));
+        assert!(module.requires[0].flags.has(RequiresAccess::Mandated));
+        assert!(module.requires[1].flags.has(RequiresAccess::Transitive));
+        assert!(module.requires[2].flags.has(RequiresAccess::StaticPhase));
+        assert!(!module.flags.has(ModuleAccess::Open));
+
+        assert_eq!("com/example/api", module.exports[0].package);
+        assert!(module.exports[0].to.is_empty());
+        assert_eq!(["java.base", "java.logging"], module.exports[1].to[..]);
+        assert_eq!("com/example/app", module.opens[0].package);
+        assert_eq!(["java/sql/Driver"], module.uses[..]);
+        assert_eq!("java/lang/Runnable", module.provides[0].service);
+        assert_eq!(["com/example/app/Main"], module.provides[0].with[..]);
+
+        assert_eq!(["com/example/api", "com/example/app"], descriptor.packages);
+        assert_eq!(
+            Some("com/example/app/Main"),
+            descriptor.main_class.map(Symbol::as_str)
+        );
+    }
+
+    #[test]
+    fn test_records_and_sealed_classes() {
+        let shape = Parser::new(SHAPE).run().unwrap();
+        assert!(!shape.is_record());
+        assert_eq!(
+            ["Shape$Circle", "Shape$Polygon"],
+            shape.permitted_subclasses()
+        );
+        assert_eq!(["Shape$Polygon", "Shape$Circle"], shape.nest_members());
+        assert_eq!(None, shape.nest_host());
+
+        let polygon = Parser::new(SHAPE_POLYGON).run().unwrap();
+        assert!(polygon.is_record());
+        assert!(polygon.permitted_subclasses().is_empty());
+        assert_eq!(Some("Shape"), polygon.nest_host().map(Symbol::as_str));
 
-        unsafe { mem::transmute::<Attribute<'_>, Attribute<'static>>(attribute) }
+        let components = polygon.record_components().unwrap();
+        assert_eq!(
+            vec![
+                ("sides", "Ljava/util/List;".to_owned()),
+                ("name", "Ljava/lang/String;".to_owned())
+            ],
+            components
+                .iter()
+                .map(|c| (c.name.as_str(), c.descriptor.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some("java.util.List<java.lang.Integer>".to_owned()),
+            components[0].signature().map(ToString::to_string)
+        );
+        assert!(components[1].signature().is_none());
     }
 }
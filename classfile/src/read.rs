@@ -0,0 +1,210 @@
+//! Parsing class files from readers, files and memory maps, and many at once across threads.
+
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::*;
+
+impl ClassFile {
+    /// Reads a class file from `reader` up to its end, and parses it.
+    pub fn parse_from_reader(
+        mut reader: impl Read,
+        options: ParseOptions,
+    ) -> Result<Self, ClassReadError> {
+        let mut raw = Vec::new();
+        reader.read_to_end(&mut raw)?;
+        Ok(Self::parse_with_options(&raw, options)?)
+    }
+
+    /// Reads and parses the class file at `path`.
+    pub fn parse_file(
+        path: impl AsRef<Path>,
+        options: ParseOptions,
+    ) -> Result<Self, ClassReadError> {
+        Ok(Self::parse_with_options(&std::fs::read(path)?, options)?)
+    }
+
+    /// Parses the class file at `path` by mapping it into memory, instead of reading it into a
+    /// buffer first. The class doesn't borrow from the map, which is gone when this returns.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it's being parsed, e.g. by another
+    /// process.
+    #[cfg(feature = "mmap")]
+    #[allow(unsafe_code)]
+    pub unsafe fn parse_mmap(
+        path: impl AsRef<Path>,
+        options: ParseOptions,
+    ) -> Result<Self, ClassReadError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the caller promises that the file isn't changed while it's mapped.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Self::parse_with_options(&map, options)?)
+    }
+
+    /// Parses each of `classes`, spread across a thread for each available core. The results
+    /// are in the same order as `classes`.
+    pub fn parse_batch<T: AsRef<[u8]> + Sync>(
+        classes: &[T],
+        options: ParseOptions,
+    ) -> Vec<Result<Self, ClassParseError>> {
+        parallel_map(classes, available_threads(), |raw| {
+            Self::parse_with_options(raw.as_ref(), options)
+        })
+    }
+
+    /// Like [`Self::parse_batch`], for the class files at `paths`, which are read in parallel
+    /// too.
+    pub fn parse_files<P: AsRef<Path> + Sync>(
+        paths: &[P],
+        options: ParseOptions,
+    ) -> Vec<Result<Self, ClassReadError>> {
+        parallel_map(paths, available_threads(), |path| {
+            Self::parse_file(path, options)
+        })
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Maps `f` over `items` on up to `threads` threads, keeping the results in order.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    // Each thread takes the next item when it's done with one, so that a few large classes
+    // don't hold up the rest.
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            return results;
+                        };
+                        results.push((index, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_unstable_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES: [&str; 4] = ["Simple", "Branches", "Lambdas", "Strings"];
+
+    fn fixture_path(name: &str) -> String {
+        format!("{}/test_data/{name}.class", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn test_parse_from_reader() {
+        let raw = std::fs::read(fixture_path("Simple")).unwrap();
+        let expected = ClassFile::parse_from_bytes(&raw).unwrap();
+        let class = ClassFile::parse_from_reader(&raw[..], ParseOptions::default()).unwrap();
+        assert_eq!(format!("{expected:?}"), format!("{class:?}"));
+
+        assert!(matches!(
+            ClassFile::parse_from_reader(&raw[..10], ParseOptions::default()),
+            Err(ClassReadError::Parse(ClassParseError {
+                kind: ClassParseErrorKind::UnexpectedEof,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            ClassFile::parse_file(fixture_path("Missing"), ParseOptions::default()),
+            Err(ClassReadError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let mut raws: Vec<_> = FIXTURES
+            .iter()
+            .map(|name| std::fs::read(fixture_path(name)).unwrap())
+            .collect();
+        raws.insert(2, b"not a class".to_vec());
+
+        let results = ClassFile::parse_batch(&raws, ParseOptions::default());
+        assert_eq!(raws.len(), results.len());
+        for (raw, result) in raws.iter().zip(&results) {
+            let expected = ClassFile::parse_from_bytes(raw);
+            assert_eq!(format!("{expected:?}"), format!("{result:?}"));
+        }
+
+        let mut paths: Vec<_> = FIXTURES.iter().map(|name| fixture_path(name)).collect();
+        paths.push(fixture_path("Missing"));
+        let results = ClassFile::parse_files(&paths, ParseOptions::default());
+        let names: Vec<_> = results
+            .iter()
+            .map(|result| {
+                result
+                    .as_ref()
+                    .ok()
+                    .map(|class| class.this_class().as_str())
+            })
+            .collect();
+        assert_eq!(
+            [
+                Some("Simple"),
+                Some("Branches"),
+                Some("Lambdas"),
+                Some("Strings"),
+                None
+            ],
+            names[..]
+        );
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..1000).collect();
+        // Uneven work, so that the threads finish out of order.
+        let results = parallel_map(&items, 4, |&i| {
+            if i % 10 == 0 {
+                thread::sleep(std::time::Duration::from_millis(1));
+            }
+            i
+        });
+        assert_eq!(items, results);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[allow(unsafe_code)]
+    #[test]
+    fn test_parse_mmap() {
+        let path = fixture_path("Lambdas");
+        let expected = ClassFile::parse_file(&path, ParseOptions::default()).unwrap();
+        // SAFETY: nothing modifies the test data.
+        let class = unsafe { ClassFile::parse_mmap(&path, ParseOptions::default()) }.unwrap();
+        assert_eq!(format!("{expected:?}"), format!("{class:?}"));
+    }
+}
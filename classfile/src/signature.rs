@@ -0,0 +1,832 @@
+use crate::{ClassParseErrorKind, Symbol};
+use std::fmt;
+
+/// The contents of a Signature attribute, which depend on what the attribute is attached to.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9>
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Signature {
+    Class(ClassSignature),
+    Field(FieldSignature),
+    Method(MethodSignature),
+}
+
+/// A primitive type in a signature.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BaseType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum JavaTypeSignature {
+    Base(BaseType),
+    Reference(ReferenceTypeSignature),
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ReferenceTypeSignature {
+    Class(ClassTypeSignature),
+    /// A use of a type variable, e.g. the `T` in `List<T>`.
+    TypeVariable(Symbol),
+    /// An array with the given element type.
+    Array(Box<JavaTypeSignature>),
+}
+
+/// A possibly parameterized class type, such as `java.util.Map<K, V>.Entry`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ClassTypeSignature {
+    /// The package, with `/` separators, e.g. `java/util`. `None` for the unnamed package.
+    pub package: Option<Symbol>,
+    /// The class, preceded by the classes it's nested in, outermost first. Never empty.
+    pub classes: Vec<SimpleClassTypeSignature>,
+}
+
+/// One class in a [`ClassTypeSignature`], with its type arguments.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SimpleClassTypeSignature {
+    pub name: Symbol,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TypeArgument {
+    /// `?`
+    Any,
+    Exact(ReferenceTypeSignature),
+    /// `? extends T`
+    Extends(ReferenceTypeSignature),
+    /// `? super T`
+    Super(ReferenceTypeSignature),
+}
+
+/// A declaration of a type variable, with its bounds.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TypeParameter {
+    pub name: Symbol,
+    /// `None` if the only bounds are interfaces.
+    pub class_bound: Option<ReferenceTypeSignature>,
+    pub interface_bounds: Vec<ReferenceTypeSignature>,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub superinterfaces: Vec<ClassTypeSignature>,
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<JavaTypeSignature>,
+    pub result: ResultSignature,
+    /// Each one is a class type or a type variable.
+    pub throws: Vec<ReferenceTypeSignature>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ResultSignature {
+    Void,
+    NonVoid(JavaTypeSignature),
+}
+
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>
+pub type FieldSignature = ReferenceTypeSignature;
+
+/// Parses the signature of a class, interface, constructor, method or field, according to
+/// `parse`, which must consume the whole string.
+fn parse_whole<'a, T>(
+    string: &'a str,
+    parse: impl FnOnce(&mut SignatureParser<'a>) -> Option<T>,
+) -> Result<T, ClassParseErrorKind> {
+    let mut parser = SignatureParser {
+        rest: string,
+        depth: 0,
+    };
+    match parse(&mut parser) {
+        Some(signature) if parser.rest.is_empty() => Ok(signature),
+        _ => Err(ClassParseErrorKind::BadSignature(string.to_owned())),
+    }
+}
+
+pub(crate) fn parse_class_signature(string: &str) -> Result<ClassSignature, ClassParseErrorKind> {
+    parse_whole(string, SignatureParser::class_signature)
+}
+
+pub(crate) fn parse_method_signature(string: &str) -> Result<MethodSignature, ClassParseErrorKind> {
+    parse_whole(string, SignatureParser::method_signature)
+}
+
+pub(crate) fn parse_field_signature(string: &str) -> Result<FieldSignature, ClassParseErrorKind> {
+    parse_whole(string, SignatureParser::reference_type)
+}
+
+/// How deeply type arguments may be nested, e.g. `List<List<String>>` is nested twice. The
+/// parser recurses for each level, so this keeps a malicious signature from overflowing the stack.
+const MAX_TYPE_ARGUMENT_DEPTH: usize = 255;
+
+/// Recursive descent parser for the grammar in
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.9.1>. Each method
+/// returns `None` if the input doesn't match.
+struct SignatureParser<'a> {
+    rest: &'a str,
+    /// How many type argument lists the parser is currently in.
+    depth: usize,
+}
+
+impl SignatureParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    /// Consumes `c` if it's the next character.
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    fn identifier(&mut self) -> Option<Symbol> {
+        let end = self
+            .rest
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (identifier, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(identifier.into())
+    }
+
+    fn class_signature(&mut self) -> Option<ClassSignature> {
+        let type_parameters = self.type_parameters()?;
+        let superclass = self.class_type()?;
+        let mut superinterfaces = Vec::new();
+        while !self.rest.is_empty() {
+            superinterfaces.push(self.class_type()?);
+        }
+
+        Some(ClassSignature {
+            type_parameters,
+            superclass,
+            superinterfaces,
+        })
+    }
+
+    fn method_signature(&mut self) -> Option<MethodSignature> {
+        let type_parameters = self.type_parameters()?;
+
+        self.expect('(')?;
+        let mut parameters = Vec::new();
+        while !self.eat(')') {
+            parameters.push(self.java_type()?);
+        }
+
+        let result = if self.eat('V') {
+            ResultSignature::Void
+        } else {
+            ResultSignature::NonVoid(self.java_type()?)
+        };
+
+        let mut throws = Vec::new();
+        while self.eat('^') {
+            throws.push(match self.peek()? {
+                'L' => ReferenceTypeSignature::Class(self.class_type()?),
+                'T' => self.type_variable()?,
+                _ => return None,
+            });
+        }
+
+        Some(MethodSignature {
+            type_parameters,
+            parameters,
+            result,
+            throws,
+        })
+    }
+
+    /// Parses optional TypeParameters, returning an empty list if there aren't any.
+    fn type_parameters(&mut self) -> Option<Vec<TypeParameter>> {
+        let mut type_parameters = Vec::new();
+        if !self.eat('<') {
+            return Some(type_parameters);
+        }
+
+        loop {
+            let name = self.identifier()?;
+            self.expect(':')?;
+            let class_bound = match self.peek()? {
+                'L' | 'T' | '[' => Some(self.reference_type()?),
+                _ => None,
+            };
+            let mut interface_bounds = Vec::new();
+            while self.eat(':') {
+                interface_bounds.push(self.reference_type()?);
+            }
+
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+
+            if self.eat('>') {
+                return Some(type_parameters);
+            }
+        }
+    }
+
+    fn java_type(&mut self) -> Option<JavaTypeSignature> {
+        use BaseType::*;
+
+        let base = match self.peek()? {
+            'B' => Byte,
+            'C' => Char,
+            'D' => Double,
+            'F' => Float,
+            'I' => Int,
+            'J' => Long,
+            'S' => Short,
+            'Z' => Boolean,
+            _ => return Some(JavaTypeSignature::Reference(self.reference_type()?)),
+        };
+        self.rest = &self.rest[1..];
+
+        Some(JavaTypeSignature::Base(base))
+    }
+
+    fn reference_type(&mut self) -> Option<ReferenceTypeSignature> {
+        match self.peek()? {
+            'L' => Some(ReferenceTypeSignature::Class(self.class_type()?)),
+            'T' => self.type_variable(),
+            '[' => {
+                // Like in descriptors, an array type has at most 255 dimensions.
+                // <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4.1>
+                let dimensions = self.rest.len() - self.rest.trim_start_matches('[').len();
+                if dimensions > 255 {
+                    return None;
+                }
+                self.rest = &self.rest[dimensions..];
+                let mut signature = ReferenceTypeSignature::Array(Box::new(self.java_type()?));
+                for _ in 1..dimensions {
+                    signature = ReferenceTypeSignature::Array(Box::new(
+                        JavaTypeSignature::Reference(signature),
+                    ));
+                }
+                Some(signature)
+            }
+            _ => None,
+        }
+    }
+
+    fn type_variable(&mut self) -> Option<ReferenceTypeSignature> {
+        self.expect('T')?;
+        let name = self.identifier()?;
+        self.expect(';')?;
+
+        Some(ReferenceTypeSignature::TypeVariable(name))
+    }
+
+    fn class_type(&mut self) -> Option<ClassTypeSignature> {
+        self.expect('L')?;
+
+        let mut package = String::new();
+        let mut name = self.identifier()?;
+        while self.eat('/') {
+            if !package.is_empty() {
+                package.push('/');
+            }
+            package.push_str(&name);
+            name = self.identifier()?;
+        }
+
+        let mut classes = vec![self.simple_class_type(name)?];
+        while self.eat('.') {
+            let name = self.identifier()?;
+            classes.push(self.simple_class_type(name)?);
+        }
+        self.expect(';')?;
+
+        Some(ClassTypeSignature {
+            package: (!package.is_empty()).then(|| package.into()),
+            classes,
+        })
+    }
+
+    /// Parses the type arguments that follow the class name `name`.
+    fn simple_class_type(&mut self, name: Symbol) -> Option<SimpleClassTypeSignature> {
+        let mut type_arguments = Vec::new();
+        if self.eat('<') {
+            if self.depth == MAX_TYPE_ARGUMENT_DEPTH {
+                return None;
+            }
+            self.depth += 1;
+            while !self.eat('>') {
+                type_arguments.push(if self.eat('*') {
+                    TypeArgument::Any
+                } else if self.eat('+') {
+                    TypeArgument::Extends(self.reference_type()?)
+                } else if self.eat('-') {
+                    TypeArgument::Super(self.reference_type()?)
+                } else {
+                    TypeArgument::Exact(self.reference_type()?)
+                });
+            }
+            if type_arguments.is_empty() {
+                return None;
+            }
+            self.depth -= 1;
+        }
+
+        Some(SimpleClassTypeSignature {
+            name,
+            type_arguments,
+        })
+    }
+}
+
+impl Signature {
+    /// The signature as it appears in a class file, e.g. `Ljava/util/List<TT;>;`.
+    pub fn encode(&self) -> String {
+        match self {
+            Signature::Class(signature) => signature.encode(),
+            Signature::Field(signature) => signature.encode(),
+            Signature::Method(signature) => signature.encode(),
+        }
+    }
+}
+
+impl ClassSignature {
+    /// The signature as it appears in a class file.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        encode_type_parameters(&mut out, &self.type_parameters);
+        self.superclass.encode_into(&mut out);
+        for superinterface in &self.superinterfaces {
+            superinterface.encode_into(&mut out);
+        }
+        out
+    }
+}
+
+impl MethodSignature {
+    /// The signature as it appears in a class file.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        encode_type_parameters(&mut out, &self.type_parameters);
+        out.push('(');
+        for parameter in &self.parameters {
+            parameter.encode_into(&mut out);
+        }
+        out.push(')');
+        match &self.result {
+            ResultSignature::Void => out.push('V'),
+            ResultSignature::NonVoid(t) => t.encode_into(&mut out),
+        }
+        for throws in &self.throws {
+            out.push('^');
+            throws.encode_into(&mut out);
+        }
+        out
+    }
+}
+
+impl ReferenceTypeSignature {
+    /// The signature as it appears in a class file.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut String) {
+        match self {
+            ReferenceTypeSignature::Class(class) => class.encode_into(out),
+            ReferenceTypeSignature::TypeVariable(name) => {
+                out.push('T');
+                out.push_str(name);
+                out.push(';');
+            }
+            ReferenceTypeSignature::Array(element) => {
+                out.push('[');
+                element.encode_into(out);
+            }
+        }
+    }
+}
+
+impl JavaTypeSignature {
+    fn encode_into(&self, out: &mut String) {
+        use BaseType::*;
+
+        match self {
+            JavaTypeSignature::Base(base) => out.push(match base {
+                Byte => 'B',
+                Char => 'C',
+                Double => 'D',
+                Float => 'F',
+                Int => 'I',
+                Long => 'J',
+                Short => 'S',
+                Boolean => 'Z',
+            }),
+            JavaTypeSignature::Reference(reference) => reference.encode_into(out),
+        }
+    }
+}
+
+impl ClassTypeSignature {
+    fn encode_into(&self, out: &mut String) {
+        out.push('L');
+        if let Some(package) = &self.package {
+            out.push_str(package);
+            out.push('/');
+        }
+        for (i, class) in self.classes.iter().enumerate() {
+            if i != 0 {
+                out.push('.');
+            }
+            out.push_str(&class.name);
+            if !class.type_arguments.is_empty() {
+                out.push('<');
+                for argument in &class.type_arguments {
+                    match argument {
+                        TypeArgument::Any => out.push('*'),
+                        TypeArgument::Exact(t) => t.encode_into(out),
+                        TypeArgument::Extends(t) => {
+                            out.push('+');
+                            t.encode_into(out);
+                        }
+                        TypeArgument::Super(t) => {
+                            out.push('-');
+                            t.encode_into(out);
+                        }
+                    }
+                }
+                out.push('>');
+            }
+        }
+        out.push(';');
+    }
+}
+
+/// Writes `<T:Ljava/lang/Object;>`, or nothing if there are no type parameters.
+fn encode_type_parameters(out: &mut String, type_parameters: &[TypeParameter]) {
+    if type_parameters.is_empty() {
+        return;
+    }
+    out.push('<');
+    for parameter in type_parameters {
+        out.push_str(&parameter.name);
+        out.push(':');
+        if let Some(bound) = &parameter.class_bound {
+            bound.encode_into(out);
+        }
+        for bound in &parameter.interface_bounds {
+            out.push(':');
+            bound.encode_into(out);
+        }
+    }
+    out.push('>');
+}
+
+/// Writes `items` separated by `separator`.
+fn write_separated<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    items: &[T],
+    separator: &str,
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            f.write_str(separator)?;
+        }
+        item.fmt(f)?;
+    }
+    Ok(())
+}
+
+/// Writes `<T, U extends Comparable<U>>`, or nothing if there are no type parameters.
+fn write_type_parameters(
+    f: &mut fmt::Formatter<'_>,
+    type_parameters: &[TypeParameter],
+) -> fmt::Result {
+    if !type_parameters.is_empty() {
+        f.write_str("<")?;
+        write_separated(f, type_parameters, ", ")?;
+        f.write_str(">")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signature::Class(signature) => signature.fmt(f),
+            Signature::Field(signature) => signature.fmt(f),
+            Signature::Method(signature) => signature.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for BaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BaseType::*;
+
+        f.write_str(match self {
+            Byte => "byte",
+            Char => "char",
+            Double => "double",
+            Float => "float",
+            Int => "int",
+            Long => "long",
+            Short => "short",
+            Boolean => "boolean",
+        })
+    }
+}
+
+impl fmt::Display for JavaTypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JavaTypeSignature::Base(base) => base.fmt(f),
+            JavaTypeSignature::Reference(reference) => reference.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for ReferenceTypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferenceTypeSignature::Class(class) => class.fmt(f),
+            ReferenceTypeSignature::TypeVariable(name) => name.fmt(f),
+            ReferenceTypeSignature::Array(element) => write!(f, "{element}[]"),
+        }
+    }
+}
+
+impl ClassTypeSignature {
+    /// Whether this is plain `java.lang.Object`, which javac writes as the class bound of
+    /// otherwise unbounded type parameters.
+    fn is_object(&self) -> bool {
+        matches!(
+            (self.package.as_deref(), self.classes.as_slice()),
+            (Some("java/lang"), [SimpleClassTypeSignature { name, type_arguments }])
+                if name == "Object" && type_arguments.is_empty()
+        )
+    }
+}
+
+/// Writes the type as it would appear in Java source, e.g. `java.util.Map.Entry<K, V>`.
+impl fmt::Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(package) = &self.package {
+            for part in package.split('/') {
+                write!(f, "{part}.")?;
+            }
+        }
+        write_separated(f, &self.classes, ".")
+    }
+}
+
+impl fmt::Display for SimpleClassTypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)?;
+        if !self.type_arguments.is_empty() {
+            f.write_str("<")?;
+            write_separated(f, &self.type_arguments, ", ")?;
+            f.write_str(">")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeArgument::Any => f.write_str("?"),
+            TypeArgument::Exact(t) => t.fmt(f),
+            TypeArgument::Extends(t) => write!(f, "? extends {t}"),
+            TypeArgument::Super(t) => write!(f, "? super {t}"),
+        }
+    }
+}
+
+/// Writes `T extends A & B`. A class bound of plain `Object` is left out.
+impl fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)?;
+
+        let class_bound = self.class_bound.as_ref().filter(
+            |bound| !matches!(bound, ReferenceTypeSignature::Class(class) if class.is_object()),
+        );
+        let mut bounds = class_bound.into_iter().chain(&self.interface_bounds);
+        if let Some(first) = bounds.next() {
+            write!(f, " extends {first}")?;
+            for bound in bounds {
+                write!(f, " & {bound}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes e.g. `<T> extends java.lang.Object implements java.lang.Comparable<T>`.
+impl fmt::Display for ClassSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+        if !self.type_parameters.is_empty() {
+            f.write_str(" ")?;
+        }
+        write!(f, "extends {}", self.superclass)?;
+        if !self.superinterfaces.is_empty() {
+            f.write_str(" implements ")?;
+            write_separated(f, &self.superinterfaces, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes e.g. `<T> T (java.util.List<T>, int) throws java.io.IOException`.
+impl fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+        if !self.type_parameters.is_empty() {
+            f.write_str(" ")?;
+        }
+        match &self.result {
+            ResultSignature::Void => f.write_str("void")?,
+            ResultSignature::NonVoid(t) => t.fmt(f)?,
+        }
+        f.write_str(" (")?;
+        write_separated(f, &self.parameters, ", ")?;
+        f.write_str(")")?;
+        if !self.throws.is_empty() {
+            f.write_str(" throws ")?;
+            write_separated(f, &self.throws, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_signature() {
+        let signature = parse_class_signature(
+            "<K::Ljava/lang/Comparable<-TK;>;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;",
+        )
+        .unwrap();
+
+        assert_eq!(2, signature.type_parameters.len());
+        assert_eq!(None, signature.type_parameters[0].class_bound);
+        assert_eq!(1, signature.type_parameters[0].interface_bounds.len());
+        assert_eq!(Some("java/util".into()), signature.superclass.package);
+        assert_eq!(
+            "<K extends java.lang.Comparable<? super K>, V> extends java.util.AbstractMap<K, V> implements java.io.Serializable",
+            signature.to_string()
+        );
+    }
+
+    #[test]
+    fn test_method_signature() {
+        let signature = parse_method_signature(
+            "<T:Ljava/lang/Number;E:Ljava/lang/Exception;>([TT;ILjava/util/List<+TT;>;)TT;^TE;^Ljava/io/IOException;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                JavaTypeSignature::Reference(ReferenceTypeSignature::Array(Box::new(
+                    JavaTypeSignature::Reference(ReferenceTypeSignature::TypeVariable("T".into()))
+                ))),
+                JavaTypeSignature::Base(BaseType::Int),
+            ],
+            signature.parameters[..2]
+        );
+        assert_eq!(
+            "<T extends java.lang.Number, E extends java.lang.Exception> T (T[], int, java.util.List<? extends T>) throws E, java.io.IOException",
+            signature.to_string()
+        );
+
+        assert_eq!(
+            "void ()",
+            parse_method_signature("()V").unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_field_signature() {
+        let signature = parse_field_signature("Lcom/example/Outer<TT;>.Inner<*>.Deeper;").unwrap();
+        let ReferenceTypeSignature::Class(class) = &signature else {
+            panic!("expected a class type");
+        };
+        assert_eq!(
+            vec!["Outer", "Inner", "Deeper"],
+            class
+                .classes
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "com.example.Outer<T>.Inner<?>.Deeper",
+            signature.to_string()
+        );
+
+        assert_eq!(
+            "Foo[][]",
+            parse_field_signature("[[LFoo;").unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        for class in [
+            "<K::Ljava/lang/Comparable<-TK;>;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;",
+            "LFoo;",
+        ] {
+            assert_eq!(class, parse_class_signature(class).unwrap().encode());
+        }
+        for method in [
+            "<T:Ljava/lang/Number;E:Ljava/lang/Exception;>([TT;ILjava/util/List<+TT;>;)TT;^TE;^Ljava/io/IOException;",
+            "()V",
+            "([[ZJ)[D",
+        ] {
+            assert_eq!(method, parse_method_signature(method).unwrap().encode());
+        }
+        for field in ["Lcom/example/Outer<TT;>.Inner<*>.Deeper;", "[[LFoo;", "TT;"] {
+            assert_eq!(field, parse_field_signature(field).unwrap().encode());
+        }
+    }
+
+    #[test]
+    fn test_nesting_limits() {
+        let deepest_array = format!("{}LFoo;", "[".repeat(255));
+        assert_eq!(
+            Ok(deepest_array.clone()),
+            parse_field_signature(&deepest_array).map(|signature| signature.encode())
+        );
+        let deepest_arguments = format!("{}LFoo;{}", "LFoo<".repeat(255), ">;".repeat(255));
+        assert_eq!(
+            Ok(deepest_arguments.clone()),
+            parse_field_signature(&deepest_arguments).map(|signature| signature.encode())
+        );
+    }
+
+    #[test]
+    fn test_bad_signatures() {
+        for bad in [
+            "",
+            "I",
+            "Ljava/util/List",
+            "Ljava/util/List<>;",
+            "TT",
+            "L;",
+            "[V",
+            &format!("{}LFoo;", "[".repeat(256)),
+            &format!("{}LFoo;", "[".repeat(20000)),
+            &format!("{}LFoo;{}", "LFoo<".repeat(256), ">;".repeat(256)),
+            &format!("{}LFoo;{}", "LFoo<".repeat(100000), ">;".repeat(100000)),
+        ] {
+            assert_eq!(
+                Err(ClassParseErrorKind::BadSignature(bad.to_owned())),
+                parse_field_signature(bad)
+            );
+        }
+
+        for bad in ["", "()", "(V)V", "()VV", "<>()V", "<T>()V", "()V^I"] {
+            assert_eq!(
+                Err(ClassParseErrorKind::BadSignature(bad.to_owned())),
+                parse_method_signature(bad)
+            );
+        }
+
+        for bad in ["", "<T:>", "Ljava/lang/Object;I"] {
+            assert_eq!(
+                Err(ClassParseErrorKind::BadSignature(bad.to_owned())),
+                parse_class_signature(bad)
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+/// A source map (SMAP), which maps the line numbers in a class file back to lines of the
+/// sources it was generated from, e.g. Kotlin inline functions or JSP pages. Each stratum is one
+/// view of the sources; the line numbers in `LineNumberTable`s are in the "Java" stratum.
+/// <https://jcp.org/en/jsr/detail?id=45>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    /// The name of the generated source file, e.g. `Foo.java`.
+    pub output_file: String,
+    /// The stratum that a debugger should show by default.
+    pub default_stratum: String,
+    pub strata: Vec<Stratum>,
+}
+
+impl SourceMap {
+    pub fn stratum(&self, id: &str) -> Option<&Stratum> {
+        self.strata.iter().find(|stratum| stratum.id == id)
+    }
+}
+
+/// A StratumSection along with its FileSection and LineSection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stratum {
+    pub id: String,
+    pub files: Vec<SmapFile>,
+    pub lines: Vec<LineMapping>,
+}
+
+impl Stratum {
+    /// The source file and line in this stratum that `output_line` was generated from, if any.
+    /// The first matching entry of the LineSection wins.
+    pub fn map_line(&self, output_line: u32) -> Option<SourceLocation<'_>> {
+        self.lines.iter().find_map(|mapping| {
+            let line = mapping.map_line(output_line)?;
+            let file = self.files.iter().find(|file| file.id == mapping.file_id)?;
+            Some(SourceLocation {
+                file: &file.name,
+                path: file.path.as_deref(),
+                line,
+            })
+        })
+    }
+}
+
+/// An entry of a FileSection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmapFile {
+    pub id: u32,
+    /// The file name, e.g. `Foo.kt`.
+    pub name: String,
+    /// The path of the file relative to the source root, if given.
+    pub path: Option<String>,
+}
+
+/// An entry of a LineSection: lines `input_start_line..input_start_line + repeat_count` of a
+/// source file each map to `output_line_increment` consecutive output lines, starting at
+/// `output_start_line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMapping {
+    pub input_start_line: u32,
+    /// Refers to an [`SmapFile`] of the same stratum.
+    pub file_id: u32,
+    pub repeat_count: u32,
+    pub output_start_line: u32,
+    pub output_line_increment: u32,
+}
+
+impl LineMapping {
+    /// The input line that `output_line` was generated from, if it's covered by this entry and
+    /// the input line fits in a `u32`.
+    pub fn map_line(&self, output_line: u32) -> Option<u32> {
+        let offset = output_line.checked_sub(self.output_start_line)?;
+        let index = offset.checked_div(self.output_line_increment)?;
+        if index < self.repeat_count {
+            self.input_start_line.checked_add(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// A line of a source file, as resolved through a [`SourceMap`] or the class's SourceFile
+/// attribute.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    /// The file name, e.g. `Foo.kt`.
+    pub file: &'a str,
+    /// The path of the file relative to the source root, if known.
+    pub path: Option<&'a str>,
+    pub line: u32,
+}
+
+/// Parses a resolved SMAP, i.e. one without embedded source maps. On failure, returns the
+/// 1-based number of the offending line.
+pub(crate) fn parse_source_map(smap: &str) -> Result<SourceMap, usize> {
+    let mut lines = smap.lines().enumerate().map(|(i, line)| (i + 1, line));
+    // Running out of lines is reported as an error on the line after the last one.
+    let eof = smap.lines().count() + 1;
+
+    let (number, header) = lines.next().ok_or(eof)?;
+    if header != "SMAP" {
+        return Err(number);
+    }
+    let (_, output_file) = lines.next().ok_or(eof)?;
+    let (_, default_stratum) = lines.next().ok_or(eof)?;
+
+    let mut strata: Vec<Stratum> = Vec::new();
+    let mut section = Section::Unknown;
+    let mut file_id = 0;
+
+    loop {
+        let (number, line) = lines.next().ok_or(eof)?;
+
+        if let Some(header) = line.strip_prefix('*') {
+            section = match header.chars().next() {
+                Some('E') => break,
+                Some('S') => {
+                    let id = header[1..].trim();
+                    if id.is_empty() {
+                        return Err(number);
+                    }
+                    strata.push(Stratum {
+                        id: id.to_owned(),
+                        files: Vec::new(),
+                        lines: Vec::new(),
+                    });
+                    Section::Unknown
+                }
+                Some('F') if !strata.is_empty() => Section::Files,
+                Some('L') if !strata.is_empty() => {
+                    file_id = 0;
+                    Section::Lines
+                }
+                // File and line sections belong to a stratum, and embedded source maps are only
+                // allowed before resolution.
+                Some('F' | 'L' | 'O' | 'C') | None => return Err(number),
+                // Vendor sections and sections from future versions are skipped.
+                _ => Section::Unknown,
+            };
+            continue;
+        }
+
+        match section {
+            Section::Unknown => {}
+            Section::Files => {
+                let (has_path, file) = match line.strip_prefix('+') {
+                    Some(file) => (true, file.trim_start()),
+                    None => (false, line),
+                };
+                let (id, name) = file.split_once(' ').ok_or(number)?;
+                let id = id.parse().map_err(|_| number)?;
+                let path = if has_path {
+                    Some(lines.next().ok_or(eof)?.1.to_owned())
+                } else {
+                    None
+                };
+                strata.last_mut().unwrap().files.push(SmapFile {
+                    id,
+                    name: name.to_owned(),
+                    path,
+                });
+            }
+            Section::Lines => {
+                let mapping = parse_line_info(line, &mut file_id).ok_or(number)?;
+                strata.last_mut().unwrap().lines.push(mapping);
+            }
+        }
+    }
+
+    Ok(SourceMap {
+        output_file: output_file.to_owned(),
+        default_stratum: default_stratum.to_owned(),
+        strata,
+    })
+}
+
+enum Section {
+    Unknown,
+    Files,
+    Lines,
+}
+
+/// Parses `InputStartLine [ "#" LineFileID ] [ "," RepeatCount ] ":" OutputStartLine
+/// [ "," OutputLineIncrement ]`. An omitted LineFileID is the same as the previous line's.
+fn parse_line_info(line: &str, file_id: &mut u32) -> Option<LineMapping> {
+    let (input, output) = line.split_once(':')?;
+
+    let (input, repeat_count) = match input.split_once(',') {
+        Some((input, repeat_count)) => (input, repeat_count.parse().ok()?),
+        None => (input, 1),
+    };
+    let input_start_line = match input.split_once('#') {
+        Some((input_start_line, id)) => {
+            *file_id = id.parse().ok()?;
+            input_start_line
+        }
+        None => input,
+    };
+    let (output_start_line, output_line_increment) = match output.split_once(',') {
+        Some((output, increment)) => (output, increment.parse().ok()?),
+        None => (output, 1),
+    };
+
+    Some(LineMapping {
+        input_start_line: input_start_line.parse().ok()?,
+        file_id: *file_id,
+        repeat_count,
+        output_start_line: output_start_line.parse().ok()?,
+        output_line_increment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOTLIN: &str = "SMAP
+Foo.kt
+Kotlin
+*S Kotlin
+*F
++ 1 Foo.kt
+com/example/Foo.kt
++ 2 Util.kt
+com/example/Util.kt
+*L
+1#1,11:1
+5#2,2:12,2
+*S KotlinDebug
+*F
++ 1 Foo.kt
+com/example/Foo.kt
+*L
+7#1:12
+8:14,2
+*V
+vendor data
+*E
+";
+
+    #[test]
+    fn test_parse() {
+        let smap = parse_source_map(KOTLIN).unwrap();
+        assert_eq!("Foo.kt", smap.output_file);
+        assert_eq!("Kotlin", smap.default_stratum);
+        assert_eq!(
+            vec!["Kotlin", "KotlinDebug"],
+            smap.strata
+                .iter()
+                .map(|s| s.id.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let kotlin = smap.stratum("Kotlin").unwrap();
+        assert_eq!(
+            SmapFile {
+                id: 2,
+                name: "Util.kt".to_owned(),
+                path: Some("com/example/Util.kt".to_owned())
+            },
+            kotlin.files[1]
+        );
+        assert_eq!(
+            LineMapping {
+                input_start_line: 5,
+                file_id: 2,
+                repeat_count: 2,
+                output_start_line: 12,
+                output_line_increment: 2,
+            },
+            kotlin.lines[1]
+        );
+
+        let debug = smap.stratum("KotlinDebug").unwrap();
+        assert_eq!(1, debug.lines[1].file_id);
+        assert_eq!(1, debug.lines[1].repeat_count);
+    }
+
+    #[test]
+    fn test_map_line() {
+        let smap = parse_source_map(KOTLIN).unwrap();
+        let kotlin = smap.stratum("Kotlin").unwrap();
+        let at = |line| kotlin.map_line(line).map(|loc| (loc.file, loc.line));
+
+        assert_eq!(Some(("Foo.kt", 1)), at(1));
+        assert_eq!(Some(("Foo.kt", 11)), at(11));
+        assert_eq!(Some(("Util.kt", 5)), at(12));
+        assert_eq!(Some(("Util.kt", 5)), at(13));
+        assert_eq!(Some(("Util.kt", 6)), at(15));
+        assert_eq!(None, at(16));
+        assert_eq!(None, at(0));
+
+        let mapping = LineMapping {
+            input_start_line: u32::MAX - 1,
+            file_id: 1,
+            repeat_count: 3,
+            output_start_line: 1,
+            output_line_increment: 1,
+        };
+        assert_eq!(Some(u32::MAX), mapping.map_line(2));
+        assert_eq!(None, mapping.map_line(3));
+    }
+
+    #[test]
+    fn test_bad_smap() {
+        assert_eq!(Err(1), parse_source_map("Foo.kt\nKotlin\n*E"));
+        assert_eq!(Err(4), parse_source_map("SMAP\nFoo.kt\nKotlin\n*F\n*E"));
+        assert_eq!(
+            Err(7),
+            parse_source_map("SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*L\n1:1\n1#x:2\n*E")
+        );
+        // Missing *E.
+        assert_eq!(Err(5), parse_source_map("SMAP\nFoo.kt\nKotlin\n*S Kotlin"));
+    }
+}
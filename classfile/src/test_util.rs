@@ -0,0 +1,25 @@
+//! Helpers shared by the tests of several modules.
+
+use std::path::PathBuf;
+
+/// The path and bytes of each class file in `test_data`.
+pub(crate) fn fixtures() -> Vec<(PathBuf, Vec<u8>)> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data");
+    let mut fixtures = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "class") {
+            let raw = std::fs::read(&path).unwrap();
+            fixtures.push((path, raw));
+        }
+    }
+    fixtures
+}
+
+/// A Utf8 constant pool entry for the ASCII string `s`.
+pub(crate) fn utf8(s: &str) -> Vec<u8> {
+    let mut v = vec![1];
+    v.extend((s.len() as u16).to_be_bytes());
+    v.extend(s.as_bytes());
+    v
+}
@@ -0,0 +1,122 @@
+//! Which class file versions this crate supports, and which features each version has.
+
+/// The oldest supported major version, from JDK 1.0.2.
+pub const MIN_MAJOR_VERSION: u16 = 45;
+
+/// The newest supported major version, from Java 21.
+pub const LATEST_MAJOR_VERSION: u16 = 65;
+
+/// The minor version of class files that depend on the preview features of their Java SE
+/// release.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-B.2>
+pub const PREVIEW_MINOR_VERSION: u16 = 0xFFFF;
+
+/// How to parse a class file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The newest major version to accept. Anything newer is handled according to
+    /// `unsupported_version`.
+    pub max_major_version: u16,
+    /// Whether to accept class files that use preview features. Those are only valid in the
+    /// release they were compiled for, so their major version has to be `max_major_version`.
+    pub allow_preview: bool,
+    pub unsupported_version: VersionPolicy,
+    /// Whether to defer decoding each method's Code attribute until it's first accessed (see
+    /// [`MethodInfo::code`](crate::MethodInfo::code)). Until then only its structure is
+    /// checked, and the raw bytes are kept instead of the decoded instructions.
+    pub lazy_code: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_major_version: LATEST_MAJOR_VERSION,
+            allow_preview: false,
+            unsupported_version: VersionPolicy::Reject,
+            lazy_code: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Whether a class file with this version is supported.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.1-200-B.2>
+    pub fn supports_version(&self, major_version: u16, minor_version: u16) -> bool {
+        let in_range = (MIN_MAJOR_VERSION..=self.max_major_version).contains(&major_version);
+        // Since Java 12, the minor version only says whether preview features are used.
+        if major_version < 56 {
+            return in_range;
+        }
+        match minor_version {
+            0 => in_range,
+            PREVIEW_MINOR_VERSION => {
+                in_range && self.allow_preview && major_version == self.max_major_version
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What to do with a class file whose version isn't supported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// Fail with [`ClassParseErrorKind::UnsupportedVersion`](crate::ClassParseErrorKind).
+    Reject,
+    /// Parse it anyway, and record the unsupported version in
+    /// [`ClassFile::warnings`](crate::ClassFile::warnings).
+    Warn,
+}
+
+/// The first major version that defines the predefined attribute `name`, or `None` if `name`
+/// isn't predefined. Older class files may have attributes with these names, but they're
+/// treated like any other unknown attribute.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7-310>
+pub fn attribute_major_version(name: &str) -> Option<u16> {
+    Some(match name {
+        "ConstantValue" | "Code" | "Exceptions" | "InnerClasses" | "Synthetic" | "SourceFile"
+        | "LineNumberTable" | "LocalVariableTable" | "Deprecated" => 45,
+        "EnclosingMethod"
+        | "Signature"
+        | "SourceDebugExtension"
+        | "LocalVariableTypeTable"
+        | "RuntimeVisibleAnnotations"
+        | "RuntimeInvisibleAnnotations"
+        | "RuntimeVisibleParameterAnnotations"
+        | "RuntimeInvisibleParameterAnnotations"
+        | "AnnotationDefault" => 49,
+        "StackMapTable" => 50,
+        "BootstrapMethods" => 51,
+        "RuntimeVisibleTypeAnnotations"
+        | "RuntimeInvisibleTypeAnnotations"
+        | "MethodParameters" => 52,
+        "Module" | "ModulePackages" | "ModuleMainClass" => 53,
+        "NestHost" | "NestMembers" => 55,
+        "Record" => 60,
+        "PermittedSubclasses" => 61,
+        _ => return None,
+    })
+}
+
+/// The first major version that allows constants with `tag`, or `None` if the tag isn't
+/// defined.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4-310>
+pub fn constant_major_version(tag: u8) -> Option<u16> {
+    Some(match tag {
+        1 | 3..=12 => 45,
+        15 | 16 | 18 => 51,
+        19 | 20 => 53,
+        17 => 55,
+        _ => return None,
+    })
+}
+
+/// Whether `opcode` may appear in the code of a class file with `major_version`: `invokedynamic`
+/// was added in version 51, which also removed `jsr` and `jsr_w`.
+/// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.9.1>
+pub fn opcode_allowed(opcode: u8, major_version: u16) -> bool {
+    match opcode {
+        0xba => major_version >= 51,
+        0xa8 | 0xc9 => major_version < 51,
+        _ => true,
+    }
+}
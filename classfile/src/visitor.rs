@@ -0,0 +1,824 @@
+//! Streaming a class through a chain of visitors, e.g. to instrument its methods.
+//!
+//! [`ClassFile::accept`] takes a class apart into events: a [`ClassVisitor`] call for the class
+//! itself, each field, method and attribute, with a [`FieldVisitor`], [`MethodVisitor`] or
+//! [`CodeVisitor`] for the parts of each one. Every method of the visitor traits forwards its
+//! event to the visitor's [`delegate`](ClassVisitor::delegate) by default, so a transformer
+//! only overrides the events it changes and passes the rest along unchanged.
+//!
+//! A [`ClassBuilder`](crate::builder::ClassBuilder) can be at the end of the chain to put the
+//! class back together, with a [`CodeBuilder`](crate::builder::CodeBuilder) for each method's
+//! code. In code events, branches, switches, exception handlers, line numbers and local
+//! variables refer to [`Label`]s instead of offsets, so they stay valid when instructions are
+//! inserted or removed.
+//!
+//! Attributes of the Code attribute other than LineNumberTable, LocalVariableTable and
+//! LocalVariableTypeTable are passed along as they are. The offsets in a StackMapTable or in
+//! type annotations don't move with the instructions, so after changing a method's code its
+//! frames should be recomputed, e.g. with
+//! [`ClassBuilder::build_with_frames`](crate::builder::ClassBuilder::build_with_frames).
+
+use crate::builder::Label;
+use crate::op::Op;
+use crate::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Receives the parts of a class. `visit` comes first and `visit_end` last, with the fields,
+/// methods and attributes in between.
+pub trait ClassVisitor {
+    /// The visitor that events are forwarded to by default.
+    fn delegate(&mut self) -> Option<&mut dyn ClassVisitor> {
+        None
+    }
+
+    fn visit(
+        &mut self,
+        major_version: u16,
+        minor_version: u16,
+        access_flags: ClassAccessFlags,
+        name: Symbol,
+        super_class: Option<Symbol>,
+        interfaces: Vec<Symbol>,
+    ) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit(
+                major_version,
+                minor_version,
+                access_flags,
+                name,
+                super_class,
+                interfaces,
+            );
+        }
+    }
+
+    /// The constant pool of the class, right after `visit`. The bytes of
+    /// [`Attribute::Unknown`] attributes can refer to its constants, so a
+    /// [`ClassBuilder`](crate::builder::ClassBuilder) keeps them at the same indices.
+    fn visit_constant_pool(&mut self, constant_pool: &Arc<ConstantPool>) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_constant_pool(constant_pool);
+        }
+    }
+
+    /// Returns a visitor for the field's attributes, or `None` to leave the field out.
+    fn visit_field(
+        &mut self,
+        access_flags: FieldAccessFlags,
+        name: Symbol,
+        descriptor: FieldDescriptor,
+    ) -> Option<Box<dyn FieldVisitor + '_>> {
+        self.delegate()?.visit_field(access_flags, name, descriptor)
+    }
+
+    /// Returns a visitor for the method's code and attributes, or `None` to leave the method
+    /// out.
+    fn visit_method(
+        &mut self,
+        access_flags: MethodAccessFlags,
+        name: Symbol,
+        descriptor: MethodDescriptor,
+    ) -> Option<Box<dyn MethodVisitor + '_>> {
+        self.delegate()?
+            .visit_method(access_flags, name, descriptor)
+    }
+
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_attribute(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_end();
+        }
+    }
+}
+
+/// Receives the attributes of a field, and then `visit_end`.
+pub trait FieldVisitor {
+    /// The visitor that events are forwarded to by default.
+    fn delegate(&mut self) -> Option<&mut dyn FieldVisitor> {
+        None
+    }
+
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_attribute(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_end();
+        }
+    }
+}
+
+/// Receives the code and other attributes of a method, and then `visit_end`.
+pub trait MethodVisitor {
+    /// The visitor that events are forwarded to by default.
+    fn delegate(&mut self) -> Option<&mut dyn MethodVisitor> {
+        None
+    }
+
+    /// Returns a visitor for the method's code, or `None` to leave the code out. Only called
+    /// for methods that have code, before any attributes.
+    fn visit_code(&mut self) -> Option<Box<dyn CodeVisitor + '_>> {
+        self.delegate()?.visit_code()
+    }
+
+    /// Receives an attribute other than Code.
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_attribute(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_end();
+        }
+    }
+}
+
+/// Receives the instructions of a method's code in order. Exception handlers come first, labels
+/// and line numbers come before the instruction they're at, and local variables, maximums and
+/// attributes come after the last instruction, with `visit_end` last of all.
+pub trait CodeVisitor {
+    /// The visitor that events are forwarded to by default.
+    fn delegate(&mut self) -> Option<&mut dyn CodeVisitor> {
+        None
+    }
+
+    /// Creates a label, to be placed with [`Self::visit_label`]. Labels come from the end of
+    /// the chain, so a visitor there that doesn't have a delegate has to override this.
+    ///
+    /// Panics if there's no delegate to create the label.
+    fn new_label(&mut self) -> Label {
+        self.delegate()
+            .expect("CodeVisitor without a delegate must implement new_label")
+            .new_label()
+    }
+
+    /// Places `label` before the next instruction.
+    fn visit_label(&mut self, label: Label) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_label(label);
+        }
+    }
+
+    /// An instruction that isn't a branch or a switch.
+    fn visit_op(&mut self, op: Op) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_op(op);
+        }
+    }
+
+    /// A branch to `target`. The offset in `op` is ignored.
+    fn visit_branch(&mut self, op: Op, target: Label) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_branch(op, target);
+        }
+    }
+
+    /// A `tableswitch` that jumps to `targets[i]` for the value `low + i`.
+    fn visit_tableswitch(&mut self, low: i32, default: Label, targets: Vec<Label>) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_tableswitch(low, default, targets);
+        }
+    }
+
+    fn visit_lookupswitch(&mut self, default: Label, pairs: Vec<(i32, Label)>) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_lookupswitch(default, pairs);
+        }
+    }
+
+    /// An exception handler at `handler` for the code from `start` up to `end`, which catches
+    /// anything if `catch_type` is `None`.
+    fn visit_exception_handler(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<Symbol>,
+    ) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_exception_handler(start, end, handler, catch_type);
+        }
+    }
+
+    /// The instructions from `start` on come from `line` in the source file.
+    fn visit_line_number(&mut self, line: u16, start: Label) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_line_number(line, start);
+        }
+    }
+
+    /// A local variable that's in scope from `start` up to `end`, from a LocalVariableTable.
+    fn visit_local_variable(
+        &mut self,
+        name: Symbol,
+        descriptor: FieldDescriptor,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_local_variable(name, descriptor, start, end, index);
+        }
+    }
+
+    /// The generic type of a local variable, from a LocalVariableTypeTable.
+    fn visit_local_variable_type(
+        &mut self,
+        name: Symbol,
+        signature: FieldSignature,
+        start: Label,
+        end: Label,
+        index: u16,
+    ) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_local_variable_type(name, signature, start, end, index);
+        }
+    }
+
+    fn visit_maxs(&mut self, max_stack: u16, max_locals: u16) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_maxs(max_stack, max_locals);
+        }
+    }
+
+    /// An attribute of the Code attribute that isn't described by the other events.
+    fn visit_attribute(&mut self, attribute: Attribute) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_attribute(attribute);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        if let Some(delegate) = self.delegate() {
+            delegate.visit_end();
+        }
+    }
+}
+
+impl<V: ClassVisitor> ClassVisitor for &mut V {
+    fn delegate(&mut self) -> Option<&mut dyn ClassVisitor> {
+        Some(&mut **self)
+    }
+}
+
+impl<V: FieldVisitor> FieldVisitor for &mut V {
+    fn delegate(&mut self) -> Option<&mut dyn FieldVisitor> {
+        Some(&mut **self)
+    }
+}
+
+impl<V: MethodVisitor> MethodVisitor for &mut V {
+    fn delegate(&mut self) -> Option<&mut dyn MethodVisitor> {
+        Some(&mut **self)
+    }
+}
+
+impl<V: CodeVisitor> CodeVisitor for &mut V {
+    fn delegate(&mut self) -> Option<&mut dyn CodeVisitor> {
+        Some(&mut **self)
+    }
+}
+
+impl ClassFile {
+    /// Sends the parts of the class to `visitor`; see the [module documentation](self).
+    ///
+    /// Labels at offsets that aren't the start of an instruction are placed at the next one.
+    /// Returns an error if a method's code can't be decoded, in a class parsed with
+    /// [`ParseOptions::lazy_code`], or if the catch type of one of its exception handlers isn't
+    /// a Class constant. The offset of the latter error is 0, since the code may not have come
+    /// from a class file.
+    pub fn accept(self, visitor: &mut dyn ClassVisitor) -> Result<(), ClassParseError> {
+        visitor.visit(
+            self.major_version,
+            self.minor_version,
+            self.access_flags,
+            self.this_class,
+            self.super_class,
+            self.interfaces,
+        );
+        visitor.visit_constant_pool(&self.constant_pool);
+
+        for field in self.fields {
+            let Some(mut field_visitor) =
+                visitor.visit_field(field.access_flags, field.name, field.descriptor)
+            else {
+                continue;
+            };
+            for attribute in field.attributes {
+                field_visitor.visit_attribute(attribute);
+            }
+            field_visitor.visit_end();
+        }
+
+        for method in self.methods {
+            let Some(mut method_visitor) =
+                visitor.visit_method(method.access_flags, method.name, method.descriptor)
+            else {
+                continue;
+            };
+            // Code that was parsed lazily is only decoded if it's visited.
+            if let Some(code) = method.code {
+                if let Some(mut code_visitor) = method_visitor.visit_code() {
+                    accept_code(code.into_inner()?, &self.constant_pool, &mut *code_visitor)?;
+                }
+            }
+            for attribute in method.attributes {
+                method_visitor.visit_attribute(attribute);
+            }
+            method_visitor.visit_end();
+        }
+
+        for attribute in self.attributes {
+            visitor.visit_attribute(attribute);
+        }
+        visitor.visit_end();
+        Ok(())
+    }
+}
+
+fn accept_code(
+    code: CodeAttribute,
+    cp: &ConstantPool,
+    visitor: &mut dyn CodeVisitor,
+) -> Result<(), ClassParseError> {
+    // Checked before anything is visited, so that the visitor doesn't get part of the code.
+    let catch_types = code
+        .exception_table
+        .iter()
+        .map(|handler| match handler.catch_type {
+            0 => Ok(None),
+            index => cp.get_class(index).map(|class| Some(class.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|kind| ClassParseError::new(0, kind))?;
+
+    let mut pcs = Vec::with_capacity(code.code.len());
+    let mut pc = 0;
+    for op in &code.code {
+        pcs.push(pc);
+        pc += op.encoded_len(pc);
+    }
+
+    // Take apart the attributes whose offsets can be turned into labels.
+    let mut line_numbers = Vec::new();
+    let mut local_variables = Vec::new();
+    let mut local_variable_types = Vec::new();
+    let mut attributes = Vec::new();
+    for attribute in code.attributes {
+        match attribute {
+            Attribute::LineNumberTable(entries) => line_numbers.extend(entries),
+            Attribute::LocalVariableTable(entries) => local_variables.extend(entries),
+            Attribute::LocalVariableTypeTable(entries) => local_variable_types.extend(entries),
+            attribute => attributes.push(attribute),
+        }
+    }
+
+    // Create a label for every offset that's referred to, in order.
+    let mut targets = Vec::new();
+    for (op, &pc) in code.code.iter().zip(&pcs) {
+        let target = |offset: i32| (pc as i64 + i64::from(offset)).max(0) as usize;
+        match op {
+            Op::Tableswitch(switch) => targets.extend(
+                switch
+                    .offsets
+                    .iter()
+                    .chain([&switch.default])
+                    .map(|&o| target(o)),
+            ),
+            Op::Lookupswitch(switch) => targets.extend(
+                switch
+                    .pairs
+                    .iter()
+                    .map(|&(_, o)| o)
+                    .chain([switch.default])
+                    .map(target),
+            ),
+            op => targets.extend(op.branch_offset().map(target)),
+        }
+    }
+    for handler in &code.exception_table {
+        targets.extend([handler.start_pc, handler.end_pc, handler.handler_pc].map(usize::from));
+    }
+    targets.extend(line_numbers.iter().map(|entry| usize::from(entry.start_pc)));
+    for (start_pc, length) in local_variables
+        .iter()
+        .map(|entry| (entry.start_pc, entry.length))
+        .chain(
+            local_variable_types
+                .iter()
+                .map(|entry| (entry.start_pc, entry.length)),
+        )
+    {
+        targets.extend([
+            usize::from(start_pc),
+            usize::from(start_pc) + usize::from(length),
+        ]);
+    }
+    targets.sort_unstable();
+    targets.dedup();
+    let labels: BTreeMap<usize, Label> = targets
+        .into_iter()
+        .map(|pc| (pc, visitor.new_label()))
+        .collect();
+
+    for (handler, catch_type) in code.exception_table.into_iter().zip(catch_types) {
+        visitor.visit_exception_handler(
+            labels[&handler.start_pc.into()],
+            labels[&handler.end_pc.into()],
+            labels[&handler.handler_pc.into()],
+            catch_type,
+        );
+    }
+
+    let place = |visitor: &mut dyn CodeVisitor, pc: usize, label: Label| {
+        visitor.visit_label(label);
+        for entry in &line_numbers {
+            if usize::from(entry.start_pc) == pc {
+                visitor.visit_line_number(entry.line_number, label);
+            }
+        }
+    };
+    let mut unplaced = labels.iter().peekable();
+    for (op, pc) in code.code.into_iter().zip(pcs) {
+        while let Some((&label_pc, &label)) = unplaced.next_if(|(&label_pc, _)| label_pc <= pc) {
+            place(visitor, label_pc, label);
+        }
+
+        let label = |offset: i32| labels[&((pc as i64 + i64::from(offset)).max(0) as usize)];
+        match op {
+            Op::Tableswitch(switch) => visitor.visit_tableswitch(
+                switch.low,
+                label(switch.default),
+                switch.offsets.into_iter().map(label).collect(),
+            ),
+            Op::Lookupswitch(switch) => visitor.visit_lookupswitch(
+                label(switch.default),
+                switch
+                    .pairs
+                    .into_iter()
+                    .map(|(value, offset)| (value, label(offset)))
+                    .collect(),
+            ),
+            op => match op.branch_offset() {
+                Some(offset) => visitor.visit_branch(op, label(offset)),
+                None => visitor.visit_op(op),
+            },
+        }
+    }
+    for (&label_pc, &label) in unplaced {
+        place(visitor, label_pc, label);
+    }
+
+    let range = |start_pc: u16, length: u16| {
+        let start = usize::from(start_pc);
+        (labels[&start], labels[&(start + usize::from(length))])
+    };
+    for entry in local_variables {
+        let (start, end) = range(entry.start_pc, entry.length);
+        visitor.visit_local_variable(entry.name, entry.descriptor, start, end, entry.index);
+    }
+    for entry in local_variable_types {
+        let (start, end) = range(entry.start_pc, entry.length);
+        visitor.visit_local_variable_type(entry.name, entry.signature, start, end, entry.index);
+    }
+
+    visitor.visit_maxs(code.max_stack, code.max_locals);
+    for attribute in attributes {
+        visitor.visit_attribute(attribute);
+    }
+    visitor.visit_end();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ClassBuilder;
+    use crate::test_util::fixtures;
+
+    /// Everything about a class except its constant pool, which is rebuilt from scratch.
+    fn contents(class: &ClassFile) -> String {
+        let mut contents = format!(
+            "{} {} {:?} {} {:?} {:?} {:?} {:?}",
+            class.major_version,
+            class.minor_version,
+            class.access_flags(),
+            class.this_class(),
+            class.super_class(),
+            class.interfaces(),
+            class.fields(),
+            class.attributes()
+        );
+        for method in class.methods() {
+            contents += &format!(
+                "\n{:?} {} {:?} {:?}",
+                method.access_flags,
+                method.name,
+                method.descriptor,
+                method.attributes()
+            );
+            if let Some(code) = method.code().unwrap() {
+                let handlers: Vec<_> = code
+                    .exception_table
+                    .iter()
+                    .map(|handler| {
+                        let catch_type = class.constant_pool().get_class(handler.catch_type);
+                        (
+                            handler.start_pc,
+                            handler.end_pc,
+                            handler.handler_pc,
+                            catch_type.ok(),
+                        )
+                    })
+                    .collect();
+                contents += &format!(
+                    " {} {} {:?} {:?} {:?}",
+                    code.max_stack, code.max_locals, code.code, handlers, code.attributes
+                );
+            }
+        }
+        contents
+    }
+
+    fn rebuild(
+        class: ClassFile,
+        transform: impl FnOnce(ClassFile, &mut ClassBuilder) -> Result<(), ClassParseError>,
+    ) -> ClassFile {
+        let mut builder = ClassBuilder::new("");
+        transform(class, &mut builder).unwrap();
+        let bytes = builder.build().unwrap().to_bytes().unwrap();
+        ClassFile::parse_from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_fixtures() {
+        for (path, raw) in fixtures() {
+            let expected = ClassFile::parse_from_bytes(&raw).unwrap();
+            let class = ClassFile::parse_from_bytes(&raw).unwrap();
+            let rebuilt = rebuild(class, |class, builder| class.accept(builder));
+            assert_eq!(
+                contents(&expected),
+                contents(&rebuilt),
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    /// Adds a `nop` before every branch and switch.
+    struct AddNops<'a>(&'a mut dyn ClassVisitor);
+
+    impl ClassVisitor for AddNops<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn ClassVisitor> {
+            Some(self.0)
+        }
+
+        fn visit_method(
+            &mut self,
+            access_flags: MethodAccessFlags,
+            name: Symbol,
+            descriptor: MethodDescriptor,
+        ) -> Option<Box<dyn MethodVisitor + '_>> {
+            let next = self.0.visit_method(access_flags, name, descriptor)?;
+            Some(Box::new(AddNopsToMethod(next)))
+        }
+    }
+
+    struct AddNopsToMethod<'a>(Box<dyn MethodVisitor + 'a>);
+
+    impl MethodVisitor for AddNopsToMethod<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn MethodVisitor> {
+            Some(&mut *self.0)
+        }
+
+        fn visit_code(&mut self) -> Option<Box<dyn CodeVisitor + '_>> {
+            let next = self.0.visit_code()?;
+            Some(Box::new(AddNopsToCode(next)))
+        }
+    }
+
+    struct AddNopsToCode<'a>(Box<dyn CodeVisitor + 'a>);
+
+    impl CodeVisitor for AddNopsToCode<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn CodeVisitor> {
+            Some(&mut *self.0)
+        }
+
+        fn visit_branch(&mut self, op: Op, target: Label) {
+            self.0.visit_op(Op::Nop);
+            self.0.visit_branch(op, target);
+        }
+
+        fn visit_tableswitch(&mut self, low: i32, default: Label, targets: Vec<Label>) {
+            self.0.visit_op(Op::Nop);
+            self.0.visit_tableswitch(low, default, targets);
+        }
+
+        fn visit_lookupswitch(&mut self, default: Label, pairs: Vec<(i32, Label)>) {
+            self.0.visit_op(Op::Nop);
+            self.0.visit_lookupswitch(default, pairs);
+        }
+    }
+
+    /// Removes every `nop`.
+    struct RemoveNops<'a>(&'a mut dyn ClassVisitor);
+
+    impl ClassVisitor for RemoveNops<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn ClassVisitor> {
+            Some(self.0)
+        }
+
+        fn visit_method(
+            &mut self,
+            access_flags: MethodAccessFlags,
+            name: Symbol,
+            descriptor: MethodDescriptor,
+        ) -> Option<Box<dyn MethodVisitor + '_>> {
+            let next = self.0.visit_method(access_flags, name, descriptor)?;
+            Some(Box::new(RemoveNopsFromMethod(next)))
+        }
+    }
+
+    struct RemoveNopsFromMethod<'a>(Box<dyn MethodVisitor + 'a>);
+
+    impl MethodVisitor for RemoveNopsFromMethod<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn MethodVisitor> {
+            Some(&mut *self.0)
+        }
+
+        fn visit_code(&mut self) -> Option<Box<dyn CodeVisitor + '_>> {
+            let next = self.0.visit_code()?;
+            Some(Box::new(RemoveNopsFromCode(next)))
+        }
+    }
+
+    struct RemoveNopsFromCode<'a>(Box<dyn CodeVisitor + 'a>);
+
+    impl CodeVisitor for RemoveNopsFromCode<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn CodeVisitor> {
+            Some(&mut *self.0)
+        }
+
+        fn visit_op(&mut self, op: Op) {
+            if !matches!(op, Op::Nop) {
+                self.0.visit_op(op);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let raw = include_bytes!("../test_data/Branches.class");
+        let class = ClassFile::parse_from_bytes(raw).unwrap();
+        let with_nops = rebuild(class, |class, builder| class.accept(&mut AddNops(builder)));
+
+        let expected = ClassFile::parse_from_bytes(raw).unwrap();
+        for (method, expected) in with_nops.methods().iter().zip(expected.methods()) {
+            let (code, expected) = (
+                method.code().unwrap().unwrap(),
+                expected.code().unwrap().unwrap(),
+            );
+            // Every instruction keeps its line number.
+            let lines = |code: &CodeAttribute| {
+                let mut pc = 0;
+                let mut lines = Vec::new();
+                for op in &code.code {
+                    if !matches!(op, Op::Nop) {
+                        lines.push(code.line_number_at(pc as u16));
+                    }
+                    pc += op.encoded_len(pc);
+                }
+                lines
+            };
+            assert_eq!(lines(expected), lines(code), "{}", method.name);
+        }
+        let describe = &with_nops.methods()[2];
+        let code = describe.code().unwrap().unwrap();
+        let nops = code.code.iter().filter(|op| matches!(op, Op::Nop)).count();
+        assert_eq!((Symbol::from("describe"), 8), (describe.name.clone(), nops));
+
+        // The StackMapTable was passed along unchanged, so it's wrong now, but it can be
+        // recomputed.
+        let mut builder = ClassBuilder::new("");
+        ClassFile::parse_from_bytes(raw)
+            .unwrap()
+            .accept(&mut AddNops(&mut builder))
+            .unwrap();
+        let bytes = builder
+            .build_with_frames(&Vec::new())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(check(&bytes).unwrap().is_empty());
+
+        // Taking the nops out again gives back the original code, with the original frames.
+        let without_nops = rebuild(with_nops, |class, builder| {
+            class.accept(&mut RemoveNops(builder))
+        });
+        assert_eq!(contents(&expected), contents(&without_nops));
+    }
+
+    /// Leaves out the SourceFile attribute.
+    struct RemoveSourceFile<'a>(&'a mut dyn ClassVisitor);
+
+    impl ClassVisitor for RemoveSourceFile<'_> {
+        fn delegate(&mut self) -> Option<&mut dyn ClassVisitor> {
+            Some(self.0)
+        }
+
+        fn visit_attribute(&mut self, attribute: Attribute) {
+            if !matches!(attribute, Attribute::SourceFile(_)) {
+                self.0.visit_attribute(attribute);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_attribute_indices() {
+        let with_custom = |bytes: Vec<u8>| {
+            ClassBuilder::new("Foo")
+                .attribute(Attribute::SourceFile("Foo.java".into()))
+                .attribute(Attribute::Unknown {
+                    name: "Custom".into(),
+                    bytes,
+                })
+                .build()
+                .unwrap()
+        };
+        let pool = with_custom(vec![0, 0]).constant_pool;
+        let (index, _) = pool
+            .iter()
+            .find(|(index, _)| pool.get_utf8(*index).is_ok_and(|utf8| utf8 == "Foo.java"))
+            .unwrap();
+        let class = with_custom(index.to_be_bytes().to_vec());
+
+        // Foo.java is no longer needed, but the Custom attribute still refers to it.
+        let rebuilt = rebuild(class, |class, builder| {
+            class.accept(&mut RemoveSourceFile(builder))
+        });
+        let [Attribute::Unknown { bytes, .. }] = rebuilt.attributes() else {
+            panic!("{:?}", rebuilt.attributes());
+        };
+        let index = u16::from_be_bytes([bytes[0], bytes[1]]);
+        assert_eq!(
+            Ok(&Symbol::from("Foo.java")),
+            rebuilt.constant_pool().get_utf8(index)
+        );
+    }
+
+    #[test]
+    fn test_bad_catch_type() {
+        use crate::builder::{CodeBuilder, MethodBuilder};
+        use crate::descriptor::parse_method_descriptor;
+
+        let mut code = CodeBuilder::new();
+        let (start, handler) = (code.new_label(), code.new_label());
+        code.place(start).op(Op::Return);
+        code.place(handler).op(Op::Athrow);
+        code.exception_handler(start, handler, handler, Some("java/lang/Exception"));
+        let method = MethodBuilder::new(
+            &[MethodAccess::Static],
+            "run",
+            parse_method_descriptor("()V").unwrap(),
+        );
+        let mut class = ClassBuilder::new("Foo")
+            .method(method.code(code))
+            .build()
+            .unwrap();
+
+        // Point the catch type at the Utf8 constant with the class name.
+        let code = class.methods()[0].code().unwrap().unwrap();
+        let index = match class
+            .constant_pool()
+            .get(code.exception_table[0].catch_type)
+        {
+            Ok(Constant::Class(ClassConstant { name_index })) => *name_index,
+            constant => panic!("{constant:?}"),
+        };
+        let code = class.methods_mut()[0].code_mut().unwrap().unwrap();
+        code.exception_table[0].catch_type = index;
+
+        let mut builder = ClassBuilder::new("");
+        assert_eq!(
+            Err(ClassParseError::new(
+                0,
+                ClassParseErrorKind::WrongConstantKind {
+                    index,
+                    expected: "Class"
+                }
+            )),
+            class.accept(&mut builder)
+        );
+    }
+}
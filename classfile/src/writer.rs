@@ -0,0 +1,1233 @@
+//! Serializing a [`ClassFile`] back into the class file format.
+
+use crate::*;
+use std::collections::HashMap;
+use std::io::Write;
+
+type Result<T> = std::result::Result<T, ClassWriteError>;
+
+/// A constant, with any references to other constants as indices. Two constants with equal
+/// keys are interchangeable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Utf8(JavaString),
+    Integer(i32),
+    /// The bits of the float, so that every NaN is kept as it is.
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class(u16),
+    String(u16),
+    Fieldref(u16, u16),
+    Methodref(u16, u16),
+    InterfaceMethodref(u16, u16),
+    NameAndType(u16, u16),
+    MethodHandle(ReferenceKind, u16),
+    MethodType(u16),
+    Dynamic(u16, u16),
+    InvokeDynamic(u16, u16),
+    Module(u16),
+    Package(u16),
+}
+
+impl ConstantKey {
+    fn new(constant: &Constant) -> Option<Self> {
+        Some(match constant {
+            Constant::Class(c) => Self::Class(c.name_index),
+            Constant::Methodref(c) => Self::Methodref(c.class_index, c.name_and_type_index),
+            Constant::InterfaceMethodref(c) => {
+                Self::InterfaceMethodref(c.class_index, c.name_and_type_index)
+            }
+            Constant::NameAndType(c) => Self::NameAndType(c.name_index, c.descriptor_index),
+            Constant::Fieldref(c) => Self::Fieldref(c.class_index, c.name_and_type_index),
+            Constant::Utf8(s) => Self::Utf8(s.clone()),
+            Constant::ConstantValue(value) => match value {
+                ConstantValueKind::Integer(x) => Self::Integer(*x),
+                ConstantValueKind::Float(x) => Self::Float(x.to_bits()),
+                ConstantValueKind::Long(x) => Self::Long(*x),
+                ConstantValueKind::Double(x) => Self::Double(x.to_bits()),
+                ConstantValueKind::String(index) => Self::String(*index),
+            },
+            Constant::MethodHandle(c) => Self::MethodHandle(c.reference_kind, c.reference_index),
+            Constant::MethodType(c) => Self::MethodType(c.descriptor_index),
+            Constant::Dynamic(c) => {
+                Self::Dynamic(c.bootstrap_method_attr_index, c.name_and_type_index)
+            }
+            Constant::InvokeDynamic(c) => {
+                Self::InvokeDynamic(c.bootstrap_method_attr_index, c.name_and_type_index)
+            }
+            Constant::Module(c) => Self::Module(c.name_index),
+            Constant::Package(c) => Self::Package(c.name_index),
+            Constant::Unusable => return None,
+        })
+    }
+
+    /// The key with every constant pool index in it replaced by `f(index)`.
+    fn map_indices(&self, f: impl Fn(u16) -> u16) -> Self {
+        match *self {
+            Self::Class(index) => Self::Class(f(index)),
+            Self::String(index) => Self::String(f(index)),
+            Self::Fieldref(a, b) => Self::Fieldref(f(a), f(b)),
+            Self::Methodref(a, b) => Self::Methodref(f(a), f(b)),
+            Self::InterfaceMethodref(a, b) => Self::InterfaceMethodref(f(a), f(b)),
+            Self::NameAndType(a, b) => Self::NameAndType(f(a), f(b)),
+            Self::MethodHandle(kind, index) => Self::MethodHandle(kind, f(index)),
+            Self::MethodType(index) => Self::MethodType(f(index)),
+            // The first index of these is into the BootstrapMethods attribute.
+            Self::Dynamic(a, b) => Self::Dynamic(a, f(b)),
+            Self::InvokeDynamic(a, b) => Self::InvokeDynamic(a, f(b)),
+            Self::Module(index) => Self::Module(f(index)),
+            Self::Package(index) => Self::Package(f(index)),
+            _ => self.clone(),
+        }
+    }
+
+    /// How deeply the constant refers to other constants. A constant only refers to constants
+    /// of lower depths.
+    fn depth(&self) -> u8 {
+        match self {
+            Self::Utf8(_) | Self::Integer(_) | Self::Float(_) | Self::Long(_) | Self::Double(_) => {
+                0
+            }
+            Self::Class(_)
+            | Self::String(_)
+            | Self::NameAndType(..)
+            | Self::MethodType(_)
+            | Self::Module(_)
+            | Self::Package(_) => 1,
+            Self::Fieldref(..)
+            | Self::Methodref(..)
+            | Self::InterfaceMethodref(..)
+            | Self::Dynamic(..)
+            | Self::InvokeDynamic(..) => 2,
+            Self::MethodHandle(..) => 3,
+        }
+    }
+
+    fn is_wide(&self) -> bool {
+        matches!(self, Self::Long(_) | Self::Double(_))
+    }
+
+    fn to_constant(&self) -> Constant {
+        match *self {
+            Self::Utf8(ref s) => Constant::Utf8(s.clone()),
+            Self::Integer(x) => Constant::ConstantValue(ConstantValueKind::Integer(x)),
+            Self::Float(bits) => {
+                Constant::ConstantValue(ConstantValueKind::Float(f32::from_bits(bits)))
+            }
+            Self::Long(x) => Constant::ConstantValue(ConstantValueKind::Long(x)),
+            Self::Double(bits) => {
+                Constant::ConstantValue(ConstantValueKind::Double(f64::from_bits(bits)))
+            }
+            Self::Class(name_index) => Constant::Class(ClassConstant { name_index }),
+            Self::String(index) => Constant::ConstantValue(ConstantValueKind::String(index)),
+            Self::Fieldref(class_index, name_and_type_index) => {
+                Constant::Fieldref(FieldrefConstant {
+                    class_index,
+                    name_and_type_index,
+                })
+            }
+            Self::Methodref(class_index, name_and_type_index) => {
+                Constant::Methodref(MethodrefConstant {
+                    class_index,
+                    name_and_type_index,
+                })
+            }
+            Self::InterfaceMethodref(class_index, name_and_type_index) => {
+                Constant::InterfaceMethodref(MethodrefConstant {
+                    class_index,
+                    name_and_type_index,
+                })
+            }
+            Self::NameAndType(name_index, descriptor_index) => {
+                Constant::NameAndType(NameAndTypeConstant {
+                    name_index,
+                    descriptor_index,
+                })
+            }
+            Self::MethodHandle(reference_kind, reference_index) => {
+                Constant::MethodHandle(MethodHandleConstant {
+                    reference_kind,
+                    reference_index,
+                })
+            }
+            Self::MethodType(descriptor_index) => {
+                Constant::MethodType(MethodTypeConstant { descriptor_index })
+            }
+            Self::Dynamic(bootstrap_method_attr_index, name_and_type_index) => {
+                Constant::Dynamic(DynamicConstant {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                })
+            }
+            Self::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index) => {
+                Constant::InvokeDynamic(DynamicConstant {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                })
+            }
+            Self::Module(name_index) => Constant::Module(ModuleConstant { name_index }),
+            Self::Package(name_index) => Constant::Package(PackageConstant { name_index }),
+        }
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.4>
+    fn write(&self, out: &mut Vec<u8>) {
+        let (tag, a, b) = match self {
+            Self::Utf8(s) => {
+                let bytes = s.to_modified_utf8();
+                out.push(1);
+                // The length was checked when the constant was added, or when it was parsed.
+                out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+                out.extend_from_slice(&bytes);
+                return;
+            }
+            Self::Integer(x) => {
+                out.push(3);
+                out.extend_from_slice(&x.to_be_bytes());
+                return;
+            }
+            Self::Float(bits) => {
+                out.push(4);
+                out.extend_from_slice(&bits.to_be_bytes());
+                return;
+            }
+            Self::Long(x) => {
+                out.push(5);
+                out.extend_from_slice(&x.to_be_bytes());
+                return;
+            }
+            Self::Double(bits) => {
+                out.push(6);
+                out.extend_from_slice(&bits.to_be_bytes());
+                return;
+            }
+            Self::MethodHandle(kind, index) => {
+                out.push(15);
+                out.push(*kind as u8);
+                out.extend_from_slice(&index.to_be_bytes());
+                return;
+            }
+            Self::Class(index) => (7, *index, None),
+            Self::String(index) => (8, *index, None),
+            Self::Fieldref(a, b) => (9, *a, Some(*b)),
+            Self::Methodref(a, b) => (10, *a, Some(*b)),
+            Self::InterfaceMethodref(a, b) => (11, *a, Some(*b)),
+            Self::NameAndType(a, b) => (12, *a, Some(*b)),
+            Self::MethodType(index) => (16, *index, None),
+            Self::Dynamic(a, b) => (17, *a, Some(*b)),
+            Self::InvokeDynamic(a, b) => (18, *a, Some(*b)),
+            Self::Module(index) => (19, *index, None),
+            Self::Package(index) => (20, *index, None),
+        };
+
+        out.push(tag);
+        out.extend_from_slice(&a.to_be_bytes());
+        if let Some(b) = b {
+            out.extend_from_slice(&b.to_be_bytes());
+        }
+    }
+}
+
+/// The constant pool of a class file that's being written. It starts out with the constants
+/// of an existing pool, and constants that aren't in it yet are added to the end.
+///
+/// Looking up a constant finds the first equal entry, where constants that only differ in which
+/// of several equal entries they refer to are also equal. Some class files do have duplicate
+/// entries though, so the indices that the parsed class file referred to are replayed as hints:
+/// as long as the class file is written in the same order that it was parsed in, each index
+/// comes out as it went in.
+#[derive(Debug)]
+pub(crate) struct ConstantPoolWriter {
+    /// `None` for the unusable entries after Long and Double constants.
+    entries: Vec<Option<ConstantKey>>,
+    /// The first index of each key, with the indices in the key replaced by canonical ones.
+    indices: HashMap<ConstantKey, u16>,
+    /// For each entry, the index of the first entry that's equal to it.
+    canonical: Vec<u16>,
+    hints: Vec<u16>,
+    next_hint: usize,
+    /// The major version of the pool that the writer started from.
+    major_version: u16,
+}
+
+impl ConstantPoolWriter {
+    /// `references` are the indices that a parsed class file referred to, in order.
+    pub fn new(cp: &ConstantPool, references: &[u16]) -> Self {
+        let mut entries = Vec::with_capacity(cp.len());
+        for (index, constant) in cp.iter() {
+            // Keep the slot of an unusable entry
+            entries.resize(usize::from(index) - 1, None);
+            entries.push(ConstantKey::new(constant));
+        }
+        entries.resize(cp.len(), None);
+
+        // An index that's out of range or refers to a constant of the same depth or deeper
+        // stays as it is.
+        let mut canonical: Vec<u16> = (1..=entries.len() as u16).collect();
+        let mut indices = HashMap::with_capacity(entries.len());
+        for depth in 0..=3 {
+            for (i, key) in entries.iter().enumerate() {
+                let Some(key) = key.as_ref().filter(|key| key.depth() == depth) else {
+                    continue;
+                };
+                let key = key.map_indices(|index| {
+                    canonical
+                        .get(usize::from(index).wrapping_sub(1))
+                        .copied()
+                        .unwrap_or(index)
+                });
+                let index = *indices.entry(key).or_insert(i as u16 + 1);
+                canonical[i] = index;
+            }
+        }
+
+        Self {
+            entries,
+            indices,
+            canonical,
+            hints: references.to_vec(),
+            next_hint: 0,
+            major_version: cp.major_version(),
+        }
+    }
+
+    /// Makes the pool from [`Self::to_constant_pool`] one for a class with `major_version`.
+    pub fn with_major_version(mut self, major_version: u16) -> Self {
+        self.major_version = major_version;
+        self
+    }
+
+    /// The index to write for a reference to the constant at `index`: the next hint if it's
+    /// an equal constant, and `index` otherwise.
+    pub fn hinted(&mut self, index: u16) -> u16 {
+        let Some(&hint) = self.hints.get(self.next_hint) else {
+            return index;
+        };
+        self.next_hint += 1;
+        let hint_canonical = self.canonical.get(usize::from(hint).wrapping_sub(1));
+        if hint_canonical == Some(&index) {
+            hint
+        } else {
+            index
+        }
+    }
+
+    /// The index of `key`, adding it to the pool if it isn't there yet.
+    fn add(&mut self, key: ConstantKey) -> Result<u16> {
+        if let Some(&index) = self.indices.get(&key) {
+            return Ok(index);
+        }
+
+        if let ConstantKey::Utf8(s) = &key {
+            if s.to_modified_utf8().len() > usize::from(u16::MAX) {
+                return Err(ClassWriteError::TooLong("Utf8 constant"));
+            }
+        }
+
+        let width = if key.is_wide() { 2 } else { 1 };
+        // constant_pool_count is a u16 that's one more than the number of entries.
+        let index = u16::try_from(self.entries.len() + 1)
+            .ok()
+            .filter(|index| usize::from(*index) + width <= usize::from(u16::MAX))
+            .ok_or(ClassWriteError::TooManyConstants)?;
+
+        self.indices.insert(key.clone(), index);
+        self.entries.push(Some(key));
+        self.canonical.push(index);
+        if width == 2 {
+            self.entries.push(None);
+            self.canonical.push(index + 1);
+        }
+        Ok(index)
+    }
+
+    pub fn utf8(&mut self, s: &str) -> Result<u16> {
+        self.java_string(&s.into())
+    }
+
+    pub fn java_string(&mut self, s: &JavaString) -> Result<u16> {
+        self.add(ConstantKey::Utf8(s.clone()))
+    }
+
+    pub fn class(&mut self, name: &str) -> Result<u16> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantKey::Class(name_index))
+    }
+
+    pub fn string(&mut self, s: &JavaString) -> Result<u16> {
+        let string_index = self.java_string(s)?;
+        self.add(ConstantKey::String(string_index))
+    }
+
+    pub fn integer(&mut self, x: i32) -> Result<u16> {
+        self.add(ConstantKey::Integer(x))
+    }
+
+    pub fn float(&mut self, x: f32) -> Result<u16> {
+        self.add(ConstantKey::Float(x.to_bits()))
+    }
+
+    pub fn long(&mut self, x: i64) -> Result<u16> {
+        self.add(ConstantKey::Long(x))
+    }
+
+    pub fn double(&mut self, x: f64) -> Result<u16> {
+        self.add(ConstantKey::Double(x.to_bits()))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> Result<u16> {
+        let name_index = self.utf8(name)?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(ConstantKey::NameAndType(name_index, descriptor_index))
+    }
+
+    pub fn fieldref(&mut self, field: &FieldRef) -> Result<u16> {
+        let class_index = self.class(&field.class)?;
+        let name_and_type_index = self.name_and_type(&field.name, &field.descriptor.to_string())?;
+        self.add(ConstantKey::Fieldref(class_index, name_and_type_index))
+    }
+
+    /// A Methodref or InterfaceMethodref, depending on `method.is_interface`.
+    pub fn methodref(&mut self, method: &MethodRef) -> Result<u16> {
+        let class_index = self.class(&method.class)?;
+        let name_and_type_index =
+            self.name_and_type(&method.name, &method.descriptor.to_string())?;
+        self.add(if method.is_interface {
+            ConstantKey::InterfaceMethodref(class_index, name_and_type_index)
+        } else {
+            ConstantKey::Methodref(class_index, name_and_type_index)
+        })
+    }
+
+    pub fn method_handle(&mut self, handle: &MethodHandle) -> Result<u16> {
+        let reference_index = match &handle.reference {
+            MethodHandleRef::Field(field) => self.fieldref(field)?,
+            MethodHandleRef::Method(method) => self.methodref(method)?,
+        };
+        self.add(ConstantKey::MethodHandle(handle.kind, reference_index))
+    }
+
+    pub fn method_type(&mut self, descriptor: &MethodDescriptor) -> Result<u16> {
+        let descriptor_index = self.utf8(&descriptor.to_string())?;
+        self.add(ConstantKey::MethodType(descriptor_index))
+    }
+
+    pub fn dynamic(&mut self, dynamic: &DynamicRef) -> Result<u16> {
+        let name_and_type_index =
+            self.name_and_type(&dynamic.name, &dynamic.descriptor.to_string())?;
+        self.add(ConstantKey::Dynamic(
+            dynamic.bootstrap_method_attr_index,
+            name_and_type_index,
+        ))
+    }
+
+    pub fn invoke_dynamic(&mut self, call_site: &InvokeDynamicRef) -> Result<u16> {
+        let name_and_type_index =
+            self.name_and_type(&call_site.name, &call_site.descriptor.to_string())?;
+        self.add(ConstantKey::InvokeDynamic(
+            call_site.bootstrap_method_attr_index,
+            name_and_type_index,
+        ))
+    }
+
+    pub fn loadable(&mut self, constant: &LoadableConstant) -> Result<u16> {
+        match constant {
+            LoadableConstant::Integer(x) => self.integer(*x),
+            LoadableConstant::Float(x) => self.float(*x),
+            LoadableConstant::Long(x) => self.long(*x),
+            LoadableConstant::Double(x) => self.double(*x),
+            LoadableConstant::Class(name) => self.class(name),
+            LoadableConstant::String(s) => self.string(s),
+            LoadableConstant::MethodHandle(handle) => self.method_handle(handle),
+            LoadableConstant::MethodType(descriptor) => self.method_type(descriptor),
+            LoadableConstant::Dynamic(dynamic) => self.dynamic(dynamic),
+        }
+    }
+
+    pub fn module(&mut self, name: &str) -> Result<u16> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantKey::Module(name_index))
+    }
+
+    pub fn package(&mut self, name: &str) -> Result<u16> {
+        let name_index = self.utf8(name)?;
+        self.add(ConstantKey::Package(name_index))
+    }
+
+    /// The constants as a [`ConstantPool`], as if it had been parsed.
+    pub fn to_constant_pool(&self) -> ConstantPool {
+        let constants = self.entries.iter().map(|key| match key {
+            Some(key) => key.to_constant(),
+            None => Constant::Unusable,
+        });
+        ConstantPool::new(constants.collect(), self.major_version)
+    }
+
+    /// Writes `constant_pool_count` followed by the constants.
+    fn write(&self, out: &mut Vec<u8>) {
+        // `add` keeps the count within a u16.
+        out.extend_from_slice(&(self.entries.len() as u16 + 1).to_be_bytes());
+        for key in self.entries.iter().flatten() {
+            key.write(out);
+        }
+    }
+}
+
+/// Writes everything in `class` after the constant pool, which is then complete.
+fn write_class_body(class: &ClassFile) -> Result<Writer> {
+    let mut writer = Writer {
+        cp: ConstantPoolWriter::new(class.constant_pool(), &class.cp_references),
+        buf: Vec::new(),
+    };
+    writer.class_body(class)?;
+    Ok(writer)
+}
+
+/// The constant pool that `class` is written with: its own, plus the constants that it refers
+/// to but that aren't in it yet.
+pub(crate) fn complete_constant_pool(class: &ClassFile) -> Result<ConstantPool> {
+    Ok(write_class_body(class)?.cp.to_constant_pool())
+}
+
+/// Writes `class` in the class file format.
+pub(crate) fn write_class(class: &ClassFile, out: &mut impl Write) -> Result<()> {
+    let writer = write_class_body(class)?;
+
+    // The constant pool comes first, but it's only complete once everything else is written.
+    let (major_version, minor_version) = class.version();
+    let mut header = Vec::new();
+    header.extend_from_slice(&0xCAFEBABE_u32.to_be_bytes());
+    header.extend_from_slice(&minor_version.to_be_bytes());
+    header.extend_from_slice(&major_version.to_be_bytes());
+    writer.cp.write(&mut header);
+
+    out.write_all(&header)?;
+    out.write_all(&writer.buf)?;
+    Ok(())
+}
+
+/// Converts the length of a table to the type of its length field.
+fn table_len<T: TryFrom<usize>>(len: usize, what: &'static str) -> Result<T> {
+    T::try_from(len).map_err(|_| ClassWriteError::TooLong(what))
+}
+
+/// Writer state for everything after the constant pool.
+struct Writer {
+    cp: ConstantPoolWriter,
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, x: u8) {
+        self.buf.push(x);
+    }
+
+    fn u16(&mut self, x: u16) {
+        self.buf.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.buf.extend_from_slice(&x.to_be_bytes());
+    }
+
+    /// Writes a u16 length followed by each item of `items`.
+    fn table<T>(
+        &mut self,
+        items: &[T],
+        what: &'static str,
+        mut write_fn: impl FnMut(&mut Self, &T) -> Result<()>,
+    ) -> Result<()> {
+        self.u16(table_len(items.len(), what)?);
+        for item in items {
+            write_fn(self, item)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a u8 length followed by each item of `items`.
+    fn short_table<T>(
+        &mut self,
+        items: &[T],
+        what: &'static str,
+        mut write_fn: impl FnMut(&mut Self, &T) -> Result<()>,
+    ) -> Result<()> {
+        self.u8(table_len(items.len(), what)?);
+        for item in items {
+            write_fn(self, item)?;
+        }
+        Ok(())
+    }
+
+    /// Calls `write_fn` with `hints` in place of the class's constant pool references, for code
+    /// that was parsed lazily and whose references weren't recorded with the class's.
+    fn with_hints(
+        &mut self,
+        hints: &[u16],
+        write_fn: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let hints = std::mem::replace(&mut self.cp.hints, hints.to_vec());
+        let next_hint = std::mem::replace(&mut self.cp.next_hint, 0);
+        let result = write_fn(self);
+        self.cp.hints = hints;
+        self.cp.next_hint = next_hint;
+        result
+    }
+
+    /// Writes a reference to the constant at `index`.
+    fn index(&mut self, index: u16) {
+        let index = self.cp.hinted(index);
+        self.u16(index);
+    }
+
+    fn utf8(&mut self, s: &str) -> Result<()> {
+        let index = self.cp.utf8(s)?;
+        self.index(index);
+        Ok(())
+    }
+
+    /// Writes the index of a Utf8 constant, or zero for `None`.
+    fn optional_utf8(&mut self, s: Option<&Symbol>) -> Result<()> {
+        match s {
+            Some(s) => {
+                let index = self.cp.utf8(s)?;
+                self.index(index);
+            }
+            None => self.u16(0),
+        }
+        Ok(())
+    }
+
+    fn class(&mut self, name: &str) -> Result<()> {
+        let index = self.cp.class(name)?;
+        self.index(index);
+        Ok(())
+    }
+
+    fn classes(&mut self, names: &[Symbol]) -> Result<()> {
+        self.table(names, "class table", |w, name| w.class(name))
+    }
+
+    /// Writes the index of a Class constant, or zero for `None`.
+    fn optional_class(&mut self, name: Option<&Symbol>) -> Result<()> {
+        match name {
+            Some(name) => {
+                let index = self.cp.class(name)?;
+                self.index(index);
+            }
+            None => self.u16(0),
+        }
+        Ok(())
+    }
+
+    fn module(&mut self, name: &str) -> Result<()> {
+        let index = self.cp.module(name)?;
+        self.index(index);
+        Ok(())
+    }
+
+    fn package(&mut self, name: &str) -> Result<()> {
+        let index = self.cp.package(name)?;
+        self.index(index);
+        Ok(())
+    }
+
+    /// Everything in the class file after `constant_pool`.
+    fn class_body(&mut self, class: &ClassFile) -> Result<()> {
+        self.u16(class.access_flags().bits());
+        self.class(class.this_class())?;
+        self.optional_class(class.super_class())?;
+        self.classes(class.interfaces())?;
+
+        self.table(class.fields(), "fields", |w, field| {
+            w.u16(field.access_flags.bits());
+            w.utf8(&field.name)?;
+            w.utf8(&field.descriptor.to_string())?;
+            w.attributes(&field.attributes)
+        })?;
+
+        self.table(class.methods(), "methods", |w, method| {
+            w.u16(method.access_flags.bits());
+            w.utf8(&method.name)?;
+            w.utf8(&method.descriptor.to_string())?;
+
+            // The Code attribute goes back where it was parsed from.
+            let attributes = method.attributes();
+            let (before, after) = attributes.split_at(method.code_position().min(attributes.len()));
+            let count = attributes.len() + usize::from(method.has_code());
+            w.u16(table_len(count, "attributes")?);
+            for attr in before {
+                w.attribute(attr)?;
+            }
+            if let Some(code) = method.lazy_code() {
+                w.attribute_with("Code", |w| match code.raw_bytes() {
+                    // Code that hasn't been modified since it was parsed refers to the same
+                    // constants, so it's copied as is.
+                    Some(bytes) => {
+                        w.buf.extend_from_slice(bytes);
+                        Ok(())
+                    }
+                    None => {
+                        let decoded = code.get().expect("modified code is decoded");
+                        match code.references() {
+                            Some(references) => w.with_hints(references, |w| w.code(decoded)),
+                            None => w.code(decoded),
+                        }
+                    }
+                })?;
+            }
+            for attr in after {
+                w.attribute(attr)?;
+            }
+            Ok(())
+        })?;
+
+        self.attributes(class.attributes())
+    }
+
+    fn attributes(&mut self, attributes: &[Attribute]) -> Result<()> {
+        self.table(attributes, "attributes", |w, attr| w.attribute(attr))
+    }
+
+    /// Writes an attribute called `name`, whose contents are written by `write_fn`.
+    fn attribute_with(
+        &mut self,
+        name: &str,
+        write_fn: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        self.utf8(name)?;
+        let length_offset = self.buf.len();
+        self.u32(0);
+        write_fn(self)?;
+
+        let length: u32 = table_len(self.buf.len() - length_offset - 4, "attribute")?;
+        self.buf[length_offset..length_offset + 4].copy_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7>
+    fn attribute(&mut self, attr: &Attribute) -> Result<()> {
+        self.attribute_with(attr.name(), |w| match attr {
+            Attribute::ConstantValue(value) => {
+                let index = match value {
+                    ConstantValueAttribute::Integer(x) => w.cp.integer(*x)?,
+                    ConstantValueAttribute::Float(x) => w.cp.float(*x)?,
+                    ConstantValueAttribute::Long(x) => w.cp.long(*x)?,
+                    ConstantValueAttribute::Double(x) => w.cp.double(*x)?,
+                    ConstantValueAttribute::String(s) => w.cp.string(s)?,
+                };
+                w.index(index);
+                Ok(())
+            }
+            Attribute::Code(code) => w.code(code),
+            Attribute::LineNumberTable(entries) => w.table(entries, "LineNumberTable", |w, e| {
+                w.u16(e.start_pc);
+                w.u16(e.line_number);
+                Ok(())
+            }),
+            Attribute::LocalVariableTable(entries) => {
+                w.table(entries, "LocalVariableTable", |w, e| {
+                    w.u16(e.start_pc);
+                    w.u16(e.length);
+                    w.utf8(&e.name)?;
+                    w.utf8(&e.descriptor.to_string())?;
+                    w.u16(e.index);
+                    Ok(())
+                })
+            }
+            Attribute::LocalVariableTypeTable(entries) => {
+                w.table(entries, "LocalVariableTypeTable", |w, e| {
+                    w.u16(e.start_pc);
+                    w.u16(e.length);
+                    w.utf8(&e.name)?;
+                    w.utf8(&e.signature.encode())?;
+                    w.u16(e.index);
+                    Ok(())
+                })
+            }
+            Attribute::StackMapTable(frames) => {
+                w.table(frames, "StackMapTable", |w, frame| w.stack_map_frame(frame))
+            }
+            Attribute::Exceptions(classes)
+            | Attribute::NestMembers(classes)
+            | Attribute::PermittedSubclasses(classes) => w.classes(classes),
+            Attribute::InnerClasses(classes) => w.table(classes, "InnerClasses", |w, class| {
+                w.class(&class.inner_class)?;
+                w.optional_class(class.outer_class.as_ref())?;
+                w.optional_utf8(class.inner_name.as_ref())?;
+                w.u16(class.inner_class_access_flags.bits());
+                Ok(())
+            }),
+            Attribute::EnclosingMethod(enclosing) => {
+                w.class(&enclosing.class)?;
+                match &enclosing.method {
+                    Some(method) => {
+                        let index = w.cp.name_and_type(&method.name, &method.descriptor)?;
+                        w.index(index);
+                    }
+                    None => w.u16(0),
+                }
+                Ok(())
+            }
+            Attribute::Signature(signature) => w.utf8(&signature.encode()),
+            Attribute::SourceFile(file) => w.utf8(file),
+            Attribute::SourceDebugExtension(extension) => {
+                w.buf
+                    .extend_from_slice(&extension.debug_extension.to_modified_utf8());
+                Ok(())
+            }
+            Attribute::RuntimeVisibleAnnotations(annotations)
+            | Attribute::RuntimeInvisibleAnnotations(annotations) => w.annotations(annotations),
+            Attribute::RuntimeVisibleParameterAnnotations(parameters)
+            | Attribute::RuntimeInvisibleParameterAnnotations(parameters) => {
+                w.short_table(parameters, "parameter annotations", |w, annotations| {
+                    w.annotations(annotations)
+                })
+            }
+            Attribute::RuntimeVisibleTypeAnnotations(annotations)
+            | Attribute::RuntimeInvisibleTypeAnnotations(annotations) => {
+                w.table(annotations, "type annotations", |w, annotation| {
+                    w.type_annotation(annotation)
+                })
+            }
+            Attribute::AnnotationDefault(value) => w.element_value(value),
+            Attribute::BootstrapMethods(methods) => {
+                w.table(methods, "BootstrapMethods", |w, method| {
+                    let method_index = w.cp.method_handle(&method.method)?;
+                    w.index(method_index);
+                    w.table(&method.arguments, "bootstrap arguments", |w, argument| {
+                        let index = w.cp.loadable(argument)?;
+                        w.index(index);
+                        Ok(())
+                    })
+                })
+            }
+            Attribute::MethodParameters(parameters) => {
+                w.short_table(parameters, "MethodParameters", |w, parameter| {
+                    w.optional_utf8(parameter.name.as_ref())?;
+                    w.u16(parameter.access_flags.bits());
+                    Ok(())
+                })
+            }
+            Attribute::Module(module) => w.module_attribute(module),
+            Attribute::ModulePackages(packages) => {
+                w.table(packages, "ModulePackages", |w, package| w.package(package))
+            }
+            Attribute::ModuleMainClass(class) | Attribute::NestHost(class) => w.class(class),
+            Attribute::Record(components) => w.table(components, "Record", |w, component| {
+                w.utf8(&component.name)?;
+                w.utf8(&component.descriptor.to_string())?;
+                w.attributes(&component.attributes)
+            }),
+            Attribute::Unknown { bytes, .. } => {
+                w.buf.extend_from_slice(bytes);
+                Ok(())
+            }
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.3>
+    fn code(&mut self, code: &CodeAttribute) -> Result<()> {
+        self.u16(code.max_stack);
+        self.u16(code.max_locals);
+
+        let bytes = crate::op::write_ops(&code.code, &mut self.cp)?;
+        // code_length is a u32, but it has to be less than 65536.
+        if bytes.len() > usize::from(u16::MAX) {
+            return Err(ClassWriteError::TooLong("code"));
+        }
+        self.u32(bytes.len() as u32);
+        self.buf.extend_from_slice(&bytes);
+
+        self.table(&code.exception_table, "exception table", |w, handler| {
+            w.u16(handler.start_pc);
+            w.u16(handler.end_pc);
+            w.u16(handler.handler_pc);
+            w.u16(handler.catch_type);
+            Ok(())
+        })?;
+
+        self.attributes(&code.attributes)
+    }
+
+    /// Writes the frame in its shortest form, which is what javac does, unless it's marked as
+    /// extended.
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.4>
+    fn stack_map_frame(&mut self, frame: &StackMapFrame) -> Result<()> {
+        match frame {
+            StackMapFrame::Same {
+                offset_delta,
+                extended,
+            } => match u8::try_from(*offset_delta) {
+                Ok(delta @ 0..=63) if !extended => self.u8(delta),
+                _ => {
+                    self.u8(251);
+                    self.u16(*offset_delta);
+                }
+            },
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta,
+                stack,
+                extended,
+            } => {
+                match u8::try_from(*offset_delta) {
+                    Ok(delta @ 0..=63) if !extended => self.u8(64 + delta),
+                    _ => {
+                        self.u8(247);
+                        self.u16(*offset_delta);
+                    }
+                }
+                self.verification_type(stack)?;
+            }
+            StackMapFrame::Chop { offset_delta, k } => {
+                if !(1..=3).contains(k) {
+                    return Err(ClassWriteError::BadStackMapFrame);
+                }
+                self.u8(251 - k);
+                self.u16(*offset_delta);
+            }
+            StackMapFrame::Append {
+                offset_delta,
+                locals,
+            } => {
+                if !(1..=3).contains(&locals.len()) {
+                    return Err(ClassWriteError::BadStackMapFrame);
+                }
+                self.u8(251 + locals.len() as u8);
+                self.u16(*offset_delta);
+                for local in locals {
+                    self.verification_type(local)?;
+                }
+            }
+            StackMapFrame::Full {
+                offset_delta,
+                locals,
+                stack,
+            } => {
+                self.u8(255);
+                self.u16(*offset_delta);
+                self.table(locals, "stack map frame", |w, t| w.verification_type(t))?;
+                self.table(stack, "stack map frame", |w, t| w.verification_type(t))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verification_type(&mut self, t: &VerificationTypeInfo) -> Result<()> {
+        match t {
+            VerificationTypeInfo::Top => self.u8(0),
+            VerificationTypeInfo::Integer => self.u8(1),
+            VerificationTypeInfo::Float => self.u8(2),
+            VerificationTypeInfo::Double => self.u8(3),
+            VerificationTypeInfo::Long => self.u8(4),
+            VerificationTypeInfo::Null => self.u8(5),
+            VerificationTypeInfo::UninitializedThis => self.u8(6),
+            VerificationTypeInfo::Object(class) => {
+                self.u8(7);
+                self.class(class)?;
+            }
+            VerificationTypeInfo::Uninitialized(offset) => {
+                self.u8(8);
+                self.u16(*offset);
+            }
+        }
+        Ok(())
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.25>
+    fn module_attribute(&mut self, module: &ModuleAttribute) -> Result<()> {
+        self.module(&module.name)?;
+        self.u16(module.flags.bits());
+        self.optional_utf8(module.version.as_ref())?;
+
+        self.table(&module.requires, "requires", |w, requires| {
+            w.module(&requires.module)?;
+            w.u16(requires.flags.bits());
+            w.optional_utf8(requires.version.as_ref())
+        })?;
+        self.table(&module.exports, "exports", |w, exports| {
+            w.package(&exports.package)?;
+            w.u16(exports.flags.bits());
+            w.table(&exports.to, "exports", |w, module| w.module(module))
+        })?;
+        self.table(&module.opens, "opens", |w, opens| {
+            w.package(&opens.package)?;
+            w.u16(opens.flags.bits());
+            w.table(&opens.to, "opens", |w, module| w.module(module))
+        })?;
+        self.classes(&module.uses)?;
+        self.table(&module.provides, "provides", |w, provides| {
+            w.class(&provides.service)?;
+            w.classes(&provides.with)
+        })
+    }
+
+    fn annotations(&mut self, annotations: &[Annotation]) -> Result<()> {
+        self.table(annotations, "annotations", |w, annotation| {
+            w.annotation(annotation)
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16>
+    fn annotation(&mut self, annotation: &Annotation) -> Result<()> {
+        self.utf8(&annotation.type_descriptor.to_string())?;
+        self.table(&annotation.elements, "annotation elements", |w, pair| {
+            w.utf8(&pair.name)?;
+            w.element_value(&pair.value)
+        })
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.16.1>
+    fn element_value(&mut self, value: &ElementValue) -> Result<()> {
+        let (tag, index) = match value {
+            ElementValue::Byte(x) => (b'B', self.cp.integer((*x).into())?),
+            ElementValue::Char(x) => (b'C', self.cp.integer((*x).into())?),
+            ElementValue::Double(x) => (b'D', self.cp.double(*x)?),
+            ElementValue::Float(x) => (b'F', self.cp.float(*x)?),
+            ElementValue::Int(x) => (b'I', self.cp.integer(*x)?),
+            ElementValue::Long(x) => (b'J', self.cp.long(*x)?),
+            ElementValue::Short(x) => (b'S', self.cp.integer((*x).into())?),
+            ElementValue::Boolean(x) => (b'Z', self.cp.integer((*x).into())?),
+            ElementValue::OutOfRange { tag, value } => (*tag, self.cp.integer(*value)?),
+            ElementValue::String(s) => (b's', self.cp.java_string(s)?),
+            ElementValue::Class(descriptor) => (b'c', self.cp.utf8(&descriptor.to_string())?),
+            ElementValue::Enum {
+                type_descriptor,
+                const_name,
+            } => {
+                self.u8(b'e');
+                self.utf8(&type_descriptor.to_string())?;
+                return self.utf8(const_name);
+            }
+            ElementValue::Annotation(annotation) => {
+                self.u8(b'@');
+                return self.annotation(annotation);
+            }
+            ElementValue::Array(values) => {
+                self.u8(b'[');
+                return self.table(values, "element value array", |w, value| {
+                    w.element_value(value)
+                });
+            }
+        };
+
+        self.u8(tag);
+        self.index(index);
+        Ok(())
+    }
+
+    /// <https://docs.oracle.com/javase/specs/jvms/se20/html/jvms-4.html#jvms-4.7.20>
+    fn type_annotation(&mut self, annotation: &TypeAnnotation) -> Result<()> {
+        self.u8(annotation.target_type as u8);
+
+        match &annotation.target_info {
+            TargetInfo::TypeParameter {
+                type_parameter_index,
+            } => self.u8(*type_parameter_index),
+            TargetInfo::Supertype { supertype_index } => self.u16(*supertype_index),
+            TargetInfo::TypeParameterBound {
+                type_parameter_index,
+                bound_index,
+            } => {
+                self.u8(*type_parameter_index);
+                self.u8(*bound_index);
+            }
+            TargetInfo::Empty => {}
+            TargetInfo::FormalParameter {
+                formal_parameter_index,
+            } => self.u8(*formal_parameter_index),
+            TargetInfo::Throws { throws_type_index } => self.u16(*throws_type_index),
+            TargetInfo::Localvar(entries) => {
+                self.table(entries, "localvar target", |w, entry| {
+                    w.u16(entry.start_pc);
+                    w.u16(entry.length);
+                    w.u16(entry.index);
+                    Ok(())
+                })?;
+            }
+            TargetInfo::Catch {
+                exception_table_index,
+            } => self.u16(*exception_table_index),
+            TargetInfo::Offset(offset) => self.u16(*offset),
+            TargetInfo::TypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                self.u16(*offset);
+                self.u8(*type_argument_index);
+            }
+        }
+
+        self.short_table(&annotation.target_path, "type path", |w, entry| {
+            w.u8(entry.type_path_kind as u8);
+            w.u8(entry.type_argument_index);
+            Ok(())
+        })?;
+
+        self.annotation(&annotation.annotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixtures, utf8};
+
+    fn round_trip(raw: &[u8]) -> Vec<u8> {
+        ClassFile::parse_from_bytes(raw)
+            .unwrap()
+            .to_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_fixtures() {
+        let fixtures = fixtures();
+        for (path, raw) in &fixtures {
+            assert!(*raw == round_trip(raw), "{} changed", path.display());
+        }
+        assert!(fixtures.len() >= 14);
+    }
+
+    #[test]
+    fn test_round_trip_unusual_encodings() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 50, 0, 10],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("Foo"),              // #5, a duplicate of #1
+            // #6 Float NaN with a payload, and a Long with its unusable entry at #8
+            vec![4, 0x7F, 0x80, 0x00, 0x01],
+            vec![5, 0, 0, 0, 0, 0, 0, 0, 1],
+            vec![7, 0, 5], // #9, a duplicate of #2 through the duplicate Utf8
+            vec![0, 0x21, 0, 9, 0, 4, 0, 0, 0, 0, 0, 0],
+            // An unknown attribute called Foo, through the duplicate Utf8
+            vec![0, 1, 0, 5, 0, 0, 0, 2, 0xAB, 0xCD],
+        ]
+        .concat();
+
+        let class = ClassFile::parse_from_bytes(&raw).unwrap();
+        assert_eq!(raw, class.to_bytes().unwrap());
+
+        // Without the references from parsing, the first of the equal constants is used.
+        let mut class = class;
+        class.cp_references.clear();
+        let written = class.to_bytes().unwrap();
+        assert_eq!(
+            written[written.len() - 22..written.len() - 18],
+            [0, 0x21, 0, 2]
+        );
+        assert_eq!(written[written.len() - 10..written.len() - 6], [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_round_trip_extended_frames() {
+        let raw = [
+            vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 50, 0, 9],
+            utf8("Foo"),              // #1
+            vec![7, 0, 1],            // #2 Class
+            utf8("java/lang/Object"), // #3
+            vec![7, 0, 3],            // #4 Class
+            utf8("run"),              // #5
+            utf8("()V"),              // #6
+            utf8("Code"),             // #7
+            utf8("StackMapTable"),    // #8
+            vec![0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0],
+            // `static void run()`, with nine nops and a return
+            vec![0, 1, 0, 0x08, 0, 5, 0, 6, 0, 1, 0, 7, 0, 0, 0, 37],
+            vec![
+                0, 1, 0, 0, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xB1, 0, 0,
+            ],
+            // same_frame_extended and same_locals_1_stack_item_frame_extended, with deltas that
+            // would fit in the short forms
+            vec![0, 1, 0, 8, 0, 0, 0, 9, 0, 2, 251, 0, 5, 247, 0, 3, 1],
+            vec![0, 0],
+        ]
+        .concat();
+
+        let class = ClassFile::parse_from_bytes(&raw).unwrap();
+        let code = class.methods()[0].code().unwrap().unwrap();
+        assert_eq!(
+            Some(
+                &[
+                    StackMapFrame::Same {
+                        offset_delta: 5,
+                        extended: true
+                    },
+                    StackMapFrame::SameLocals1StackItem {
+                        offset_delta: 3,
+                        stack: VerificationTypeInfo::Integer,
+                        extended: true
+                    }
+                ][..]
+            ),
+            code.stack_map_table()
+        );
+        assert_eq!(raw, round_trip(&raw));
+    }
+
+    #[test]
+    fn test_add_constants() {
+        let class =
+            ClassFile::parse_from_bytes(include_bytes!("../test_data/Simple.class")).unwrap();
+        let mut cp = ConstantPoolWriter::new(class.constant_pool(), &[]);
+        let len = class.constant_pool().len() as u16;
+
+        // Constants that are already there are reused.
+        let this_class = cp.class(class.this_class()).unwrap();
+        assert_eq!(
+            Ok(class.this_class()),
+            class.constant_pool().get_class(this_class)
+        );
+
+        // New ones go at the end, with the entries they refer to first.
+        assert_eq!(len + 2, cp.class("com/example/New").unwrap());
+        assert_eq!(len + 3, cp.long(1 << 40).unwrap());
+        assert_eq!(len + 5, cp.integer(-1).unwrap());
+        assert_eq!(len + 2, cp.class("com/example/New").unwrap());
+
+        let mut out = Vec::new();
+        cp.write(&mut out);
+        assert_eq!(len + 6, u16::from_be_bytes([out[0], out[1]]));
+    }
+
+    #[test]
+    fn test_narrow_element_value_out_of_range() {
+        let cp = ConstantPool::new(
+            vec![Constant::ConstantValue(ConstantValueKind::Integer(300))],
+            52,
+        );
+        let mut w = Writer {
+            cp: ConstantPoolWriter::new(&cp, &[]),
+            buf: Vec::new(),
+        };
+        w.element_value(&ElementValue::OutOfRange {
+            tag: b'B',
+            value: 300,
+        })
+        .unwrap();
+        assert_eq!(b"B\0\x01", &w.buf[..]);
+    }
+
+    #[test]
+    fn test_ldc_index_too_large() {
+        use crate::op::Op;
+
+        let class =
+            ClassFile::parse_from_bytes(include_bytes!("../test_data/Simple.class")).unwrap();
+        let mut cp = ConstantPoolWriter::new(class.constant_pool(), &[]);
+        for i in 0..300 {
+            cp.integer(1000 + i).unwrap();
+        }
+
+        assert!(matches!(
+            crate::op::write_ops(&[Op::Ldc(LoadableConstant::Integer(1299))], &mut cp),
+            Err(ClassWriteError::LdcIndexTooLarge(_))
+        ));
+        assert!(
+            crate::op::write_ops(&[Op::LdcW(LoadableConstant::Integer(1299))], &mut cp).is_ok()
+        );
+    }
+}
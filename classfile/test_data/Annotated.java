@@ -0,0 +1,32 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.List;
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Info {
+    String name() default "none";
+    int[] numbers() default {1, 2};
+    Class<?> type() default void.class;
+    ElementType kind() default ElementType.FIELD;
+    Retention nested() default @Retention(RetentionPolicy.SOURCE);
+    char letter() default 'x';
+    boolean flag() default true;
+    long big() default 1L << 40;
+}
+
+@Target(ElementType.TYPE_USE)
+@interface NotNull {}
+
+@interface Internal {}
+
+@Deprecated
+@Internal
+@Info(name = "annotated", numbers = {}, type = String.class)
+public class Annotated {
+    @Info(kind = ElementType.METHOD, flag = false)
+    List<@NotNull String> names;
+
+    void run(@Internal int count, @Info String label) {}
+}
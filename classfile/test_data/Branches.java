@@ -0,0 +1,42 @@
+public class Branches {
+    static int sum(int[] values) {
+        int total = 0;
+        for (int i = 0; i < values.length; i++) {
+            total += values[i];
+        }
+        return total;
+    }
+
+    static long describe(int x, String s) {
+        long result = 0;
+        if (x > 10) {
+            String upper = s.toUpperCase();
+            result = upper.length();
+        }
+        switch (x) {
+            case 1: result += 1; break;
+            case 2: result += 2; break;
+            case 3: result += 3; break;
+            case 1000: result -= 1; break;
+        }
+        try {
+            result += Integer.parseInt(s);
+        } catch (NumberFormatException e) {
+            result = -1;
+        }
+        return x > 0 ? result : -result;
+    }
+
+    static char letter(int x) {
+        switch (x) {
+            case 0: return 'a';
+            case 1: return 'b';
+            case 2: return 'c';
+            default: return '?';
+        }
+    }
+
+    Object create(boolean big) {
+        return new StringBuilder(big ? 100 : 10);
+    }
+}
@@ -0,0 +1,14 @@
+import java.util.List;
+import java.util.Map;
+
+public class Generics<K extends Comparable<K>, V> implements Comparable<Generics<K, V>> {
+    Map<K, List<? super V>> entries;
+
+    public <E extends Exception> V get(K key, List<? extends V>[] fallback) throws E {
+        return null;
+    }
+
+    public int compareTo(Generics<K, V> other) {
+        return 0;
+    }
+}
@@ -0,0 +1,7 @@
+import java.util.function.Supplier;
+
+public class Lambdas {
+    static Supplier<String> greeter(String name) {
+        return () -> "Hello, " + name;
+    }
+}
@@ -0,0 +1,13 @@
+import java.util.ArrayList;
+import java.util.List;
+
+public class Locals {
+    static <T> List<T> repeat(final T item, int times) {
+        List<T> result = new ArrayList<>();
+        for (int i = 0; i < times; i++) {
+            result.add(item);
+        }
+        long done = times;
+        return result;
+    }
+}
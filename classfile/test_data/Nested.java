@@ -0,0 +1,13 @@
+import java.io.IOException;
+
+public class Nested {
+    class Inner {}
+
+    private static final class StaticNested {}
+
+    Runnable anonymous() throws IOException, InterruptedException {
+        return new Runnable() {
+            public void run() {}
+        };
+    }
+}
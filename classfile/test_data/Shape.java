@@ -0,0 +1,7 @@
+import java.util.List;
+
+public sealed interface Shape permits Shape.Circle, Shape.Polygon {
+    record Circle(double radius) implements Shape {}
+
+    record Polygon(List<Integer> sides, String name) implements Shape {}
+}
@@ -0,0 +1,11 @@
+public class Simple {
+    int x;
+
+    Simple() {
+        x = 5;
+    }
+
+    int get() {
+        return x;
+    }
+}
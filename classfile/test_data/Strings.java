@@ -0,0 +1,5 @@
+public class Strings {
+    static final String NUL = "a\0b";
+    static final String EMOJI = "smile 😀";
+    static final String LONE_SURROGATE = "x\uD800y";
+}
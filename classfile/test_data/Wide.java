@@ -0,0 +1,8 @@
+public class Wide {
+    static final long A = 1234567890123L;
+    static final double B = 3.5;
+    static final long C = -1L << 40;
+    static final int D = 100000;
+    static final String E = "after the wide constants";
+    static final float F = 1.25f;
+}
@@ -0,0 +1,11 @@
+// Compiled together with com.example.app.Main and com.example.api.Api, then packed with
+// `jar --main-class com.example.app.Main`, which adds the ModulePackages and ModuleMainClass attributes.
+module com.example.app {
+    requires transitive java.logging;
+    requires static java.sql;
+    exports com.example.api;
+    exports com.example.app to java.base, java.logging;
+    opens com.example.app;
+    uses java.sql.Driver;
+    provides java.lang.Runnable with com.example.app.Main;
+}
@@ -1,20 +1,13 @@
-use classfile::ClassFile;
-use std::io::Read;
+use classfile::{ClassFile, ParseOptions};
 use vm::Vm;
 
 fn main() {
-    let mut data = Vec::new();
-    std::fs::File::open(
-        std::env::args()
-            .skip(1)
-            .next()
-            .unwrap_or("Foo.class".into()),
-    )
-    .unwrap()
-    .read_to_end(&mut data)
-    .unwrap();
+    let path = std::env::args().nth(1).unwrap_or("Foo.class".into());
 
-    let fooclass = ClassFile::parse_from_bytes(&data);
+    let fooclass = ClassFile::parse_file(path, ParseOptions::default()).unwrap_or_else(|e| {
+        eprintln!("Failed to parse class file: {e}");
+        std::process::exit(1);
+    });
 
     let mut vm = Vm::from_init_class(fooclass);
 
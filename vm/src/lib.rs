@@ -1,7 +1,6 @@
 use classfile::*;
 use gc::*;
 use std::collections::HashMap;
-use std::mem;
 
 #[derive(Debug)]
 enum Value {
@@ -31,7 +30,8 @@ struct Frame {
 
 impl Frame {
     fn from_method(class: &str, meth: &MethodInfo) -> Frame {
-        let Some(code) = &meth.code else {
+        // Classes are parsed eagerly, so their code has already been decoded.
+        let Ok(Some(code)) = meth.code() else {
             panic!("{class}.{}() doesn't have code!", meth.name);
         };
 
@@ -44,7 +44,7 @@ impl Frame {
 
 #[derive(Debug)]
 pub struct Vm {
-    classes: HashMap<&'static str, ClassFile>,
+    classes: HashMap<Symbol, ClassFile>,
     heap: Heap,
     frame_stack: Vec<Frame>,
 }
@@ -57,10 +57,8 @@ impl Vm {
             classes: HashMap::new(),
         };
 
-        it.classes.insert(
-            unsafe { mem::transmute::<&'_ str, &'static str>(main_class.this_class()) },
-            main_class,
-        );
+        it.classes
+            .insert(main_class.this_class().clone(), main_class);
 
         let main_class = it.classes.iter().next().map(|(_, v)| v).unwrap();
 